@@ -0,0 +1,1166 @@
+#!/usr/bin/env python3
+"""Generates the framebuffer console's 8x16 PSF2 font from the glyphs drawn below.
+
+Usage: python3 kernel/font/mkfont.py kernel/src/vga/font.psf
+
+Each glyph is drawn in rows of up to 8 columns, starting two rows from the top of the cell. Rows 0
+to 9 are above the baseline and rows 10 to 12 are descenders. Characters without a glyph are blank.
+"""
+
+import struct
+import sys
+
+WIDTH = 8
+HEIGHT = 16
+TOP = 2
+GLYPH_COUNT = 256
+
+PSF2_MAGIC = 0x864AB572
+PSF2_HEADER_SIZE = 32
+
+GLYPHS = {
+    "!": """
+...##..
+..####.
+..####.
+..####.
+...##..
+...##..
+...##..
+.......
+...##..
+...##..
+""",
+    '"': """
+.##..##
+.##..##
+.##..##
+..#..#.
+""",
+    "#": """
+.......
+.##.##.
+.##.##.
+#######
+.##.##.
+.##.##.
+.##.##.
+#######
+.##.##.
+.##.##.
+""",
+    "$": """
+...##..
+.#####.
+##...##
+##.....
+.####..
+....##.
+.....##
+##...##
+.#####.
+...##..
+...##..
+""",
+    "%": """
+.......
+.......
+##...##
+##..##.
+...##..
+..##...
+.##....
+##..##.
+#...##.
+.......
+""",
+    "&": """
+..###..
+.##.##.
+.##.##.
+..###..
+.###.##
+##.###.
+##..##.
+##..##.
+##.###.
+.###.##
+""",
+    "'": """
+..##...
+..##...
+..##...
+.##....
+""",
+    "(": """
+....##.
+...##..
+..##...
+..##...
+..##...
+..##...
+..##...
+..##...
+...##..
+....##.
+""",
+    ")": """
+..##...
+...##..
+....##.
+....##.
+....##.
+....##.
+....##.
+....##.
+...##..
+..##...
+""",
+    "*": """
+.......
+.......
+.......
+.##..##
+..####.
+#######
+..####.
+.##..##
+""",
+    "+": """
+.......
+.......
+.......
+...##..
+...##..
+.######
+...##..
+...##..
+""",
+    ",": """
+.......
+.......
+.......
+.......
+.......
+.......
+.......
+...##..
+...##..
+...##..
+..##...
+""",
+    "-": """
+.......
+.......
+.......
+.......
+.......
+.######
+""",
+    ".": """
+.......
+.......
+.......
+.......
+.......
+.......
+.......
+.......
+...##..
+...##..
+""",
+    "/": """
+.......
+.......
+.....##
+....##.
+...##..
+..##...
+.##....
+##.....
+#......
+""",
+    "0": """
+..###..
+.##.##.
+##...##
+##..###
+##.####
+####.##
+###..##
+##...##
+.##.##.
+..###..
+""",
+    "1": """
+...##..
+..###..
+.####..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+.######
+""",
+    "2": """
+.#####.
+##...##
+.....##
+....##.
+...##..
+..##...
+.##....
+##.....
+##...##
+#######
+""",
+    "3": """
+.#####.
+##...##
+.....##
+.....##
+..####.
+.....##
+.....##
+.....##
+##...##
+.#####.
+""",
+    "4": """
+....##.
+...###.
+..####.
+.##.##.
+##..##.
+#######
+....##.
+....##.
+....##.
+...####
+""",
+    "5": """
+#######
+##.....
+##.....
+##.....
+######.
+.....##
+.....##
+.....##
+##...##
+.#####.
+""",
+    "6": """
+..###..
+.##....
+##.....
+##.....
+######.
+##...##
+##...##
+##...##
+##...##
+.#####.
+""",
+    "7": """
+#######
+##...##
+.....##
+....##.
+...##..
+..##...
+..##...
+..##...
+..##...
+..##...
+""",
+    "8": """
+.#####.
+##...##
+##...##
+##...##
+.#####.
+##...##
+##...##
+##...##
+##...##
+.#####.
+""",
+    "9": """
+.#####.
+##...##
+##...##
+##...##
+.######
+.....##
+.....##
+.....##
+....##.
+.####..
+""",
+    ":": """
+.......
+.......
+...##..
+...##..
+.......
+.......
+.......
+...##..
+...##..
+""",
+    ";": """
+.......
+.......
+...##..
+...##..
+.......
+.......
+.......
+...##..
+...##..
+..##...
+""",
+    "<": """
+.......
+.....##
+....##.
+...##..
+..##...
+.##....
+..##...
+...##..
+....##.
+.....##
+""",
+    "=": """
+.......
+.......
+.......
+.......
+.######
+.......
+.######
+""",
+    ">": """
+.......
+.##....
+..##...
+...##..
+....##.
+.....##
+....##.
+...##..
+..##...
+.##....
+""",
+    "?": """
+.#####.
+##...##
+##...##
+....##.
+...##..
+...##..
+...##..
+.......
+...##..
+...##..
+""",
+    "@": """
+.......
+.#####.
+##...##
+##...##
+##.####
+##.####
+##.####
+##.###.
+##.....
+.#####.
+""",
+    "A": """
+...#...
+..###..
+.##.##.
+##...##
+##...##
+#######
+##...##
+##...##
+##...##
+##...##
+""",
+    "B": """
+######.
+.##..##
+.##..##
+.##..##
+.#####.
+.##..##
+.##..##
+.##..##
+.##..##
+######.
+""",
+    "C": """
+..####.
+.##..##
+##....#
+##.....
+##.....
+##.....
+##.....
+##....#
+.##..##
+..####.
+""",
+    "D": """
+#####..
+.##.##.
+.##..##
+.##..##
+.##..##
+.##..##
+.##..##
+.##..##
+.##.##.
+#####..
+""",
+    "E": """
+#######
+.##..##
+.##...#
+.##.#..
+.####..
+.##.#..
+.##....
+.##...#
+.##..##
+#######
+""",
+    "F": """
+#######
+.##..##
+.##...#
+.##.#..
+.####..
+.##.#..
+.##....
+.##....
+.##....
+####...
+""",
+    "G": """
+..####.
+.##..##
+##....#
+##.....
+##.....
+##.####
+##...##
+##...##
+.##..##
+..###.#
+""",
+    "H": """
+##...##
+##...##
+##...##
+##...##
+#######
+##...##
+##...##
+##...##
+##...##
+##...##
+""",
+    "I": """
+..####.
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+..####.
+""",
+    "J": """
+...####
+....##.
+....##.
+....##.
+....##.
+....##.
+##..##.
+##..##.
+##..##.
+.####..
+""",
+    "K": """
+###..##
+.##..##
+.##.##.
+.##.##.
+.####..
+.####..
+.##.##.
+.##..##
+.##..##
+###..##
+""",
+    "L": """
+####...
+.##....
+.##....
+.##....
+.##....
+.##....
+.##....
+.##...#
+.##..##
+#######
+""",
+    "M": """
+##...##
+###.###
+#######
+#######
+##.#.##
+##...##
+##...##
+##...##
+##...##
+##...##
+""",
+    "N": """
+##...##
+###..##
+####.##
+#######
+##.####
+##..###
+##...##
+##...##
+##...##
+##...##
+""",
+    "O": """
+.#####.
+##...##
+##...##
+##...##
+##...##
+##...##
+##...##
+##...##
+##...##
+.#####.
+""",
+    "P": """
+######.
+.##..##
+.##..##
+.##..##
+.#####.
+.##....
+.##....
+.##....
+.##....
+####...
+""",
+    "Q": """
+.#####.
+##...##
+##...##
+##...##
+##...##
+##...##
+##...##
+##.#.##
+##.####
+.#####.
+.....##
+......#
+""",
+    "R": """
+######.
+.##..##
+.##..##
+.##..##
+.#####.
+.##.##.
+.##..##
+.##..##
+.##..##
+###..##
+""",
+    "S": """
+.#####.
+##...##
+##...##
+.##....
+..###..
+....##.
+.....##
+##...##
+##...##
+.#####.
+""",
+    "T": """
+######.
+######.
+#.##.#.
+..##...
+..##...
+..##...
+..##...
+..##...
+..##...
+.####..
+""",
+    "U": """
+##...##
+##...##
+##...##
+##...##
+##...##
+##...##
+##...##
+##...##
+##...##
+.#####.
+""",
+    "V": """
+##...##
+##...##
+##...##
+##...##
+##...##
+##...##
+##...##
+.##.##.
+..###..
+...#...
+""",
+    "W": """
+##...##
+##...##
+##...##
+##...##
+##.#.##
+##.#.##
+##.#.##
+#######
+###.###
+.##.##.
+""",
+    "X": """
+##...##
+##...##
+.##.##.
+.#####.
+..###..
+..###..
+.#####.
+.##.##.
+##...##
+##...##
+""",
+    "Y": """
+##..##.
+##..##.
+##..##.
+##..##.
+.####..
+..##...
+..##...
+..##...
+..##...
+.####..
+""",
+    "Z": """
+#######
+##...##
+#...##.
+...##..
+..##...
+.##....
+##.....
+##....#
+##...##
+#######
+""",
+    "[": """
+.####..
+.##....
+.##....
+.##....
+.##....
+.##....
+.##....
+.##....
+.##....
+.####..
+""",
+    "\\": """
+.......
+#......
+##.....
+.##....
+..##...
+...##..
+....##.
+.....##
+......#
+""",
+    "]": """
+.####..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+.####..
+""",
+    "^": """
+...#...
+..###..
+.##.##.
+##...##
+""",
+    "_": """
+.......
+.......
+.......
+.......
+.......
+.......
+.......
+.......
+.......
+.......
+.......
+########
+""",
+    "`": """
+..##...
+..##...
+...##..
+""",
+    "a": """
+.......
+.......
+.......
+.####..
+....##.
+.#####.
+##..##.
+##..##.
+##..##.
+.###.##
+""",
+    "b": """
+###....
+.##....
+.##....
+.####..
+.##.##.
+.##..##
+.##..##
+.##..##
+.##..##
+.#####.
+""",
+    "c": """
+.......
+.......
+.......
+.#####.
+##...##
+##.....
+##.....
+##.....
+##...##
+.#####.
+""",
+    "d": """
+...###.
+....##.
+....##.
+..####.
+.##.##.
+##..##.
+##..##.
+##..##.
+##..##.
+.###.##
+""",
+    "e": """
+.......
+.......
+.......
+.#####.
+##...##
+#######
+##.....
+##.....
+##...##
+.#####.
+""",
+    "f": """
+..###..
+.##.##.
+.##..#.
+.##....
+####...
+.##....
+.##....
+.##....
+.##....
+####...
+""",
+    "g": """
+.......
+.......
+.......
+.###.##
+##..##.
+##..##.
+##..##.
+##..##.
+##..##.
+.#####.
+....##.
+##..##.
+.####..
+""",
+    "h": """
+###....
+.##....
+.##....
+.##.##.
+.###.##
+.##..##
+.##..##
+.##..##
+.##..##
+###..##
+""",
+    "i": """
+...##..
+...##..
+.......
+..###..
+...##..
+...##..
+...##..
+...##..
+...##..
+..####.
+""",
+    "j": """
+.....##
+.....##
+.......
+....###
+.....##
+.....##
+.....##
+.....##
+.....##
+.....##
+.##..##
+.##..##
+..####.
+""",
+    "k": """
+###....
+.##....
+.##....
+.##..##
+.##.##.
+.####..
+.####..
+.##.##.
+.##..##
+###..##
+""",
+    "l": """
+..###..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+..####.
+""",
+    "m": """
+.......
+.......
+.......
+###.##.
+#######
+##.#.##
+##.#.##
+##.#.##
+##.#.##
+##...##
+""",
+    "n": """
+.......
+.......
+.......
+##.###.
+.##..##
+.##..##
+.##..##
+.##..##
+.##..##
+.##..##
+""",
+    "o": """
+.......
+.......
+.......
+.#####.
+##...##
+##...##
+##...##
+##...##
+##...##
+.#####.
+""",
+    "p": """
+.......
+.......
+.......
+##.###.
+.##..##
+.##..##
+.##..##
+.##..##
+.##..##
+.#####.
+.##....
+.##....
+####...
+""",
+    "q": """
+.......
+.......
+.......
+.###.##
+##..##.
+##..##.
+##..##.
+##..##.
+##..##.
+.#####.
+....##.
+....##.
+...####
+""",
+    "r": """
+.......
+.......
+.......
+##.###.
+.###.##
+.##..##
+.##....
+.##....
+.##....
+####...
+""",
+    "s": """
+.......
+.......
+.......
+.#####.
+##...##
+.##....
+..###..
+....##.
+##...##
+.#####.
+""",
+    "t": """
+...#...
+..##...
+..##...
+######.
+..##...
+..##...
+..##...
+..##...
+..##.##
+...###.
+""",
+    "u": """
+.......
+.......
+.......
+##..##.
+##..##.
+##..##.
+##..##.
+##..##.
+##..##.
+.###.##
+""",
+    "v": """
+.......
+.......
+.......
+##...##
+##...##
+##...##
+##...##
+.##.##.
+..###..
+...#...
+""",
+    "w": """
+.......
+.......
+.......
+##...##
+##...##
+##.#.##
+##.#.##
+##.#.##
+#######
+.##.##.
+""",
+    "x": """
+.......
+.......
+.......
+##...##
+.##.##.
+..###..
+..###..
+..###..
+.##.##.
+##...##
+""",
+    "y": """
+.......
+.......
+.......
+##...##
+##...##
+##...##
+##...##
+##...##
+##...##
+.######
+.....##
+....##.
+#####..
+""",
+    "z": """
+.......
+.......
+.......
+#######
+##..##.
+...##..
+..##...
+.##....
+##...##
+#######
+""",
+    "{": """
+....###
+...##..
+...##..
+...##..
+.###...
+...##..
+...##..
+...##..
+...##..
+....###
+""",
+    "|": """
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+...##..
+""",
+    "}": """
+###....
+..##...
+..##...
+..##...
+...###.
+..##...
+..##...
+..##...
+..##...
+###....
+""",
+    "~": """
+.###.##
+##.###.
+""",
+}
+
+
+def glyph_bytes(art):
+    rows = [row for row in art.strip("\n").split("\n")]
+    assert len(rows) <= HEIGHT - TOP, rows
+
+    cell = [0] * HEIGHT
+    for y, row in enumerate(rows):
+        assert len(row) <= WIDTH, row
+
+        for x, pixel in enumerate(row):
+            if pixel == "#":
+                cell[TOP + y] |= 0x80 >> x
+
+    return bytes(cell)
+
+
+def main():
+    header = struct.pack(
+        "<IIIIIIII",
+        PSF2_MAGIC,
+        0,  # version
+        PSF2_HEADER_SIZE,
+        0,  # flags: no unicode table
+        GLYPH_COUNT,
+        HEIGHT,  # bytes per glyph, as each row is one byte
+        HEIGHT,
+        WIDTH,
+    )
+
+    glyphs = b"".join(
+        glyph_bytes(GLYPHS[chr(c)]) if chr(c) in GLYPHS else bytes(HEIGHT)
+        for c in range(GLYPH_COUNT)
+    )
+
+    with open(sys.argv[1], "wb") as f:
+        f.write(header + glyphs)
+
+
+if __name__ == "__main__":
+    main()
@@ -1,16 +1,40 @@
+use crate::boot_info::boot_info;
+use crate::efi;
 use crate::memory::physical_mapping::{self, PhysicalMapping};
+use acpi::sdt::Signature;
 use acpi::{self, AcpiError, AcpiHandler, AcpiTables};
+use alloc::boxed::Box;
+use aml::{AmlContext, AmlError, DebugVerbosity};
+use core::{cmp, mem, slice};
+use spin::Once;
 
-pub fn acpi_init() -> Result<AcpiTables<WolffiaAcpiHandler>, AcpiError> {
+pub mod aml_handler;
+pub mod fadt;
+pub mod mcfg;
+
+use self::aml_handler::WolffiaAmlHandler;
+use self::fadt::Fadt;
+
+static ACPI_TABLES: Once<AcpiTables<WolffiaAcpiHandler>> = Once::new();
+
+/// Finds and parses the ACPI tables, keeping them for [`acpi_tables`](fn.acpi_tables.html).
+pub fn acpi_init() -> Result<(), AcpiError> {
     info!("acpi: initializing");
     let handler = WolffiaAcpiHandler;
-    // We're BIOS. We'd have crashed by now if we weren't.
-    let search_result = unsafe { AcpiTables::search_for_rsdp_bios(handler) };
+
+    let search_result = match find_rsdp() {
+        Some(rsdp) => unsafe { AcpiTables::from_rsdp(handler, rsdp as usize) },
+        None => {
+            debug!("acpi: rsdp not given by bootloader, searching bios area");
+            unsafe { AcpiTables::search_for_rsdp_bios(handler) }
+        }
+    };
 
     match search_result {
         Ok(tables) => {
+            ACPI_TABLES.call_once(|| tables);
             info!("acpi: init successful");
-            Ok(tables)
+            Ok(())
         }
         Err(e) => {
             error!("acpi: init unsuccessful {:?}", e);
@@ -19,6 +43,75 @@ pub fn acpi_init() -> Result<AcpiTables<WolffiaAcpiHandler>, AcpiError> {
     }
 }
 
+/// Returns the ACPI tables, or `None` if they could not be found or have not been parsed yet
+pub fn acpi_tables() -> Option<&'static AcpiTables<WolffiaAcpiHandler>> {
+    ACPI_TABLES.wait()
+}
+
+/// Finds the physical address of the RSDP as given by the bootloader, preferring the multiboot2
+/// RSDP tags over the EFI system table
+fn find_rsdp() -> Option<u64> {
+    // The RSDP is copied into its tag, directly after the tag's type and size fields
+    const RSDP_TAG_OFFSET: u64 = 8;
+    let boot_info = boot_info();
+
+    if let Some(tag) = boot_info.rsdp_v2_tag() {
+        debug!("acpi: using rsdp v2 from multiboot2 tag");
+        Some(boot_info.physical_address_of(tag) + RSDP_TAG_OFFSET)
+    } else if let Some(tag) = boot_info.rsdp_v1_tag() {
+        debug!("acpi: using rsdp v1 from multiboot2 tag");
+        Some(boot_info.physical_address_of(tag) + RSDP_TAG_OFFSET)
+    } else {
+        let rsdp = boot_info
+            .efi_sdt_64_tag()
+            .and_then(|tag| efi::find_rsdp(tag.sdt_address() as u64));
+
+        if rsdp.is_some() {
+            debug!("acpi: using rsdp from efi system table");
+        }
+
+        rsdp
+    }
+}
+
+/// Maps the system description table with the given signature, if it is present. The mapping is at
+/// least as large as `T`, even if the table itself is shorter (e.g older revisions).
+pub fn map_sdt<T>(signature: Signature) -> Option<PhysicalMapping<T>> {
+    let sdt = acpi_tables()?.sdts.get(&signature)?;
+    let size = cmp::max(sdt.length as usize, mem::size_of::<T>());
+
+    // SAFETY: the table was found by the acpi crate and so is valid
+    let mapping = unsafe {
+        physical_mapping::map_physical_region(sdt.physical_address as u64, size as u64, false)
+    };
+
+    Some(mapping)
+}
+
+pub fn fadt() -> Option<PhysicalMapping<Fadt>> {
+    map_sdt(Signature::FADT)
+}
+
+/// Parses the DSDT and SSDTs into a new AML namespace. The ACPI tables must have been found.
+pub fn parse_aml() -> Result<AmlContext, AmlError> {
+    let tables = acpi_tables().expect("ACPI tables not initialised!");
+    let mut context = AmlContext::new(Box::new(WolffiaAmlHandler), false, DebugVerbosity::None);
+
+    for table in tables.dsdt.iter().chain(tables.ssdts.iter()) {
+        let length = table.length as usize;
+
+        // SAFETY: the table was found by the acpi crate and so is valid
+        let mapping = unsafe {
+            physical_mapping::map_physical_region::<u8>(table.address as u64, length as u64, false)
+        };
+        let stream = unsafe { slice::from_raw_parts(&*mapping as *const u8, length) };
+
+        context.parse_table(stream)?;
+    }
+
+    Ok(context)
+}
+
 #[derive(Clone)]
 pub struct WolffiaAcpiHandler;
 
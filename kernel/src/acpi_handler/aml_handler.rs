@@ -0,0 +1,156 @@
+//! Gives the AML interpreter access to memory, IO ports and PCI configuration space.
+
+use crate::memory::physical_mapping;
+use crate::pci::{self, PciAddress};
+use core::{mem, ptr};
+use x86_64::instructions::port::{Port, PortRead, PortWrite};
+
+pub struct WolffiaAmlHandler;
+
+fn read_physical<T: Copy>(address: usize) -> T {
+    unsafe {
+        let mapping = physical_mapping::map_physical_region::<T>(
+            address as u64,
+            mem::size_of::<T>() as u64,
+            false,
+        );
+        ptr::read_volatile(&*mapping as *const T)
+    }
+}
+
+fn write_physical<T: Copy>(address: usize, value: T) {
+    unsafe {
+        let mut mapping = physical_mapping::map_physical_region::<T>(
+            address as u64,
+            mem::size_of::<T>() as u64,
+            true,
+        );
+        ptr::write_volatile(mapping.deref_mut().unwrap() as *mut T, value);
+    }
+}
+
+fn read_io<T: PortRead>(port: u16) -> T {
+    unsafe { Port::<T>::new(port).read() }
+}
+
+fn write_io<T: PortWrite>(port: u16, value: T) {
+    unsafe { Port::<T>::new(port).write(value) }
+}
+
+impl aml::Handler for WolffiaAmlHandler {
+    fn read_u8(&self, address: usize) -> u8 {
+        read_physical(address)
+    }
+
+    fn read_u16(&self, address: usize) -> u16 {
+        read_physical(address)
+    }
+
+    fn read_u32(&self, address: usize) -> u32 {
+        read_physical(address)
+    }
+
+    fn read_u64(&self, address: usize) -> u64 {
+        read_physical(address)
+    }
+
+    fn write_u8(&mut self, address: usize, value: u8) {
+        write_physical(address, value)
+    }
+
+    fn write_u16(&mut self, address: usize, value: u16) {
+        write_physical(address, value)
+    }
+
+    fn write_u32(&mut self, address: usize, value: u32) {
+        write_physical(address, value)
+    }
+
+    fn write_u64(&mut self, address: usize, value: u64) {
+        write_physical(address, value)
+    }
+
+    fn read_io_u8(&self, port: u16) -> u8 {
+        read_io(port)
+    }
+
+    fn read_io_u16(&self, port: u16) -> u16 {
+        read_io(port)
+    }
+
+    fn read_io_u32(&self, port: u16) -> u32 {
+        read_io(port)
+    }
+
+    fn write_io_u8(&self, port: u16, value: u8) {
+        write_io(port, value)
+    }
+
+    fn write_io_u16(&self, port: u16, value: u16) {
+        write_io(port, value)
+    }
+
+    fn write_io_u32(&self, port: u16, value: u32) {
+        write_io(port, value)
+    }
+
+    fn read_pci_u8(&self, segment: u16, bus: u8, device: u8, function: u8, offset: u16) -> u8 {
+        pci::config::read(PciAddress::new(segment, bus, device, function), offset)
+    }
+
+    fn read_pci_u16(&self, segment: u16, bus: u8, device: u8, function: u8, offset: u16) -> u16 {
+        pci::config::read(PciAddress::new(segment, bus, device, function), offset)
+    }
+
+    fn read_pci_u32(&self, segment: u16, bus: u8, device: u8, function: u8, offset: u16) -> u32 {
+        pci::config::read(PciAddress::new(segment, bus, device, function), offset)
+    }
+
+    fn write_pci_u8(
+        &self,
+        segment: u16,
+        bus: u8,
+        device: u8,
+        function: u8,
+        offset: u16,
+        value: u8,
+    ) {
+        pci::config::write(
+            PciAddress::new(segment, bus, device, function),
+            offset,
+            value,
+        )
+    }
+
+    fn write_pci_u16(
+        &self,
+        segment: u16,
+        bus: u8,
+        device: u8,
+        function: u8,
+        offset: u16,
+        value: u16,
+    ) {
+        pci::config::write(
+            PciAddress::new(segment, bus, device, function),
+            offset,
+            value,
+        )
+    }
+
+    fn write_pci_u32(
+        &self,
+        segment: u16,
+        bus: u8,
+        device: u8,
+        function: u8,
+        offset: u16,
+        value: u32,
+    ) {
+        pci::config::write(
+            PciAddress::new(segment, bus, device, function),
+            offset,
+            value,
+        )
+    }
+}
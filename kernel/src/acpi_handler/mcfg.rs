@@ -0,0 +1,47 @@
+//! The PCI Express memory mapped configuration space base address description table, which
+//! describes where each PCI segment group's enhanced configuration space is mapped.
+//! From: PCI Firmware Specification 3.0, 4.1.2
+
+use super::fadt::SdtHeader;
+use super::map_sdt;
+use acpi::sdt::Signature;
+use alloc::vec::Vec;
+use core::{mem, ptr};
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+struct McfgHeader {
+    header: SdtHeader,
+    _reserved: u64,
+}
+
+/// The configuration space of the given bus range in a segment group, mapped at a base address
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct McfgEntry {
+    pub base_address: u64,
+    pub segment_group: u16,
+    pub bus_start: u8,
+    pub bus_end: u8,
+    _reserved: u32,
+}
+
+/// Returns the entries of the MCFG, or `None` if there is no MCFG (i.e PCIe enhanced
+/// configuration access is unavailable)
+pub fn entries() -> Option<Vec<McfgEntry>> {
+    let mapping = map_sdt::<McfgHeader>(Signature::MCFG)?;
+    let length = mapping.header.length as usize;
+    let count = length.saturating_sub(mem::size_of::<McfgHeader>()) / mem::size_of::<McfgEntry>();
+
+    // The entries directly follow the header
+    let first = unsafe { (&*mapping as *const McfgHeader).add(1) as *const McfgEntry };
+
+    let entries = (0..count)
+        .map(|i| unsafe {
+            // SAFETY: map_sdt maps the whole table, so the entry is mapped
+            ptr::read_unaligned(first.add(i))
+        })
+        .collect();
+
+    Some(entries)
+}
@@ -12,7 +12,24 @@ header_start:
     ; header checksum (0x100000000 - (magic number + mode + length))
     dd 0x100000000 - (0xe85250d6 + 0 + (header_end - header_start))
 
+    ; framebuffer tag, requesting a graphics mode. Optional, so that the kernel still boots in text
+    ; mode if the bootloader can't set one
+    align 8
+    dw 5 ; type
+    dw 1 ; flags (optional)
+    dd 20 ; size
+    dd 1024 ; width
+    dd 768 ; height
+    dd 32 ; depth
+
+    ; module alignment tag, so that the initrd can be mapped into processes a page at a time
+    align 8
+    dw 6 ; type
+    dw 0 ; flags
+    dd 8 ; size
+
     ; end tag
+    align 8
     dw 0 ; type
     dw 0 ; flags
     dd 8 ; size
@@ -0,0 +1,261 @@
+//! Block devices, such as disks, which are read and written a sector at a time. Drivers register
+//! their devices here, and processes with the `STORAGE` capability transfer sectors to and from
+//! them through system calls. Transfers are made by DMA straight into the process's memory, so a
+//! process which exits during one is only freed once it completes.
+
+use crate::memory::paging::{Page, ACTIVE_PAGE_TABLES};
+use crate::process::{self, Process, ProcessId};
+use crate::scheduler;
+use crate::syscall::{Error, UserContext};
+use alloc::vec::Vec;
+use core::{mem, slice};
+use spin::{Mutex, RwLock};
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// The largest transfer which a single request may make
+pub const MAX_TRANSFER: usize = 64 * 1024;
+
+/// The registered devices, indexed by their id
+static DEVICES: RwLock<Vec<&'static dyn BlockDevice>> = RwLock::new(Vec::new());
+/// Processes blocked until their requests complete
+static WAITERS: Mutex<Vec<Waiter>> = Mutex::new(Vec::new());
+
+/// A driver for a block device. The methods are called with interrupts disabled, so that they can
+/// share state with the device's interrupt handler.
+pub trait BlockDevice: Sync {
+    fn info(&self) -> BlockDeviceInfo;
+
+    /// Starts a request, returning a tag which identifies it when it completes
+    fn submit(&self, request: &Request) -> Result<u16, BlockError>;
+
+    /// Calls `complete` with the tag and result of each request which has completed since the last
+    /// poll
+    fn poll(&self, complete: &mut dyn FnMut(u16, Result<(), BlockError>));
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BlockError {
+    /// The request is past the end of the device, or isn't a whole number of sectors
+    OutOfRange,
+    ReadOnly,
+    /// The device has too many requests in flight to take another
+    Busy,
+    /// The device failed to complete the request
+    Io,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Direction {
+    /// From the device into memory
+    Read,
+    /// From memory to the device
+    Write,
+}
+
+/// A physically contiguous piece of the memory which a request transfers to or from
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Segment {
+    pub physical: u64,
+    pub len: u32,
+}
+
+pub struct Request<'a> {
+    pub direction: Direction,
+    /// The first sector to transfer
+    pub sector: u64,
+    pub segments: &'a [Segment],
+}
+
+impl Request<'_> {
+    /// The number of bytes to transfer
+    pub fn bytes(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.len as u64).sum()
+    }
+}
+
+/// Describes a block device to userspace. Kept free of padding, as it is copied out byte-for-byte.
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct BlockDeviceInfo {
+    pub sector_size: u32,
+    pub flags: u32,
+    pub sector_count: u64,
+}
+
+impl BlockDeviceInfo {
+    pub const FLAG_READ_ONLY: u32 = 1;
+
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: repr(C) without any padding
+        unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<Self>()) }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.flags & BlockDeviceInfo::FLAG_READ_ONLY != 0
+    }
+}
+
+struct Waiter {
+    device: usize,
+    tag: u16,
+    pid: ProcessId,
+    block_id: u64,
+    /// What the system call returns if the request succeeds
+    len: u64,
+    /// The process which made the request, if it has exited since. It is kept until the request
+    /// completes, as the device transfers to or from its memory, and is then freed rather than
+    /// woken.
+    orphan: Option<Process>,
+}
+
+/// Registers a device, returning its id
+pub fn register(device: &'static dyn BlockDevice) -> usize {
+    let mut devices = DEVICES.write();
+    devices.push(device);
+
+    let info = without_interrupts(|| device.info());
+    info!(
+        "block: device {} has {} sectors of {} bytes{}",
+        devices.len() - 1,
+        info.sector_count,
+        info.sector_size,
+        if info.is_read_only() {
+            " (read-only)"
+        } else {
+            ""
+        }
+    );
+
+    devices.len() - 1
+}
+
+/// Returns information about each registered device, in order of id
+pub fn devices() -> Vec<BlockDeviceInfo> {
+    let devices = DEVICES.read();
+    without_interrupts(|| devices.iter().map(|device| device.info()).collect())
+}
+
+/// Splits a buffer in the current address space into the physical segments which it is made of,
+/// merging those which are contiguous. Returns None if any of it isn't mapped.
+pub fn segments_of(buf: &[u8]) -> Option<Vec<Segment>> {
+    let tables = ACTIVE_PAGE_TABLES.lock();
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut addr = buf.as_ptr() as u64;
+    let end = addr + buf.len() as u64;
+
+    while addr < end {
+        let (entry, size) = tables.walk_page_table(Page::containing_address(addr))?;
+        let page_offset = addr % size.bytes();
+        let len = (size.bytes() - page_offset).min(end - addr);
+        let physical = entry.physical_address()?.as_u64() + page_offset;
+
+        match segments.last_mut() {
+            Some(last) if last.physical + last.len as u64 == physical => last.len += len as u32,
+            _ => segments.push(Segment {
+                physical,
+                len: len as u32,
+            }),
+        }
+
+        addr += len;
+    }
+
+    Some(segments)
+}
+
+/// Starts a request on the device with the given id, checking that it is in range
+pub fn submit(device: usize, request: &Request) -> Result<u16, BlockError> {
+    let device = *DEVICES.read().get(device).ok_or(BlockError::OutOfRange)?;
+    let info = without_interrupts(|| device.info());
+    let len = request.bytes();
+    let sectors = len / info.sector_size as u64;
+
+    if len % info.sector_size as u64 != 0 || len > MAX_TRANSFER as u64 {
+        return Err(BlockError::OutOfRange);
+    }
+
+    match request.sector.checked_add(sectors) {
+        Some(end) if end <= info.sector_count => (),
+        _ => return Err(BlockError::OutOfRange),
+    }
+
+    if request.direction == Direction::Write && info.is_read_only() {
+        return Err(BlockError::ReadOnly);
+    }
+
+    without_interrupts(|| device.submit(request))
+}
+
+/// Blocks the current process in its system call until the request with the given tag completes.
+/// The system call then returns `len`, or an error if the request failed.
+///
+/// No locks may be held when calling this, as it never returns.
+pub fn wait(context: &UserContext, device: usize, tag: u16, len: u64) -> ! {
+    scheduler::block_current_on(context, None, |pid, block_id| {
+        WAITERS.lock().push(Waiter {
+            device,
+            tag,
+            pid,
+            block_id,
+            len,
+            orphan: None,
+        })
+    })
+}
+
+/// Orphans the request which an exiting process is blocked on, if any, so that the process isn't
+/// woken when it completes. The process is kept until then, and None is returned; otherwise, it is
+/// returned to be freed.
+pub fn release(pid: ProcessId, exited: Process) -> Option<Process> {
+    let mut waiters = WAITERS.lock();
+
+    match waiters.iter_mut().find(|waiter| waiter.pid == pid) {
+        Some(waiter) => {
+            waiter.orphan = Some(exited);
+            None
+        }
+        None => Some(exited),
+    }
+}
+
+/// Wakes the processes whose requests have completed. Interrupt handlers only note that requests
+/// have completed, as they may have interrupted code holding the process table's locks.
+pub fn poll() {
+    if WAITERS.lock().is_empty() {
+        return;
+    }
+
+    let mut completed = Vec::new();
+
+    for (id, device) in DEVICES.read().iter().enumerate() {
+        without_interrupts(|| {
+            device.poll(&mut |tag, result| completed.push((id, tag, result)));
+        });
+    }
+
+    for (device, tag, result) in completed {
+        let waiter = {
+            let mut waiters = WAITERS.lock();
+            let index = waiters
+                .iter()
+                .position(|waiter| waiter.device == device && waiter.tag == tag);
+
+            match index {
+                Some(index) => waiters.swap_remove(index),
+                None => continue,
+            }
+        };
+
+        if let Some(orphan) = waiter.orphan {
+            process::free(orphan);
+            continue;
+        }
+
+        let result = match result {
+            Ok(()) => waiter.len as i64,
+            Err(err) => Error::from(err) as i64,
+        };
+
+        scheduler::wake(waiter.pid, waiter.block_id, result);
+    }
+}
@@ -0,0 +1,56 @@
+//! Access to the multiboot2 boot information after the kernel has been remapped, when it is no
+//! longer identity mapped.
+
+use crate::memory::physical_mapping::{self, PhysicalMapping};
+use core::ops::Deref;
+use multiboot2::BootInformation;
+use spin::Once;
+
+static BOOT_INFO: Once<BootInfo> = Once::new();
+
+pub struct BootInfo {
+    info: BootInformation,
+    physical_start: u64,
+    _mapping: PhysicalMapping<u8>,
+}
+
+// Safety: the boot information is never modified
+unsafe impl Send for BootInfo {}
+unsafe impl Sync for BootInfo {}
+
+/// Maps the boot information into the kernel heap. Must be called after memory is initialised.
+pub fn init(mb_info_addr: u64) {
+    BOOT_INFO.call_once(|| unsafe {
+        // The first field of the boot information is its total size
+        let total_size = *physical_mapping::map_physical_region::<u32>(mb_info_addr, 4, false);
+        let mapping =
+            physical_mapping::map_physical_region::<u8>(mb_info_addr, total_size as u64, false);
+        let info = multiboot2::load(&*mapping as *const u8 as usize);
+
+        BootInfo {
+            info,
+            physical_start: mb_info_addr,
+            _mapping: mapping,
+        }
+    });
+}
+
+/// Returns the boot information. Panics if it has not been initialised.
+pub fn boot_info() -> &'static BootInfo {
+    BOOT_INFO.wait().expect("Boot information not initialised!")
+}
+
+impl BootInfo {
+    /// Translates a pointer into the boot information (e.g to a tag) into its physical address
+    pub fn physical_address_of<T>(&self, ptr: *const T) -> u64 {
+        ptr as u64 - self.info.start_address() as u64 + self.physical_start
+    }
+}
+
+impl Deref for BootInfo {
+    type Target = BootInformation;
+
+    fn deref(&self) -> &BootInformation {
+        &self.info
+    }
+}
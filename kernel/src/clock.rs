@@ -0,0 +1,71 @@
+//! Wall-clock and monotonic time. The wall-clock time is read from the RTC once at boot, and from
+//! then on is advanced using the PIT's monotonic counter.
+
+use crate::acpi_handler;
+use crate::pit;
+use crate::rtc::RTC;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Milliseconds since the unix epoch at which the monotonic clock read zero
+static BOOT_TIME_MS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u64)]
+pub enum Clock {
+    /// Milliseconds since the unix epoch
+    Realtime = 0,
+    /// Milliseconds since boot
+    Monotonic = 1,
+}
+
+impl Clock {
+    pub fn from_u64(v: u64) -> Option<Clock> {
+        match v {
+            0 => Some(Clock::Realtime),
+            1 => Some(Clock::Monotonic),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the wall-clock time from the RTC. The PIT must be initialized beforehand.
+pub fn init() {
+    info!("clock: initializing");
+
+    match acpi_handler::fadt() {
+        Some(fadt) => RTC.lock().set_century_register(fadt.century),
+        None => warn!("clock: no fadt, assuming 21st century"),
+    }
+
+    let now = RTC.lock().read();
+    let timestamp = now.unix_timestamp().unwrap_or_else(|| {
+        warn!("clock: the rtc's time is invalid, assuming it is the unix epoch");
+        0
+    });
+    let boot_time = (timestamp * 1000).saturating_sub(pit::time_ms() as u64);
+    BOOT_TIME_MS.store(boot_time, Ordering::SeqCst);
+
+    info!(
+        "clock: it is {}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        now.year, now.month, now.day, now.hour, now.minute, now.second
+    );
+}
+
+/// Returns the current time of the given clock in milliseconds
+pub fn now_ms(clock: Clock) -> u64 {
+    let monotonic = pit::time_ms() as u64;
+
+    match clock {
+        Clock::Realtime => BOOT_TIME_MS.load(Ordering::SeqCst) + monotonic,
+        Clock::Monotonic => monotonic,
+    }
+}
+
+/// Converts a time in milliseconds on the given clock into milliseconds on the monotonic clock.
+/// Times before boot are clamped to zero.
+pub fn to_monotonic_ms(clock: Clock, time_ms: u64) -> u64 {
+    match clock {
+        Clock::Realtime => time_ms.saturating_sub(BOOT_TIME_MS.load(Ordering::SeqCst)),
+        Clock::Monotonic => time_ms,
+    }
+}
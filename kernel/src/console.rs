@@ -0,0 +1,188 @@
+//! A line-oriented debug console on the first serial port, for inspecting the kernel without a
+//! display, e.g through QEMU's `-serial stdio`.
+
+use crate::memory::physical_allocator::PHYSICAL_ALLOCATOR;
+use crate::process::PROCESSES;
+use crate::vga::VGA_WRITER;
+use crate::{memory, scheduler, serial, serial1_print};
+use alloc::string::String;
+use alloc::vec::Vec;
+use log::Level;
+use spin::Mutex;
+
+const PROMPT: &str = "> ";
+/// Longer lines are cut off
+const MAX_LINE_LEN: usize = 128;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+
+static CONSOLE: Mutex<Console> = Mutex::new(Console::new());
+
+struct Console {
+    line: String,
+    /// Whether the last byte ended a line with a carriage return, so that a following line feed
+    /// doesn't end another
+    after_carriage_return: bool,
+}
+
+pub fn init() {
+    serial::init();
+    info!("console: listening on serial, type 'help' for commands");
+    serial1_print(format_args!("{}", PROMPT));
+}
+
+/// Handles the bytes received since the last call, running any commands which were completed.
+/// Must not be called from an interrupt handler, as commands take the process table's locks.
+pub fn poll() {
+    let mut console = match CONSOLE.try_lock() {
+        Some(console) => console,
+        None => return,
+    };
+
+    while let Some(byte) = serial::read_byte() {
+        console.handle_byte(byte);
+    }
+}
+
+impl Console {
+    const fn new() -> Console {
+        Console {
+            line: String::new(),
+            after_carriage_return: false,
+        }
+    }
+
+    fn handle_byte(&mut self, byte: u8) {
+        let after_carriage_return = self.after_carriage_return;
+        self.after_carriage_return = byte == b'\r';
+
+        match byte {
+            b'\n' if after_carriage_return => (),
+            b'\r' | b'\n' => {
+                serial1_print(format_args!("\r\n"));
+                run(self.line.trim());
+                self.line.clear();
+                serial1_print(format_args!("{}", PROMPT));
+            }
+            BACKSPACE | DELETE => {
+                if self.line.pop().is_some() {
+                    serial1_print(format_args!("\x08 \x08"));
+                }
+            }
+            b' '..=b'~' if self.line.len() < MAX_LINE_LEN => {
+                self.line.push(byte as char);
+                serial1_print(format_args!("{}", byte as char));
+            }
+            _ => (),
+        }
+    }
+}
+
+fn run(line: &str) {
+    let mut words = line.split_whitespace();
+
+    match (words.next(), words.next()) {
+        (None, _) => (),
+        (Some("help"), _) => {
+            serial1_print(format_args!("help          show this message\r\n"));
+            serial1_print(format_args!("mem           show memory usage\r\n"));
+            serial1_print(format_args!("ps            list processes\r\n"));
+            serial1_print(format_args!("log [level]   show or set the log level\r\n"));
+            serial1_print(format_args!("scrollback    dump the screen history\r\n"));
+        }
+        (Some("mem"), _) => mem(),
+        (Some("ps"), _) => ps(),
+        (Some("log"), level) => log_level(level),
+        (Some("scrollback"), _) => scrollback(),
+        (Some(command), _) => serial1_print(format_args!("unknown command '{}'\r\n", command)),
+    }
+}
+
+fn mem() {
+    serial1_print(format_args!(
+        "usable physical memory:    {} KiB\r\n",
+        memory::usable_bytes() / 1024
+    ));
+    serial1_print(format_args!(
+        "allocated physical memory: {} KiB\r\n",
+        PHYSICAL_ALLOCATOR.allocated_bytes() / 1024
+    ));
+    serial1_print(format_args!(
+        "kernel heap in use:        {} KiB\r\n",
+        crate::HEAP.allocated_bytes() / 1024
+    ));
+}
+
+fn ps() {
+    let current = scheduler::current();
+    let mut processes: Vec<_> = PROCESSES
+        .iter()
+        .map(|entry| {
+            (
+                *entry.key(),
+                String::from(entry.value().name()),
+                entry.value().state(),
+                entry.value().capabilities(),
+            )
+        })
+        .collect();
+    processes.sort_by_key(|(pid, _, _, _)| *pid);
+
+    serial1_print(format_args!(
+        "pid   name             state         capabilities\r\n"
+    ));
+
+    for (pid, name, state, capabilities) in processes {
+        let marker = if Some(pid) == current { "*" } else { " " };
+        let state = format!("{:?}", state);
+
+        serial1_print(format_args!(
+            "{:<4}{} {:<16} {:<13} {:?}\r\n",
+            pid, marker, name, state, capabilities
+        ));
+    }
+}
+
+fn log_level(level: Option<&str>) {
+    let level = match level {
+        Some(level) => level,
+        None => {
+            serial1_print(format_args!("log level is {}\r\n", crate::log::level()));
+            return;
+        }
+    };
+
+    let level = match level.parse::<Level>() {
+        Ok(level) => level,
+        Err(_) => {
+            serial1_print(format_args!(
+                "unknown level '{}', expected one of error, warn, info, debug or trace\r\n",
+                level
+            ));
+            return;
+        }
+    };
+
+    match crate::log::set_level(level) {
+        Ok(()) => serial1_print(format_args!("log level set to {}\r\n", level)),
+        Err(max) => serial1_print(format_args!(
+            "the kernel was built without {} logging, the most verbose level is {}\r\n",
+            level, max
+        )),
+    }
+}
+
+fn scrollback() {
+    let mut line = String::new();
+
+    VGA_WRITER.lock().dump_history(|cells| {
+        line.clear();
+        line.extend(cells.iter().map(|cell| match cell.character {
+            b' '..=b'~' => cell.character as char,
+            _ => '.',
+        }));
+
+        serial1_print(format_args!("{}\r\n", line.trim_end()));
+    });
+}
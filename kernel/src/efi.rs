@@ -0,0 +1,105 @@
+//! Reading the tables which UEFI firmware leaves behind after boot services have been exited.
+//! From: [UEFI 2.8 spec](https://uefi.org/sites/default/files/resources/UEFI_Spec_2_8_final.pdf)
+//! 4.3 and 4.6
+
+use crate::memory::physical_mapping;
+use core::{mem, slice};
+
+const SYSTEM_TABLE_SIGNATURE: u64 = 0x5453_5953_2049_4249; // "IBI SYST"
+
+const ACPI_20_TABLE_GUID: Guid = Guid {
+    data1: 0x8868_e871,
+    data2: 0xe4f1,
+    data3: 0x11d3,
+    data4: [0xbc, 0x22, 0x00, 0x80, 0xc7, 0x3c, 0x88, 0x81],
+};
+
+const ACPI_TABLE_GUID: Guid = Guid {
+    data1: 0xeb9d_2d30,
+    data2: 0x2d88,
+    data3: 0x11d3,
+    data4: [0x9a, 0x16, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d],
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+struct Guid {
+    data1: u32,
+    data2: u16,
+    data3: u16,
+    data4: [u8; 8],
+}
+
+#[repr(C)]
+struct TableHeader {
+    signature: u64,
+    revision: u32,
+    header_size: u32,
+    crc32: u32,
+    _reserved: u32,
+}
+
+/// The EFI system table. Pointers are physical addresses.
+#[repr(C)]
+struct SystemTable {
+    header: TableHeader,
+    firmware_vendor: u64,
+    firmware_revision: u32,
+    console_in_handle: u64,
+    console_in: u64,
+    console_out_handle: u64,
+    console_out: u64,
+    standard_error_handle: u64,
+    standard_error: u64,
+    runtime_services: u64,
+    boot_services: u64,
+    number_of_table_entries: u64,
+    configuration_table: u64,
+}
+
+#[repr(C)]
+struct ConfigurationTable {
+    vendor_guid: Guid,
+    vendor_table: u64,
+}
+
+/// Finds the physical address of the RSDP in the EFI system table's configuration tables,
+/// preferring the ACPI 2.0 one.
+pub fn find_rsdp(system_table_addr: u64) -> Option<u64> {
+    let system_table = unsafe {
+        physical_mapping::map_physical_region::<SystemTable>(
+            system_table_addr,
+            mem::size_of::<SystemTable>() as u64,
+            false,
+        )
+    };
+
+    if system_table.header.signature != SYSTEM_TABLE_SIGNATURE {
+        warn!("efi: invalid system table signature");
+        return None;
+    }
+
+    let entries = system_table.number_of_table_entries as usize;
+    if entries == 0 {
+        return None;
+    }
+
+    let mapping = unsafe {
+        physical_mapping::map_physical_region::<ConfigurationTable>(
+            system_table.configuration_table,
+            (entries * mem::size_of::<ConfigurationTable>()) as u64,
+            false,
+        )
+    };
+
+    // SAFETY: the firmware guarantees that there are this many entries
+    let tables = unsafe { slice::from_raw_parts(&*mapping as *const ConfigurationTable, entries) };
+    let find = |guid| {
+        tables
+            .iter()
+            .find(|table| table.vendor_guid == guid)
+            .map(|table| table.vendor_table)
+    };
+
+    find(ACPI_20_TABLE_GUID).or_else(|| find(ACPI_TABLE_GUID))
+}
@@ -0,0 +1,88 @@
+//! File descriptors, through which a process reads and writes streams of bytes, such as pipes. Each
+//! process has its own table of them. Descriptors 0, 1 and 2 are its standard input, output and
+//! error, which it inherits from the process which spawned it unless it is given others.
+
+use crate::pipe;
+use crate::syscall::{Error, UserContext};
+use crate::vga::VGA_WRITER;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::str;
+
+pub const STDIN: u64 = 0;
+pub const STDOUT: u64 = 1;
+pub const STDERR: u64 = 2;
+
+/// The most descriptors which a process can have open
+pub const MAX_FILES: usize = 64;
+
+#[derive(Debug, Clone)]
+pub enum File {
+    /// The screen, which is written to like with `Print`. There is nothing to read from it, so
+    /// reads return the end of the file.
+    Console,
+    PipeReader(Arc<pipe::Reader>),
+    PipeWriter(Arc<pipe::Writer>),
+}
+
+impl File {
+    /// Reads up to `len` bytes for the user's buffer, which must have been checked to be writable.
+    /// This may block the current process, and copy the data into its buffer once it is woken.
+    pub fn read(self, context: &UserContext, ptr: u64, len: usize) -> Result<Vec<u8>, Error> {
+        match self {
+            File::Console => Ok(Vec::new()),
+            File::PipeReader(reader) => reader.read(context, ptr, len),
+            File::PipeWriter(_) => Err(Error::PermissionDenied),
+        }
+    }
+
+    /// Writes all of the data, which may block the current process. Returns its length.
+    pub fn write(self, context: &UserContext, data: Vec<u8>) -> Result<usize, Error> {
+        match self {
+            File::Console => {
+                let string = str::from_utf8(&data).map_err(|_| Error::InvalidUtf8)?;
+                VGA_WRITER.lock().write_str(string);
+                Ok(data.len())
+            }
+            File::PipeReader(_) => Err(Error::PermissionDenied),
+            File::PipeWriter(writer) => writer.write(context, data),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FileTable {
+    files: BTreeMap<u64, File>,
+}
+
+impl FileTable {
+    /// A table whose standard input, output and error are the given files
+    pub fn with_standard(stdin: File, stdout: File, stderr: File) -> FileTable {
+        let mut table = FileTable::default();
+
+        table.files.insert(STDIN, stdin);
+        table.files.insert(STDOUT, stdout);
+        table.files.insert(STDERR, stderr);
+        table
+    }
+
+    pub fn get(&self, fd: u64) -> Option<File> {
+        self.files.get(&fd).cloned()
+    }
+
+    /// Adds a file under the lowest free descriptor, which is returned
+    pub fn insert(&mut self, file: File) -> Result<u64, Error> {
+        if self.files.len() >= MAX_FILES {
+            return Err(Error::Busy);
+        }
+
+        let fd = (0..).find(|fd| !self.files.contains_key(fd)).unwrap();
+        self.files.insert(fd, file);
+        Ok(fd)
+    }
+
+    pub fn remove(&mut self, fd: u64) -> Option<File> {
+        self.files.remove(&fd)
+    }
+}
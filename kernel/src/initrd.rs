@@ -0,0 +1,112 @@
+//! The initial ramdisk, loaded by the bootloader as the first multiboot2 module. It is a ustar or
+//! newc cpio archive, which is mapped read-only into processes with the `INITRD` capability so
+//! that programs and their files don't need to be built into the kernel.
+
+use crate::boot_info;
+use crate::memory::physical_mapping;
+use spin::Once;
+
+static INITRD: Once<Initrd> = Once::new();
+
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_MAGIC_OFFSET: usize = 257;
+
+const CPIO_MAGIC: &[u8] = b"070701";
+/// The same format, but with checksums
+const CPIO_MAGIC_CRC: &[u8] = b"070702";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InitrdError {
+    /// The bootloader wasn't given any modules
+    NoModule,
+    /// The module must be page aligned so that it can be mapped into processes without exposing
+    /// what is before it
+    NotPageAligned(u64),
+    UnknownFormat,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Format {
+    Tar,
+    Cpio,
+}
+
+pub struct Initrd {
+    physical_start: u64,
+    len: u64,
+}
+
+/// Finds and maps the initrd. Must be called after the boot information is initialised.
+pub fn init() -> Result<&'static Initrd, InitrdError> {
+    let module = boot_info::boot_info()
+        .module_tags()
+        .next()
+        .ok_or(InitrdError::NoModule)?;
+
+    let physical_start = module.start_address() as u64;
+    let len = (module.end_address() - module.start_address()) as u64;
+
+    if physical_start & 0xfff != 0 {
+        return Err(InitrdError::NotPageAligned(physical_start));
+    }
+
+    if len == 0 {
+        return Err(InitrdError::UnknownFormat);
+    }
+
+    // The kernel only looks at the initrd to check its format, as its files are read by processes,
+    // through `libwolffia::initrd`
+    let format = {
+        // SAFETY: the bootloader loaded the module here, and it is never freed
+        let mapping =
+            unsafe { physical_mapping::map_physical_region::<u8>(physical_start, len, false) };
+
+        // SAFETY: the mapping is at least `len` bytes long
+        let data = unsafe { core::slice::from_raw_parts(&*mapping as *const u8, len as usize) };
+        detect_format(data).ok_or(InitrdError::UnknownFormat)?
+    };
+
+    let initrd = INITRD.call_once(|| Initrd {
+        physical_start,
+        len,
+    });
+
+    info!(
+        "initrd: {} KiB {:?} archive at 0x{:x}",
+        len / 1024,
+        format,
+        physical_start
+    );
+
+    Ok(initrd)
+}
+
+/// Returns the initrd, if one was found
+pub fn get() -> Option<&'static Initrd> {
+    INITRD.wait()
+}
+
+fn detect_format(data: &[u8]) -> Option<Format> {
+    if data.starts_with(CPIO_MAGIC) || data.starts_with(CPIO_MAGIC_CRC) {
+        Some(Format::Cpio)
+    } else if data.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len()) == Some(TAR_MAGIC) {
+        Some(Format::Tar)
+    } else {
+        None
+    }
+}
+
+impl Initrd {
+    pub fn physical_start(&self) -> u64 {
+        self.physical_start
+    }
+
+    /// The length of the initrd in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
@@ -0,0 +1,212 @@
+//! The queue of keyboard and mouse events which userspace reads from. Drivers push events from
+//! their interrupt handlers, so the queue is a fixed size ring buffer which never allocates.
+
+use crate::memory::buffer::PlainOldData;
+use crate::syscall::UserContext;
+use crate::wait_queue::WaitQueue;
+use core::{mem, slice};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+pub mod keys;
+
+pub use self::keys::{KeyCode, Modifiers};
+
+/// Events which arrive while the queue is full are dropped
+const QUEUE_CAPACITY: usize = 256;
+
+static QUEUE: Mutex<EventQueue> = Mutex::new(EventQueue::new());
+/// Processes blocked until there are events to read
+static READERS: WaitQueue = WaitQueue::new();
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InputEvent {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct KeyEvent {
+    pub key: KeyCode,
+    pub pressed: bool,
+    /// The modifiers held and locks on after the event
+    pub modifiers: Modifiers,
+    /// The character typed by the key press, if any
+    pub character: Option<char>,
+}
+
+/// Relative movement of the mouse, and the state of its buttons afterwards
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MouseEvent {
+    pub buttons: MouseButtons,
+    /// Positive towards the right
+    pub dx: i32,
+    /// Positive towards the bottom of the screen
+    pub dy: i32,
+    /// Positive towards the user
+    pub wheel: i32,
+}
+
+bitflags::bitflags! {
+    /// The mouse buttons held down. The values are part of the system call ABI.
+    pub struct MouseButtons: u32 {
+        const LEFT = 1;
+        const RIGHT = 1 << 1;
+        const MIDDLE = 1 << 2;
+    }
+}
+
+/// An input event as read by userspace. Kept free of padding, as it is copied out byte-for-byte.
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct RawInputEvent {
+    pub kind: u32,
+    /// For key events, the modifiers. For mouse events, the buttons.
+    pub state: u32,
+    /// For key events, the key code and the character typed, or 0 if there is none. For mouse
+    /// events, the x, y and wheel movement.
+    pub data: [i32; 3],
+}
+
+impl RawInputEvent {
+    pub const KIND_NONE: u32 = 0;
+    pub const KIND_KEY_PRESS: u32 = 1;
+    pub const KIND_KEY_RELEASE: u32 = 2;
+    pub const KIND_MOUSE: u32 = 3;
+
+    const NONE: RawInputEvent = RawInputEvent {
+        kind: RawInputEvent::KIND_NONE,
+        state: 0,
+        data: [0; 3],
+    };
+}
+
+unsafe impl PlainOldData for RawInputEvent {
+    fn from_bytes(buf: &[u8]) -> &[Self] {
+        // SAFETY: repr(C) without any padding, and every bit pattern is valid
+        unsafe {
+            slice::from_raw_parts(
+                buf.as_ptr() as *const Self,
+                buf.len() / mem::size_of::<Self>(),
+            )
+        }
+    }
+
+    fn from_bytes_mut(buf: &mut [u8]) -> &mut [Self] {
+        // SAFETY: repr(C) without any padding, and every bit pattern is valid
+        unsafe {
+            slice::from_raw_parts_mut(
+                buf.as_mut_ptr() as *mut Self,
+                buf.len() / mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+impl From<InputEvent> for RawInputEvent {
+    fn from(event: InputEvent) -> Self {
+        match event {
+            InputEvent::Key(key) => RawInputEvent {
+                kind: if key.pressed {
+                    RawInputEvent::KIND_KEY_PRESS
+                } else {
+                    RawInputEvent::KIND_KEY_RELEASE
+                },
+                state: key.modifiers.bits(),
+                data: [
+                    key.key as i32,
+                    key.character.map(|c| c as i32).unwrap_or(0),
+                    0,
+                ],
+            },
+            InputEvent::Mouse(mouse) => RawInputEvent {
+                kind: RawInputEvent::KIND_MOUSE,
+                state: mouse.buttons.bits(),
+                data: [mouse.dx, mouse.dy, mouse.wheel],
+            },
+        }
+    }
+}
+
+struct EventQueue {
+    events: [RawInputEvent; QUEUE_CAPACITY],
+    head: usize,
+    len: usize,
+}
+
+impl EventQueue {
+    const fn new() -> EventQueue {
+        EventQueue {
+            events: [RawInputEvent::NONE; QUEUE_CAPACITY],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, event: RawInputEvent) {
+        if self.len == QUEUE_CAPACITY {
+            return;
+        }
+
+        self.events[(self.head + self.len) % QUEUE_CAPACITY] = event;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<RawInputEvent> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let event = self.events[self.head];
+        self.head = (self.head + 1) % QUEUE_CAPACITY;
+        self.len -= 1;
+
+        Some(event)
+    }
+}
+
+/// Queues an event for userspace to read. Called by drivers from their interrupt handlers.
+pub fn push(event: InputEvent) {
+    QUEUE.lock().push(event.into());
+}
+
+/// Moves as many queued events as fit into the buffer, returning how many were moved
+pub fn read(buf: &mut [RawInputEvent]) -> usize {
+    // Interrupts are disabled so that a driver can't try to push while the lock is held
+    without_interrupts(|| {
+        let mut queue = QUEUE.lock();
+        let mut read = 0;
+
+        for slot in buf.iter_mut() {
+            match queue.pop() {
+                Some(event) => *slot = event,
+                None => break,
+            }
+
+            read += 1;
+        }
+
+        read
+    })
+}
+
+fn is_empty() -> bool {
+    without_interrupts(|| QUEUE.lock().len == 0)
+}
+
+/// Blocks the current process in its system call until there are events to read. Returns straight
+/// away if there already are.
+pub fn wait(context: &UserContext) -> i64 {
+    if !is_empty() {
+        return 0;
+    }
+
+    READERS.wait(context, None)
+}
+
+/// Wakes the processes waiting for events if any have arrived
+pub fn wake_readers() {
+    if !READERS.is_empty() && !is_empty() {
+        READERS.wake_all(0);
+    }
+}
@@ -0,0 +1,283 @@
+//! Key codes, which name keys by their position on a US keyboard, and the US keyboard layout.
+
+/// A key, independent of the scancode set it was decoded from. The values are part of the system
+/// call ABI.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum KeyCode {
+    Escape = 1,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Backtick,
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+    Digit0,
+    Minus,
+    Equals,
+    Backspace,
+    Tab,
+    Q,
+    W,
+    E,
+    R,
+    T,
+    Y,
+    U,
+    I,
+    O,
+    P,
+    LeftBracket,
+    RightBracket,
+    Backslash,
+    CapsLock,
+    A,
+    S,
+    D,
+    F,
+    G,
+    H,
+    J,
+    K,
+    L,
+    Semicolon,
+    Quote,
+    Enter,
+    LeftShift,
+    /// The extra key next to left shift on non-US keyboards
+    NonUsBackslash,
+    Z,
+    X,
+    C,
+    V,
+    B,
+    N,
+    M,
+    Comma,
+    Period,
+    Slash,
+    RightShift,
+    LeftCtrl,
+    LeftGui,
+    LeftAlt,
+    Space,
+    RightAlt,
+    RightGui,
+    Menu,
+    RightCtrl,
+    PrintScreen,
+    ScrollLock,
+    Pause,
+    Insert,
+    Home,
+    PageUp,
+    Delete,
+    End,
+    PageDown,
+    Up,
+    Left,
+    Down,
+    Right,
+    NumLock,
+    KeypadSlash,
+    KeypadAsterisk,
+    KeypadMinus,
+    KeypadPlus,
+    KeypadEnter,
+    KeypadPeriod,
+    Keypad0,
+    Keypad1,
+    Keypad2,
+    Keypad3,
+    Keypad4,
+    Keypad5,
+    Keypad6,
+    Keypad7,
+    Keypad8,
+    Keypad9,
+}
+
+bitflags::bitflags! {
+    /// The modifier keys held and lock keys toggled on when a key event happened. The values are
+    /// part of the system call ABI.
+    pub struct Modifiers: u32 {
+        const LEFT_SHIFT = 1;
+        const RIGHT_SHIFT = 1 << 1;
+        const LEFT_CTRL = 1 << 2;
+        const RIGHT_CTRL = 1 << 3;
+        const LEFT_ALT = 1 << 4;
+        const RIGHT_ALT = 1 << 5;
+        const LEFT_GUI = 1 << 6;
+        const RIGHT_GUI = 1 << 7;
+        const CAPS_LOCK = 1 << 8;
+        const NUM_LOCK = 1 << 9;
+        const SCROLL_LOCK = 1 << 10;
+
+        const SHIFT = Self::LEFT_SHIFT.bits | Self::RIGHT_SHIFT.bits;
+        const CTRL = Self::LEFT_CTRL.bits | Self::RIGHT_CTRL.bits;
+        const ALT = Self::LEFT_ALT.bits | Self::RIGHT_ALT.bits;
+        const GUI = Self::LEFT_GUI.bits | Self::RIGHT_GUI.bits;
+    }
+}
+
+impl KeyCode {
+    /// The modifier which this key holds, if it is a modifier key
+    pub fn modifier(self) -> Option<Modifiers> {
+        match self {
+            KeyCode::LeftShift => Some(Modifiers::LEFT_SHIFT),
+            KeyCode::RightShift => Some(Modifiers::RIGHT_SHIFT),
+            KeyCode::LeftCtrl => Some(Modifiers::LEFT_CTRL),
+            KeyCode::RightCtrl => Some(Modifiers::RIGHT_CTRL),
+            KeyCode::LeftAlt => Some(Modifiers::LEFT_ALT),
+            KeyCode::RightAlt => Some(Modifiers::RIGHT_ALT),
+            KeyCode::LeftGui => Some(Modifiers::LEFT_GUI),
+            KeyCode::RightGui => Some(Modifiers::RIGHT_GUI),
+            _ => None,
+        }
+    }
+
+    /// The lock which this key toggles, if it is a lock key
+    pub fn lock(self) -> Option<Modifiers> {
+        match self {
+            KeyCode::CapsLock => Some(Modifiers::CAPS_LOCK),
+            KeyCode::NumLock => Some(Modifiers::NUM_LOCK),
+            KeyCode::ScrollLock => Some(Modifiers::SCROLL_LOCK),
+            _ => None,
+        }
+    }
+}
+
+/// Translates a key press into the character it types on a US keyboard, if any. Control
+/// combinations of letters and `@[\]^_` type the corresponding ASCII control characters.
+pub fn us_layout(key: KeyCode, modifiers: Modifiers) -> Option<char> {
+    use self::KeyCode::*;
+
+    let shift = modifiers.intersects(Modifiers::SHIFT);
+    let num_lock = modifiers.contains(Modifiers::NUM_LOCK);
+
+    let (normal, shifted) = match key {
+        Backtick => ('`', '~'),
+        Digit1 => ('1', '!'),
+        Digit2 => ('2', '@'),
+        Digit3 => ('3', '#'),
+        Digit4 => ('4', '$'),
+        Digit5 => ('5', '%'),
+        Digit6 => ('6', '^'),
+        Digit7 => ('7', '&'),
+        Digit8 => ('8', '*'),
+        Digit9 => ('9', '('),
+        Digit0 => ('0', ')'),
+        Minus => ('-', '_'),
+        Equals => ('=', '+'),
+        Backspace => ('\x08', '\x08'),
+        Tab => ('\t', '\t'),
+        LeftBracket => ('[', '{'),
+        RightBracket => (']', '}'),
+        Backslash | NonUsBackslash => ('\\', '|'),
+        Semicolon => (';', ':'),
+        Quote => ('\'', '"'),
+        Enter | KeypadEnter => ('\n', '\n'),
+        Comma => (',', '<'),
+        Period => ('.', '>'),
+        Slash => ('/', '?'),
+        Space => (' ', ' '),
+        Escape => ('\x1b', '\x1b'),
+        Delete => ('\x7f', '\x7f'),
+        KeypadSlash => ('/', '/'),
+        KeypadAsterisk => ('*', '*'),
+        KeypadMinus => ('-', '-'),
+        KeypadPlus => ('+', '+'),
+        // Without num lock, the rest of the keypad are navigation keys
+        KeypadPeriod if num_lock => ('.', '.'),
+        Keypad0 if num_lock => ('0', '0'),
+        Keypad1 if num_lock => ('1', '1'),
+        Keypad2 if num_lock => ('2', '2'),
+        Keypad3 if num_lock => ('3', '3'),
+        Keypad4 if num_lock => ('4', '4'),
+        Keypad5 if num_lock => ('5', '5'),
+        Keypad6 if num_lock => ('6', '6'),
+        Keypad7 if num_lock => ('7', '7'),
+        Keypad8 if num_lock => ('8', '8'),
+        Keypad9 if num_lock => ('9', '9'),
+        _ => match letter(key) {
+            Some(lower) => {
+                let upper = shift != modifiers.contains(Modifiers::CAPS_LOCK);
+                let letter = if upper {
+                    lower.to_ascii_uppercase()
+                } else {
+                    lower
+                };
+
+                return Some(control(letter, modifiers).unwrap_or(letter));
+            }
+            None => return None,
+        },
+    };
+
+    let character = if shift { shifted } else { normal };
+    Some(control(character, modifiers).unwrap_or(character))
+}
+
+/// The ASCII control character typed by holding control with the given character, if any
+fn control(character: char, modifiers: Modifiers) -> Option<char> {
+    if !modifiers.intersects(Modifiers::CTRL) {
+        return None;
+    }
+
+    match character.to_ascii_uppercase() {
+        c @ '@'..='_' => Some((c as u8 - b'@') as char),
+        _ => None,
+    }
+}
+
+fn letter(key: KeyCode) -> Option<char> {
+    use self::KeyCode::*;
+
+    let letter = match key {
+        A => 'a',
+        B => 'b',
+        C => 'c',
+        D => 'd',
+        E => 'e',
+        F => 'f',
+        G => 'g',
+        H => 'h',
+        I => 'i',
+        J => 'j',
+        K => 'k',
+        L => 'l',
+        M => 'm',
+        N => 'n',
+        O => 'o',
+        P => 'p',
+        Q => 'q',
+        R => 'r',
+        S => 's',
+        T => 't',
+        U => 'u',
+        V => 'v',
+        W => 'w',
+        X => 'x',
+        Y => 'y',
+        Z => 'z',
+        _ => return None,
+    };
+
+    Some(letter)
+}
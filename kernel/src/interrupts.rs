@@ -1,14 +1,20 @@
 //! Module for interrupt handling/IDT
 
 use crate::gdt;
-use crate::interrupts::exceptions::page_fault;
 use alloc::vec::Vec;
+use core::mem;
 use lazy_static::lazy_static;
 use spin::RwLock;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::structures::idt::{
+    HandlerFunc, InterruptDescriptorTable, InterruptStackFrame, PageFaultHandlerFunc,
+};
 
 mod exceptions;
+pub mod lapic;
 mod pic;
+mod preempt;
+pub mod user;
+pub mod vectors;
 
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
@@ -18,21 +24,43 @@ lazy_static! {
     };
 }
 
+/// The vector the PICs remap IRQ 0 to
+pub const IRQ_BASE_VECTOR: u8 = 32;
+
 type IrqListeners = Vec<fn()>;
 
 lazy_static! {
-    static ref LISTENERS: RwLock<[IrqListeners; 16]> =
-        RwLock::new(array_init::array_init(|_| Vec::with_capacity(1)));
+    /// Listeners for each interrupt vector. Those below the IRQ base vector are exceptions, and so
+    /// are never dispatched to.
+    static ref LISTENERS: RwLock<[IrqListeners; 256]> =
+        RwLock::new(array_init::array_init(|_| Vec::new()));
 }
 
 /// Registers a listener for the given IRQ
 pub fn listen<I: Into<u8>>(irq: I, listener: fn()) {
-    LISTENERS.write()[irq.into() as usize].push(listener);
+    listen_vector(IRQ_BASE_VECTOR + irq.into(), listener);
+}
+
+/// Registers a listener for the given interrupt vector, e.g one allocated for MSI
+pub fn listen_vector(vector: u8, listener: fn()) {
+    LISTENERS.write()[vector as usize].push(listener);
+}
+
+/// Whether the kernel listens to the given IRQ
+pub fn has_listeners(irq: u8) -> bool {
+    !LISTENERS.read()[(IRQ_BASE_VECTOR + irq) as usize].is_empty()
 }
 
-/// Dispatches the given IRQ to all relevant registered listeners
+/// Dispatches the given IRQ to all relevant registered listeners, and to the process listening to
+/// it, if any
 pub fn dispatch_irq(irq: u8) {
-    for listener in LISTENERS.read()[irq as usize].iter() {
+    dispatch_vector(IRQ_BASE_VECTOR + irq);
+    user::raise(irq);
+}
+
+/// Dispatches the given interrupt vector to all relevant registered listeners
+pub fn dispatch_vector(vector: u8) {
+    for listener in LISTENERS.read()[vector as usize].iter() {
         listener();
     }
 }
@@ -41,6 +69,7 @@ pub fn dispatch_irq(irq: u8) {
 pub enum Irq {
     Pit = 0,
     Ps2Keyboard = 1,
+    Serial1 = 4,
     Ps2Mouse = 12,
 }
 
@@ -59,6 +88,9 @@ pub fn init() {
 
     pic::CHAINED_PICS.lock().init_and_remap();
     debug!("interrupts: pic initialized and remapped");
+
+    lapic::init();
+    debug!("interrupts: local apic initialized");
     info!("interrupts: initialized");
 }
 
@@ -87,7 +119,13 @@ macro_rules! init_irq_handlers {
         $(
             {
                 extern "x86-interrupt" fn handle_irq(_: &mut InterruptStackFrame) {
-                    pic::CHAINED_PICS.lock().handle_interrupt($irq, || dispatch_irq($irq));
+                    let mut pics = pic::CHAINED_PICS.lock();
+                    pics.handle_interrupt($irq, || dispatch_irq($irq));
+
+                    // Keep the line masked until the process listening to it has handled it
+                    if user::is_pending($irq) {
+                        pics.disable_line($irq);
+                    }
                 }
                 $idt[$irq + 32].set_handler_fn(handle_irq);
             }
@@ -95,6 +133,23 @@ macro_rules! init_irq_handlers {
     };
 }
 
+/// Handlers for dynamically allocated vectors, which are raised through the local APIC
+macro_rules! init_vector_handlers {
+    ($idt:expr, $($vector:expr),*) => {
+        $(
+            {
+                extern "x86-interrupt" fn handle_vector(_: &mut InterruptStackFrame) {
+                    dispatch_vector($vector);
+                    lapic::end_of_interrupt();
+                }
+                $idt[$vector].set_handler_fn(handle_vector);
+            }
+        )*
+    };
+}
+
+extern "x86-interrupt" fn spurious_interrupt(_: &mut InterruptStackFrame) {}
+
 fn init_interrupt_handlers(idt: &mut InterruptDescriptorTable) {
     unsafe {
         idt.divide_error
@@ -136,6 +191,9 @@ fn init_interrupt_handlers(idt: &mut InterruptDescriptorTable) {
         idt.general_protection_fault
             .set_handler_fn(exceptions::general_protection_fault)
             .set_stack_index(gdt::PANICKING_EXCEPTION_IST_INDEX);
+        // SAFETY: the entry point expects the stack of a page fault, as an x86-interrupt handler
+        // would
+        let page_fault: PageFaultHandlerFunc = mem::transmute(exceptions::page_fault as usize);
         idt.page_fault
             .set_handler_fn(page_fault)
             .set_stack_index(gdt::PANICKING_EXCEPTION_IST_INDEX);
@@ -160,4 +218,24 @@ fn init_interrupt_handlers(idt: &mut InterruptDescriptorTable) {
     }
 
     init_irq_handlers!(idt, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15);
+
+    // The PIT's IRQ is handled like the others, but may also preempt the running process
+    // SAFETY: the entry point expects the stack of an interrupt, as an x86-interrupt handler would
+    let tick: HandlerFunc = unsafe { mem::transmute(preempt::tick as usize) };
+    idt[(IRQ_BASE_VECTOR + Irq::Pit as u8) as usize].set_handler_fn(tick);
+
+    init_vector_handlers!(
+        idt, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67, 68,
+        69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91,
+        92, 93, 94, 95, 96, 97, 98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111,
+        112, 113, 114, 115, 116, 117, 118, 119, 120, 121, 122, 123, 124, 125, 126, 127, 128, 129,
+        130, 131, 132, 133, 134, 135, 136, 137, 138, 139, 140, 141, 142, 143, 144, 145, 146, 147,
+        148, 149, 150, 151, 152, 153, 154, 155, 156, 157, 158, 159, 160, 161, 162, 163, 164, 165,
+        166, 167, 168, 169, 170, 171, 172, 173, 174, 175, 176, 177, 178, 179, 180, 181, 182, 183,
+        184, 185, 186, 187, 188, 189, 190, 191, 192, 193, 194, 195, 196, 197, 198, 199, 200, 201,
+        202, 203, 204, 205, 206, 207, 208, 209, 210, 211, 212, 213, 214, 215, 216, 217, 218, 219,
+        220, 221, 222, 223, 224, 225, 226, 227, 228, 229, 230, 231, 232, 233, 234, 235, 236, 237,
+        238, 239, 240, 241, 242, 243, 244, 245, 246, 247, 248, 249, 250, 251, 252, 253, 254
+    );
+    idt[lapic::SPURIOUS_VECTOR as usize].set_handler_fn(spurious_interrupt);
 }
@@ -1,8 +1,29 @@
 //! Exception handlers
 
+use crate::page_cache::{self, FaultError};
+use crate::process::ExitStatus;
+use crate::scheduler;
+use crate::syscall::UserContext;
 use x86_64::structures::idt::{InterruptStackFrame, PageFaultErrorCode};
 
+/// Ends the current process if the exception was raised in user mode, as only that process is
+/// affected by it. Otherwise, returns so that the kernel can panic.
+fn kill_if_user(stack_frame: &InterruptStackFrame, exception: &str) {
+    if stack_frame.code_segment & 3 != 3 {
+        return;
+    }
+
+    warn!(
+        "cpuex: killing process {:?} after {} at {:?}",
+        scheduler::current(),
+        exception,
+        stack_frame.instruction_pointer
+    );
+    scheduler::exit_current(ExitStatus::Killed)
+}
+
 pub extern "x86-interrupt" fn divide_by_zero(stack_frame: &mut InterruptStackFrame) {
+    kill_if_user(stack_frame, "dividing by zero");
     panic!("cpuex: divide by zero\n{:#x?}", stack_frame);
 }
 
@@ -19,14 +40,17 @@ pub extern "x86-interrupt" fn breakpoint(stack_frame: &mut InterruptStackFrame)
 }
 
 pub extern "x86-interrupt" fn overflow(stack_frame: &mut InterruptStackFrame) {
+    kill_if_user(stack_frame, "an overflow");
     panic!("cpuex: overflow\n{:#x?}", stack_frame);
 }
 
 pub extern "x86-interrupt" fn out_of_bounds(stack_frame: &mut InterruptStackFrame) {
+    kill_if_user(stack_frame, "an out of bounds index");
     panic!("cpuex: out of bounds\n{:#x?}", stack_frame);
 }
 
 pub extern "x86-interrupt" fn invalid_opcode(stack_frame: &mut InterruptStackFrame) {
+    kill_if_user(stack_frame, "an invalid opcode");
     panic!(
         "cpuex: invalid opcode \n{:#x?}\n => note: qword at {:?} is 0x{:x}",
         stack_frame,
@@ -61,6 +85,7 @@ pub extern "x86-interrupt" fn stack_segment_fault(
     stack_frame: &mut InterruptStackFrame,
     code: u64,
 ) {
+    kill_if_user(stack_frame, "a stack segment fault");
     panic!(
         "cpuex: stack segment fault 0x{:x}\n{:#x?}",
         code, stack_frame
@@ -71,13 +96,119 @@ pub extern "x86-interrupt" fn general_protection_fault(
     stack_frame: &mut InterruptStackFrame,
     code: u64,
 ) {
+    kill_if_user(stack_frame, "a general protection fault");
     panic!(
         "cpuex: general protection fault 0x{:x}\n{:#x?}",
         code, stack_frame
     );
 }
 
-pub extern "x86-interrupt" fn page_fault(
+/// The entry point of page faults. Faults in user mode are handled by [user_page_fault], which may
+/// fault in a page of a memory object, and any others are passed on to [kernel_page_fault].
+///
+/// This is installed as an x86-interrupt handler for page faults, which it must stand in for.
+#[naked]
+pub unsafe extern "C" fn page_fault() {
+    asm!(
+        "
+        test qword ptr [rsp + 16], 3 // Whether the code segment is the user's
+        jz kernel_page_fault
+
+        // Push the user's context (reverse order because of struct layout)
+        push r11
+        push rcx
+        push qword ptr [rsp + 40] // RFLAGS
+        push qword ptr [rsp + 32] // RIP
+        push qword ptr [rsp + 64] // RSP
+        push r15
+        push r14
+        push r13
+        push r12
+        push rbp
+        push rbx
+        push rax
+        push r9
+        push r8
+        push r10
+        push rdx
+        push rsi
+        push rdi
+
+        sti
+
+        mov rdi, rsp // &mut UserContext
+        mov rsi, [rsp + 144] // Error code
+        call user_page_fault
+
+        // If the process blocked until its page was read, it will not return here, and the process
+        // will instead be resumed by the scheduler
+
+        cli
+
+        pop rdi
+        pop rsi
+        pop rdx
+        pop r10
+        pop r8
+        pop r9
+        pop rax
+        pop rbx
+        pop rbp
+        pop r12
+        pop r13
+        pop r14
+        pop r15
+        add rsp, 24 // Skip RSP, RIP and RFLAGS -- they are restored from the interrupt stack frame
+        pop rcx
+        pop r11
+        add rsp, 8 // Skip the error code
+
+        iretq",
+        options(noreturn)
+    )
+}
+
+#[no_mangle]
+extern "C" fn user_page_fault(context: &mut UserContext, error_code: u64) {
+    let cr2: u64;
+    unsafe {
+        asm!("mov {}, cr2", out(reg) cr2);
+    }
+
+    let error_code = PageFaultErrorCode::from_bits_truncate(error_code);
+
+    if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+        let write = error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
+
+        match page_cache::fault(context, cr2, write) {
+            Ok(()) => return,
+            Err(FaultError::NotMapped) => (),
+            Err(err) => {
+                warn!(
+                    "cpuex: killing process {:?}, as its page fault at 0x{:x} could not be \
+                    handled ({:?})",
+                    scheduler::current(),
+                    cr2,
+                    err
+                );
+                scheduler::exit_current(ExitStatus::Killed)
+            }
+        }
+    }
+
+    // Only the process which faulted is affected, so it is ended rather than the kernel
+    warn!(
+        "cpuex: killing process {:?} after a page fault (flags: {:?}) at 0x{:x}\n{:#x?}",
+        scheduler::current(),
+        error_code,
+        cr2,
+        context
+    );
+    scheduler::exit_current(ExitStatus::Killed)
+}
+
+#[no_mangle]
+pub extern "x86-interrupt" fn kernel_page_fault(
     stack_frame: &mut InterruptStackFrame,
     error_code: PageFaultErrorCode,
 ) {
@@ -94,10 +225,12 @@ pub extern "x86-interrupt" fn page_fault(
 }
 
 pub extern "x86-interrupt" fn x87_floating_point(stack_frame: &mut InterruptStackFrame) {
+    kill_if_user(stack_frame, "an x87 floating point exception");
     panic!("cpuex: x87 floating point\n{:#x?}", stack_frame);
 }
 
 pub extern "x86-interrupt" fn alignment_check(stack_frame: &mut InterruptStackFrame, code: u64) {
+    kill_if_user(stack_frame, "an alignment check");
     panic!("cpuex: alignment check 0x{:x}\n{:#x?}", code, stack_frame);
 }
 
@@ -106,6 +239,7 @@ pub extern "x86-interrupt" fn machine_check(stack_frame: &mut InterruptStackFram
 }
 
 pub extern "x86-interrupt" fn simd_floating_point(stack_frame: &mut InterruptStackFrame) {
+    kill_if_user(stack_frame, "a simd floating point exception");
     panic!("cpuex: simd floating point\n{:#x?}", stack_frame);
 }
 
@@ -0,0 +1,89 @@
+//! The local APIC. Only message signalled interrupts are delivered through it -- the legacy IRQs
+//! still go through the PICs, which are connected to it in virtual wire mode.
+//! From: Intel SDM Vol. 3A, chapter 10
+
+use crate::memory::physical_mapping::{self, PhysicalMapping};
+use core::ptr;
+use spin::Once;
+use x86_64::registers::model_specific::Msr;
+
+const IA32_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_GLOBAL_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+const REGISTER_ID: usize = 0x20;
+const REGISTER_END_OF_INTERRUPT: usize = 0xB0;
+const REGISTER_SPURIOUS_VECTOR: usize = 0xF0;
+const REGISTER_LVT_LINT0: usize = 0x350;
+const REGISTER_LVT_LINT1: usize = 0x360;
+
+const SPURIOUS_APIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+/// Delivers the PICs' interrupts as if the local APIC were not there
+const LVT_DELIVERY_EXTINT: u32 = 0b111 << 8;
+const LVT_DELIVERY_NMI: u32 = 0b100 << 8;
+
+/// Delivered when an interrupt is withdrawn before it is acknowledged. It must not be
+/// acknowledged, and so cannot be allocated to devices.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+static LOCAL_APIC: Once<LocalApic> = Once::new();
+
+struct LocalApic {
+    registers: *mut u32,
+    _mapping: PhysicalMapping<u32>,
+}
+
+// Safety: the registers are only accessed volatilely
+// TODO(SMP): every CPU has its own local APIC at the same physical address
+unsafe impl Send for LocalApic {}
+unsafe impl Sync for LocalApic {}
+
+impl LocalApic {
+    fn read(&self, register: usize) -> u32 {
+        unsafe { ptr::read_volatile(self.registers.add(register / 4)) }
+    }
+
+    fn write(&self, register: usize, value: u32) {
+        unsafe { ptr::write_volatile(self.registers.add(register / 4), value) }
+    }
+}
+
+/// Maps and enables the local APIC, keeping the PICs' interrupts working through it
+pub fn init() {
+    let mut base_msr = Msr::new(IA32_APIC_BASE);
+
+    let base = unsafe {
+        let base = base_msr.read();
+        base_msr.write(base | APIC_BASE_GLOBAL_ENABLE);
+        base & APIC_BASE_ADDRESS_MASK
+    };
+
+    let mut mapping = unsafe { physical_mapping::map_physical_region::<u32>(base, 4096, true) };
+    let apic = LocalApic {
+        registers: mapping.deref_mut().unwrap() as *mut u32,
+        _mapping: mapping,
+    };
+
+    apic.write(REGISTER_LVT_LINT0, LVT_DELIVERY_EXTINT);
+    apic.write(REGISTER_LVT_LINT1, LVT_DELIVERY_NMI);
+    apic.write(
+        REGISTER_SPURIOUS_VECTOR,
+        SPURIOUS_APIC_SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32,
+    );
+
+    debug!("interrupts: local apic at 0x{:x}", base);
+    LOCAL_APIC.call_once(|| apic);
+}
+
+/// The id of the local APIC, which message signalled interrupts are addressed to
+pub fn id() -> u8 {
+    let apic = LOCAL_APIC.wait().expect("Local APIC not initialised!");
+    (apic.read(REGISTER_ID) >> 24) as u8
+}
+
+/// Acknowledges the interrupt currently being handled
+pub fn end_of_interrupt() {
+    if let Some(apic) = LOCAL_APIC.wait() {
+        apic.write(REGISTER_END_OF_INTERRUPT, 0);
+    }
+}
@@ -0,0 +1,99 @@
+//! The PIT's interrupt handler, which preempts the running process once its time slice is used up
+//! or a timer has expired. Otherwise, a process which never makes a system call would stop every
+//! other process and timer from running.
+
+use super::{dispatch_irq, pic, user, Irq};
+use crate::scheduler;
+use crate::syscall::UserContext;
+use x86_64::structures::idt::InterruptStackFrame;
+
+const IRQ: u8 = Irq::Pit as u8;
+
+/// The entry point of the PIT's interrupt. Interrupts of user mode are handled by [user_tick], which
+/// may switch to another process, and any others by [kernel_tick].
+///
+/// This is installed as an x86-interrupt handler, which it must stand in for.
+#[naked]
+pub unsafe extern "C" fn tick() {
+    asm!(
+        "
+        test qword ptr [rsp + 8], 3 // Whether the code segment is the user's
+        jz kernel_tick
+
+        // Push the user's context (reverse order because of struct layout)
+        push r11
+        push rcx
+        push qword ptr [rsp + 32] // RFLAGS
+        push qword ptr [rsp + 24] // RIP
+        push qword ptr [rsp + 56] // RSP
+        push r15
+        push r14
+        push r13
+        push r12
+        push rbp
+        push rbx
+        push rax
+        push r9
+        push r8
+        push r10
+        push rdx
+        push rsi
+        push rdi
+
+        mov rdi, rsp // &UserContext
+
+        // Without an error code, the stack is 8 bytes off of the alignment which calls expect
+        sub rsp, 8
+        call user_tick
+        add rsp, 8
+
+        // If the process was preempted, it will not return here, and will instead be resumed by
+        // the scheduler
+
+        pop rdi
+        pop rsi
+        pop rdx
+        pop r10
+        pop r8
+        pop r9
+        pop rax
+        pop rbx
+        pop rbp
+        pop r12
+        pop r13
+        pop r14
+        pop r15
+        add rsp, 24 // Skip RSP, RIP and RFLAGS -- they are restored from the interrupt stack frame
+        pop rcx
+        pop r11
+
+        iretq",
+        options(noreturn)
+    )
+}
+
+#[no_mangle]
+extern "C" fn user_tick(context: &UserContext) {
+    handle_irq();
+
+    // The process was interrupted in user mode, so no locks are held and it can be switched out
+    if scheduler::should_preempt() {
+        scheduler::preempt_current(context);
+    }
+}
+
+#[no_mangle]
+extern "x86-interrupt" fn kernel_tick(_: &mut InterruptStackFrame) {
+    handle_irq();
+}
+
+/// Handles the IRQ as any other legacy IRQ is handled, releasing the PICs before returning
+fn handle_irq() {
+    let mut pics = pic::CHAINED_PICS.lock();
+    pics.handle_interrupt(IRQ, || dispatch_irq(IRQ));
+
+    // Keep the line masked until the process listening to it has handled it
+    if user::is_pending(IRQ) {
+        pics.disable_line(IRQ);
+    }
+}
@@ -0,0 +1,121 @@
+//! Legacy IRQs which are handled by userspace drivers. When one fires, its line is masked and the
+//! driver is sent a notification. The line stays masked until the driver has handled the interrupt
+//! and acknowledged it, as a level triggered line would otherwise fire again straight away.
+
+use super::{disable_irq, enable_irq, has_listeners};
+use crate::notification;
+use crate::process::ProcessId;
+use core::sync::atomic::{AtomicU16, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// The IRQs which have fired since they were last polled
+static PENDING: AtomicU16 = AtomicU16::new(0);
+/// The IRQs which a process listens to, so that interrupt handlers don't need to take any locks
+static LISTENED: AtomicU16 = AtomicU16::new(0);
+static LISTENERS: Mutex<[Option<Listener>; 16]> = Mutex::new([None; 16]);
+
+#[derive(Debug, Copy, Clone)]
+struct Listener {
+    pid: ProcessId,
+    /// The notification bits signalled when the IRQ fires
+    notify: u64,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ListenError {
+    NoSuchIrq,
+    /// The kernel or another process listens to the IRQ
+    AlreadyListened,
+}
+
+/// Sends the IRQ to the process as a notification, and unmasks it. Listening to an IRQ twice adds
+/// to the bits which are signalled.
+pub fn listen(irq: u8, pid: ProcessId, notify: u64) -> Result<(), ListenError> {
+    if irq >= 16 {
+        return Err(ListenError::NoSuchIrq);
+    }
+
+    if has_listeners(irq) {
+        return Err(ListenError::AlreadyListened);
+    }
+
+    {
+        let mut listeners = LISTENERS.lock();
+
+        match &mut listeners[irq as usize] {
+            Some(listener) if listener.pid == pid => listener.notify |= notify,
+            Some(_) => return Err(ListenError::AlreadyListened),
+            listener @ None => *listener = Some(Listener { pid, notify }),
+        }
+    }
+
+    LISTENED.fetch_or(1 << irq, Ordering::Release);
+    without_interrupts(|| enable_irq(irq));
+    Ok(())
+}
+
+/// Unmasks an IRQ once the process listening to it has handled it. Returns false if the process
+/// doesn't listen to it.
+pub fn acknowledge(irq: u8, pid: ProcessId) -> bool {
+    if irq >= 16 {
+        return false;
+    }
+
+    match LISTENERS.lock()[irq as usize] {
+        Some(listener) if listener.pid == pid => (),
+        _ => return false,
+    }
+
+    without_interrupts(|| enable_irq(irq));
+    true
+}
+
+/// Stops sending IRQs to an exiting process, masking them
+pub fn release(pid: ProcessId) {
+    let mut listeners = LISTENERS.lock();
+
+    for (irq, listener) in listeners.iter_mut().enumerate() {
+        match listener {
+            Some(listener) if listener.pid == pid => (),
+            _ => continue,
+        }
+
+        *listener = None;
+        LISTENED.fetch_and(!(1 << irq), Ordering::Release);
+        PENDING.fetch_and(!(1 << irq), Ordering::AcqRel);
+        without_interrupts(|| disable_irq(irq as u8));
+    }
+}
+
+/// Marks an IRQ as pending if a process listens to it. Called from the IRQ's interrupt handler.
+pub fn raise(irq: u8) {
+    if LISTENED.load(Ordering::Acquire) & (1 << irq) != 0 {
+        PENDING.fetch_or(1 << irq, Ordering::AcqRel);
+    }
+}
+
+/// Whether the IRQ has fired and its listening process has not yet been notified. Its line must be
+/// kept masked until the process acknowledges it.
+pub fn is_pending(irq: u8) -> bool {
+    PENDING.load(Ordering::Acquire) & (1 << irq) != 0
+}
+
+/// Notifies the processes whose IRQs have fired
+pub fn poll() {
+    let pending = PENDING.swap(0, Ordering::AcqRel);
+
+    if pending == 0 {
+        return;
+    }
+
+    let listeners = *LISTENERS.lock();
+
+    for (irq, listener) in listeners.iter().enumerate() {
+        if let Some(listener) = listener {
+            if pending & (1 << irq) != 0 {
+                notification::signal(listener.pid, listener.notify);
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Allocation of the interrupt vectors above the legacy IRQs, for devices which raise vectors
+//! directly, e.g with MSI.
+
+use super::lapic::SPURIOUS_VECTOR;
+use spin::Mutex;
+
+/// The first vector after those the PICs are remapped to
+pub const FIRST: u8 = 48;
+/// The last vector which can be allocated. The spurious vector is after it.
+pub const LAST: u8 = SPURIOUS_VECTOR - 1;
+
+/// A bit is set for every allocated vector
+static ALLOCATED: Mutex<[u64; 4]> = Mutex::new([0; 4]);
+
+/// Allocates a single vector, or returns `None` if all are in use
+pub fn allocate() -> Option<u8> {
+    allocate_block(1)
+}
+
+/// Allocates `count` contiguous vectors, aligned to `count`, which must be a power of two. This is
+/// what multiple message MSI requires. Returns the first vector.
+pub fn allocate_block(count: u8) -> Option<u8> {
+    assert!(
+        count.is_power_of_two(),
+        "Vector block size must be a power of two"
+    );
+
+    let mut allocated = ALLOCATED.lock();
+    let is_free = |allocated: &[u64; 4], vector: u16| {
+        allocated[vector as usize / 64] & (1 << (vector % 64)) == 0
+    };
+
+    // Work in u16 so that the end of the last block cannot overflow
+    let count = count as u16;
+    let mut first = (FIRST as u16 + count - 1) / count * count;
+
+    while first + count - 1 <= LAST as u16 {
+        let block = first..first + count;
+
+        if block.clone().all(|vector| is_free(&allocated, vector)) {
+            for vector in block {
+                allocated[vector as usize / 64] |= 1 << (vector % 64);
+            }
+
+            return Some(first as u8);
+        }
+
+        first += count;
+    }
+
+    None
+}
+
+/// Frees a vector allocated with `allocate`, or one vector of a block from `allocate_block`
+pub fn free(vector: u8) {
+    assert!(
+        (FIRST..=LAST).contains(&vector),
+        "Vector {} is not dynamically allocated",
+        vector
+    );
+
+    ALLOCATED.lock()[vector as usize / 64] &= !(1 << (vector % 64));
+}
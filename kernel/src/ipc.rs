@@ -0,0 +1,502 @@
+//! Synchronous message passing between processes. A server creates an endpoint, optionally under a
+//! name which clients can look it up by, and receives calls on it. A client calls an endpoint with
+//! a request and is blocked until the server replies. Messages are copied through the kernel, and
+//! into a blocked process's buffer when it is next run.
+//!
+//! The kernel can serve endpoints itself, such as the tmpfs's. Calls to them are answered straight
+//! away, within the caller's system call. It can also call endpoints which processes serve, e.g to
+//! read pages of a mapped file, and handles their replies itself.
+
+use crate::clock::{self, Clock};
+use crate::memory::buffer::BorrowedKernelBufferMut;
+use crate::process::{Delivery, ProcessId};
+use crate::scheduler;
+use crate::syscall::{Error, UserContext};
+use crate::timer::Deadline;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ptr::NonNull;
+use spin::Mutex;
+
+/// The longest request or reply
+pub const MAX_MESSAGE: usize = 64 * 1024;
+/// The longest name which an endpoint can be given
+pub const MAX_NAME: usize = 64;
+/// Each received message is preceded by the id of its call and the id of the calling process, each
+/// a u64
+const RECEIVE_HEADER_SIZE: usize = 16;
+
+lazy_static::lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State::default());
+}
+
+#[derive(Debug, Default)]
+struct State {
+    endpoints: BTreeMap<u64, Endpoint>,
+    names: BTreeMap<String, u64>,
+    /// Calls which have not yet been replied to
+    calls: BTreeMap<u64, Call>,
+    next_endpoint: u64,
+    next_call: u64,
+}
+
+/// Answers a call to an endpoint which the kernel serves, given the caller and the request. It is
+/// run in the caller's system call, without any IPC state locked.
+pub type KernelServer = fn(ProcessId, &[u8]) -> Vec<u8>;
+
+#[derive(Debug, Copy, Clone)]
+enum Owner {
+    Process(ProcessId),
+    Kernel(KernelServer),
+}
+
+impl Owner {
+    fn is(self, pid: ProcessId) -> bool {
+        match self {
+            Owner::Process(owner) => owner == pid,
+            Owner::Kernel(_) => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    owner: Owner,
+    /// Calls which have not yet been received
+    queue: VecDeque<Message>,
+    /// The owner, if it is blocked waiting to receive a call
+    receiver: Option<Receiver>,
+}
+
+#[derive(Debug)]
+struct Message {
+    call: u64,
+    sender: ProcessId,
+    data: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct Receiver {
+    block_id: u64,
+    ptr: u64,
+    len: u64,
+}
+
+#[derive(Debug)]
+struct Call {
+    endpoint: u64,
+    caller: Caller,
+}
+
+/// Handles the reply to a call which the kernel made itself. It is run in the replying process's
+/// system call, without any IPC state locked.
+pub type ReplyHandler = Box<dyn FnOnce(&[u8]) + Send>;
+
+enum Caller {
+    /// A process, blocked until the call is replied to
+    Process {
+        pid: ProcessId,
+        block_id: u64,
+        reply_ptr: u64,
+        reply_len: u64,
+    },
+    Kernel(ReplyHandler),
+}
+
+impl fmt::Debug for Caller {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Caller::Process { pid, block_id, .. } => f
+                .debug_struct("Process")
+                .field("pid", pid)
+                .field("block_id", block_id)
+                .finish(),
+            Caller::Kernel(_) => f.write_str("Kernel"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IpcError {
+    NoSuchEndpoint,
+    /// Another endpoint has the name
+    NameTaken,
+    /// Only the process which created an endpoint can receive and reply to calls on it
+    NotOwner,
+    NoSuchCall,
+    /// A process can't call its own endpoint, as it would never be able to receive the call
+    CallToSelf,
+}
+
+impl From<IpcError> for Error {
+    fn from(err: IpcError) -> Self {
+        match err {
+            IpcError::NoSuchEndpoint | IpcError::NoSuchCall => Error::NotFound,
+            IpcError::NameTaken => Error::Busy,
+            IpcError::NotOwner => Error::PermissionDenied,
+            IpcError::CallToSelf => Error::InvalidArgument,
+        }
+    }
+}
+
+/// Creates an endpoint owned by the process, with the given name if there is one. Returns its id.
+pub fn create(owner: ProcessId, name: Option<&str>) -> Result<u64, IpcError> {
+    create_owned(Owner::Process(owner), name)
+}
+
+/// Creates an endpoint under the given name, whose calls the kernel answers with the server.
+/// Returns its id.
+pub fn create_kernel(name: &str, server: KernelServer) -> Result<u64, IpcError> {
+    create_owned(Owner::Kernel(server), Some(name))
+}
+
+fn create_owned(owner: Owner, name: Option<&str>) -> Result<u64, IpcError> {
+    let mut state = STATE.lock();
+
+    if let Some(name) = name {
+        if state.names.contains_key(name) {
+            return Err(IpcError::NameTaken);
+        }
+    }
+
+    let id = state.next_endpoint;
+    state.next_endpoint += 1;
+    state.endpoints.insert(
+        id,
+        Endpoint {
+            owner,
+            queue: VecDeque::new(),
+            receiver: None,
+        },
+    );
+
+    if let Some(name) = name {
+        state.names.insert(String::from(name), id);
+    }
+
+    Ok(id)
+}
+
+/// Returns the id of the endpoint with the given name
+pub fn lookup(name: &str) -> Option<u64> {
+    STATE.lock().names.get(name).copied()
+}
+
+/// Sends a request to an endpoint and blocks the current process until it is replied to. The system
+/// call returns the length of the reply, which is copied into the reply buffer, truncated to its
+/// length. The reply buffer must have been checked to be writable.
+pub fn call(
+    context: &UserContext,
+    endpoint: u64,
+    request: Vec<u8>,
+    reply_ptr: u64,
+    reply_len: u64,
+) -> i64 {
+    let client = scheduler::current().expect("No process is running");
+    let owner = STATE
+        .lock()
+        .endpoints
+        .get(&endpoint)
+        .map(|endpoint| endpoint.owner);
+
+    match owner {
+        Some(Owner::Kernel(server)) => {
+            return call_kernel(server, client, &request, reply_ptr, reply_len)
+        }
+        Some(owner) if owner.is(client) => return Error::from(IpcError::CallToSelf) as i64,
+        Some(_) => (),
+        None => return Error::from(IpcError::NoSuchEndpoint) as i64,
+    }
+
+    scheduler::block_current_on(context, None, |client, block_id| {
+        let caller = Caller::Process {
+            pid: client,
+            block_id,
+            reply_ptr,
+            reply_len,
+        };
+
+        send(endpoint, client, request, caller);
+    })
+}
+
+/// Makes a call to an endpoint which a process serves on behalf of the kernel, e.g to read a page
+/// of a file. The message's sender is the process which the call is made for. The handler is given
+/// the reply.
+pub fn call_from_kernel(
+    endpoint: u64,
+    sender: ProcessId,
+    request: Vec<u8>,
+    on_reply: ReplyHandler,
+) -> Result<(), IpcError> {
+    let owner = STATE
+        .lock()
+        .endpoints
+        .get(&endpoint)
+        .map(|endpoint| endpoint.owner);
+
+    match owner {
+        Some(Owner::Process(_)) => {
+            send(endpoint, sender, request, Caller::Kernel(on_reply));
+            Ok(())
+        }
+        // The kernel doesn't call its own endpoints, as it can answer them directly
+        Some(Owner::Kernel(_)) => Err(IpcError::CallToSelf),
+        None => Err(IpcError::NoSuchEndpoint),
+    }
+}
+
+/// Removes the endpoints which an exiting process served. Calls to them which haven't been replied
+/// to fail with `NotFound`.
+pub fn release(pid: ProcessId) {
+    let failed: Vec<Call> = {
+        let mut state = STATE.lock();
+        let owned: Vec<u64> = state
+            .endpoints
+            .iter()
+            .filter(|(_, endpoint)| endpoint.owner.is(pid))
+            .map(|(id, _)| *id)
+            .collect();
+
+        if owned.is_empty() {
+            return;
+        }
+
+        for id in &owned {
+            state.endpoints.remove(id);
+        }
+
+        let names: Vec<String> = state
+            .names
+            .iter()
+            .filter(|(_, id)| owned.contains(id))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        for name in names {
+            state.names.remove(&name);
+        }
+
+        let calls: Vec<u64> = state
+            .calls
+            .iter()
+            .filter(|(_, call)| owned.contains(&call.endpoint))
+            .map(|(id, _)| *id)
+            .collect();
+
+        calls
+            .into_iter()
+            .map(|id| state.calls.remove(&id).unwrap())
+            .collect()
+    };
+
+    for call in failed {
+        match call.caller {
+            Caller::Process { pid, block_id, .. } => {
+                scheduler::wake(pid, block_id, Error::from(IpcError::NoSuchEndpoint) as i64)
+            }
+            Caller::Kernel(on_reply) => on_reply(&[]),
+        }
+    }
+}
+
+pub fn exists(endpoint: u64) -> bool {
+    STATE.lock().endpoints.contains_key(&endpoint)
+}
+
+/// Whether the process serves the endpoint
+pub fn is_owner(endpoint: u64, pid: ProcessId) -> bool {
+    match STATE.lock().endpoints.get(&endpoint) {
+        Some(endpoint) => endpoint.owner.is(pid),
+        None => false,
+    }
+}
+
+/// Records a call to an endpoint which a process serves, and delivers it if the process is waiting
+/// to receive one
+fn send(endpoint: u64, sender: ProcessId, request: Vec<u8>, caller: Caller) {
+    let delivery = {
+        let mut state = STATE.lock();
+        let id = state.next_call;
+        state.next_call += 1;
+        state.calls.insert(id, Call { endpoint, caller });
+
+        let endpoint = state.endpoints.get_mut(&endpoint).unwrap();
+        let message = Message {
+            call: id,
+            sender,
+            data: request,
+        };
+
+        let owner = match endpoint.owner {
+            Owner::Process(owner) => owner,
+            Owner::Kernel(_) => unreachable!("Calls to the kernel aren't queued"),
+        };
+
+        match endpoint.receiver.take() {
+            Some(receiver) => Some((owner, receiver, message)),
+            None => {
+                endpoint.queue.push_back(message);
+                None
+            }
+        }
+    };
+
+    if let Some((owner, receiver, message)) = delivery {
+        let (len, delivery) = receive_into(&message, receiver.ptr, receiver.len);
+
+        // The receiver may have stopped waiting, if its receive timed out, in which case the
+        // message is kept for the next one
+        if !scheduler::wake_with(owner, receiver.block_id, len as i64, delivery) {
+            if let Some(endpoint) = STATE.lock().endpoints.get_mut(&endpoint) {
+                endpoint.queue.push_front(message);
+            }
+        }
+    }
+}
+
+/// Answers a call to an endpoint which the kernel serves, copying the reply into the caller's
+/// buffer, which must have been checked to be writable
+fn call_kernel(
+    server: KernelServer,
+    client: ProcessId,
+    request: &[u8],
+    reply_ptr: u64,
+    reply_len: u64,
+) -> i64 {
+    let reply = server(client, request);
+    let len = reply.len().min(reply_len as usize);
+
+    if len > 0 {
+        // SAFETY: we are in the caller's page tables
+        let res = unsafe {
+            BorrowedKernelBufferMut::<u8>::try_from_user(
+                NonNull::new(reply_ptr as *mut u8),
+                len as u64,
+            )
+        };
+
+        match res {
+            Ok(buf) => buf.0.copy_from_slice(&reply[..len]),
+            Err(_) => return Error::InvalidBuffer as i64,
+        }
+    }
+
+    len as i64
+}
+
+/// Receives the next call on an endpoint which the current process owns into its buffer, blocking
+/// until there is one, or until the timeout has passed if there is one. The buffer gets the call's
+/// id and the caller's process id, followed by the request, truncated to fit. The system call
+/// returns the length of the request as received.
+pub fn receive(
+    context: &UserContext,
+    endpoint: u64,
+    ptr: u64,
+    len: u64,
+    timeout_ms: Option<u64>,
+) -> i64 {
+    let pid = scheduler::current().expect("No process is running");
+
+    if len < RECEIVE_HEADER_SIZE as u64 {
+        return Error::InvalidBuffer as i64;
+    }
+
+    // SAFETY: we are in the user's page tables
+    let res =
+        unsafe { BorrowedKernelBufferMut::<u8>::try_from_user(NonNull::new(ptr as *mut u8), len) };
+
+    let buf = match res {
+        Ok(buf) => buf,
+        Err(_) => return Error::InvalidBuffer as i64,
+    };
+
+    let message = {
+        let mut state = STATE.lock();
+        let endpoint = match state.endpoints.get_mut(&endpoint) {
+            Some(endpoint) if endpoint.owner.is(pid) => endpoint,
+            Some(_) => return Error::from(IpcError::NotOwner) as i64,
+            None => return Error::from(IpcError::NoSuchEndpoint) as i64,
+        };
+
+        endpoint.queue.pop_front()
+    };
+
+    if let Some(message) = message {
+        let (len, delivery) = receive_into(&message, ptr, len);
+        buf.0[..delivery.data.len()].copy_from_slice(&delivery.data);
+        return len as i64;
+    }
+
+    if timeout_ms == Some(0) {
+        return Error::TimedOut as i64;
+    }
+
+    let now = clock::now_ms(Clock::Monotonic);
+    let deadline = timeout_ms.map(|timeout_ms| Deadline {
+        at_ms: now.saturating_add(timeout_ms),
+        result: Error::TimedOut as i64,
+    });
+
+    scheduler::block_current_on(context, deadline, |_, block_id| {
+        let mut state = STATE.lock();
+
+        if let Some(endpoint) = state.endpoints.get_mut(&endpoint) {
+            endpoint.receiver = Some(Receiver { block_id, ptr, len });
+        }
+    })
+}
+
+/// Lays out a message as it is received into a buffer of the given length. Returns the length of
+/// the request as received.
+fn receive_into(message: &Message, ptr: u64, len: u64) -> (usize, Delivery) {
+    let request_len = message.data.len().min(len as usize - RECEIVE_HEADER_SIZE);
+    let mut data = Vec::with_capacity(RECEIVE_HEADER_SIZE + request_len);
+    data.extend_from_slice(&message.call.to_le_bytes());
+    data.extend_from_slice(&message.sender.as_u64().to_le_bytes());
+    data.extend_from_slice(&message.data[..request_len]);
+
+    (request_len, Delivery { ptr, data })
+}
+
+/// Replies to a call on an endpoint which the process owns, waking the caller
+pub fn reply(pid: ProcessId, call: u64, mut reply: Vec<u8>) -> Result<(), IpcError> {
+    let call = {
+        let mut state = STATE.lock();
+        let owner = state
+            .calls
+            .get(&call)
+            .and_then(|call| state.endpoints.get(&call.endpoint))
+            .map(|endpoint| endpoint.owner);
+
+        match owner {
+            Some(owner) if owner.is(pid) => state.calls.remove(&call).unwrap(),
+            Some(_) => return Err(IpcError::NotOwner),
+            None => return Err(IpcError::NoSuchCall),
+        }
+    };
+
+    match call.caller {
+        Caller::Process {
+            pid,
+            block_id,
+            reply_ptr,
+            reply_len,
+        } => {
+            reply.truncate(reply_len as usize);
+            let len = reply.len();
+            let delivery = Delivery {
+                ptr: reply_ptr,
+                data: reply,
+            };
+
+            scheduler::wake_with(pid, block_id, len as i64, delivery);
+        }
+        Caller::Kernel(on_reply) => on_reply(&reply),
+    }
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
 //! Lang items
 
 use crate::halt;
-use crate::vga::{Colour, ColourPair, VgaWriter};
+use crate::vga::{Colour, ColourPair, VgaWriter, VGA_WRITER};
 use core::alloc::Layout;
 use core::fmt::Write;
 use core::panic::PanicInfo;
@@ -12,7 +12,20 @@ fn eh_personality() {}
 
 #[panic_handler]
 fn panic_fmt(info: &PanicInfo) -> ! {
-    let mut vga_writer = unsafe { VgaWriter::new() };
+    // The global writer is used if possible, as it may be drawing onto the framebuffer. If the
+    // panic happened while it was locked, a new writer is made for the text buffer instead.
+    let mut guard;
+    let mut fallback;
+    let vga_writer: &mut VgaWriter = match VGA_WRITER.try_lock() {
+        Some(locked) => {
+            guard = locked;
+            &mut guard
+        }
+        None => {
+            fallback = unsafe { VgaWriter::new() };
+            &mut fallback
+        }
+    };
     let mut serial = unsafe { SerialPort::new(0x3f8) };
 
     vga_writer.colour = ColourPair::new(Colour::Red, Colour::Black);
@@ -24,7 +37,7 @@ fn panic_fmt(info: &PanicInfo) -> ! {
 
     if let Some(loc) = info.location() {
         let _ = write!(
-            &mut vga_writer,
+            vga_writer,
             "Panicked at \"{}\", {file}:{line}",
             arguments,
             file = loc.file(),
@@ -40,7 +53,7 @@ fn panic_fmt(info: &PanicInfo) -> ! {
         );
     } else {
         let _ = write!(
-            &mut vga_writer,
+            vga_writer,
             "Panicked at \"{}\" at an undefined location",
             arguments
         );
@@ -16,8 +16,9 @@
 #[macro_use]
 extern crate alloc;
 
+use crate::fd::{File, FileTable};
 use crate::memory::heap::Heap;
-use crate::process::Process;
+use crate::process::{Capabilities, Process};
 use crate::vga::VGA_WRITER;
 use core::fmt;
 use core::fmt::Write;
@@ -33,13 +34,37 @@ mod log;
 #[macro_use]
 mod util;
 mod acpi_handler;
+mod block;
+mod boot_info;
+mod clock;
+mod console;
+mod efi;
+mod fd;
 mod gdt;
+mod initrd;
+mod input;
 mod interrupts;
+mod ipc;
 mod memory;
+mod net;
+mod notification;
+mod page_cache;
+mod pci;
+mod pipe;
 mod pit;
+mod power;
 pub mod process;
+mod ps2;
+mod rtc;
+mod scheduler;
+mod serial;
 mod syscall;
+mod timer;
+mod tmpfs;
 mod tss;
+mod vfs;
+mod virtio;
+mod wait_queue;
 
 #[global_allocator]
 pub static HEAP: Heap = Heap::new();
@@ -56,8 +81,15 @@ pub extern "C" fn kmain(mb_info_addr: u64, guard_page_addr: u64) -> ! {
     VGA_WRITER.lock().clear();
     log::init();
     memory::init_memory(mb_info_addr, guard_page_addr);
+    vga::init_scrollback();
+    boot_info::init(mb_info_addr);
     gdt::init();
 
+    match vga::init_framebuffer() {
+        Ok(resolution) => info!("vga: using {}x{} framebuffer", resolution.x, resolution.y),
+        Err(e) => info!("vga: using text mode ({:?})", e),
+    }
+
     interrupts::init();
     interrupts::enable();
     info!("interrupts: ready");
@@ -68,16 +100,47 @@ pub extern "C" fn kmain(mb_info_addr: u64, guard_page_addr: u64) -> ! {
     pit::CONTROLLER.lock().initialize();
     info!("pit: ready");
 
-    let _acpi = acpi_handler::acpi_init();
+    ps2::init();
+    info!("ps2: ready");
+
+    let acpi = acpi_handler::acpi_init();
+
+    pci::init();
+    info!("pci: ready");
+
+    virtio::init();
+    info!("virtio: ready");
+
+    clock::init();
+    info!("clock: ready");
+
+    if acpi.is_ok() {
+        power::init();
+        info!("power: ready");
+    }
+
     unsafe { syscall::setup_syscall() };
 
+    console::init();
+    info!("console: ready");
+
+    match initrd::init() {
+        Ok(_) => info!("initrd: ready"),
+        Err(e) => warn!("initrd: not loaded ({:?})", e),
+    }
+
+    tmpfs::init();
+    info!("tmpfs: ready");
+
     info!("init: loading");
-    let pid = Process::spawn_from_elf(INIT_ELF)
+    let files = FileTable::with_standard(File::Console, File::Console, File::Console);
+    let pid = Process::spawn_from_elf(INIT_ELF, "init", None, Capabilities::all(), files)
         .map_err(|e| panic!("{:#x?}", e))
         .unwrap();
     info!("init: launching");
 
-    Process::run_by_pid(&pid).expect("Out of physical memory")
+    scheduler::enqueue(pid);
+    scheduler::run_next()
 }
 
 fn enable_features() {
@@ -1,18 +1,23 @@
 use crate::vga::VGA_WRITER;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use log::{self, Level, Log, Metadata, Record};
 
 static LOGGER: Logger = Logger;
+/// The most verbose level which is currently logged
+static LEVEL: AtomicUsize = AtomicUsize::new(log_level() as usize);
 
 #[allow(unused_macros)]
 macro_rules! error {
     ($thing:expr, $($extra:tt)*) => {
         {
-            use alloc::fmt::Write;
-            crate::vga::VGA_WRITER.lock().write_str_coloured("[error] ", colour!(Red on Black));
-            write!(crate::vga::VGA_WRITER.lock(), "{}\n", format_args!($thing, $($extra)*)).unwrap();
-            crate::serial1_print(format_args!("[error] "));
-            crate::serial1_print(format_args!($thing, $($extra)*));
-            crate::serial1_print(format_args!("\n"));
+            if crate::log::enabled(::log::Level::Error) {
+                use alloc::fmt::Write;
+                crate::vga::VGA_WRITER.lock().write_str_coloured("[error] ", colour!(Red on Black));
+                write!(crate::vga::VGA_WRITER.lock(), "{}\n", format_args!($thing, $($extra)*)).unwrap();
+                crate::serial1_print(format_args!("[error] "));
+                crate::serial1_print(format_args!($thing, $($extra)*));
+                crate::serial1_print(format_args!("\n"));
+            }
         }
     };
 
@@ -25,12 +30,14 @@ macro_rules! error {
 macro_rules! warn {
     ($thing:expr, $($extra:tt)*) => {
         {
-            use alloc::fmt::Write;
-            crate::vga::VGA_WRITER.lock().write_str_coloured("[warn]  ", colour!(LightRed on Black));
-            write!(crate::vga::VGA_WRITER.lock(), "{}\n", format_args!($thing, $($extra)*)).unwrap();
-            crate::serial1_print(format_args!("[warn]  "));
-            crate::serial1_print(format_args!($thing, $($extra)*));
-            crate::serial1_print(format_args!("\n"));
+            if crate::log::enabled(::log::Level::Warn) {
+                use alloc::fmt::Write;
+                crate::vga::VGA_WRITER.lock().write_str_coloured("[warn]  ", colour!(LightRed on Black));
+                write!(crate::vga::VGA_WRITER.lock(), "{}\n", format_args!($thing, $($extra)*)).unwrap();
+                crate::serial1_print(format_args!("[warn]  "));
+                crate::serial1_print(format_args!($thing, $($extra)*));
+                crate::serial1_print(format_args!("\n"));
+            }
         }
     };
 
@@ -42,12 +49,14 @@ macro_rules! warn {
 macro_rules! info {
     ($thing:expr, $($extra:tt)*) => {
         {
-            use alloc::fmt::Write;
-            crate::vga::VGA_WRITER.lock().write_str_coloured("[info]  ", colour!(LightBlue on Black));
-            write!(crate::vga::VGA_WRITER.lock(), "{}\n", format_args!($thing, $($extra)*)).unwrap();
-            crate::serial1_print(format_args!("[info]  "));
-            crate::serial1_print(format_args!($thing, $($extra)*));
-            crate::serial1_print(format_args!("\n"));
+            if crate::log::enabled(::log::Level::Info) {
+                use alloc::fmt::Write;
+                crate::vga::VGA_WRITER.lock().write_str_coloured("[info]  ", colour!(LightBlue on Black));
+                write!(crate::vga::VGA_WRITER.lock(), "{}\n", format_args!($thing, $($extra)*)).unwrap();
+                crate::serial1_print(format_args!("[info]  "));
+                crate::serial1_print(format_args!($thing, $($extra)*));
+                crate::serial1_print(format_args!("\n"));
+            }
         }
     };
 
@@ -60,12 +69,14 @@ macro_rules! debug {
     ($thing:expr, $($extra:tt)*) => {
         #[cfg(feature = "debug")]
         {
-            use alloc::fmt::Write;
-            crate::vga::VGA_WRITER.lock().write_str_coloured("[debug] ", colour!(Cyan on Black));
-            write!(crate::vga::VGA_WRITER.lock(), "{}\n", format_args!($thing, $($extra)*)).unwrap();
-            crate::serial1_print(format_args!("[debug] "));
-            crate::serial1_print(format_args!($thing, $($extra)*));
-            crate::serial1_print(format_args!("\n"));
+            if crate::log::enabled(::log::Level::Debug) {
+                use alloc::fmt::Write;
+                crate::vga::VGA_WRITER.lock().write_str_coloured("[debug] ", colour!(Cyan on Black));
+                write!(crate::vga::VGA_WRITER.lock(), "{}\n", format_args!($thing, $($extra)*)).unwrap();
+                crate::serial1_print(format_args!("[debug] "));
+                crate::serial1_print(format_args!($thing, $($extra)*));
+                crate::serial1_print(format_args!("\n"));
+            }
         }
     };
 
@@ -78,12 +89,14 @@ macro_rules! trace {
     ($thing:expr, $($extra:tt)*) => {
         #[cfg(feature = "trace")]
         {
-            use alloc::fmt::Write;
-            crate::vga::VGA_WRITER.lock().write_str_coloured("[trace] ", colour!(White on Black));
-            write!(crate::vga::VGA_WRITER.lock(), "{}\n", format_args!($thing, $($extra)*)).unwrap();
-            crate::serial1_print(format_args!("[trace] "));
-            crate::serial1_print(format_args!($thing, $($extra)*));
-            crate::serial1_print(format_args!("\n"));
+            if crate::log::enabled(::log::Level::Trace) {
+                use alloc::fmt::Write;
+                crate::vga::VGA_WRITER.lock().write_str_coloured("[trace] ", colour!(White on Black));
+                write!(crate::vga::VGA_WRITER.lock(), "{}\n", format_args!($thing, $($extra)*)).unwrap();
+                crate::serial1_print(format_args!("[trace] "));
+                crate::serial1_print(format_args!($thing, $($extra)*));
+                crate::serial1_print(format_args!("\n"));
+            }
         }
     };
 
@@ -108,7 +121,7 @@ const fn log_level() -> Level {
 
 impl Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= log_level()
+        enabled(metadata.level())
     }
 
     fn log(&self, record: &Record) {
@@ -138,6 +151,34 @@ impl Log for Logger {
     fn flush(&self) {}
 }
 
+/// Whether messages of the given level are currently logged
+pub fn enabled(level: Level) -> bool {
+    level as usize <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Changes the most verbose level which is logged. The level can't be made more verbose than the
+/// kernel was built with, as the debug and trace macros are compiled out otherwise. Returns the
+/// most verbose level allowed if it would be.
+pub fn set_level(level: Level) -> Result<(), Level> {
+    if level > log_level() {
+        return Err(log_level());
+    }
+
+    LEVEL.store(level as usize, Ordering::Relaxed);
+    log::set_max_level(level.to_level_filter());
+    Ok(())
+}
+
+pub fn level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
 pub fn init() {
     log::set_logger(&LOGGER)
         .map(|()| log::set_max_level(log_level().to_level_filter()))
@@ -18,6 +18,8 @@
 pub mod paging;
 pub mod bootstrap_heap;
 pub mod buffer;
+pub mod dma;
+pub mod frame;
 pub mod heap;
 pub mod physical_allocator;
 pub mod physical_mapping;
@@ -34,9 +36,10 @@ use crate::util::round_up_divide;
 use core::{
     iter, mem,
     ops::{Range, RangeInclusive},
+    sync::atomic::{AtomicU64, Ordering},
 };
 use friendly::Block;
-use multiboot2::{self, BootInformation, MemoryMapTag};
+use multiboot2::{self, BootInformation};
 use tinyvec::ArrayVec;
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::{PhysAddr, VirtAddr};
@@ -49,6 +52,9 @@ pub const LAST_USABLE_PAGE: Page = Page {
     size: Some(PageSize::Kib4),
 };
 
+/// The amount of usable physical memory, from the memory map
+static USABLE_BYTES: AtomicU64 = AtomicU64::new(0);
+
 pub fn init_memory(mb_info_addr: u64, guard_page_addr: u64) {
     info!("mem: initialising");
 
@@ -56,11 +62,9 @@ pub fn init_memory(mb_info_addr: u64, guard_page_addr: u64) {
     let kernel_area = kernel_area(&mb_info);
 
     let mb_info_phys = mb_info.start_address() as u64..=mb_info.end_address() as u64;
-    let memory_map = mb_info
-        .memory_map_tag()
-        .expect("Expected a multiboot2 memory map tag, but it is not present!");
+    let memory_areas = memory_areas(&mb_info);
 
-    print_memory_info(memory_map);
+    print_memory_info(&memory_areas);
 
     debug!("mem: initialising bootstrap heap");
     let (bootstrap_heap_phys, bootstrap_heap_virtual) = unsafe {
@@ -72,7 +76,12 @@ pub fn init_memory(mb_info_addr: u64, guard_page_addr: u64) {
 
     debug!("mem: initialising pmm (1/2)");
     let (gibbibytes, usable) = unsafe {
-        setup_physical_allocator_prelim(&mb_info, mb_info_phys, bootstrap_heap_phys, kernel_area)
+        setup_physical_allocator_prelim(
+            &memory_areas,
+            mb_info_phys,
+            bootstrap_heap_phys,
+            kernel_area,
+        )
     };
 
     // ** IMPORTANT! **
@@ -101,24 +110,83 @@ pub fn init_memory(mb_info_addr: u64, guard_page_addr: u64) {
     info!("mem: initialised");
 }
 
-fn print_memory_info(memory_map: &MemoryMapTag) {
+/// The amount of usable physical memory in bytes
+pub fn usable_bytes() -> u64 {
+    USABLE_BYTES.load(Ordering::Relaxed)
+}
+
+/// Returns the usable physical memory areas, preferring the EFI memory map if booted through UEFI
+fn memory_areas(mb_info: &BootInformation) -> ArrayVec<[Range<u64>; 256]> {
+    if let Some(efi_memory_map) = mb_info.efi_memory_map_tag() {
+        debug!("mem: using efi memory map");
+
+        return collect_areas(
+            efi_memory_map
+                .usable_areas()
+                .map(|area| area.physical_address()..area.physical_address() + area.size()),
+        );
+    }
+
+    collect_areas(
+        mb_info
+            .memory_map_tag()
+            .expect("Expected a multiboot2 memory map tag, but it is not present!")
+            .memory_areas()
+            .map(|area| area.start_address() as u64..area.end_address() as u64),
+    )
+}
+
+/// Collects memory areas, merging those which are adjacent to the area before them, as firmware
+/// splits usable memory into many areas by what it was used for. Areas which still don't fit are
+/// dropped, and their memory goes unused.
+fn collect_areas<I>(areas: I) -> ArrayVec<[Range<u64>; 256]>
+where
+    I: Iterator<Item = Range<u64>>,
+{
+    // Leave room for the three areas which the kernel, multiboot info and bootstrap heap may each
+    // split in two in `setup_physical_allocator_prelim`
+    const MAX_AREAS: usize = 256 - 3;
+
+    let mut collected: ArrayVec<[Range<u64>; 256]> = ArrayVec::new();
+    let mut dropped = 0;
+
+    for area in areas {
+        if let Some(last) = collected.last_mut() {
+            if last.end == area.start {
+                last.end = area.end;
+                continue;
+            }
+        }
+
+        if collected.len() < MAX_AREAS {
+            collected.push(area);
+        } else {
+            dropped += area.end - area.start;
+        }
+    }
+
+    if dropped > 0 {
+        warn!(
+            "mem: too many memory areas, dropping {} KiB of usable memory",
+            dropped / 1024
+        );
+    }
+
+    collected
+}
+
+fn print_memory_info(memory_areas: &[Range<u64>]) {
     trace!("mem: Usable memory areas: ");
 
     // For when log_level != debug | trace
     #[allow(unused_variables)]
-    for area in memory_map.memory_areas() {
-        trace!(
-            " - 0x{:x} to 0x{:x}",
-            area.start_address(),
-            area.end_address()
-        );
+    for area in memory_areas {
+        trace!(" - 0x{:x} to 0x{:x}", area.start, area.end);
     }
 
     // Calculate how many GiBs are available
-    let bytes_available: u64 = memory_map
-        .memory_areas()
-        .map(|area| (area.end_address() - area.start_address()) as u64)
-        .sum();
+    let bytes_available: u64 = memory_areas.iter().map(|area| area.end - area.start).sum();
+    USABLE_BYTES.store(bytes_available, Ordering::Relaxed);
 
     let gibbibytes_available = bytes_available as f64 / (1 << 30) as f64;
     if gibbibytes_available > 1.0 {
@@ -213,18 +281,14 @@ unsafe fn setup_bootstrap_heap(
 }
 
 unsafe fn setup_physical_allocator_prelim(
-    mb_info: &BootInformation,
+    memory_areas: &[Range<u64>],
     mb_info_phys: RangeInclusive<u64>,
     bootstrap_heap_phys: RangeInclusive<u64>,
     kernel_area: RangeInclusive<u64>,
 ) -> (u8, ArrayVec<[Range<u64>; 256]>) {
-    let memory_map = mb_info
-        .memory_map_tag()
-        .expect("Expected a multiboot2 memory map tag, but it is not present!");
-
-    let highest_address = memory_map
-        .memory_areas()
-        .map(|area| area.end_address() - 1)
+    let highest_address = memory_areas
+        .iter()
+        .map(|area| area.end - 1)
         .max()
         .expect("No usable physical memory available!");
 
@@ -232,11 +296,8 @@ unsafe fn setup_physical_allocator_prelim(
     let trees = round_up_divide(highest_address as u64, 1 << 30) as u8;
     trace!("Allocating {} trees", trees);
 
-    // Calculate the usable memory areas by using the MB2 memory map but excluding kernel areas
-    let usable_areas = memory_map
-        .memory_areas()
-        .map(|area| (area.start_address(), area.end_address()))
-        .map(|(start, end)| start..end);
+    // Calculate the usable memory areas by using the memory map but excluding kernel areas
+    let usable_areas = memory_areas.iter().cloned();
 
     // Remove already used physical mem areas
     let kernel_area_phys = 0..=kernel_area.end() - KERNEL_MAPPING_BEGIN;
@@ -1,5 +1,7 @@
 use crate::memory::paging::{EntryFlags, Page, ACTIVE_PAGE_TABLES};
 use crate::memory::LAST_USABLE_PAGE;
+use crate::page_cache;
+use crate::syscall;
 use core::ptr::NonNull;
 use core::{mem, slice};
 
@@ -7,12 +9,19 @@ use core::{mem, slice};
 pub unsafe trait PlainOldData: Sized {
     /// Safely transmute from a byte slice to a byte slice of the type
     fn from_bytes(buf: &[u8]) -> &[Self];
+
+    /// Safely transmute from a mutable byte slice to a mutable byte slice of the type
+    fn from_bytes_mut(buf: &mut [u8]) -> &mut [Self];
 }
 
 unsafe impl PlainOldData for u8 {
     fn from_bytes(buf: &[u8]) -> &[u8] {
         buf
     }
+
+    fn from_bytes_mut(buf: &mut [u8]) -> &mut [u8] {
+        buf
+    }
 }
 
 pub enum InvalidBufferError {
@@ -33,45 +42,101 @@ impl<'a, T: PlainOldData> BorrowedKernelBuffer<'a, T> {
         ptr: Option<NonNull<u8>>,
         len: u64,
     ) -> Result<Self, InvalidBufferError> {
-        let ptr = ptr.ok_or(InvalidBufferError::Null)?.as_ptr() as *const u8;
+        let ptr = check_user_buffer::<T>(ptr, len, EntryFlags::USER_ACCESSIBLE)?;
 
-        if (ptr as usize) % mem::align_of::<T>() != 0 {
-            return Err(InvalidBufferError::Unaligned);
-        }
+        // SAFETY: all memory is mapped and aligned.
+        let byte_slice = slice::from_raw_parts(ptr, len as usize * mem::size_of::<T>());
 
-        if len == 0 || len > isize::MAX as u64 {
-            return Err(InvalidBufferError::InvalidLen);
-        }
+        Ok(BorrowedKernelBuffer(T::from_bytes(byte_slice)))
+    }
+}
 
-        let added = (ptr as u64).checked_add(len * mem::size_of::<T>() as u64 - 1);
-        let buffer_end_byte = match added {
-            Some(end) if end < (LAST_USABLE_PAGE + 1).start_address().unwrap() => end,
-            Some(_invalid_end) => return Err(InvalidBufferError::OverlapsKernelSpace),
-            None => return Err(InvalidBufferError::InvalidLen),
-        };
+/// A user buffer which the kernel may write into, e.g to return data from a system call
+pub struct BorrowedKernelBufferMut<'a, T>(pub &'a mut [T]);
 
-        // Split the buffer into its memory pages
-        let page_begin = Page::containing_address(ptr as u64);
-        let page_end = Page::containing_address(buffer_end_byte as u64);
+impl<'a, T: PlainOldData> BorrowedKernelBufferMut<'a, T> {
+    /// # Safety
+    ///
+    /// The current page tables must be of the same address space where the buffer comes from.
+    pub unsafe fn try_from_user(
+        ptr: Option<NonNull<u8>>,
+        len: u64,
+    ) -> Result<Self, InvalidBufferError> {
+        let required_flags = EntryFlags::USER_ACCESSIBLE | EntryFlags::WRITABLE;
+        let ptr = check_user_buffer::<T>(ptr, len, required_flags)?;
 
-        let all_mapped = (page_begin..=page_end)
-            .map(|p| ACTIVE_PAGE_TABLES.lock().walk_page_table(p))
-            .all(|opt| {
-                opt.map(|(entry, _)| entry.flags().contains(EntryFlags::USER_ACCESSIBLE))
-                    .unwrap_or(false)
-            });
+        // SAFETY: all memory is mapped writable and aligned.
+        let byte_slice = slice::from_raw_parts_mut(ptr, len as usize * mem::size_of::<T>());
 
-        if !all_mapped {
-            return Err(InvalidBufferError::Unmapped);
-        }
+        Ok(BorrowedKernelBufferMut(T::from_bytes_mut(byte_slice)))
+    }
+}
 
-        let byte_slice = if all_mapped {
-            // SAFETY: all memory is mapped and aligned.
-            slice::from_raw_parts(ptr, len as usize)
-        } else {
-            return Err(InvalidBufferError::Unmapped);
+/// Checks that a user buffer of `len` elements of `T` is aligned, lies in user space and is
+/// entirely mapped with the given flags. Pages of memory objects which haven't been touched yet are
+/// faulted in, if a system call is being handled.
+///
+/// # Safety
+///
+/// The current page tables must be of the same address space where the buffer comes from.
+unsafe fn check_user_buffer<T>(
+    ptr: Option<NonNull<u8>>,
+    len: u64,
+    required_flags: EntryFlags,
+) -> Result<*mut u8, InvalidBufferError> {
+    let ptr = ptr.ok_or(InvalidBufferError::Null)?.as_ptr();
+
+    if (ptr as usize) % mem::align_of::<T>() != 0 {
+        return Err(InvalidBufferError::Unaligned);
+    }
+
+    if len == 0 || len > isize::MAX as u64 {
+        return Err(InvalidBufferError::InvalidLen);
+    }
+
+    let added = len
+        .checked_mul(mem::size_of::<T>() as u64)
+        .and_then(|bytes| (ptr as u64).checked_add(bytes - 1));
+    let buffer_end_byte = match added {
+        Some(end) if end < (LAST_USABLE_PAGE + 1).start_address().unwrap() => end,
+        Some(_invalid_end) => return Err(InvalidBufferError::OverlapsKernelSpace),
+        None => return Err(InvalidBufferError::InvalidLen),
+    };
+
+    // Split the buffer into its memory pages
+    let page_begin = Page::containing_address(ptr as u64);
+    let page_end = Page::containing_address(buffer_end_byte as u64);
+    let write = required_flags.contains(EntryFlags::WRITABLE);
+
+    for page in page_begin..=page_end {
+        let entry = ACTIVE_PAGE_TABLES.lock().walk_page_table(page);
+
+        let flags = match entry {
+            Some((entry, _)) => entry.flags(),
+            None => fault_in(page, write)?,
         };
 
-        Ok(BorrowedKernelBuffer(T::from_bytes(byte_slice)))
+        if !flags.contains(required_flags) {
+            return Err(InvalidBufferError::Unmapped);
+        }
     }
+
+    Ok(ptr)
+}
+
+/// Faults in a page of a memory object, as if the process had touched it, returning the flags it is
+/// mapped with. If the page has to be read from a server first, the system call is blocked until it
+/// has been, and is then made again. Outside of system calls nothing is faulted in, as the process
+/// table may be locked.
+fn fault_in(page: Page, write: bool) -> Result<EntryFlags, InvalidBufferError> {
+    let context = syscall::restart_context().ok_or(InvalidBufferError::Unmapped)?;
+    let addr = page.start_address().unwrap();
+
+    page_cache::fault(&context, addr, write).map_err(|_| InvalidBufferError::Unmapped)?;
+
+    ACTIVE_PAGE_TABLES
+        .lock()
+        .walk_page_table(page)
+        .map(|(entry, _)| entry.flags())
+        .ok_or(InvalidBufferError::Unmapped)
 }
@@ -0,0 +1,74 @@
+//! Physically contiguous memory for devices to access directly, e.g for virtqueues or command
+//! lists. Regions are allocated from the physical allocator and mapped into the kernel heap.
+
+use super::physical_allocator::PHYSICAL_ALLOCATOR;
+use super::physical_mapping::{self, PhysicalMapping};
+use core::{fmt, ptr};
+
+/// The physical allocator's smallest block is a 4KiB frame
+const FRAME_SIZE: usize = 4096;
+
+pub struct DmaRegion {
+    physical_start: u64,
+    len: usize,
+    order: u8,
+    mapping: PhysicalMapping<u8>,
+}
+
+// Safety: the region is only accessed through its owner
+unsafe impl Send for DmaRegion {}
+
+impl DmaRegion {
+    /// Allocates a zeroed region of at least `len` bytes, aligned to its size rounded up to a power
+    /// of two frames. Returns None if there is not enough physical memory.
+    pub fn allocate(len: usize) -> Option<DmaRegion> {
+        let frames = (len + FRAME_SIZE - 1) / FRAME_SIZE;
+        let order = frames.next_power_of_two().trailing_zeros() as u8;
+        let frame = PHYSICAL_ALLOCATOR.allocate(order)?;
+        let physical_start = frame.start_address().as_u64();
+        let len = FRAME_SIZE << order;
+
+        // SAFETY: the frames were just allocated, so nothing else uses them
+        let mut mapping = unsafe {
+            physical_mapping::map_physical_region::<u8>(physical_start, len as u64, true)
+        };
+
+        // SAFETY: the mapping is `len` bytes long
+        unsafe { ptr::write_bytes(mapping.deref_mut().unwrap() as *mut u8, 0, len) };
+
+        Some(DmaRegion {
+            physical_start,
+            len,
+            order,
+            mapping,
+        })
+    }
+
+    /// Returns a pointer to the byte at `offset` into the region, which must be within it
+    pub fn ptr(&mut self, offset: usize) -> *mut u8 {
+        assert!(offset < self.len, "Offset is out of the DMA region");
+
+        // SAFETY: the offset was checked to be within the mapping
+        unsafe { (self.mapping.deref_mut().unwrap() as *mut u8).add(offset) }
+    }
+
+    /// Returns the physical address of the byte at `offset` into the region
+    pub fn physical(&self, offset: usize) -> u64 {
+        self.physical_start + offset as u64
+    }
+}
+
+impl fmt::Debug for DmaRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DmaRegion")
+            .field("physical_start", &self.physical_start)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl Drop for DmaRegion {
+    fn drop(&mut self) {
+        PHYSICAL_ALLOCATOR.deallocate(self.physical_start, self.order);
+    }
+}
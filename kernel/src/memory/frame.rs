@@ -0,0 +1,54 @@
+//! Single frames of physical memory which can be shared, e.g between a file and the processes it is
+//! mapped into. A frame is freed when it is dropped, so shared frames are kept in `Arc`s.
+
+use super::physical_allocator::PHYSICAL_ALLOCATOR;
+use super::physical_mapping;
+
+pub const FRAME_SIZE: usize = 4096;
+
+#[derive(Debug)]
+pub struct Frame {
+    physical: u64,
+}
+
+impl Frame {
+    /// Allocates a zeroed frame. Returns None if there is no physical memory left.
+    pub fn allocate() -> Option<Frame> {
+        let frame = PHYSICAL_ALLOCATOR.allocate(0)?;
+        let frame = Frame {
+            physical: frame.start_address().as_u64(),
+        };
+
+        frame.with_bytes(|bytes| bytes.iter_mut().for_each(|byte| *byte = 0));
+        Some(frame)
+    }
+
+    pub fn physical(&self) -> u64 {
+        self.physical
+    }
+
+    /// Maps the frame into the kernel while the closure accesses it. The active page tables must
+    /// not be locked.
+    pub fn with_bytes<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8; FRAME_SIZE]) -> R,
+    {
+        // SAFETY: the frame is allocated for as long as this lives. Its users synchronise access to
+        // its contents, e.g through the lock of the file which it belongs to.
+        let mut mapping = unsafe {
+            physical_mapping::map_physical_region::<[u8; FRAME_SIZE]>(
+                self.physical,
+                FRAME_SIZE as u64,
+                true,
+            )
+        };
+
+        f(mapping.deref_mut().unwrap())
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        PHYSICAL_ALLOCATOR.deallocate(self.physical, 0);
+    }
+}
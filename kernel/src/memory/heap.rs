@@ -4,6 +4,7 @@ pub const HEAP_START: u64 = 0xffffffff40000000;
 use crate::memory::paging::*;
 use crate::util;
 use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::{iter, mem, ptr};
 use friendly::{Block, Tree};
 use spin::{Mutex, Once};
@@ -16,11 +17,21 @@ pub type HeapTree = Tree<&'static mut RawArray, 25, BASE_ORDER>;
 
 pub struct Heap {
     tree: Once<Mutex<HeapTree>>,
+    /// Bytes requested through the global allocator and not yet freed
+    allocated: AtomicU64,
 }
 
 impl Heap {
     pub const fn new() -> Self {
-        Heap { tree: Once::new() }
+        Heap {
+            tree: Once::new(),
+            allocated: AtomicU64::new(0),
+        }
+    }
+
+    /// The number of bytes currently allocated through the global allocator
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated.load(Ordering::Relaxed)
     }
 
     /// Initializes the heap. Required for it to be usable, otherwise all of its methods will panic.
@@ -210,6 +221,9 @@ unsafe impl GlobalAlloc for Heap {
                 }
             }
         }
+
+        self.allocated
+            .fetch_add(layout.size() as u64, Ordering::Relaxed);
         ptr
     }
 
@@ -226,6 +240,9 @@ unsafe impl GlobalAlloc for Heap {
             ptr,
         );
 
+        self.allocated
+            .fetch_sub(layout.size() as u64, Ordering::Relaxed);
+
         let global_ptr = ptr;
         let ptr = ptr as u64 - HEAP_START;
 
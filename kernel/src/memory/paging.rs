@@ -10,13 +10,15 @@ use bitflags::_core::cmp::Ordering;
 use core::iter::Step;
 use core::marker::PhantomData;
 use core::ops::{Add, Index, IndexMut, Sub};
-use spin::Mutex;
+use spin::{Mutex, Once};
 use x86_64::instructions::tlb;
 use x86_64::registers::control::Cr3;
 use x86_64::PhysAddr;
 
 const PAGE_TABLE_ENTRIES: u64 = 512;
 pub static ACTIVE_PAGE_TABLES: Mutex<ActivePageMap> = Mutex::new(unsafe { ActivePageMap::new() });
+/// The kernel's own page tables, which are switched to when a process's are freed
+pub static KERNEL_PAGE_TABLES: Once<InactivePageMap> = Once::new();
 
 /// The size of a page. Distinct from `memory::PageSize` in that it only enumerates page sizes
 /// supported by the paging module at this time.
@@ -27,7 +29,7 @@ pub enum PageSize {
 }
 
 impl PageSize {
-    const fn bytes(self) -> u64 {
+    pub const fn bytes(self) -> u64 {
         use self::PageSize::*;
 
         match self {
@@ -217,6 +219,9 @@ bitflags::bitflags! {
         const HUGE_PAGE = 1 << 7;
         /// If set, this page will not be flushed in the TLB if CR3 is reset. PGE bit in CR4 must be set.
         const GLOBAL = 1 << 8; // TODO(userspace): map kernel pages as global?
+        /// Ignored by the CPU. Set on user pages whose frames the page tables don't own, e.g device
+        /// memory or the page cache's, so that they aren't freed with the process.
+        const BORROWED = 1 << 9;
         /// Do not allow executing code from this page. NXE bit in EFER must be set.
         const NO_EXECUTE = 1 << 63;
     }
@@ -191,7 +191,11 @@ impl Mapper {
         };
 
         let frame = PHYSICAL_ALLOCATOR.allocate(order).ok_or(OutOfMemory)?;
-        self.map_to(page, frame.start_address(), flags, invplg)?;
+
+        if let Err(err) = self.map_to(page, frame.start_address(), flags, invplg) {
+            PHYSICAL_ALLOCATOR.deallocate(frame.start_address().as_u64(), order);
+            return Err(err);
+        }
 
         // Zero the page
         if zero == ZeroPage::Zero {
@@ -227,7 +231,8 @@ impl Mapper {
         Ok(())
     }
 
-    /// Tries to map a range of pages for a user.
+    /// Tries to map a range of pages for a user. If `ignore_already_mapped` is set, pages which are
+    /// already mapped are left as they are, rather than being an error.
     pub unsafe fn try_map_user_range(
         &mut self,
         pages: RangeInclusive<Page>,
@@ -236,54 +241,114 @@ impl Mapper {
         ignore_already_mapped: bool,
         zero: ZeroPage,
     ) -> Result<(), TryMapError> {
-        assert!(
-            pages.start().page_size() == Some(PageSize::Kib4)
-                && pages.end().page_size() == Some(PageSize::Kib4),
-            "Only mapping of 4kib pages is supported"
-        );
+        check_user_range(&pages)?;
 
-        let v_start = pages.start().start_address().unwrap();
-        let v_end = pages.end().start_address().unwrap();
+        for no in pages.start().number()..=pages.end().number() {
+            let page = Page::containing_address(no as u64 * 0x1000);
 
-        // Page above last usable page's last addr + 1 = noncanonical, which creates syscall bug
-        if *pages.end() > LAST_USABLE_PAGE {
-            trace!("v_end + 1 noncanonical");
-            return Err(TryMapError::InvalidAddress(pages.end().clone()));
-        }
+            if self.walk_page_table(page).is_some() {
+                if ignore_already_mapped {
+                    continue;
+                }
 
-        // Noncanonical address
-        if VirtAddr::try_new(v_end).is_err() {
-            return Err(TryMapError::InvalidAddress(pages.end().clone()));
-        } else if VirtAddr::try_new(v_start).is_err() {
-            return Err(TryMapError::InvalidAddress(pages.start().clone()));
+                return Err(TryMapError::AlreadyMapped(page));
+            }
+
+            self.map(page, flags, invplg, zero)?;
         }
 
-        // Kernel memory (higher half)
-        if v_end >> 63 == 1 {
-            return Err(TryMapError::InvalidAddress(pages.end().clone()));
-        } else if v_start >> 63 == 1 {
-            return Err(TryMapError::InvalidAddress(pages.start().clone()));
+        Ok(())
+    }
+
+    /// Tries to map a range of pages for a user to the given contiguous physical memory, such as
+    /// device memory. The physical memory is not freed when the pages are unmapped, nor when the
+    /// process exits.
+    pub unsafe fn try_map_user_range_to(
+        &mut self,
+        pages: RangeInclusive<Page>,
+        physical_start: PhysAddr,
+        flags: EntryFlags,
+        invplg: InvalidateTlb,
+    ) -> Result<(), TryMapError> {
+        check_user_range(&pages)?;
+
+        for no in pages.start().number()..=pages.end().number() {
+            let page = Page::containing_address(no as u64 * 0x1000);
+
+            if self.walk_page_table(page).is_some() {
+                return Err(TryMapError::AlreadyMapped(page));
+            }
         }
 
-        // Program stack
-        let stack_bottom = Page::containing_address(STACK_BOTTOM.as_u64());
-        if *pages.end() > stack_bottom {
-            return Err(TryMapError::InvalidAddress(pages.end().clone()));
+        for (i, no) in (pages.start().number()..=pages.end().number()).enumerate() {
+            let page = Page::containing_address(no as u64 * 0x1000);
+            let frame = physical_start + i as u64 * 0x1000;
+
+            self.map_to(page, frame, flags | EntryFlags::BORROWED, invplg)?;
         }
 
+        Ok(())
+    }
+
+    /// Checks that a range of pages could be mapped for a user, without mapping them, e.g so that
+    /// they can be mapped lazily when they are first accessed
+    pub fn check_user_unmapped(&self, pages: RangeInclusive<Page>) -> Result<(), TryMapError> {
+        check_user_range(&pages)?;
+
         for no in pages.start().number()..=pages.end().number() {
             let page = Page::containing_address(no as u64 * 0x1000);
 
-            if !ignore_already_mapped && self.walk_page_table(page).is_some() {
+            if self.walk_page_table(page).is_some() {
                 return Err(TryMapError::AlreadyMapped(page));
             }
-
-            self.map(page, flags, invplg, zero)?;
         }
 
         Ok(())
     }
 
+    /// Unmaps every page in the lower half, freeing the frames of those which aren't borrowed, and
+    /// the page tables which mapped them.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may access the lower half's memory afterwards, e.g the process must be exiting.
+    pub unsafe fn free_user_pages(&mut self) {
+        fn free_entry(entry: &mut PageTableEntry) {
+            if let Some(frame) = entry.physical_address() {
+                if !entry.flags().contains(EntryFlags::BORROWED) {
+                    PHYSICAL_ALLOCATOR.deallocate(frame.as_u64(), 0);
+                }
+
+                entry.set_unused();
+            }
+        }
+
+        for p4_index in 0..256 {
+            if let Some(p3) = self.p4_mut().next_page_table_mut(p4_index) {
+                for p3_index in 0..PAGE_TABLE_ENTRIES as usize {
+                    if let Some(p2) = p3.next_page_table_mut(p3_index) {
+                        for p2_index in 0..PAGE_TABLE_ENTRIES as usize {
+                            // Users are never given huge pages
+                            if let Some(p1) = p2.next_page_table_mut(p2_index) {
+                                for p1_index in 0..PAGE_TABLE_ENTRIES as usize {
+                                    free_entry(&mut p1[p1_index]);
+                                }
+                            }
+
+                            free_entry(&mut p2[p2_index]);
+                        }
+                    }
+
+                    free_entry(&mut p3[p3_index]);
+                }
+            }
+
+            free_entry(&mut self.p4_mut()[p4_index]);
+        }
+
+        tlb::flush_all();
+    }
+
     pub unsafe fn set_flags(
         &mut self,
         pages: RangeInclusive<Page>,
@@ -427,6 +492,47 @@ impl Mapper {
     }
 }
 
+/// Checks that a range of pages can be mapped for a user, i.e that it is canonical and is not in
+/// kernel space or the program stack
+fn check_user_range(pages: &RangeInclusive<Page>) -> Result<(), TryMapError> {
+    assert!(
+        pages.start().page_size() == Some(PageSize::Kib4)
+            && pages.end().page_size() == Some(PageSize::Kib4),
+        "Only mapping of 4kib pages is supported"
+    );
+
+    let v_start = pages.start().start_address().unwrap();
+    let v_end = pages.end().start_address().unwrap();
+
+    // Page above last usable page's last addr + 1 = noncanonical, which creates syscall bug
+    if *pages.end() > LAST_USABLE_PAGE {
+        trace!("v_end + 1 noncanonical");
+        return Err(TryMapError::InvalidAddress(pages.end().clone()));
+    }
+
+    // Noncanonical address
+    if VirtAddr::try_new(v_end).is_err() {
+        return Err(TryMapError::InvalidAddress(pages.end().clone()));
+    } else if VirtAddr::try_new(v_start).is_err() {
+        return Err(TryMapError::InvalidAddress(pages.start().clone()));
+    }
+
+    // Kernel memory (higher half)
+    if v_end >> 63 == 1 {
+        return Err(TryMapError::InvalidAddress(pages.end().clone()));
+    } else if v_start >> 63 == 1 {
+        return Err(TryMapError::InvalidAddress(pages.start().clone()));
+    }
+
+    // Program stack
+    let stack_bottom = Page::containing_address(STACK_BOTTOM.as_u64());
+    if *pages.end() > stack_bottom {
+        return Err(TryMapError::InvalidAddress(pages.end().clone()));
+    }
+
+    Ok(())
+}
+
 /// A 4kib page range mapping -- represents a contigous area of 4kib pages mapped to a contigous
 /// area of 4kib frames. However, this does not need to be an identity mapping, i.e there may be
 /// an offset
@@ -696,6 +802,25 @@ impl Default for InactivePageMap {
 }
 
 impl InactivePageMap {
+    /// Frees the page tables of a process which is exiting, and the memory which they own. If they
+    /// are active, `fallback` is switched to.
+    ///
+    /// # Safety
+    ///
+    /// The process must never be run again.
+    pub unsafe fn free(self, active: &mut ActivePageMap, fallback: InactivePageMap) {
+        let previous = active.switch(self.clone());
+        active.free_user_pages();
+
+        if previous.p4_frame == self.p4_frame {
+            active.switch(fallback);
+        } else {
+            active.switch(previous);
+        }
+
+        PHYSICAL_ALLOCATOR.deallocate(self.p4_frame.start_address().as_u64(), 0);
+    }
+
     /// # Safety:
     ///
     /// Frame must be valid.
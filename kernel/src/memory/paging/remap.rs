@@ -101,6 +101,7 @@ pub fn remap_kernel(boot_info: &BootInformation, heap_tree_start_virt: u64) {
     );
 
     trace!("mem: switching page tables");
+    KERNEL_PAGE_TABLES.call_once(|| new_table.clone());
     active_table.switch(new_table);
 
     // Drop this lock so that the RAII guarded temporary page can be destroyed
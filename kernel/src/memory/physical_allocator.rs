@@ -2,6 +2,7 @@ use super::bootstrap_heap::{BootstrapHeapBox, BOOTSTRAP_HEAP};
 use alloc::boxed::Box;
 use alloc::vec::Vec;
 use core::convert::TryInto;
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::{
     iter,
     ops::{Deref, DerefMut, Range},
@@ -19,14 +20,18 @@ const BASE_ORDER: u8 = 12;
 
 /// The physical frame allocator. Requires the bootstrap heap to be initialized, or else the
 /// initializer will panic.
-pub static PHYSICAL_ALLOCATOR: PhysicalAllocator<'static> =
-    PhysicalAllocator { trees: Once::new() };
+pub static PHYSICAL_ALLOCATOR: PhysicalAllocator<'static> = PhysicalAllocator {
+    trees: Once::new(),
+    allocated: AtomicU64::new(0),
+};
 
 pub type PhysicalTree<'a> = Tree<TreeBox<'a>, LEVEL_COUNT, BASE_ORDER>;
 
 pub struct PhysicalAllocator<'a> {
     // Max 256GiB
     trees: Once<[Mutex<Option<PhysicalTree<'a>>>; 256]>,
+    /// Bytes allocated through `allocate` and not yet deallocated
+    allocated: AtomicU64,
 }
 
 impl<'a> PhysicalAllocator<'a> {
@@ -138,6 +143,8 @@ impl<'a> PhysicalAllocator<'a> {
                         Some(address) => {
                             let addr =
                                 address + (index * (1 << (PhysicalTree::max_order() + BASE_ORDER)));
+                            self.allocated
+                                .fetch_add(1 << (order + BASE_ORDER), Ordering::Relaxed);
                             return Some(PhysFrame::containing_address(PhysAddr::new(addr as u64)));
                         }
                         None => tried[index] = TryState::Tried, // Tree empty for alloc of this size
@@ -165,6 +172,14 @@ impl<'a> PhysicalAllocator<'a> {
         let tree = lock.as_mut().unwrap();
 
         tree.deallocate(local_ptr as usize, order);
+        self.allocated
+            .fetch_sub(1 << (order + BASE_ORDER), Ordering::Relaxed);
+    }
+
+    /// The number of bytes currently allocated. Memory reserved at boot, e.g for the kernel, is not
+    /// included.
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated.load(Ordering::Relaxed)
     }
 }
 
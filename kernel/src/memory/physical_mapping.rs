@@ -7,8 +7,10 @@ pub unsafe fn map_physical_region<T>(
     size: u64,
     mutable: bool,
 ) -> PhysicalMapping<T> {
-    let frames = util::round_up_divide(size as u64, 4096) as u64;
     let physical_begin_frame = physical_address / 4096;
+    // The region may not start on a frame boundary, so account for the offset into the first frame
+    let offset = physical_address - (physical_begin_frame * 4096);
+    let frames = util::round_up_divide(offset + size as u64, 4096) as u64;
 
     let alloc_ptr = crate::HEAP.alloc_specific(physical_begin_frame, frames) as u64;
 
@@ -16,7 +18,7 @@ pub unsafe fn map_physical_region<T>(
         panic!("Ran out of heap memory!");
     }
 
-    let obj_ptr = alloc_ptr + physical_address - (physical_begin_frame * 4096);
+    let obj_ptr = alloc_ptr + offset;
 
     PhysicalMapping {
         physical_start: physical_begin_frame * 4096,
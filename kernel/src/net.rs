@@ -0,0 +1,322 @@
+//! Network devices, which send and receive raw Ethernet frames. A process with the `NETWORK`
+//! capability attaches to a device, which maps a pair of frame rings shared with the kernel into
+//! its address space. Received frames are copied into the receive ring and the process is
+//! notified, and frames it puts in the transmit ring are sent when it asks, or when it is next
+//! polled.
+
+use crate::memory::dma::DmaRegion;
+use crate::memory::paging::{EntryFlags, InvalidateTlb, Page, ACTIVE_PAGE_TABLES};
+use crate::notification;
+use crate::process::ProcessId;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::{mem, ptr, slice};
+use spin::{Mutex, RwLock};
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::PhysAddr;
+
+/// The largest frame which can be sent or received, without its frame check sequence
+pub const MAX_FRAME_SIZE: usize = 1514;
+
+/// The number of frames which each of the shared rings holds, so that with the header they fill
+/// 128 KiB
+const RING_SLOTS: u32 = 31;
+/// Each slot holds the length of its frame, followed by the frame
+const SLOT_SIZE: u32 = 2048;
+/// The header comes first, in its own page, followed by the receive ring's slots and then the
+/// transmit ring's
+const HEADER_SIZE: u32 = 4096;
+const SHARED_SIZE: u32 = HEADER_SIZE + 2 * RING_SLOTS * SLOT_SIZE;
+
+/// Indices of the shared header's fields. They are free running, and the slot which an index
+/// refers to is it modulo the number of slots.
+const RX_HEAD: usize = 0;
+const RX_TAIL: usize = 1;
+const TX_HEAD: usize = 2;
+const TX_TAIL: usize = 3;
+const SLOT_COUNT: usize = 4;
+const SLOT_SIZE_FIELD: usize = 5;
+
+static DEVICES: RwLock<Vec<Device>> = RwLock::new(Vec::new());
+
+/// A driver for a network device. The methods are called with interrupts disabled, so that they
+/// can share state with the device's interrupt handler.
+pub trait NetDevice: Sync {
+    fn info(&self) -> NetDeviceInfo;
+
+    /// Queues a frame to be sent
+    fn transmit(&self, frame: &[u8]) -> Result<(), NetError>;
+
+    /// Calls `receive` with each frame which has been received since the last poll, and reclaims
+    /// the buffers of those which have been sent
+    fn poll(&self, receive: &mut dyn FnMut(&[u8]));
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NetError {
+    /// The device can't queue any more frames until some have been sent
+    Busy,
+    TooLarge,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AttachError {
+    NoSuchDevice,
+    /// Another process is attached to the device
+    AlreadyAttached,
+    OutOfMemory,
+    /// The rings could not be mapped at the given address
+    InvalidAddress,
+}
+
+/// Describes a network device to userspace. Kept free of padding, as it is copied out
+/// byte-for-byte.
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct NetDeviceInfo {
+    pub mac: [u8; 6],
+    _reserved: [u8; 2],
+    /// The largest frame which can be sent, without its frame check sequence
+    pub max_frame_size: u32,
+    pub flags: u32,
+}
+
+impl NetDeviceInfo {
+    pub const FLAG_LINK_UP: u32 = 1;
+
+    pub fn new(mac: [u8; 6], max_frame_size: u32, flags: u32) -> NetDeviceInfo {
+        NetDeviceInfo {
+            mac,
+            _reserved: [0; 2],
+            max_frame_size,
+            flags,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: repr(C) without any padding
+        unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<Self>()) }
+    }
+}
+
+struct Device {
+    driver: &'static dyn NetDevice,
+    attachment: Mutex<Option<Attachment>>,
+}
+
+/// A process attached to a device, and the rings it shares with the kernel
+struct Attachment {
+    pid: ProcessId,
+    /// The notification bits signalled when frames arrive
+    notify: u64,
+    shared: DmaRegion,
+}
+
+impl Attachment {
+    fn field(&mut self, field: usize) -> &AtomicU32 {
+        // SAFETY: the fields are aligned and within the header's page
+        unsafe { &*(self.shared.ptr(field * 4) as *const AtomicU32) }
+    }
+
+    /// Returns the slot of the receive ring (0) or the transmit ring (1) with the given index
+    fn slot(&mut self, ring: u32, index: u32) -> *mut u8 {
+        let slot = ring * RING_SLOTS + index % RING_SLOTS;
+        self.shared.ptr((HEADER_SIZE + slot * SLOT_SIZE) as usize)
+    }
+
+    /// Copies a frame into the receive ring. Returns false if the ring is full, in which case the
+    /// frame is dropped.
+    fn receive(&mut self, frame: &[u8]) -> bool {
+        let head = self.field(RX_HEAD).load(Ordering::Relaxed);
+        let tail = self.field(RX_TAIL).load(Ordering::Acquire);
+
+        if head.wrapping_sub(tail) >= RING_SLOTS || frame.len() > MAX_FRAME_SIZE {
+            return false;
+        }
+
+        let slot = self.slot(0, head);
+
+        // SAFETY: the frame fits in the slot after its length
+        unsafe {
+            ptr::write_volatile(slot as *mut u32, frame.len() as u32);
+            ptr::copy_nonoverlapping(frame.as_ptr(), slot.add(4), frame.len());
+        }
+
+        self.field(RX_HEAD)
+            .store(head.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Sends the frames which are waiting in the transmit ring, until the device is busy. Returns
+    /// how many were sent. Frames which are too large are skipped.
+    fn transmit(&mut self, driver: &dyn NetDevice) -> u32 {
+        let mut tail = self.field(TX_TAIL).load(Ordering::Relaxed);
+        let head = self.field(TX_HEAD).load(Ordering::Acquire);
+        let mut sent = 0;
+
+        // The process could write a bogus head, so never look at more than a ring's worth
+        let head = if head.wrapping_sub(tail) > RING_SLOTS {
+            tail.wrapping_add(RING_SLOTS)
+        } else {
+            head
+        };
+
+        while tail != head {
+            let slot = self.slot(1, tail);
+
+            // SAFETY: the length is clamped so that the frame is within the slot
+            let frame = unsafe {
+                let len = ptr::read_volatile(slot as *const u32).min(SLOT_SIZE - 4);
+                slice::from_raw_parts(slot.add(4), len as usize)
+            };
+
+            match driver.transmit(frame) {
+                Ok(()) => sent += 1,
+                Err(NetError::TooLarge) => (),
+                Err(NetError::Busy) => break,
+            }
+
+            tail = tail.wrapping_add(1);
+        }
+
+        self.field(TX_TAIL).store(tail, Ordering::Release);
+        sent
+    }
+}
+
+/// Registers a device, returning its id
+pub fn register(driver: &'static dyn NetDevice) -> usize {
+    let mut devices = DEVICES.write();
+    devices.push(Device {
+        driver,
+        attachment: Mutex::new(None),
+    });
+
+    let info = without_interrupts(|| driver.info());
+    let mac = info.mac;
+    info!(
+        "net: device {} has mac address {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        devices.len() - 1,
+        mac[0],
+        mac[1],
+        mac[2],
+        mac[3],
+        mac[4],
+        mac[5]
+    );
+
+    devices.len() - 1
+}
+
+/// Returns information about each registered device, in order of id
+pub fn devices() -> Vec<NetDeviceInfo> {
+    let devices = DEVICES.read();
+    without_interrupts(|| devices.iter().map(|device| device.driver.info()).collect())
+}
+
+/// Attaches the current process to a device, mapping the shared rings at the given page aligned
+/// address. `notify` is the notification bits signalled when frames arrive. Returns the length of
+/// the mapping.
+///
+/// # Safety
+///
+/// The current page tables must be the process's.
+pub unsafe fn attach(
+    device: usize,
+    pid: ProcessId,
+    addr_begin: u64,
+    notify: u64,
+) -> Result<u64, AttachError> {
+    let devices = DEVICES.read();
+    let device = devices.get(device).ok_or(AttachError::NoSuchDevice)?;
+    let mut attachment = device.attachment.lock();
+
+    if attachment.is_some() {
+        return Err(AttachError::AlreadyAttached);
+    }
+
+    let shared = DmaRegion::allocate(SHARED_SIZE as usize).ok_or(AttachError::OutOfMemory)?;
+
+    let flags = EntryFlags::PRESENT
+        | EntryFlags::USER_ACCESSIBLE
+        | EntryFlags::WRITABLE
+        | EntryFlags::NO_EXECUTE;
+    let page_begin = Page::containing_address(addr_begin);
+    let page_end = page_begin + (SHARED_SIZE / 4096 - 1) as usize;
+
+    ACTIVE_PAGE_TABLES
+        .lock()
+        .try_map_user_range_to(
+            page_begin..=page_end,
+            PhysAddr::new(shared.physical(0)),
+            flags,
+            InvalidateTlb::Invalidate,
+        )
+        .map_err(|_| AttachError::InvalidAddress)?;
+
+    let mut new = Attachment {
+        pid,
+        notify,
+        shared,
+    };
+    new.field(SLOT_COUNT).store(RING_SLOTS, Ordering::Relaxed);
+    new.field(SLOT_SIZE_FIELD)
+        .store(SLOT_SIZE, Ordering::Relaxed);
+    *attachment = Some(new);
+
+    Ok(SHARED_SIZE as u64)
+}
+
+/// Detaches an exiting process from the devices it is attached to, freeing their shared rings
+pub fn detach(pid: ProcessId) {
+    for device in DEVICES.read().iter() {
+        let mut attachment = device.attachment.lock();
+
+        if attachment.as_ref().map(|attachment| attachment.pid) == Some(pid) {
+            *attachment = None;
+        }
+    }
+}
+
+/// Sends the frames waiting in the transmit ring of a device which the process is attached to.
+/// Returns how many were sent, or None if the process isn't attached.
+pub fn transmit(device: usize, pid: ProcessId) -> Option<u32> {
+    let devices = DEVICES.read();
+    let device = devices.get(device)?;
+    let mut attachment = device.attachment.lock();
+
+    match attachment.as_mut() {
+        Some(attachment) if attachment.pid == pid => {
+            Some(without_interrupts(|| attachment.transmit(device.driver)))
+        }
+        _ => None,
+    }
+}
+
+/// Moves received frames into the attached processes' rings, notifying them, and sends any frames
+/// waiting to be. Interrupt handlers only acknowledge the interrupt, as they may have interrupted
+/// code holding the process table's locks.
+pub fn poll() {
+    for device in DEVICES.read().iter() {
+        let mut attachment = device.attachment.lock();
+        let mut received = false;
+
+        without_interrupts(|| {
+            device.driver.poll(&mut |frame| {
+                if let Some(attachment) = attachment.as_mut() {
+                    received |= attachment.receive(frame);
+                }
+            });
+
+            if let Some(attachment) = attachment.as_mut() {
+                attachment.transmit(device.driver);
+            }
+        });
+
+        if let Some(attachment) = attachment.as_ref() {
+            if received {
+                notification::signal(attachment.pid, attachment.notify);
+            }
+        }
+    }
+}
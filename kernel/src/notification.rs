@@ -0,0 +1,80 @@
+//! Notifications, which let the kernel signal events to a process without sending it any data,
+//! e.g that frames have arrived in a network device's shared ring. Each process has 63 notification
+//! bits, which are set when signalled and cleared once the process has waited on them. There is no
+//! 64th, as system calls which return with the top bit set have failed.
+
+use crate::process::ProcessId;
+use crate::scheduler;
+use crate::syscall::UserContext;
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+
+/// The bits which can be signalled
+pub const BITS: u64 = !(1 << 63);
+
+lazy_static::lazy_static! {
+    static ref NOTIFICATIONS: Mutex<BTreeMap<ProcessId, Notifications>> =
+        Mutex::new(BTreeMap::new());
+}
+
+#[derive(Debug, Default)]
+struct Notifications {
+    /// The bits which have been signalled but not yet waited on
+    pending: u64,
+    /// The block id of the process, and the bits it is waiting on, if it is blocked waiting
+    waiter: Option<(u64, u64)>,
+}
+
+/// Sets the given notification bits of a process, waking it if it is waiting on any of them.
+///
+/// This must not be called from an interrupt handler, as it may have interrupted code which holds
+/// the process table's locks.
+pub fn signal(pid: ProcessId, bits: u64) {
+    let wake = {
+        let mut notifications = NOTIFICATIONS.lock();
+        let entry = notifications.entry(pid).or_default();
+        entry.pending |= bits & BITS;
+
+        match entry.waiter {
+            Some((block_id, mask)) if entry.pending & mask != 0 => {
+                let woken_by = entry.pending & mask;
+                entry.pending &= !mask;
+                entry.waiter = None;
+                Some((block_id, woken_by))
+            }
+            _ => None,
+        }
+    };
+
+    if let Some((block_id, bits)) = wake {
+        scheduler::wake(pid, block_id, bits as i64);
+    }
+}
+
+/// Forgets the notifications of an exiting process
+pub fn release(pid: ProcessId) {
+    NOTIFICATIONS.lock().remove(&pid);
+}
+
+/// Blocks the current process in its system call until any of the bits in `mask` are signalled,
+/// returning straight away if some already are. The system call returns the signalled bits of the
+/// mask, which are cleared.
+pub fn wait(context: &UserContext, mask: u64) -> i64 {
+    let pid = scheduler::current().expect("No process is running");
+    let mask = mask & BITS;
+
+    {
+        let mut notifications = NOTIFICATIONS.lock();
+        let entry = notifications.entry(pid).or_default();
+        let signalled = entry.pending & mask;
+
+        if signalled != 0 {
+            entry.pending &= !signalled;
+            return signalled as i64;
+        }
+    }
+
+    scheduler::block_current_on(context, None, |pid, block_id| {
+        NOTIFICATIONS.lock().entry(pid).or_default().waiter = Some((block_id, mask));
+    })
+}
@@ -0,0 +1,816 @@
+//! Memory objects, whose pages are mapped into processes lazily. A page is only brought into the
+//! page cache when a process first touches it, from the page fault handler, or passes a buffer in
+//! it to a system call. An object is either
+//! anonymous memory or a file. Files on the tmpfs share their frames with the cache, while the pages
+//! of files which a process serves are read from it, and written back to it, over the VFS protocol.
+//!
+//! Dirty pages are written back when they are unmapped, when a process syncs them, or when the
+//! process exits. Pages stay cached until their object is dropped, i.e once it has been released
+//! and is no longer mapped, or until a tmpfs file is truncated past them.
+
+use crate::ipc;
+use crate::memory::frame::{Frame, FRAME_SIZE};
+use crate::memory::paging::{
+    ActivePageMap, EntryFlags, FreeMemory, InactivePageMap, InvalidateTlb, Page, ACTIVE_PAGE_TABLES,
+};
+use crate::memory::LAST_USABLE_PAGE;
+use crate::process::{ProcessId, PROCESSES};
+use crate::scheduler;
+use crate::syscall::{Error, UserContext};
+use crate::tmpfs;
+use crate::vfs::{Op, Reply, Request, REPLY_SIZE};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp;
+use core::ops::Range;
+use spin::Mutex;
+use x86_64::PhysAddr;
+
+lazy_static::lazy_static! {
+    static ref OBJECTS: Mutex<Objects> = Mutex::new(Objects::default());
+}
+
+#[derive(Debug, Default)]
+struct Objects {
+    objects: BTreeMap<u64, Arc<Mutex<Object>>>,
+    next: u64,
+}
+
+#[derive(Debug)]
+struct Object {
+    /// The process which created the object. Only it may map or release the object, and the
+    /// object's file is accessed on its behalf.
+    creator: ProcessId,
+    source: Source,
+    /// The pages which are in memory, by their number within the object
+    pages: BTreeMap<u64, CachedPage>,
+    /// The pages which are being read from a server, and the processes which faulted on them
+    loading: BTreeMap<u64, Vec<Waiter>>,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Source {
+    /// Zeroed memory of a fixed number of pages
+    Anonymous { pages: u64 },
+    /// A file which the creator has open on the tmpfs
+    Tmpfs { handle: u64 },
+    /// A file which the creator has open on a filesystem which a process serves
+    Server { endpoint: u64, handle: u64 },
+}
+
+#[derive(Debug)]
+struct CachedPage {
+    frame: Arc<Frame>,
+    /// How many bytes of the page lie within the file, and so are written back
+    len: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Waiter {
+    pid: ProcessId,
+    block_id: u64,
+    /// The user's `rax`, which must be left as it was when the process resumes after the fault
+    rax: u64,
+}
+
+/// A range of a process's pages which an object backs
+#[derive(Debug, Clone)]
+struct Mapping {
+    /// The number of the first page
+    first: u64,
+    pages: u64,
+    object: Arc<Mutex<Object>>,
+    /// The page of the object which the first page maps
+    offset: u64,
+    flags: EntryFlags,
+}
+
+impl Mapping {
+    fn end(&self) -> u64 {
+        self.first + self.pages
+    }
+
+    /// The part of the mapping which lies within a range of page numbers
+    fn clip(&self, start: u64, end: u64) -> Option<Mapping> {
+        let first = cmp::max(self.first, start);
+        let last = cmp::min(self.end(), end);
+
+        if first >= last {
+            return None;
+        }
+
+        Some(Mapping {
+            first,
+            pages: last - first,
+            object: self.object.clone(),
+            offset: self.offset + (first - self.first),
+            flags: self.flags,
+        })
+    }
+}
+
+/// The objects which are mapped into a process
+#[derive(Debug, Default)]
+pub struct Mappings(Vec<Mapping>);
+
+impl Mappings {
+    fn find(&self, page: u64) -> Option<&Mapping> {
+        self.0
+            .iter()
+            .find(|mapping| mapping.first <= page && page < mapping.end())
+    }
+
+    /// The parts of the mappings which lie within a range of page numbers
+    fn within(&self, start: u64, end: u64) -> Vec<Mapping> {
+        self.0
+            .iter()
+            .filter_map(|mapping| mapping.clip(start, end))
+            .collect()
+    }
+
+    /// Removes a range of page numbers from the mappings, splitting those which it cuts through.
+    /// Returns the parts which were removed.
+    fn remove(&mut self, start: u64, end: u64) -> Vec<Mapping> {
+        let mut removed = Vec::new();
+        let mut kept = Vec::new();
+
+        for mapping in self.0.drain(..) {
+            match mapping.clip(start, end) {
+                Some(inner) => {
+                    removed.push(inner);
+                    kept.extend(mapping.clip(0, start));
+                    kept.extend(mapping.clip(end, u64::max_value()));
+                }
+                None => kept.push(mapping),
+            }
+        }
+
+        self.0 = kept;
+        removed
+    }
+}
+
+/// Why a page fault could not be handled
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FaultError {
+    NotMapped,
+    ReadOnly,
+    OutOfMemory,
+    /// The object's file could not provide the page, e.g because it has been closed
+    Unavailable,
+}
+
+/// The state of the write back of a process's dirty pages, which it is blocked on
+struct Writeback {
+    pid: ProcessId,
+    block_id: u64,
+    remaining: usize,
+    failed: bool,
+}
+
+fn insert(object: Object) -> u64 {
+    let mut objects = OBJECTS.lock();
+    objects.next += 1;
+
+    let id = objects.next;
+    objects.objects.insert(id, Arc::new(Mutex::new(object)));
+    id
+}
+
+/// Creates an object of zeroed memory. Returns its id.
+pub fn create(creator: ProcessId, pages: u64) -> Result<u64, Error> {
+    if pages == 0 {
+        return Err(Error::InvalidPagesLength);
+    }
+
+    Ok(insert(Object {
+        creator,
+        source: Source::Anonymous { pages },
+        pages: BTreeMap::new(),
+        loading: BTreeMap::new(),
+    }))
+}
+
+/// Creates an object for a file which the process has open, on the tmpfs or on a filesystem which
+/// another process serves. Each call creates a new object, with its own cache. Returns its id.
+pub fn from_file(creator: ProcessId, endpoint: u64, handle: u64) -> Result<u64, Error> {
+    let source = if tmpfs::is_endpoint(endpoint) {
+        Source::Tmpfs { handle }
+    } else if !ipc::exists(endpoint) {
+        return Err(Error::NotFound);
+    } else if ipc::is_owner(endpoint, creator) {
+        // The server would be blocked on its own page faults, so could never answer them
+        return Err(Error::InvalidArgument);
+    } else {
+        Source::Server { endpoint, handle }
+    };
+
+    Ok(insert(Object {
+        creator,
+        source,
+        pages: BTreeMap::new(),
+        loading: BTreeMap::new(),
+    }))
+}
+
+/// Releases an object which the process created. It lives on until it is no longer mapped.
+pub fn release(pid: ProcessId, id: u64) -> Result<(), Error> {
+    let mut objects = OBJECTS.lock();
+
+    match objects.objects.get(&id) {
+        Some(object) if object.lock().creator == pid => (),
+        Some(_) => return Err(Error::PermissionDenied),
+        None => return Err(Error::NotFound),
+    }
+
+    objects.objects.remove(&id);
+    Ok(())
+}
+
+/// Releases every object which an exiting process created
+pub fn release_all(pid: ProcessId) {
+    let mut objects = OBJECTS.lock();
+    let created: Vec<u64> = objects
+        .objects
+        .iter()
+        .filter(|(_, object)| object.lock().creator == pid)
+        .map(|(id, _)| *id)
+        .collect();
+
+    for id in created {
+        objects.objects.remove(&id);
+    }
+}
+
+/// Maps pages of an object into the current process, starting at the given page number. `offset`
+/// is the page of the object which the first page maps. The pages are faulted in when they are
+/// first accessed.
+pub fn map(first: u64, pages: u64, flags: EntryFlags, id: u64, offset: u64) -> Result<(), Error> {
+    let pid = scheduler::current().expect("No process is running");
+    let object = OBJECTS.lock().objects.get(&id).cloned();
+    let object = object.ok_or(Error::NotFound)?;
+
+    let end = first.checked_add(pages).ok_or(Error::InvalidPage)?;
+    let object_end = offset.checked_add(pages).ok_or(Error::InvalidArgument)?;
+
+    {
+        let object = object.lock();
+
+        if object.creator != pid {
+            return Err(Error::PermissionDenied);
+        }
+
+        match object.source {
+            Source::Anonymous { pages } if object_end > pages => {
+                return Err(Error::InvalidArgument)
+            }
+            Source::Tmpfs { handle } => {
+                let writable = flags.contains(EntryFlags::WRITABLE);
+                tmpfs::check_mapping(pid, handle, offset, pages, writable)?;
+            }
+            _ => (),
+        }
+    }
+
+    let range =
+        Page::containing_address(first * 0x1000)..=Page::containing_address((end - 1) * 0x1000);
+    let res = ACTIVE_PAGE_TABLES.lock().check_user_unmapped(range);
+
+    if res.is_err() {
+        return Err(Error::InvalidPage);
+    }
+
+    let mut process = PROCESSES.get_mut(&pid).unwrap();
+    let mappings = process.mappings();
+
+    if !mappings.within(first, end).is_empty() {
+        return Err(Error::InvalidPage);
+    }
+
+    mappings.0.push(Mapping {
+        first,
+        pages,
+        object,
+        offset,
+        flags,
+    });
+
+    Ok(())
+}
+
+/// Handles a fault on a page which is not present in the current process, by mapping the page of
+/// the object which backs it. If the page has to be read from a server, the process is blocked
+/// with the context until it has been, and then resumes from it, faulting on the page again.
+pub fn fault(context: &UserContext, addr: u64, write: bool) -> Result<(), FaultError> {
+    let pid = scheduler::current().expect("No process is running");
+    let page = addr / FRAME_SIZE as u64;
+
+    let (object, object_page, flags) = {
+        let mut process = PROCESSES.get_mut(&pid).unwrap();
+        let mapping = process.mappings().find(page).ok_or(FaultError::NotMapped)?;
+
+        let object_page = mapping.offset + (page - mapping.first);
+        (mapping.object.clone(), object_page, mapping.flags)
+    };
+
+    if write && !flags.contains(EntryFlags::WRITABLE) {
+        return Err(FaultError::ReadOnly);
+    }
+
+    let frame = match load(&object, object_page)? {
+        Some(frame) => frame,
+        None => wait_for_page(context, object, object_page),
+    };
+
+    // SAFETY: we are in the process's page tables, and the object keeps the frame for as long as
+    // it is mapped
+    let res = unsafe {
+        ACTIVE_PAGE_TABLES.lock().map_to(
+            Page::containing_address(page * FRAME_SIZE as u64),
+            PhysAddr::new(frame.physical()),
+            flags | EntryFlags::BORROWED,
+            InvalidateTlb::Invalidate,
+        )
+    };
+
+    res.map_err(|_| FaultError::OutOfMemory)
+}
+
+/// Returns the frame of a page of an object, bringing it into the cache. Returns None if it is
+/// being read from the object's server.
+fn load(object: &Arc<Mutex<Object>>, page: u64) -> Result<Option<Arc<Frame>>, FaultError> {
+    let mut locked = object.lock();
+
+    if let Some(cached) = locked.pages.get(&page) {
+        return Ok(Some(cached.frame.clone()));
+    }
+
+    let frame = match locked.source {
+        Source::Anonymous { .. } => Arc::new(Frame::allocate().ok_or(FaultError::OutOfMemory)?),
+        Source::Tmpfs { handle } => tmpfs::frame(locked.creator, handle, page, false)
+            .map_err(|_| FaultError::Unavailable)?,
+        Source::Server { endpoint, handle } => {
+            if !locked.loading.contains_key(&page) {
+                let frame = Frame::allocate().ok_or(FaultError::OutOfMemory)?;
+                let request = Request {
+                    handle,
+                    offset: page * FRAME_SIZE as u64,
+                    len: FRAME_SIZE as u64,
+                    ..Request::new(Op::Read)
+                };
+
+                let on_reply = read_reply(object.clone(), page, frame);
+                ipc::call_from_kernel(endpoint, locked.creator, request.encode(&[]), on_reply)
+                    .map_err(|_| FaultError::Unavailable)?;
+                locked.loading.insert(page, Vec::new());
+            }
+
+            return Ok(None);
+        }
+    };
+
+    let cached = CachedPage {
+        frame: frame.clone(),
+        len: FRAME_SIZE,
+    };
+    locked.pages.insert(page, cached);
+    Ok(Some(frame))
+}
+
+/// Blocks the current process until a page which is being read from a server is in the cache
+fn wait_for_page(context: &UserContext, object: Arc<Mutex<Object>>, page: u64) -> ! {
+    let rax = context.rax;
+
+    scheduler::block_current_on(context, None, move |pid, block_id| {
+        let waiter = Waiter { pid, block_id, rax };
+        let mut locked = object.lock();
+
+        match locked.loading.get_mut(&page) {
+            Some(waiters) => waiters.push(waiter),
+            None => {
+                drop(locked);
+                scheduler::wake(pid, block_id, rax as i64);
+            }
+        }
+    })
+}
+
+/// Fills the frame with a page which was read from a server, caches it, and wakes the processes
+/// waiting for it. Pages which couldn't be read are left zeroed and are never written back.
+fn read_reply(object: Arc<Mutex<Object>>, page: u64, frame: Frame) -> ipc::ReplyHandler {
+    Box::new(move |reply| {
+        let len = match Reply::decode(reply) {
+            Some(header) if header.status >= 0 => {
+                let data = &reply[REPLY_SIZE..];
+                let len = cmp::min(header.value as usize, cmp::min(data.len(), FRAME_SIZE));
+
+                frame.with_bytes(|bytes| bytes[..len].copy_from_slice(&data[..len]));
+                len
+            }
+            _ => {
+                warn!("page_cache: failed to read page {} of a file", page);
+                0
+            }
+        };
+
+        let waiters = {
+            let mut locked = object.lock();
+            let cached = CachedPage {
+                frame: Arc::new(frame),
+                len,
+            };
+
+            locked.pages.insert(page, cached);
+            locked.loading.remove(&page).unwrap_or_default()
+        };
+
+        for waiter in waiters {
+            scheduler::wake(waiter.pid, waiter.block_id, waiter.rax as i64);
+        }
+    })
+}
+
+/// The parts of the current process's mappings within a range of pages, which must all be mapped
+/// from objects
+fn mappings_within(pid: ProcessId, first: u64, pages: u64) -> Result<Vec<Mapping>, Error> {
+    let end = first.checked_add(pages).ok_or(Error::InvalidPage)?;
+    let within = PROCESSES
+        .get_mut(&pid)
+        .unwrap()
+        .mappings()
+        .within(first, end);
+
+    if within.iter().map(|mapping| mapping.pages).sum::<u64>() != pages {
+        return Err(Error::InvalidPage);
+    }
+
+    Ok(within)
+}
+
+/// The pages within a range which no object mapping covers, as ranges of page numbers
+fn gaps(within: &[Mapping], start: u64, end: u64) -> Vec<Range<u64>> {
+    let mut covered: Vec<Range<u64>> = within
+        .iter()
+        .map(|mapping| mapping.first..mapping.end())
+        .collect();
+    covered.sort_by_key(|range| range.start);
+
+    let mut gaps = Vec::new();
+    let mut next = start;
+
+    for range in covered {
+        if range.start > next {
+            gaps.push(next..range.start);
+        }
+
+        next = cmp::max(next, range.end);
+    }
+
+    if next < end {
+        gaps.push(next..end);
+    }
+
+    gaps
+}
+
+/// Unmaps pages of the current process. Pages which were mapped from objects have those which are
+/// dirty written back. If there are any, the process is blocked until they have been written back,
+/// and the system call fails with `IoError` if any of them couldn't be. Other pages must be memory
+/// which the process owns, e.g from `Map`, whose frames are freed.
+pub fn unmap(context: &UserContext, first: u64, pages: u64) -> Result<(), Error> {
+    let pid = scheduler::current().expect("No process is running");
+    let end = first.checked_add(pages).ok_or(Error::InvalidPage)?;
+
+    if end > LAST_USABLE_PAGE.number() as u64 + 1 {
+        return Err(Error::InvalidPage);
+    }
+
+    let within = PROCESSES
+        .get_mut(&pid)
+        .unwrap()
+        .mappings()
+        .within(first, end);
+
+    // Every other page must be mapped to a frame of the process's own, rather than one which it
+    // borrows, such as a device's memory
+    let mut owned = Vec::new();
+
+    {
+        let tables = ACTIVE_PAGE_TABLES.lock();
+
+        for gap in gaps(&within, first, end) {
+            for number in gap {
+                let page = Page::containing_address(number * 0x1000);
+
+                match tables.walk_page_table(page) {
+                    Some((entry, _)) if !entry.flags().contains(EntryFlags::BORROWED) => {
+                        owned.push(page)
+                    }
+                    _ => return Err(Error::InvalidPage),
+                }
+            }
+        }
+    }
+
+    let removed = PROCESSES
+        .get_mut(&pid)
+        .unwrap()
+        .mappings()
+        .remove(first, end);
+    let mut dirty = Vec::new();
+
+    {
+        let mut tables = ACTIVE_PAGE_TABLES.lock();
+
+        for page in owned {
+            // SAFETY: we are in the process's page tables, and the frame is the process's own
+            unsafe { tables.unmap(page, FreeMemory::Free, InvalidateTlb::Invalidate) };
+        }
+
+        for mapping in removed {
+            for i in 0..mapping.pages {
+                let page = Page::containing_address((mapping.first + i) * 0x1000);
+
+                let entry = match tables.walk_page_table(page) {
+                    Some((entry, _)) => entry,
+                    None => continue,
+                };
+
+                if entry.flags().contains(EntryFlags::DIRTY) {
+                    dirty.push((mapping.object.clone(), mapping.offset + i));
+                }
+
+                // SAFETY: we are in the process's page tables. The frame belongs to the object.
+                unsafe { tables.unmap(page, FreeMemory::NoFree, InvalidateTlb::Invalidate) };
+            }
+        }
+    }
+
+    write_back(context, dirty)
+}
+
+/// Writes back the dirty pages of the current process which were mapped from objects, like
+/// [unmap], but keeps them mapped
+pub fn sync(context: &UserContext, first: u64, pages: u64) -> Result<(), Error> {
+    let pid = scheduler::current().expect("No process is running");
+    let within = mappings_within(pid, first, pages)?;
+    let mut dirty = Vec::new();
+
+    {
+        let mut tables = ACTIVE_PAGE_TABLES.lock();
+
+        for mapping in within {
+            for i in 0..mapping.pages {
+                let page = Page::containing_address((mapping.first + i) * 0x1000);
+
+                let entry = match tables.walk_page_table(page) {
+                    Some((entry, _)) if entry.flags().contains(EntryFlags::DIRTY) => entry,
+                    _ => continue,
+                };
+
+                let flags = entry.flags() - EntryFlags::DIRTY;
+                let physical = entry.physical_address().unwrap();
+
+                // SAFETY: we are in the process's page tables, and the page stays mapped to the
+                // same frame
+                unsafe {
+                    tables
+                        .map_to(page, physical, flags, InvalidateTlb::Invalidate)
+                        .unwrap()
+                };
+
+                dirty.push((mapping.object.clone(), mapping.offset + i));
+            }
+        }
+    }
+
+    write_back(context, dirty)
+}
+
+/// A write of a dirty page back to its file's server: the endpoint, the process which it is made
+/// for, the request, and the length which should be written
+type Write = (u64, ProcessId, Vec<u8>, usize);
+
+/// The writes of dirty pages of files back to their servers. Pages of other objects share their
+/// memory with their source, so need no writing back.
+fn writes(dirty: Vec<(Arc<Mutex<Object>>, u64)>) -> Vec<Write> {
+    let mut writes = Vec::new();
+
+    for (object, page) in dirty {
+        let locked = object.lock();
+
+        let (endpoint, handle) = match locked.source {
+            Source::Server { endpoint, handle } => (endpoint, handle),
+            _ => continue,
+        };
+
+        let cached = match locked.pages.get(&page) {
+            Some(cached) if cached.len > 0 => cached,
+            _ => continue,
+        };
+
+        let data = cached
+            .frame
+            .with_bytes(|bytes| bytes[..cached.len].to_vec());
+        let request = Request {
+            handle,
+            offset: page * FRAME_SIZE as u64,
+            len: cached.len as u64,
+            ..Request::new(Op::Write)
+        };
+
+        writes.push((endpoint, locked.creator, request.encode(&data), cached.len));
+    }
+
+    writes
+}
+
+/// Whether a reply to a write says that all of it was written
+fn is_written(reply: &[u8], len: usize) -> bool {
+    match Reply::decode(reply) {
+        Some(header) => header.status >= 0 && header.value == len as u64,
+        None => false,
+    }
+}
+
+/// Writes dirty pages of files back to their servers, blocking the current process until they have
+/// been
+fn write_back(context: &UserContext, dirty: Vec<(Arc<Mutex<Object>>, u64)>) -> Result<(), Error> {
+    let writes = writes(dirty);
+
+    if writes.is_empty() {
+        return Ok(());
+    }
+
+    scheduler::block_current_on(context, None, move |pid, block_id| {
+        let writeback = Arc::new(Mutex::new(Writeback {
+            pid,
+            block_id,
+            remaining: writes.len(),
+            failed: false,
+        }));
+
+        for (endpoint, sender, request, len) in writes {
+            let state = writeback.clone();
+            let on_reply =
+                Box::new(move |reply: &[u8]| finish_write(&state, is_written(reply, len)));
+
+            if ipc::call_from_kernel(endpoint, sender, request, on_reply).is_err() {
+                finish_write(&writeback, false);
+            }
+        }
+    })
+}
+
+/// Records that a page has been written back, waking its process once it was the last one
+fn finish_write(writeback: &Mutex<Writeback>, written: bool) {
+    let mut state = writeback.lock();
+    state.failed |= !written;
+    state.remaining -= 1;
+
+    if state.remaining == 0 {
+        let result = if state.failed {
+            Error::IoError as i64
+        } else {
+            0
+        };
+        let (pid, block_id) = (state.pid, state.block_id);
+
+        drop(state);
+        scheduler::wake(pid, block_id, result);
+    }
+}
+
+/// Writes back the dirty pages of files which an exiting process had mapped, as unmapping them
+/// would have. Nothing waits for the writes, so pages which can't be written are only logged.
+///
+/// This must be called before the process's page tables are freed.
+pub fn write_back_on_exit(page_tables: &InactivePageMap, mappings: &Mappings) {
+    let mut dirty = Vec::new();
+
+    // The dirty bits are in the process's page tables, which are switched to while reading them
+    let _ = ACTIVE_PAGE_TABLES
+        .lock()
+        .with_inactive::<_, ()>(page_tables.clone(), |tables| {
+            dirty = dirty_file_pages(tables, &mappings.0);
+            Ok(())
+        });
+
+    for (endpoint, sender, request, len) in writes(dirty) {
+        let on_reply = Box::new(move |reply: &[u8]| {
+            if !is_written(reply, len) {
+                warn!("page_cache: failed to write back a page of an exited process's file");
+            }
+        });
+
+        if ipc::call_from_kernel(endpoint, sender, request, on_reply).is_err() {
+            warn!("page_cache: failed to write back a page of an exited process's file");
+        }
+    }
+}
+
+/// The pages of files which processes serve, within the mappings, which are dirty in the active
+/// page tables
+fn dirty_file_pages(
+    tables: &ActivePageMap,
+    mappings: &[Mapping],
+) -> Vec<(Arc<Mutex<Object>>, u64)> {
+    let mut dirty = Vec::new();
+
+    for mapping in mappings {
+        match mapping.object.lock().source {
+            Source::Server { .. } => (),
+            _ => continue,
+        }
+
+        for i in 0..mapping.pages {
+            let page = Page::containing_address((mapping.first + i) * 0x1000);
+
+            match tables.walk_page_table(page) {
+                Some((entry, _)) if entry.flags().contains(EntryFlags::DIRTY) => {
+                    dirty.push((mapping.object.clone(), mapping.offset + i));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    dirty
+}
+
+/// Drops frames which truncating tmpfs files cut off from every object which cached them, and
+/// unmaps them from the processes which mapped them, so that nothing can be read or written through
+/// them once they no longer belong to the file. Touching those pages again faults like touching any
+/// other page past the end of the file.
+///
+/// This must not be called with the tmpfs locked, as loading a page locks it under its object.
+pub fn evict_truncated(frames: &[Arc<Frame>]) {
+    if frames.is_empty() {
+        return;
+    }
+
+    let is_truncated =
+        |cached: &CachedPage| frames.iter().any(|frame| Arc::ptr_eq(frame, &cached.frame));
+    let mut objects: Vec<Arc<Mutex<Object>>> = OBJECTS.lock().objects.values().cloned().collect();
+
+    for mut process in PROCESSES.iter_mut() {
+        let mut truncated = Vec::new();
+
+        for mapping in &process.mappings().0 {
+            let object = mapping.object.lock();
+
+            match object.source {
+                Source::Tmpfs { .. } => (),
+                _ => continue,
+            }
+
+            let within = mapping.offset..mapping.offset + mapping.pages;
+
+            for (page, cached) in object.pages.range(within) {
+                if is_truncated(cached) {
+                    let number = mapping.first + (page - mapping.offset);
+                    truncated.push(Page::containing_address(number * 0x1000));
+                }
+            }
+
+            // Released objects are only kept alive by their mappings
+            objects.push(mapping.object.clone());
+        }
+
+        if !truncated.is_empty() {
+            unmap_borrowed(process.page_tables.clone(), truncated);
+        }
+    }
+
+    for object in objects {
+        let mut object = object.lock();
+        let evicted: Vec<u64> = object
+            .pages
+            .iter()
+            .filter(|(_, cached)| is_truncated(cached))
+            .map(|(page, _)| *page)
+            .collect();
+
+        for page in evicted {
+            object.pages.remove(&page);
+        }
+    }
+}
+
+/// Unmaps pages whose frames a process doesn't own from its page tables, leaving the frames be
+fn unmap_borrowed(page_tables: InactivePageMap, pages: Vec<Page>) {
+    let _ = ACTIVE_PAGE_TABLES
+        .lock()
+        .with_inactive::<_, ()>(page_tables, |tables| {
+            for page in pages {
+                if tables.walk_page_table(page).is_some() {
+                    // SAFETY: we are in the process's page tables, and the frame isn't freed
+                    unsafe { tables.unmap(page, FreeMemory::NoFree, InvalidateTlb::Invalidate) };
+                }
+            }
+
+            Ok(())
+        });
+}
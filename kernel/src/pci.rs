@@ -0,0 +1,364 @@
+//! PCI and PCIe bus enumeration. Devices are found once at boot and can then be claimed by a
+//! userspace driver, which is granted access to their BARs and IRQ.
+
+use crate::process::ProcessId;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::{fmt, mem, slice};
+use spin::{Mutex, Once};
+
+pub mod config;
+pub mod device;
+pub mod msi;
+
+pub use self::device::{Bar, CommandFlags, PciDevice};
+
+const CLASS_BRIDGE: u8 = 0x06;
+const SUBCLASS_PCI_BRIDGE: u8 = 0x04;
+
+static DEVICES: Once<Vec<PciDevice>> = Once::new();
+
+lazy_static::lazy_static! {
+    /// Who has claimed each device
+    static ref OWNERS: Mutex<BTreeMap<PciAddress, Owner>> = Mutex::new(BTreeMap::new());
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Owner {
+    /// The device is driven by the kernel, e.g a virtio disk
+    Kernel,
+    Process(ProcessId),
+}
+
+/// The location of a PCI function
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct PciAddress {
+    pub segment: u16,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciAddress {
+    pub const fn new(segment: u16, bus: u8, device: u8, function: u8) -> PciAddress {
+        PciAddress {
+            segment,
+            bus,
+            device,
+            function,
+        }
+    }
+
+    /// Unpacks an address passed by userspace, laid out as `segment:16 bus:8 device:5 function:3`
+    pub fn from_u64(v: u64) -> Option<PciAddress> {
+        if v > u32::max_value() as u64 {
+            return None;
+        }
+
+        Some(PciAddress {
+            segment: (v >> 16) as u16,
+            bus: (v >> 8) as u8,
+            device: (v >> 3) as u8 & 0b11111,
+            function: v as u8 & 0b111,
+        })
+    }
+
+    /// Packs the address to be passed to userspace. The inverse of `from_u64`.
+    pub fn to_u32(self) -> u32 {
+        (self.segment as u32) << 16
+            | (self.bus as u32) << 8
+            | (self.device as u32) << 3
+            | self.function as u32
+    }
+}
+
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}:{:02x}:{:02x}.{}",
+            self.segment, self.bus, self.device, self.function
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ClaimError {
+    NoSuchDevice,
+    /// The device has already been claimed by another process
+    AlreadyClaimed,
+}
+
+/// Finds all PCI devices. The ACPI tables should have been found beforehand, so that PCIe
+/// enhanced configuration access can be used.
+pub fn init() {
+    info!("pci: initializing");
+    config::init();
+
+    let mut devices = Vec::new();
+    let mut scanned_buses = BTreeSet::new();
+
+    for (segment, bus) in config::root_buses() {
+        scan_root(segment, bus, &mut scanned_buses, &mut devices);
+    }
+
+    // For when log_level != debug | trace
+    #[allow(unused_variables)]
+    for device in &devices {
+        debug!(
+            "pci: {} {:04x}:{:04x} class {:02x}:{:02x}:{:02x}",
+            device.address,
+            device.vendor_id,
+            device.device_id,
+            device.class,
+            device.subclass,
+            device.prog_if,
+        );
+    }
+
+    info!("pci: found {} functions", devices.len());
+    DEVICES.call_once(|| devices);
+}
+
+/// Returns all PCI functions, or none if they have not been enumerated yet
+pub fn devices() -> &'static [PciDevice] {
+    DEVICES.wait().map(Vec::as_slice).unwrap_or(&[])
+}
+
+pub fn device(address: PciAddress) -> Option<&'static PciDevice> {
+    devices().iter().find(|device| device.address == address)
+}
+
+/// Gives the process exclusive ownership of the device. Claiming a device twice is allowed.
+pub fn claim(address: PciAddress, pid: ProcessId) -> Result<&'static PciDevice, ClaimError> {
+    claim_for(address, Owner::Process(pid))
+}
+
+/// Claims the device for a driver in the kernel, so that no process can claim it
+pub fn claim_for_kernel(address: PciAddress) -> Result<&'static PciDevice, ClaimError> {
+    claim_for(address, Owner::Kernel)
+}
+
+fn claim_for(address: PciAddress, new_owner: Owner) -> Result<&'static PciDevice, ClaimError> {
+    let device = device(address).ok_or(ClaimError::NoSuchDevice)?;
+    let mut owners = OWNERS.lock();
+
+    match owners.get(&address) {
+        Some(owner) if *owner != new_owner => Err(ClaimError::AlreadyClaimed),
+        _ => {
+            owners.insert(address, new_owner);
+            Ok(device)
+        }
+    }
+}
+
+/// Returns the process which has claimed the device, if any
+pub fn owner(address: PciAddress) -> Option<ProcessId> {
+    match OWNERS.lock().get(&address) {
+        Some(Owner::Process(pid)) => Some(*pid),
+        _ => None,
+    }
+}
+
+/// Releases the devices which an exiting process claimed, stopping them from accessing memory
+/// directly, as the process's memory is freed
+pub fn release(pid: ProcessId) {
+    let mut owners = OWNERS.lock();
+    let claimed: Vec<PciAddress> = owners
+        .iter()
+        .filter(|(_, owner)| **owner == Owner::Process(pid))
+        .map(|(address, _)| *address)
+        .collect();
+
+    for address in claimed {
+        let device = device(address).unwrap();
+        device.set_command(device.command() - CommandFlags::BUS_MASTER);
+        owners.remove(&address);
+    }
+}
+
+/// Whether a device which the process has claimed raises the given legacy IRQ
+pub fn raises_irq(pid: ProcessId, irq: u8) -> bool {
+    OWNERS
+        .lock()
+        .iter()
+        .filter(|(_, owner)| **owner == Owner::Process(pid))
+        .filter_map(|(address, _)| device(*address))
+        .any(|device| device.irqs().contains(&irq))
+}
+
+/// Scans the buses below a host bridge. If the host bridge is multifunction, then there are
+/// several host controllers, and each function's bus number is its function number.
+fn scan_root(
+    segment: u16,
+    bus: u8,
+    scanned_buses: &mut BTreeSet<(u16, u8)>,
+    devices: &mut Vec<PciDevice>,
+) {
+    let host_bridge = PciAddress::new(segment, bus, 0, 0);
+    let header_type = config::read::<u8>(host_bridge, device::HEADER_TYPE);
+
+    if header_type & device::HEADER_TYPE_MULTIFUNCTION == 0 {
+        scan_bus(segment, bus, scanned_buses, devices);
+        return;
+    }
+
+    for function in 0..8 {
+        let address = PciAddress::new(segment, bus, 0, function);
+        if config::read::<u16>(address, device::VENDOR_ID) != device::VENDOR_NONE {
+            scan_bus(segment, bus.wrapping_add(function), scanned_buses, devices);
+        }
+    }
+}
+
+fn scan_bus(
+    segment: u16,
+    bus: u8,
+    scanned_buses: &mut BTreeSet<(u16, u8)>,
+    devices: &mut Vec<PciDevice>,
+) {
+    // Misconfigured bridges could otherwise make us loop forever
+    if !scanned_buses.insert((segment, bus)) {
+        return;
+    }
+
+    for device in 0..32 {
+        let first = match PciDevice::read(PciAddress::new(segment, bus, device, 0)) {
+            Some(first) => first,
+            None => continue,
+        };
+
+        let functions = if first.is_multifunction() { 1..8 } else { 1..1 };
+        scan_function(first, scanned_buses, devices);
+
+        for function in functions {
+            if let Some(found) = PciDevice::read(PciAddress::new(segment, bus, device, function)) {
+                scan_function(found, scanned_buses, devices);
+            }
+        }
+    }
+}
+
+fn scan_function(
+    function: PciDevice,
+    scanned_buses: &mut BTreeSet<(u16, u8)>,
+    devices: &mut Vec<PciDevice>,
+) {
+    let address = function.address;
+    let is_bridge = function.class == CLASS_BRIDGE
+        && function.subclass == SUBCLASS_PCI_BRIDGE
+        && function.is_pci_bridge();
+
+    devices.push(function);
+
+    if is_bridge {
+        let secondary_bus = config::read::<u8>(address, device::SECONDARY_BUS);
+        scan_bus(address.segment, secondary_bus, scanned_buses, devices);
+    }
+}
+
+/// Describes a PCI function to userspace. Kept free of padding, as it is copied out byte-for-byte.
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct PciDeviceInfo {
+    /// Packed as by `PciAddress::to_u32`
+    pub address: u32,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    /// The legacy IRQ routed to the function, or 0xff if it has none
+    pub interrupt_line: u8,
+    pub interrupt_pin: u8,
+    _reserved: [u8; 2],
+    pub bars: [BarInfo; 6],
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct BarInfo {
+    pub address: u64,
+    pub size: u64,
+    pub kind: u32,
+    pub flags: u32,
+}
+
+impl BarInfo {
+    pub const KIND_NONE: u32 = 0;
+    pub const KIND_MEMORY: u32 = 1;
+    pub const KIND_IO: u32 = 2;
+
+    pub const FLAG_PREFETCHABLE: u32 = 1;
+    pub const FLAG_64_BIT: u32 = 1 << 1;
+}
+
+impl PciDeviceInfo {
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: repr(C) without any padding
+        unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<Self>()) }
+    }
+}
+
+impl From<&PciDevice> for PciDeviceInfo {
+    fn from(device: &PciDevice) -> Self {
+        let mut bars = [BarInfo::default(); 6];
+
+        for (info, bar) in bars.iter_mut().zip(device.bars.iter()) {
+            *info = match *bar {
+                Some(Bar::Memory {
+                    address,
+                    size,
+                    prefetchable,
+                    is_64_bit,
+                }) => {
+                    let mut flags = 0;
+
+                    if prefetchable {
+                        flags |= BarInfo::FLAG_PREFETCHABLE;
+                    }
+
+                    if is_64_bit {
+                        flags |= BarInfo::FLAG_64_BIT;
+                    }
+
+                    BarInfo {
+                        address,
+                        size,
+                        kind: BarInfo::KIND_MEMORY,
+                        flags,
+                    }
+                }
+                Some(Bar::Io { port, size }) => BarInfo {
+                    address: port as u64,
+                    size: size as u64,
+                    kind: BarInfo::KIND_IO,
+                    flags: 0,
+                },
+                None => BarInfo {
+                    kind: BarInfo::KIND_NONE,
+                    ..BarInfo::default()
+                },
+            };
+        }
+
+        PciDeviceInfo {
+            address: device.address.to_u32(),
+            vendor_id: device.vendor_id,
+            device_id: device.device_id,
+            class: device.class,
+            subclass: device.subclass,
+            prog_if: device.prog_if,
+            revision: device.revision,
+            interrupt_line: if device.interrupt_pin != 0 {
+                device.interrupt_line
+            } else {
+                0xff
+            },
+            interrupt_pin: device.interrupt_pin,
+            _reserved: [0; 2],
+            bars,
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Access to PCI configuration space, either through the PCIe enhanced configuration access
+//! mechanism (ECAM) or the legacy configuration IO ports.
+//! From: [OsDev Wiki](https://wiki.osdev.org/PCI) and
+//! [OsDev Wiki](https://wiki.osdev.org/PCI_Express)
+
+use super::PciAddress;
+use crate::acpi_handler::mcfg;
+use crate::memory::physical_mapping::{self, PhysicalMapping};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+use core::{mem, ptr};
+use spin::{Mutex, Once};
+use x86_64::instructions::port::{Port, PortRead, PortWrite};
+
+const CONFIG_ADDRESS_PORT: u16 = 0xCF8;
+const CONFIG_DATA_PORT: u16 = 0xCFC;
+/// Set in the configuration address to enable the access
+const CONFIG_ADDRESS_ENABLE: u32 = 1 << 31;
+
+/// The legacy mechanism can only access the first 256 bytes of configuration space
+const LEGACY_CONFIG_SPACE_SIZE: u16 = 256;
+/// In ECAM, every function has 4KiB of configuration space, so every bus has 1MiB
+const ECAM_FUNCTION_SIZE: u16 = 4096;
+const ECAM_BUS_SIZE: u64 = 1 << 20;
+
+static ECAM_REGIONS: Once<Vec<EcamRegion>> = Once::new();
+/// Held while accessing configuration space through the legacy ports, as the address and data
+/// must be written one after the other
+static LEGACY_LOCK: Mutex<()> = Mutex::new(());
+
+/// A value which can be read from or written to configuration space
+pub trait ConfigValue: Copy + PortRead + PortWrite {
+    /// Read when there is no device at the address
+    const ALL_ONES: Self;
+}
+
+impl ConfigValue for u8 {
+    const ALL_ONES: u8 = 0xff;
+}
+
+impl ConfigValue for u16 {
+    const ALL_ONES: u16 = 0xffff;
+}
+
+impl ConfigValue for u32 {
+    const ALL_ONES: u32 = 0xffff_ffff;
+}
+
+/// The enhanced configuration space of a range of buses in a segment group
+struct EcamRegion {
+    base_address: u64,
+    segment: u16,
+    buses: RangeInclusive<u8>,
+    /// Buses are only mapped once they are first accessed, as mapping them all would take 256MiB
+    /// of address space. They are never unmapped.
+    mapped_buses: Mutex<BTreeMap<u8, BusMapping>>,
+}
+
+struct BusMapping(PhysicalMapping<u8>);
+
+// Safety: the mapping is never unmapped and is only accessed volatilely
+unsafe impl Send for BusMapping {}
+
+/// Reads the MCFG, if present, to find the enhanced configuration space
+pub fn init() {
+    let regions = match mcfg::entries() {
+        Some(entries) => entries
+            .iter()
+            .map(|entry| EcamRegion {
+                base_address: entry.base_address,
+                segment: entry.segment_group,
+                buses: entry.bus_start..=entry.bus_end,
+                mapped_buses: Mutex::new(BTreeMap::new()),
+            })
+            .collect(),
+        None => {
+            debug!("pci: no mcfg, using legacy configuration access");
+            Vec::new()
+        }
+    };
+
+    // For when log_level != debug | trace
+    #[allow(unused_variables)]
+    for region in &regions {
+        debug!(
+            "pci: ecam at 0x{:x} for segment {}, buses {}-{}",
+            region.base_address,
+            region.segment,
+            region.buses.start(),
+            region.buses.end()
+        );
+    }
+
+    ECAM_REGIONS.call_once(|| regions);
+}
+
+/// Returns the first bus of each segment group which can be accessed
+pub fn root_buses() -> Vec<(u16, u8)> {
+    match ECAM_REGIONS.wait() {
+        Some(regions) if !regions.is_empty() => regions
+            .iter()
+            .map(|region| (region.segment, *region.buses.start()))
+            .collect(),
+        _ => vec![(0, 0)],
+    }
+}
+
+/// Reads from the configuration space of the function at the given address. Returns all ones if
+/// the offset cannot be accessed.
+pub fn read<T: ConfigValue>(address: PciAddress, offset: u16) -> T {
+    assert_eq!(
+        offset as usize % mem::size_of::<T>(),
+        0,
+        "Unaligned configuration space access"
+    );
+
+    if let Some(ptr) = ecam_pointer::<T>(address, offset) {
+        // SAFETY: the pointer is into the mapped configuration space of the function
+        return unsafe { ptr::read_volatile(ptr) };
+    }
+
+    if address.segment != 0 || offset >= LEGACY_CONFIG_SPACE_SIZE {
+        return T::ALL_ONES;
+    }
+
+    let _guard = LEGACY_LOCK.lock();
+    unsafe {
+        Port::<u32>::new(CONFIG_ADDRESS_PORT).write(legacy_config_address(address, offset));
+        Port::<T>::new(CONFIG_DATA_PORT + (offset & 0b11)).read()
+    }
+}
+
+/// Writes to the configuration space of the function at the given address. Does nothing if the
+/// offset cannot be accessed.
+pub fn write<T: ConfigValue>(address: PciAddress, offset: u16, value: T) {
+    assert_eq!(
+        offset as usize % mem::size_of::<T>(),
+        0,
+        "Unaligned configuration space access"
+    );
+
+    if let Some(ptr) = ecam_pointer::<T>(address, offset) {
+        // SAFETY: the pointer is into the mapped configuration space of the function
+        unsafe { ptr::write_volatile(ptr, value) };
+        return;
+    }
+
+    if address.segment != 0 || offset >= LEGACY_CONFIG_SPACE_SIZE {
+        return;
+    }
+
+    let _guard = LEGACY_LOCK.lock();
+    unsafe {
+        Port::<u32>::new(CONFIG_ADDRESS_PORT).write(legacy_config_address(address, offset));
+        Port::<T>::new(CONFIG_DATA_PORT + (offset & 0b11)).write(value);
+    }
+}
+
+fn legacy_config_address(address: PciAddress, offset: u16) -> u32 {
+    CONFIG_ADDRESS_ENABLE
+        | (address.bus as u32) << 16
+        | (address.device as u32) << 11
+        | (address.function as u32) << 8
+        | (offset & 0xfc) as u32
+}
+
+/// Returns a pointer to the given offset in the function's enhanced configuration space, or `None`
+/// if it is not covered by the MCFG
+fn ecam_pointer<T>(address: PciAddress, offset: u16) -> Option<*mut T> {
+    if offset >= ECAM_FUNCTION_SIZE {
+        return None;
+    }
+
+    let region = ECAM_REGIONS
+        .wait()?
+        .iter()
+        .find(|region| region.segment == address.segment && region.buses.contains(&address.bus))?;
+
+    let mut mapped_buses = region.mapped_buses.lock();
+    let bus = mapped_buses.entry(address.bus).or_insert_with(|| {
+        // The base address is that of bus 0, even if the region starts at a later bus
+        let bus_address = region.base_address + address.bus as u64 * ECAM_BUS_SIZE;
+
+        // SAFETY: the MCFG describes this as configuration space
+        let mapping =
+            unsafe { physical_mapping::map_physical_region(bus_address, ECAM_BUS_SIZE, true) };
+        BusMapping(mapping)
+    });
+
+    let function_offset = (address.device as usize) << 15 | (address.function as usize) << 12;
+    let base = bus.0.deref_mut().unwrap() as *mut u8;
+
+    // SAFETY: the offset is within the bus's mapping
+    Some(unsafe { base.add(function_offset + offset as usize) as *mut T })
+}
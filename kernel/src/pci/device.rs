@@ -0,0 +1,376 @@
+//! Decoding of a PCI function's configuration header: its identification, base address registers
+//! and capabilities.
+
+use super::config;
+use super::PciAddress;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+pub const VENDOR_ID: u16 = 0x00;
+pub const DEVICE_ID: u16 = 0x02;
+pub const COMMAND: u16 = 0x04;
+pub const STATUS: u16 = 0x06;
+pub const REVISION_ID: u16 = 0x08;
+pub const PROG_IF: u16 = 0x09;
+pub const SUBCLASS: u16 = 0x0A;
+pub const CLASS: u16 = 0x0B;
+pub const HEADER_TYPE: u16 = 0x0E;
+pub const BAR_0: u16 = 0x10;
+pub const SECONDARY_BUS: u16 = 0x19;
+pub const CAPABILITIES_POINTER: u16 = 0x34;
+pub const INTERRUPT_LINE: u16 = 0x3C;
+pub const INTERRUPT_PIN: u16 = 0x3D;
+
+/// Read as the vendor id if there is no function at an address
+pub const VENDOR_NONE: u16 = 0xffff;
+
+/// Set in the header type if the device has more than one function
+pub const HEADER_TYPE_MULTIFUNCTION: u8 = 1 << 7;
+const HEADER_TYPE_MASK: u8 = 0x7f;
+const HEADER_TYPE_GENERAL: u8 = 0x00;
+const HEADER_TYPE_PCI_BRIDGE: u8 = 0x01;
+
+/// Set in the status register if the function has a capabilities list
+const STATUS_CAPABILITIES_LIST: u16 = 1 << 4;
+
+const BAR_IO_SPACE: u32 = 1;
+const BAR_MEMORY_TYPE_64: u32 = 0b10 << 1;
+const BAR_MEMORY_PREFETCHABLE: u32 = 1 << 3;
+
+const CLASS_MASS_STORAGE: u8 = 0x01;
+const SUBCLASS_IDE: u8 = 0x01;
+
+/// An IDE channel in compatibility mode uses the legacy ports and IRQ, rather than its BARs. The
+/// programming interface has a bit set for each channel in native mode.
+static IDE_CHANNELS: [IdeChannel; 2] = [
+    IdeChannel {
+        command: 0x1f0,
+        control: 0x3f6,
+        irq: 14,
+        native_mode: 1,
+    },
+    IdeChannel {
+        command: 0x170,
+        control: 0x376,
+        irq: 15,
+        native_mode: 1 << 2,
+    },
+];
+
+struct IdeChannel {
+    /// The first of the eight command block ports
+    command: u16,
+    /// The device control and alternate status port
+    control: u16,
+    irq: u8,
+    native_mode: u8,
+}
+
+pub const CAPABILITY_MSI: u8 = 0x05;
+pub const CAPABILITY_MSI_X: u8 = 0x11;
+/// Guards against malformed capability lists which loop. The list is in the first 256 bytes and
+/// each entry is at least 4 bytes long.
+const MAX_CAPABILITIES: usize = 48;
+
+const MSI_CONTROL_64_BIT: u16 = 1 << 7;
+const MSI_CONTROL_PER_VECTOR_MASKING: u16 = 1 << 8;
+const MSI_X_CONTROL_TABLE_SIZE_MASK: u16 = 0x7ff;
+const MSI_X_BIR_MASK: u32 = 0b111;
+
+bitflags::bitflags! {
+    pub struct CommandFlags: u16 {
+        /// Whether the function responds to accesses to its IO space BARs
+        const IO_SPACE = 1;
+        /// Whether the function responds to accesses to its memory space BARs
+        const MEMORY_SPACE = 1 << 1;
+        /// Whether the function may perform DMA
+        const BUS_MASTER = 1 << 2;
+        /// Disables the function's legacy (INTx) interrupts
+        const INTERRUPT_DISABLE = 1 << 10;
+    }
+}
+
+/// A base address register, describing a region of memory or IO space which the function decodes
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Bar {
+    Memory {
+        address: u64,
+        size: u64,
+        prefetchable: bool,
+        is_64_bit: bool,
+    },
+    Io {
+        port: u16,
+        size: u16,
+    },
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Capability {
+    pub id: u8,
+    /// The offset of the capability structure in configuration space
+    pub offset: u8,
+}
+
+/// The MSI capability of a function
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Msi {
+    pub offset: u8,
+    pub is_64_bit: bool,
+    pub per_vector_masking: bool,
+    /// The number of vectors the function can use, a power of two from 1 to 32
+    pub max_vectors: u8,
+}
+
+/// The MSI-X capability of a function
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MsiX {
+    pub offset: u8,
+    pub table_size: u16,
+    /// The BAR which holds the vector table
+    pub table_bar: u8,
+    /// The offset of the vector table into its BAR
+    pub table_offset: u32,
+    /// The BAR which holds the pending bit array
+    pub pending_bar: u8,
+    /// The offset of the pending bit array into its BAR
+    pub pending_offset: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct PciDevice {
+    pub address: PciAddress,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    pub header_type: u8,
+    /// The legacy IRQ the firmware routed the function's interrupt pin to
+    pub interrupt_line: u8,
+    /// The interrupt pin (INTA# to INTD#, 1 to 4) the function uses, or 0 if it uses none
+    pub interrupt_pin: u8,
+    pub bars: [Option<Bar>; 6],
+    pub capabilities: Vec<Capability>,
+    pub msi: Option<Msi>,
+    pub msi_x: Option<MsiX>,
+}
+
+impl PciDevice {
+    /// Reads the configuration header of the function at the given address, if there is one
+    pub fn read(address: PciAddress) -> Option<PciDevice> {
+        let vendor_id = config::read::<u16>(address, VENDOR_ID);
+        if vendor_id == VENDOR_NONE {
+            return None;
+        }
+
+        let header_type = config::read::<u8>(address, HEADER_TYPE);
+        let bar_count = match header_type & HEADER_TYPE_MASK {
+            HEADER_TYPE_GENERAL => 6,
+            HEADER_TYPE_PCI_BRIDGE => 2,
+            _ => 0,
+        };
+
+        let capabilities = read_capabilities(address);
+        let msi = capabilities
+            .iter()
+            .find(|cap| cap.id == CAPABILITY_MSI)
+            .map(|cap| read_msi(address, cap.offset));
+        let msi_x = capabilities
+            .iter()
+            .find(|cap| cap.id == CAPABILITY_MSI_X)
+            .map(|cap| read_msi_x(address, cap.offset));
+
+        Some(PciDevice {
+            address,
+            vendor_id,
+            device_id: config::read(address, DEVICE_ID),
+            class: config::read(address, CLASS),
+            subclass: config::read(address, SUBCLASS),
+            prog_if: config::read(address, PROG_IF),
+            revision: config::read(address, REVISION_ID),
+            header_type,
+            interrupt_line: config::read(address, INTERRUPT_LINE),
+            interrupt_pin: config::read(address, INTERRUPT_PIN),
+            bars: read_bars(address, bar_count),
+            capabilities,
+            msi,
+            msi_x,
+        })
+    }
+
+    pub fn is_multifunction(&self) -> bool {
+        self.header_type & HEADER_TYPE_MULTIFUNCTION != 0
+    }
+
+    pub fn is_pci_bridge(&self) -> bool {
+        self.header_type & HEADER_TYPE_MASK == HEADER_TYPE_PCI_BRIDGE
+    }
+
+    /// The legacy io ports which the function uses, besides its BARs. This is only the case for IDE
+    /// controllers in compatibility mode.
+    pub fn legacy_io_ports(&self) -> Vec<RangeInclusive<u16>> {
+        let mut ports = Vec::new();
+
+        for channel in self.compatibility_ide_channels() {
+            ports.push(channel.command..=channel.command + 7);
+            ports.push(channel.control..=channel.control);
+        }
+
+        ports
+    }
+
+    /// The legacy IRQs which the function raises: that its interrupt pin is routed to, and those
+    /// of its IDE channels in compatibility mode
+    pub fn irqs(&self) -> Vec<u8> {
+        let mut irqs: Vec<u8> = self
+            .compatibility_ide_channels()
+            .map(|channel| channel.irq)
+            .collect();
+
+        if self.interrupt_pin != 0 {
+            irqs.push(self.interrupt_line);
+        }
+
+        irqs
+    }
+
+    fn compatibility_ide_channels(&self) -> impl Iterator<Item = &'static IdeChannel> {
+        let is_ide = self.class == CLASS_MASS_STORAGE && self.subclass == SUBCLASS_IDE;
+        let prog_if = self.prog_if;
+
+        IDE_CHANNELS
+            .iter()
+            .filter(move |channel| is_ide && prog_if & channel.native_mode == 0)
+    }
+
+    pub fn command(&self) -> CommandFlags {
+        CommandFlags::from_bits_truncate(config::read(self.address, COMMAND))
+    }
+
+    pub fn set_command(&self, flags: CommandFlags) {
+        let reserved = config::read::<u16>(self.address, COMMAND) & !CommandFlags::all().bits();
+        config::write(self.address, COMMAND, reserved | flags.bits());
+    }
+}
+
+/// Sizes the function's BARs by writing all ones to them and reading back which bits are fixed
+fn read_bars(address: PciAddress, count: u16) -> [Option<Bar>; 6] {
+    let mut bars = [None; 6];
+
+    // Stop the function decoding its BARs while they temporarily hold bogus addresses
+    let command = config::read::<u16>(address, COMMAND);
+    let decode = (CommandFlags::IO_SPACE | CommandFlags::MEMORY_SPACE).bits();
+    config::write(address, COMMAND, command & !decode);
+
+    let mut index = 0;
+    while index < count {
+        let offset = BAR_0 + index * 4;
+        let (original, mask) = size_bar(address, offset);
+
+        if original & BAR_IO_SPACE != 0 {
+            // The upper 16 bits need not be implemented for IO BARs
+            let mask = mask & 0xfffc;
+            if mask != 0 {
+                bars[index as usize] = Some(Bar::Io {
+                    port: (original & 0xfffc) as u16,
+                    size: (!mask as u16).wrapping_add(1),
+                });
+            }
+
+            index += 1;
+            continue;
+        }
+
+        let is_64_bit = original & BAR_MEMORY_TYPE_64 != 0 && index + 1 < count;
+        let (address_bits, mask) = if is_64_bit {
+            let (original_high, mask_high) = size_bar(address, offset + 4);
+            (
+                (original_high as u64) << 32 | (original & !0xf) as u64,
+                (mask_high as u64) << 32 | (mask & !0xf) as u64,
+            )
+        } else {
+            ((original & !0xf) as u64, (mask & !0xf) as u64)
+        };
+
+        if mask != 0 {
+            // The upper bits of a 32 bit BAR are not implemented, so extend the mask to get the size
+            let mask = if is_64_bit {
+                mask
+            } else {
+                mask | 0xffff_ffff_0000_0000
+            };
+
+            bars[index as usize] = Some(Bar::Memory {
+                address: address_bits,
+                size: (!mask).wrapping_add(1),
+                prefetchable: original & BAR_MEMORY_PREFETCHABLE != 0,
+                is_64_bit,
+            });
+        }
+
+        index += if is_64_bit { 2 } else { 1 };
+    }
+
+    config::write(address, COMMAND, command);
+
+    bars
+}
+
+/// Returns the original value of a BAR and which of its bits are writable
+fn size_bar(address: PciAddress, offset: u16) -> (u32, u32) {
+    let original = config::read::<u32>(address, offset);
+    config::write::<u32>(address, offset, 0xffff_ffff);
+    let mask = config::read::<u32>(address, offset);
+    config::write(address, offset, original);
+
+    (original, mask)
+}
+
+fn read_capabilities(address: PciAddress) -> Vec<Capability> {
+    let mut capabilities = Vec::new();
+
+    if config::read::<u16>(address, STATUS) & STATUS_CAPABILITIES_LIST == 0 {
+        return capabilities;
+    }
+
+    let mut offset = config::read::<u8>(address, CAPABILITIES_POINTER) & 0xfc;
+
+    while offset != 0 && capabilities.len() < MAX_CAPABILITIES {
+        capabilities.push(Capability {
+            id: config::read(address, offset as u16),
+            offset,
+        });
+
+        offset = config::read::<u8>(address, offset as u16 + 1) & 0xfc;
+    }
+
+    capabilities
+}
+
+fn read_msi(address: PciAddress, offset: u8) -> Msi {
+    let control = config::read::<u16>(address, offset as u16 + 2);
+
+    Msi {
+        offset,
+        is_64_bit: control & MSI_CONTROL_64_BIT != 0,
+        per_vector_masking: control & MSI_CONTROL_PER_VECTOR_MASKING != 0,
+        max_vectors: 1 << ((control >> 1) & 0b111).min(5),
+    }
+}
+
+fn read_msi_x(address: PciAddress, offset: u8) -> MsiX {
+    let control = config::read::<u16>(address, offset as u16 + 2);
+    let table = config::read::<u32>(address, offset as u16 + 4);
+    let pending = config::read::<u32>(address, offset as u16 + 8);
+
+    MsiX {
+        offset,
+        table_size: (control & MSI_X_CONTROL_TABLE_SIZE_MASK) + 1,
+        table_bar: (table & MSI_X_BIR_MASK) as u8,
+        table_offset: table & !MSI_X_BIR_MASK,
+        pending_bar: (pending & MSI_X_BIR_MASK) as u8,
+        pending_offset: pending & !MSI_X_BIR_MASK,
+    }
+}
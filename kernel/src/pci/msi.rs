@@ -0,0 +1,151 @@
+//! Message signalled interrupts. Instead of asserting an interrupt pin, a function raises an
+//! interrupt vector directly by writing to the local APIC.
+//! From: PCI Local Bus Specification 3.0, 6.8
+
+use super::config;
+use super::device::{Bar, CommandFlags, PciDevice};
+use crate::interrupts::{lapic, vectors};
+use crate::memory::physical_mapping;
+use alloc::vec::Vec;
+use core::ptr;
+
+/// Messages are written to this address, with the id of the destination local APIC in bits 12-19
+const MESSAGE_ADDRESS_BASE: u32 = 0xFEE0_0000;
+
+const MSI_CONTROL_ENABLE: u16 = 1;
+const MSI_CONTROL_MULTIPLE_MESSAGE_ENABLE_SHIFT: u16 = 4;
+const MSI_CONTROL_MULTIPLE_MESSAGE_ENABLE_MASK: u16 =
+    0b111 << MSI_CONTROL_MULTIPLE_MESSAGE_ENABLE_SHIFT;
+
+const MSI_X_CONTROL_FUNCTION_MASK: u16 = 1 << 14;
+const MSI_X_CONTROL_ENABLE: u16 = 1 << 15;
+/// Each table entry is the message address (low then high dword), data and vector control
+const MSI_X_ENTRY_DWORDS: usize = 4;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MsiError {
+    /// The function has neither an MSI nor an MSI-X capability
+    Unsupported,
+    /// More vectors were requested than the function supports, or none were
+    InvalidVectorCount,
+    /// There are not enough free interrupt vectors left
+    NoFreeVectors,
+    /// The MSI-X table is not in a memory BAR
+    InvalidTableBar,
+}
+
+/// Enables message signalled interrupts for the function with `count` vectors, using MSI-X if it
+/// supports it and MSI otherwise. The vectors are returned in order of message number, and should
+/// be listened to with `interrupts::listen_vector`.
+pub fn enable(device: &PciDevice, count: u16) -> Result<Vec<u8>, MsiError> {
+    if device.msi_x.is_some() {
+        enable_msi_x(device, count)
+    } else {
+        enable_msi(device, count)
+    }
+}
+
+/// Enables MSI for the function. As MSI vectors must be a contiguous aligned block, `count` is
+/// rounded up to a power of two.
+pub fn enable_msi(device: &PciDevice, count: u16) -> Result<Vec<u8>, MsiError> {
+    let msi = device.msi.ok_or(MsiError::Unsupported)?;
+    if count == 0 || count > msi.max_vectors as u16 {
+        return Err(MsiError::InvalidVectorCount);
+    }
+
+    // The maximum is a power of two, so this cannot exceed it
+    let count = count.next_power_of_two() as u8;
+    let first = vectors::allocate_block(count).ok_or(MsiError::NoFreeVectors)?;
+    let (address, offset) = (device.address, msi.offset as u16);
+
+    config::write::<u32>(address, offset + 4, message_address());
+
+    let data_offset = if msi.is_64_bit {
+        config::write::<u32>(address, offset + 8, 0);
+        offset + 12
+    } else {
+        offset + 8
+    };
+
+    // The function sets the low bits of the data to the message number
+    config::write::<u16>(address, data_offset, first as u16);
+
+    let control =
+        config::read::<u16>(address, offset + 2) & !MSI_CONTROL_MULTIPLE_MESSAGE_ENABLE_MASK;
+    let multiple_message_enable =
+        (count.trailing_zeros() as u16) << MSI_CONTROL_MULTIPLE_MESSAGE_ENABLE_SHIFT;
+    config::write(
+        address,
+        offset + 2,
+        control | multiple_message_enable | MSI_CONTROL_ENABLE,
+    );
+
+    device.set_command(device.command() | CommandFlags::INTERRUPT_DISABLE);
+
+    Ok((0..count).map(|i| first + i).collect())
+}
+
+/// Enables MSI-X for the function, programming the first `count` entries of its vector table
+pub fn enable_msi_x(device: &PciDevice, count: u16) -> Result<Vec<u8>, MsiError> {
+    let msi_x = device.msi_x.ok_or(MsiError::Unsupported)?;
+
+    if count == 0 || count > msi_x.table_size {
+        return Err(MsiError::InvalidVectorCount);
+    }
+
+    let table_address = match device.bars.get(msi_x.table_bar as usize) {
+        Some(Some(Bar::Memory { address, .. })) => address + msi_x.table_offset as u64,
+        _ => return Err(MsiError::InvalidTableBar),
+    };
+
+    let mut allocated = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        match vectors::allocate() {
+            Some(vector) => allocated.push(vector),
+            None => {
+                allocated.into_iter().for_each(vectors::free);
+                return Err(MsiError::NoFreeVectors);
+            }
+        }
+    }
+
+    let (address, control_offset) = (device.address, msi_x.offset as u16 + 2);
+    let control = config::read::<u16>(address, control_offset);
+
+    // Mask all of the function's vectors while its table is being programmed. The table is only
+    // accessible once memory space decoding is enabled.
+    config::write(
+        address,
+        control_offset,
+        control | MSI_X_CONTROL_ENABLE | MSI_X_CONTROL_FUNCTION_MASK,
+    );
+    device.set_command(
+        device.command() | CommandFlags::MEMORY_SPACE | CommandFlags::INTERRUPT_DISABLE,
+    );
+
+    let table_size = (count as usize * MSI_X_ENTRY_DWORDS * 4) as u64;
+    let mut table =
+        unsafe { physical_mapping::map_physical_region::<u32>(table_address, table_size, true) };
+    let entries = table.deref_mut().unwrap() as *mut u32;
+
+    for (i, vector) in allocated.iter().enumerate() {
+        let data = [message_address(), 0, *vector as u32, 0]; // Vector control 0 = unmasked
+
+        for (j, dword) in data.iter().enumerate() {
+            // SAFETY: the entry is within the mapped table
+            unsafe { ptr::write_volatile(entries.add(i * MSI_X_ENTRY_DWORDS + j), *dword) };
+        }
+    }
+
+    config::write(
+        address,
+        control_offset,
+        (control | MSI_X_CONTROL_ENABLE) & !MSI_X_CONTROL_FUNCTION_MASK,
+    );
+
+    Ok(allocated)
+}
+
+fn message_address() -> u32 {
+    MESSAGE_ADDRESS_BASE | (lapic::id() as u32) << 12
+}
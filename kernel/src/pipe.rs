@@ -0,0 +1,261 @@
+//! Pipes, which carry a stream of bytes from the processes holding their write end to those holding
+//! their read end. Data is buffered in the kernel, up to `CAPACITY` bytes. Each end may be shared
+//! by several file descriptors, and is closed once the last of them is.
+//!
+//! A read blocks until there is data, or until the write end is closed, when it returns end of file.
+//! A write is only returned from once all of its data is in the pipe, blocking while it is full.
+//! Writing to a pipe whose read end is closed fails with `BrokenPipe`.
+
+use crate::process::{Delivery, ProcessId};
+use crate::scheduler;
+use crate::syscall::{Error, UserContext};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp;
+use spin::Mutex;
+
+/// The most data which a pipe buffers
+pub const CAPACITY: usize = 4096;
+
+#[derive(Debug, Default)]
+struct Pipe {
+    buffer: VecDeque<u8>,
+    read_end_open: bool,
+    write_end_open: bool,
+    /// Processes blocked until there is data to read, in the order they read
+    blocked_readers: VecDeque<BlockedReader>,
+    /// Processes blocked until their data fits in the pipe, in the order they wrote
+    blocked_writers: VecDeque<BlockedWriter>,
+}
+
+#[derive(Debug)]
+struct BlockedReader {
+    pid: ProcessId,
+    block_id: u64,
+    ptr: u64,
+    len: usize,
+}
+
+#[derive(Debug)]
+struct BlockedWriter {
+    pid: ProcessId,
+    block_id: u64,
+    /// The data which is not yet in the pipe
+    data: VecDeque<u8>,
+    /// The length of the whole write, which is returned once it is all in the pipe
+    len: usize,
+}
+
+/// A process to wake once the pipe is unlocked, as waking takes the process table's locks
+enum Wake {
+    Reader(BlockedReader, Vec<u8>),
+    Writer(ProcessId, u64, i64),
+}
+
+impl Wake {
+    fn wake(self) {
+        match self {
+            Wake::Reader(reader, data) => {
+                let len = data.len() as i64;
+                let delivery = Delivery {
+                    ptr: reader.ptr,
+                    data,
+                };
+
+                scheduler::wake_with(reader.pid, reader.block_id, len, delivery);
+            }
+            Wake::Writer(pid, block_id, result) => scheduler::wake(pid, block_id, result),
+        }
+    }
+}
+
+impl Pipe {
+    /// Moves the data of blocked writers into the buffer while there is room, returning the
+    /// writers which are done
+    fn fill(&mut self, wakes: &mut Vec<Wake>) {
+        while let Some(writer) = self.blocked_writers.front_mut() {
+            let room = CAPACITY - self.buffer.len();
+            let moved = cmp::min(room, writer.data.len());
+            self.buffer.extend(writer.data.drain(..moved));
+
+            if !writer.data.is_empty() {
+                break;
+            }
+
+            let writer = self.blocked_writers.pop_front().unwrap();
+            wakes.push(Wake::Writer(writer.pid, writer.block_id, writer.len as i64));
+        }
+    }
+
+    /// Hands buffered data to blocked readers, each taking as much as it asked for
+    fn drain_to_readers(&mut self, wakes: &mut Vec<Wake>) {
+        while !self.buffer.is_empty() {
+            let reader = match self.blocked_readers.pop_front() {
+                Some(reader) => reader,
+                None => break,
+            };
+
+            let len = cmp::min(reader.len, self.buffer.len());
+            let data = self.buffer.drain(..len).collect();
+            wakes.push(Wake::Reader(reader, data));
+            self.fill(wakes);
+        }
+    }
+}
+
+/// The read end of a pipe. Writers can tell when it is dropped, as nothing will then read what they
+/// write.
+#[derive(Debug)]
+pub struct Reader(Arc<Mutex<Pipe>>);
+
+/// The write end of a pipe. Readers see the end of the file once it is dropped.
+#[derive(Debug)]
+pub struct Writer(Arc<Mutex<Pipe>>);
+
+/// Creates a pipe, returning its read and write ends
+pub fn new() -> (Reader, Writer) {
+    let pipe = Pipe {
+        read_end_open: true,
+        write_end_open: true,
+        ..Pipe::default()
+    };
+    let pipe = Arc::new(Mutex::new(pipe));
+
+    (Reader(pipe.clone()), Writer(pipe))
+}
+
+impl Reader {
+    /// Reads up to `len` bytes, for the user's buffer, which must have been checked to be
+    /// writable. Blocks the current process until there is data, unless the write end is closed,
+    /// and then copies it into the buffer. The end is taken so that it isn't kept open while the
+    /// process is blocked.
+    pub fn read(
+        self: Arc<Self>,
+        context: &UserContext,
+        ptr: u64,
+        len: usize,
+    ) -> Result<Vec<u8>, Error> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut wakes = Vec::new();
+
+        let data = {
+            let mut pipe = self.0.lock();
+
+            if pipe.buffer.is_empty() && pipe.write_end_open {
+                drop(pipe);
+
+                let pipe = self.0.clone();
+                drop(self);
+
+                scheduler::block_current_on(context, None, move |pid, block_id| {
+                    let reader = BlockedReader {
+                        pid,
+                        block_id,
+                        ptr,
+                        len,
+                    };
+
+                    pipe.lock().blocked_readers.push_back(reader);
+                });
+            }
+
+            let len = cmp::min(len, pipe.buffer.len());
+            let data = pipe.buffer.drain(..len).collect();
+            pipe.fill(&mut wakes);
+            data
+        };
+
+        wakes.into_iter().for_each(Wake::wake);
+        Ok(data)
+    }
+}
+
+impl Writer {
+    /// Writes all of the data into the pipe, blocking the current process until it fits. Returns
+    /// the length of the data. The end is taken so that it isn't kept open while the process is
+    /// blocked.
+    pub fn write(self: Arc<Self>, context: &UserContext, data: Vec<u8>) -> Result<usize, Error> {
+        let mut wakes = Vec::new();
+
+        let written = {
+            let mut pipe = self.0.lock();
+
+            if !pipe.read_end_open {
+                return Err(Error::BrokenPipe);
+            }
+
+            let len = data.len();
+            let mut data = VecDeque::from(data);
+
+            // Queue behind any blocked writers, so that writes aren't interleaved
+            if pipe.blocked_writers.is_empty() {
+                let room = CAPACITY - pipe.buffer.len();
+                let moved = cmp::min(room, data.len());
+                pipe.buffer.extend(data.drain(..moved));
+                pipe.drain_to_readers(&mut wakes);
+            }
+
+            if !data.is_empty() {
+                drop(pipe);
+                wakes.into_iter().for_each(Wake::wake);
+
+                let pipe = self.0.clone();
+                drop(self);
+
+                scheduler::block_current_on(context, None, move |pid, block_id| {
+                    let writer = BlockedWriter {
+                        pid,
+                        block_id,
+                        data,
+                        len,
+                    };
+
+                    pipe.lock().blocked_writers.push_back(writer);
+                });
+            }
+
+            len
+        };
+
+        wakes.into_iter().for_each(Wake::wake);
+        Ok(written)
+    }
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        let wakes: Vec<_> = {
+            let mut pipe = self.0.lock();
+            pipe.read_end_open = false;
+
+            // Nothing will read what the blocked writers wrote
+            pipe.blocked_writers
+                .drain(..)
+                .map(|writer| Wake::Writer(writer.pid, writer.block_id, Error::BrokenPipe as i64))
+                .collect()
+        };
+
+        wakes.into_iter().for_each(Wake::wake);
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        let wakes: Vec<_> = {
+            let mut pipe = self.0.lock();
+            pipe.write_end_open = false;
+
+            // The blocked readers are at the end of the file
+            pipe.blocked_readers
+                .drain(..)
+                .map(|reader| Wake::Reader(reader, Vec::new()))
+                .collect()
+        };
+
+        wakes.into_iter().for_each(Wake::wake);
+    }
+}
@@ -0,0 +1,211 @@
+//! ACPI power management: shutting down and rebooting the machine.
+
+use crate::acpi_handler;
+use crate::acpi_handler::fadt::Fadt;
+use crate::memory::physical_mapping;
+use crate::pci::{self, PciAddress};
+use crate::{halt, interrupts, pit};
+use aml::{AmlError, AmlName, AmlValue};
+use core::ptr;
+use spin::Once;
+use x86_64::instructions::port::Port;
+
+pub static POWER: Once<PowerControl> = Once::new();
+
+/// Set in the FADT's flags if the reset register is supported
+const FADT_RESET_REGISTER_SUPPORTED: u32 = 1 << 10;
+
+/// Set in the PM1 control register if the machine is in ACPI mode
+const PM1_CONTROL_SCI_ENABLE: u16 = 1;
+const PM1_CONTROL_SLEEP_TYPE_SHIFT: u16 = 10;
+const PM1_CONTROL_SLEEP_TYPE_MASK: u16 = 0b111 << PM1_CONTROL_SLEEP_TYPE_SHIFT;
+const PM1_CONTROL_SLEEP_ENABLE: u16 = 1 << 13;
+
+/// How long to wait for the firmware to switch into ACPI mode
+const ACPI_ENABLE_TIMEOUT_MS: usize = 3000;
+
+const ADDRESS_SPACE_SYSTEM_MEMORY: u8 = 0;
+const ADDRESS_SPACE_SYSTEM_IO: u8 = 1;
+const ADDRESS_SPACE_PCI_CONFIG: u8 = 2;
+
+/// Commands the 8042 to pulse the CPU reset line
+const KEYBOARD_CONTROLLER_RESET: u8 = 0xFE;
+
+#[derive(Debug)]
+pub enum SleepStateError {
+    Aml(AmlError),
+    /// The `\_Sx` object was not a package of integers
+    Malformed,
+}
+
+pub struct PowerControl {
+    fadt: Fadt,
+    /// The SLP_TYPa and SLP_TYPb values for the S5 (soft off) sleep state, from the `\_S5` object
+    s5_sleep_types: Option<(u16, u16)>,
+}
+
+/// Reads the FADT and `\_S5` object. The ACPI tables must have been found beforehand.
+pub fn init() {
+    info!("power: initializing");
+
+    let fadt = match acpi_handler::fadt() {
+        Some(fadt) => *fadt,
+        None => {
+            warn!("power: no fadt, acpi shutdown and reset unavailable");
+            return;
+        }
+    };
+
+    let s5_sleep_types = match s5_sleep_types() {
+        Ok(sleep_types) => Some(sleep_types),
+        Err(e) => {
+            warn!("power: couldn't find s5 sleep types: {:?}", e);
+            None
+        }
+    };
+
+    POWER.call_once(|| PowerControl {
+        fadt,
+        s5_sleep_types,
+    });
+}
+
+fn s5_sleep_types() -> Result<(u16, u16), SleepStateError> {
+    let context = acpi_handler::parse_aml().map_err(SleepStateError::Aml)?;
+    let name = AmlName::from_str("\\_S5").unwrap();
+
+    let elements = match context.namespace.get_by_path(&name) {
+        Ok(AmlValue::Package(elements)) => elements,
+        Ok(_) => return Err(SleepStateError::Malformed),
+        Err(e) => return Err(SleepStateError::Aml(e)),
+    };
+
+    // SLP_TYPb is optional, as it is only used if there is a PM1b control block
+    match (elements.get(0), elements.get(1)) {
+        (Some(AmlValue::Integer(a)), Some(AmlValue::Integer(b))) => Ok((*a as u16, *b as u16)),
+        (Some(AmlValue::Integer(a)), None) => Ok((*a as u16, 0)),
+        _ => Err(SleepStateError::Malformed),
+    }
+}
+
+/// Turns the machine off, or halts if that is not possible.
+pub fn shutdown() -> ! {
+    info!("power: shutting down");
+
+    if let Some(power) = POWER.wait() {
+        if let Some((sleep_type_a, sleep_type_b)) = power.s5_sleep_types {
+            unsafe { power.enter_sleep_state(sleep_type_a, sleep_type_b) };
+        }
+    }
+
+    warn!("power: shutdown failed, halting");
+    halt()
+}
+
+/// Resets the machine, or halts if that is not possible.
+pub fn reboot() -> ! {
+    info!("power: rebooting");
+
+    if let Some(power) = POWER.wait() {
+        unsafe { power.write_reset_register() };
+    }
+
+    unsafe { keyboard_controller_reset() };
+
+    warn!("power: reset failed, halting");
+    halt()
+}
+
+impl PowerControl {
+    /// Switches the machine from legacy mode to ACPI mode, if it is not already in ACPI mode.
+    fn enable_acpi(&self) {
+        let mut pm1a_control = Port::<u16>::new(self.fadt.pm1a_control_block as u16);
+        let (smi_command, acpi_enable) = (self.fadt.smi_cmd_port, self.fadt.acpi_enable);
+
+        // A zero SMI command port or enable value means that the machine is hardware-reduced, or
+        // is already in ACPI mode
+        if unsafe { pm1a_control.read() } & PM1_CONTROL_SCI_ENABLE != 0
+            || smi_command == 0
+            || acpi_enable == 0
+        {
+            return;
+        }
+
+        unsafe { Port::<u8>::new(smi_command as u16).write(acpi_enable) };
+
+        let deadline = pit::time_ms() + ACPI_ENABLE_TIMEOUT_MS;
+        while unsafe { pm1a_control.read() } & PM1_CONTROL_SCI_ENABLE == 0 {
+            if pit::time_ms() >= deadline {
+                warn!("power: timed out enabling acpi mode");
+                return;
+            }
+
+            unsafe { asm!("hlt") };
+        }
+    }
+
+    /// # Safety
+    ///
+    /// If this succeeds, the machine will be asleep (or off). Returns if it failed.
+    unsafe fn enter_sleep_state(&self, sleep_type_a: u16, sleep_type_b: u16) {
+        self.enable_acpi();
+
+        // TODO evaluate \_PTS before going to sleep
+        interrupts::disable();
+
+        write_sleep_type(self.fadt.pm1a_control_block as u16, sleep_type_a);
+
+        if self.fadt.pm1b_control_block != 0 {
+            write_sleep_type(self.fadt.pm1b_control_block as u16, sleep_type_b);
+        }
+    }
+
+    /// # Safety
+    ///
+    /// If this succeeds, the machine will reset. Returns if it failed.
+    unsafe fn write_reset_register(&self) {
+        // The reset register was introduced in ACPI 2.0
+        if self.fadt.header.revision < 2 || self.fadt.flags & FADT_RESET_REGISTER_SUPPORTED == 0 {
+            return;
+        }
+
+        let (register, value) = (self.fadt.reset_register, self.fadt.reset_value);
+
+        match register.address_space {
+            ADDRESS_SPACE_SYSTEM_MEMORY => {
+                let mut mapping =
+                    physical_mapping::map_physical_region::<u8>(register.address, 1, true);
+                ptr::write_volatile(mapping.deref_mut().unwrap() as *mut u8, value);
+            }
+            ADDRESS_SPACE_SYSTEM_IO => Port::<u8>::new(register.address as u16).write(value),
+            ADDRESS_SPACE_PCI_CONFIG => {
+                // The reset register is always on segment 0, bus 0
+                let address = register.address;
+                let device = (address >> 32) as u8;
+                let function = (address >> 16) as u8;
+                let offset = address as u16;
+
+                pci::config::write(PciAddress::new(0, 0, device, function), offset, value);
+            }
+            space => warn!("power: unsupported reset register address space {}", space),
+        }
+    }
+}
+
+unsafe fn write_sleep_type(pm1_control_block: u16, sleep_type: u16) {
+    let mut port = Port::<u16>::new(pm1_control_block);
+    let value = port.read() & !PM1_CONTROL_SLEEP_TYPE_MASK;
+    let sleep_type = (sleep_type << PM1_CONTROL_SLEEP_TYPE_SHIFT) & PM1_CONTROL_SLEEP_TYPE_MASK;
+
+    port.write(value | sleep_type | PM1_CONTROL_SLEEP_ENABLE);
+}
+
+/// Resets the machine by pulsing the CPU reset line through the 8042 keyboard controller.
+unsafe fn keyboard_controller_reset() {
+    let mut command_port = Port::<u8>::new(0x64);
+
+    // Wait for the controller's input buffer to be empty
+    while command_port.read() & 0b10 != 0 {}
+
+    command_port.write(KEYBOARD_CONTROLLER_RESET);
+}
@@ -1,12 +1,23 @@
 use crate::memory::paging::*;
 use core::sync::atomic::{AtomicU64, Ordering};
 use dashmap::DashMap;
+use spin::Mutex;
 
+use crate::fd::FileTable;
+use crate::memory::buffer::BorrowedKernelBufferMut;
+use crate::memory::dma::DmaRegion;
 use crate::memory::physical_allocator::PHYSICAL_ALLOCATOR;
+use crate::page_cache::{self, Mappings};
+use crate::syscall::UserContext;
 use crate::tss::TSS;
+use crate::{block, interrupts, ipc, net, notification, pci, scheduler, tmpfs};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::ops::{Range, RangeInclusive};
-use core::slice;
+use core::ptr::NonNull;
+use core::{cmp, fmt, mem, slice};
 use goblin::elf::program_header::PT_LOAD;
 use goblin::elf::Elf;
 use x86_64::registers::control::Cr3;
@@ -24,10 +35,27 @@ lazy_static::lazy_static! {
 
 static NEXT_PID: AtomicU64 = AtomicU64::new(0);
 
+/// The io port ranges which are currently usable in the IOPB, i.e those of the last process run
+static USABLE_IO_PORTS: Mutex<Vec<RangeInclusive<u16>>> = Mutex::new(Vec::new());
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
 pub struct ProcessId(u64);
 
+impl fmt::Display for ProcessId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl ProcessId {
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    pub const fn from_u64(pid: u64) -> Self {
+        ProcessId(pid)
+    }
+
     pub fn next() -> Self {
         let next_pid = NEXT_PID.fetch_add(1, Ordering::Relaxed);
 
@@ -40,15 +68,181 @@ impl ProcessId {
     }
 }
 
+bitflags::bitflags! {
+    /// Privileged operations which a process is allowed to perform
+    pub struct Capabilities: u64 {
+        /// Shutting down and rebooting the machine
+        const POWER = 1;
+        /// Claiming PCI devices, accessing their BARs, handling their interrupts and mapping memory
+        /// for them to access directly
+        const DEVICES = 1 << 1;
+        /// Reading keyboard and mouse input
+        const INPUT = 1 << 2;
+        /// Mapping the initial ramdisk
+        const INITRD = 1 << 3;
+        /// Reading and writing block devices, e.g disks
+        const STORAGE = 1 << 4;
+        /// Sending and receiving raw frames on network devices
+        const NETWORK = 1 << 5;
+        /// Killing processes which it didn't spawn, directly or otherwise
+        const KILL = 1 << 6;
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ProcessState {
+    Runnable,
+    /// Blocked in a system call. The id distinguishes this block from the process's previous ones.
+    Blocked(u64),
+}
+
+/// How a process ended
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ExitStatus {
+    /// The process exited by itself, with the given code
+    Exited(u32),
+    /// Another process killed it, or the kernel did after a fault which it couldn't recover from
+    Killed,
+}
+
+impl ExitStatus {
+    /// The status as `Wait` returns it. Exit codes are returned as they are, and the other
+    /// statuses as values above them.
+    pub fn as_u64(self) -> u64 {
+        match self {
+            ExitStatus::Exited(code) => code as u64,
+            ExitStatus::Killed => 1 << 32,
+        }
+    }
+}
+
+/// Describes a process to userspace. Kept free of padding, as it is copied out byte-for-byte.
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct ProcessInfo {
+    pub pid: u64,
+    /// The process which spawned it, or `u64::max_value()` if none did
+    pub parent: u64,
+    pub capabilities: u64,
+    pub state: u32,
+    pub name_len: u32,
+    pub name: [u8; MAX_NAME],
+}
+
+impl ProcessInfo {
+    pub const STATE_RUNNABLE: u32 = 0;
+    pub const STATE_BLOCKED: u32 = 1;
+
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: repr(C) without any padding
+        unsafe { slice::from_raw_parts(self as *const _ as *const u8, mem::size_of::<Self>()) }
+    }
+}
+
+/// The longest name which a process can be given
+pub const MAX_NAME: usize = 32;
+
+/// Data to be copied into a blocked process's buffer once it is woken, when its page tables are
+/// active again
+#[derive(Debug)]
+pub struct Delivery {
+    pub ptr: u64,
+    pub data: Vec<u8>,
+}
+
+impl Delivery {
+    /// Copies the data into the buffer, unless it has been unmapped since it was checked
+    ///
+    /// # Safety
+    ///
+    /// The process's page tables must be active.
+    unsafe fn deliver(self) {
+        let res = BorrowedKernelBufferMut::<u8>::try_from_user(
+            NonNull::new(self.ptr as *mut u8),
+            self.data.len() as u64,
+        );
+
+        if let Ok(buf) = res {
+            buf.0.copy_from_slice(&self.data);
+        }
+    }
+}
+
+/// A process's x87, MMX and SSE registers, as `fxsave64` lays them out. The kernel doesn't use them
+/// itself, so they are only saved and restored when switching processes.
+#[repr(C, align(16))]
+struct FpuState([u8; 512]);
+
+impl FpuState {
+    /// The registers as they are after `fninit`, with every exception masked
+    fn new() -> Box<FpuState> {
+        let mut state = Box::new(FpuState([0; 512]));
+
+        // The x87 control word, and MXCSR
+        state.0[0..2].copy_from_slice(&0x037fu16.to_le_bytes());
+        state.0[24..28].copy_from_slice(&0x1f80u32.to_le_bytes());
+        state
+    }
+
+    /// Saves the processor's registers, which must still be those of the process
+    fn save(&mut self) {
+        unsafe { asm!("fxsave64 [{}]", in(reg) self.0.as_mut_ptr(), options(nostack)) };
+    }
+
+    /// Loads the registers into the processor, before returning to the process
+    fn restore(&self) {
+        unsafe { asm!("fxrstor64 [{}]", in(reg) self.0.as_ptr(), options(nostack)) };
+    }
+}
+
+impl fmt::Debug for FpuState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("FpuState")
+    }
+}
+
 #[derive(Debug)]
 pub struct Process {
+    /// A name to tell the process apart by, e.g the path of its ELF file
+    name: String,
+    /// The process which spawned it, if any
+    parent: Option<ProcessId>,
     pub page_tables: InactivePageMap,
-    stack_ptr: VirtAddr,
-    instruction_ptr: VirtAddr,
+    /// The user's registers, as they were when the process last entered the kernel
+    context: UserContext,
+    /// The user's floating point and vector registers, as they were when it was last switched out
+    fpu: Box<FpuState>,
+    state: ProcessState,
+    /// How many times the process has blocked, used to generate block ids
+    blocks: u64,
+    capabilities: Capabilities,
     io_port_ranges: Vec<RangeInclusive<u16>>,
+    /// Legacy IRQs which the process may listen to without having claimed a PCI function raising
+    /// them, one bit per IRQ
+    irqs: u16,
+    /// Memory mapped into the process for its devices to access directly
+    dma_regions: Vec<DmaRegion>,
+    /// Memory objects which are mapped into the process, to be faulted in lazily
+    mappings: Mappings,
+    files: FileTable,
+    /// Data to copy into the process when it is next run
+    delivery: Option<Delivery>,
+    /// Children which have exited but not yet been waited on, and how they ended
+    exited_children: BTreeMap<ProcessId, ExitStatus>,
+    /// What the process is blocked waiting to exit, and the block's id
+    waiting_on: Option<(Waiting, u64)>,
     new: bool,
 }
 
+#[derive(Debug, Copy, Clone)]
+enum Waiting {
+    Child(ProcessId),
+    /// Any child, whose status is copied to the pointer
+    AnyChild {
+        status_ptr: u64,
+    },
+}
+
 #[derive(Debug)]
 pub enum ElfLaunchError {
     NotExecutable,
@@ -59,10 +253,22 @@ pub enum ElfLaunchError {
     InvalidEntryPoint(u64),
     ParseError(goblin::error::Error),
     InvalidHeaderRange(Range<usize>),
+    /// A loadable segment is empty, overlaps another, or doesn't fit below the user stack
+    InvalidSegment {
+        vaddr: u64,
+        memsz: u64,
+    },
 }
 
 impl Process {
-    pub fn spawn_from_elf(data: &[u8]) -> Result<ProcessId, ElfLaunchError> {
+    /// Loads a process from an ELF file. It is run once it is enqueued with the scheduler.
+    pub fn spawn_from_elf(
+        data: &[u8],
+        name: &str,
+        parent: Option<ProcessId>,
+        capabilities: Capabilities,
+        files: FileTable,
+    ) -> Result<ProcessId, ElfLaunchError> {
         let elf = Elf::parse(data).map_err(ElfLaunchError::ParseError)?;
 
         if elf.is_lib || elf.entry == 0 {
@@ -77,10 +283,17 @@ impl Process {
             return Err(ElfLaunchError::NotStaticallyLinked);
         }
 
+        // Kernel space or non canonical address... no.
+        if elf.entry >> 63 == 1 || VirtAddr::try_new(elf.entry).is_err() {
+            return Err(ElfLaunchError::InvalidEntryPoint(elf.entry));
+        }
+
+        check_segments(&elf)?;
+
         let page_tables = Self::new_process_page_tables();
-        let page_tables = ACTIVE_PAGE_TABLES
+        let res = ACTIVE_PAGE_TABLES
             .lock()
-            .with_inactive(page_tables, |tables| {
+            .with_inactive(page_tables.clone(), |tables| {
                 for p_header in &elf.program_headers {
                     if p_header.p_type != PT_LOAD {
                         continue;
@@ -107,48 +320,75 @@ impl Process {
                         flags |= EntryFlags::WRITABLE;
                     }
 
+                    let src_slice = data
+                        .get(p_header.file_range())
+                        .ok_or(ElfLaunchError::InvalidHeaderRange(p_header.file_range()))?;
+
+                    // The segment may be longer in memory than in the file, e.g for .bss, but not
+                    // shorter
+                    if src_slice.len() > vm_range.len() {
+                        return Err(ElfLaunchError::InvalidHeaderRange(p_header.file_range()));
+                    }
+
                     unsafe {
+                        // Pages shared with a segment which has already been loaded are kept, and
+                        // new ones are zeroed, so that nothing is left in them from their frames'
+                        // last users
                         tables
                             .try_map_user_range(
                                 page_start..=page_end,
                                 EntryFlags::WRITABLE,
                                 InvalidateTlb::NoInvalidate,
                                 true, // ignore_already_mapped
-                                ZeroPage::NoZero,
+                                ZeroPage::Zero,
                             )
                             .map_err(ElfLaunchError::InvalidPage)?;
 
-                        let src_slice = data
-                            .get(p_header.file_range())
-                            .ok_or(ElfLaunchError::InvalidHeaderRange(p_header.file_range()))?;
-
                         // SAFETY: range is TrustedLen
                         let dst_slice =
                             slice::from_raw_parts_mut(vm_range.start as *mut u8, vm_range.len());
 
-                        if dst_slice.len() != src_slice.len() {
-                            return Err(ElfLaunchError::InvalidHeaderRange(p_header.file_range()));
-                        }
+                        // Whatever is past the file's data is zeroed, e.g .bss
+                        let (file_part, zeroed_part) = dst_slice.split_at_mut(src_slice.len());
+                        file_part.copy_from_slice(src_slice);
 
-                        dst_slice.copy_from_slice(src_slice);
+                        for byte in zeroed_part {
+                            *byte = 0;
+                        }
 
                         tables.set_flags(page_start..=page_end, flags, InvalidateTlb::NoInvalidate);
                     }
                 }
 
                 Ok(())
-            })?;
-
-        // Kernel space or non canonical address... no.
-        if elf.entry >> 63 == 1 || VirtAddr::try_new(elf.entry).is_err() {
-            return Err(ElfLaunchError::InvalidEntryPoint(elf.entry));
-        }
+            });
+
+        let page_tables = match res {
+            Ok(page_tables) => page_tables,
+            Err(err) => {
+                // SAFETY: the process was never created, so nothing runs in its page tables
+                unsafe { free_page_tables(page_tables) };
+                return Err(err);
+            }
+        };
 
         let process = Process {
+            name: truncate_name(name),
+            parent,
             page_tables,
-            stack_ptr: STACK_TOP,
-            instruction_ptr: VirtAddr::new(elf.entry),
+            context: UserContext::new(STACK_TOP, VirtAddr::new(elf.entry)),
+            fpu: FpuState::new(),
+            state: ProcessState::Runnable,
+            blocks: 0,
+            capabilities,
             io_port_ranges: Vec::new(),
+            irqs: 0,
+            dma_regions: Vec::new(),
+            mappings: Mappings::default(),
+            files,
+            delivery: None,
+            exited_children: BTreeMap::new(),
+            waiting_on: None,
             new: true,
         };
 
@@ -187,6 +427,179 @@ impl Process {
         new_table
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn parent(&self) -> Option<ProcessId> {
+        self.parent
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    pub fn state(&self) -> ProcessState {
+        self.state
+    }
+
+    /// Allows the process to access the given io ports, from the next time they are applied
+    pub fn grant_io_ports(&mut self, ports: RangeInclusive<u16>) {
+        if !self.io_port_ranges.contains(&ports) {
+            self.io_port_ranges.push(ports);
+        }
+    }
+
+    /// Makes only this process's io ports usable in the IOPB. Must be called when switching to
+    /// the process, or after granting the running process new ports.
+    pub fn apply_io_ports(&self) {
+        let iomap = TSS.wait().unwrap().iomap.lock_or_panic();
+        let mut usable_io_ports = USABLE_IO_PORTS.lock();
+
+        for ports in usable_io_ports.drain(..) {
+            iomap.set_port_range_usable(ports, false);
+        }
+
+        // TODO(permissions) track serial port access
+        iomap.set_port_range_usable(0x3f8..=0x3F8 + 7, true);
+
+        for ports in &self.io_port_ranges {
+            iomap.set_port_range_usable(ports.clone(), true);
+            usable_io_ports.push(ports.clone());
+        }
+    }
+
+    /// Allows the process to listen to a legacy IRQ, which must be less than 16
+    pub fn grant_irq(&mut self, irq: u8) {
+        self.irqs |= 1 << irq;
+    }
+
+    pub fn is_irq_granted(&self, irq: u8) -> bool {
+        irq < 16 && self.irqs & (1 << irq) != 0
+    }
+
+    /// Keeps a DMA region which has been mapped into the process for as long as the process lives
+    pub fn add_dma_region(&mut self, region: DmaRegion) {
+        self.dma_regions.push(region);
+    }
+
+    pub fn mappings(&mut self) -> &mut Mappings {
+        &mut self.mappings
+    }
+
+    pub fn files(&mut self) -> &mut FileTable {
+        &mut self.files
+    }
+
+    pub fn info(&self, pid: ProcessId) -> ProcessInfo {
+        let mut info = ProcessInfo {
+            pid: pid.as_u64(),
+            parent: self.parent.map_or(u64::max_value(), ProcessId::as_u64),
+            capabilities: self.capabilities.bits(),
+            state: match self.state {
+                ProcessState::Runnable => ProcessInfo::STATE_RUNNABLE,
+                ProcessState::Blocked(_) => ProcessInfo::STATE_BLOCKED,
+            },
+            name_len: self.name.len() as u32,
+            ..ProcessInfo::default()
+        };
+
+        info.name[..self.name.len()].copy_from_slice(self.name.as_bytes());
+        info
+    }
+
+    /// Takes how a child ended, if it has exited and not yet been waited on
+    pub fn take_exited_child(&mut self, child: ProcessId) -> Option<ExitStatus> {
+        self.exited_children.remove(&child)
+    }
+
+    /// Takes the child with the lowest id which has exited and not yet been waited on, if any
+    pub fn take_any_exited_child(&mut self) -> Option<(ProcessId, ExitStatus)> {
+        let child = *self.exited_children.keys().next()?;
+        self.exited_children
+            .remove(&child)
+            .map(|status| (child, status))
+    }
+
+    /// Records that the process is blocked waiting for a child to exit
+    pub fn wait_on(&mut self, child: ProcessId, block_id: u64) {
+        self.waiting_on = Some((Waiting::Child(child), block_id));
+    }
+
+    /// Records that the process is blocked waiting for any child to exit, to copy its status to
+    /// the pointer, which must have been checked to be writable
+    pub fn wait_on_any(&mut self, status_ptr: u64, block_id: u64) {
+        self.waiting_on = Some((Waiting::AnyChild { status_ptr }, block_id));
+    }
+
+    /// Records how a child ended. If the process is waiting on it, returns the block id to wake
+    /// the process from, the result of its system call and any data to deliver to it. Otherwise,
+    /// keeps the status until it waits.
+    fn child_exited(
+        &mut self,
+        child: ProcessId,
+        status: ExitStatus,
+    ) -> Option<(u64, i64, Option<Delivery>)> {
+        let woken = match self.waiting_on {
+            Some((Waiting::Child(waited), block_id)) if waited == child => {
+                (block_id, status.as_u64() as i64, None)
+            }
+            Some((Waiting::AnyChild { status_ptr }, block_id)) => {
+                let delivery = Delivery {
+                    ptr: status_ptr,
+                    data: status.as_u64().to_ne_bytes().to_vec(),
+                };
+
+                (block_id, child.as_u64() as i64, Some(delivery))
+            }
+            _ => {
+                self.exited_children.insert(child, status);
+                return None;
+            }
+        };
+
+        self.waiting_on = None;
+        Some(woken)
+    }
+
+    /// Marks the process as blocked in a system call, saving its context. Returns the block id.
+    pub fn block(&mut self, context: UserContext) -> u64 {
+        self.blocks += 1;
+        self.context = context;
+        self.fpu.save();
+        self.state = ProcessState::Blocked(self.blocks);
+        self.blocks
+    }
+
+    /// Saves the context of the process, which is running, when it is preempted. It stays runnable.
+    pub fn preempt(&mut self, context: UserContext) {
+        self.context = context;
+        self.fpu.save();
+    }
+
+    /// Wakes the process if it is blocked with the given block id, making its system call return
+    /// `result`. Returns whether it was woken.
+    pub fn wake(&mut self, block_id: u64, result: i64) -> bool {
+        if self.state != ProcessState::Blocked(block_id) {
+            return false;
+        }
+
+        self.context.rax = result as u64;
+        self.state = ProcessState::Runnable;
+        true
+    }
+
+    /// Like [wake], but also copies data into the process when it is next run
+    pub fn wake_with(&mut self, block_id: u64, result: i64, delivery: Delivery) -> bool {
+        let woken = self.wake(block_id, result);
+
+        if woken {
+            self.delivery = Some(delivery);
+        }
+
+        woken
+    }
+
     pub fn run_by_pid(pid: &ProcessId) -> Result<!, OutOfMemory> {
         let mut this = PROCESSES.get_mut(pid).unwrap();
         ACTIVE_PAGE_TABLES.lock().switch(this.page_tables.clone());
@@ -198,16 +611,17 @@ impl Process {
             this.new = false;
         }
 
-        // TODO(permissions) track process io ports
-        TSS.wait()
-            .unwrap()
-            .iomap
-            .lock_or_panic()
-            .set_port_range_usable(0x3f8..=0x3F8 + 7, true);
+        this.apply_io_ports();
+        this.fpu.restore();
+
+        if let Some(delivery) = this.delivery.take() {
+            // SAFETY: the process's page tables were just switched to
+            unsafe { delivery.deliver() };
+        }
 
-        let (rsp, rip) = (this.stack_ptr, this.instruction_ptr);
+        let context = this.context;
         drop(this);
-        unsafe { jump_usermode(rsp, rip) }
+        unsafe { jump_usermode(&context) }
     }
 
     /// Sets up the process for it to be run for the first time.
@@ -229,10 +643,126 @@ impl Process {
     }
 }
 
+/// Checks that an ELF's loadable segments can be loaded, before any of its address space is built.
+/// They must be non-empty, not overlap each other, lie below the user stack, which keeps them out
+/// of kernel space, and be no longer in the file than in memory.
+fn check_segments(elf: &Elf) -> Result<(), ElfLaunchError> {
+    let mut segments: Vec<Range<u64>> = Vec::new();
+
+    for p_header in &elf.program_headers {
+        if p_header.p_type != PT_LOAD {
+            continue;
+        }
+
+        let invalid = ElfLaunchError::InvalidSegment {
+            vaddr: p_header.p_vaddr,
+            memsz: p_header.p_memsz,
+        };
+
+        let end = match p_header.p_vaddr.checked_add(p_header.p_memsz) {
+            Some(end) if p_header.p_memsz > 0 && end <= STACK_BOTTOM.as_u64() => end,
+            _ => return Err(invalid),
+        };
+
+        let file_end = p_header.p_offset.checked_add(p_header.p_filesz);
+        if file_end.is_none() || p_header.p_filesz > p_header.p_memsz {
+            return Err(invalid);
+        }
+
+        let segment = p_header.p_vaddr..end;
+
+        if segments
+            .iter()
+            .any(|other| segment.start < other.end && other.start < segment.end)
+        {
+            return Err(invalid);
+        }
+
+        segments.push(segment);
+    }
+
+    Ok(())
+}
+
+/// Frees a process's page tables and the memory mapped in them.
+///
 /// # Safety
 ///
-/// Expects to be in the page tables where instruction and stack pointer are loaded and valid.
-unsafe fn jump_usermode(stack_ptr: VirtAddr, instruction_ptr: VirtAddr) -> ! {
+/// Nothing may run in the page tables again.
+unsafe fn free_page_tables(page_tables: InactivePageMap) {
+    let fallback = KERNEL_PAGE_TABLES.wait().unwrap().clone();
+    page_tables.free(&mut ACTIVE_PAGE_TABLES.lock(), fallback);
+}
+
+/// Cuts a name down to at most `MAX_NAME` bytes, on a character boundary
+fn truncate_name(name: &str) -> String {
+    let mut len = cmp::min(name.len(), MAX_NAME);
+
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+
+    String::from(&name[..len])
+}
+
+/// Ends a process, releasing everything it holds, and tells its parent how it ended. The current
+/// process must instead exit through `scheduler::exit_current`, so that it isn't returned to.
+pub fn exit(pid: ProcessId, status: ExitStatus) {
+    let process = match PROCESSES.remove(&pid) {
+        Some((_, process)) => process,
+        None => return,
+    };
+
+    debug!("process: {} ({}) exited: {:?}", pid, process.name, status);
+
+    ipc::release(pid);
+    page_cache::write_back_on_exit(&process.page_tables, &process.mappings);
+    page_cache::release_all(pid);
+    tmpfs::release(pid);
+    pci::release(pid);
+    interrupts::user::release(pid);
+    net::detach(pid);
+    notification::release(pid);
+
+    let parent = process.parent;
+
+    // A device may still be transferring to or from the process's memory, in which case it is only
+    // freed once the transfer completes
+    if let Some(process) = block::release(pid, process) {
+        free(process);
+    }
+
+    if let Some(parent) = parent {
+        let woken = PROCESSES
+            .get_mut(&parent)
+            .and_then(|mut process| process.child_exited(pid, status));
+
+        match woken {
+            Some((block_id, result, Some(delivery))) => {
+                scheduler::wake_with(parent, block_id, result, delivery);
+            }
+            Some((block_id, result, None)) => scheduler::wake(parent, block_id, result),
+            None => (),
+        }
+    }
+}
+
+/// Frees the memory of a process which has exited, and closes its files.
+///
+/// No locks on the process table may be held when calling this, as closing the process's files may
+/// wake processes, e.g those reading the other ends of its pipes.
+pub fn free(process: Process) {
+    // SAFETY: the process has been removed, so it is never run again
+    unsafe { free_page_tables(process.page_tables.clone()) };
+}
+
+/// Restores the user's context and jumps to it.
+///
+/// # Safety
+///
+/// Expects to be in the page tables where the context's instruction and stack pointer are loaded
+/// and valid.
+unsafe fn jump_usermode(context: &UserContext) -> ! {
     asm!("
         mov ax, 0x2b
         mov ds, ax
@@ -241,15 +771,31 @@ unsafe fn jump_usermode(stack_ptr: VirtAddr, instruction_ptr: VirtAddr) -> ! {
         mov gs, ax
 
         push 0x2b // stack segment
-        push {0} // stack pointer
-        pushfq // push RFLAGS
+        push qword ptr [rdi + 104] // stack pointer
+        push qword ptr [rdi + 120] // RFLAGS
         push 0x33 // code segment
-        push {1} // instruction pointer
+        push qword ptr [rdi + 112] // instruction pointer
+
+        // Restore the user's registers, leaving rdi (the context pointer) until last
+        mov rcx, [rdi + 128]
+        mov r11, [rdi + 136]
+        mov rsi, [rdi + 8]
+        mov rdx, [rdi + 16]
+        mov r10, [rdi + 24]
+        mov r8, [rdi + 32]
+        mov r9, [rdi + 40]
+        mov rax, [rdi + 48]
+        mov rbx, [rdi + 56]
+        mov rbp, [rdi + 64]
+        mov r12, [rdi + 72]
+        mov r13, [rdi + 80]
+        mov r14, [rdi + 88]
+        mov r15, [rdi + 96]
+        mov rdi, [rdi]
+
         iretq
         ",
-    in(reg) stack_ptr.as_u64(),
-    in(reg) instruction_ptr.as_u64(),
+    in("rdi") context as *const UserContext,
+    options(noreturn)
     );
-
-    unreachable!()
 }
@@ -0,0 +1,311 @@
+//! The 8042 PS/2 controller, which the keyboard and mouse are attached to.
+//! From: [OsDev Wiki](https://wiki.osdev.org/%228042%22_PS/2_Controller)
+
+use crate::interrupts::{self, Irq};
+use crate::pit;
+use x86_64::instructions::port::Port;
+
+pub mod keyboard;
+pub mod mouse;
+mod scancode;
+
+const DATA_PORT: u16 = 0x60;
+/// Read as the status register, written as the command register
+const COMMAND_PORT: u16 = 0x64;
+
+/// Set in the status register if there is data to read from the data port
+const STATUS_OUTPUT_FULL: u8 = 1;
+/// Set in the status register if the controller has not yet taken the last byte written
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+
+const COMMAND_READ_CONFIG: u8 = 0x20;
+const COMMAND_WRITE_CONFIG: u8 = 0x60;
+const COMMAND_DISABLE_SECOND_PORT: u8 = 0xA7;
+const COMMAND_ENABLE_SECOND_PORT: u8 = 0xA8;
+const COMMAND_TEST_SECOND_PORT: u8 = 0xA9;
+const COMMAND_SELF_TEST: u8 = 0xAA;
+const COMMAND_TEST_FIRST_PORT: u8 = 0xAB;
+const COMMAND_DISABLE_FIRST_PORT: u8 = 0xAD;
+const COMMAND_ENABLE_FIRST_PORT: u8 = 0xAE;
+/// Sends the next byte written to the data port to the device on the second port
+const COMMAND_WRITE_SECOND_PORT: u8 = 0xD4;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+const CONFIG_FIRST_PORT_IRQ: u8 = 1;
+const CONFIG_SECOND_PORT_IRQ: u8 = 1 << 1;
+const CONFIG_SECOND_PORT_CLOCK_DISABLED: u8 = 1 << 5;
+/// Set if the controller translates the keyboard's scancodes to scancode set 1
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+pub const DEVICE_RESET: u8 = 0xFF;
+pub const DEVICE_ENABLE_SCANNING: u8 = 0xF4;
+pub const DEVICE_ACK: u8 = 0xFA;
+pub const DEVICE_RESEND: u8 = 0xFE;
+pub const DEVICE_SELF_TEST_PASSED: u8 = 0xAA;
+
+/// How long to wait for the controller or a device to respond
+const TIMEOUT_MS: usize = 500;
+/// Devices may take a while to reset
+const RESET_TIMEOUT_MS: usize = 1000;
+const MAX_RESENDS: usize = 3;
+/// The PIT doesn't tick with interrupts disabled, e.g in an IRQ handler, so polling the controller
+/// is also bounded by a number of attempts
+const MAX_POLLS: usize = 1_000_000;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Ps2Error {
+    /// The controller or device did not respond in time
+    Timeout,
+    SelfTestFailed(u8),
+    /// The device kept asking for a command to be resent
+    Resend,
+    UnexpectedResponse(u8),
+}
+
+/// The ports of the controller which have working devices attached
+#[derive(Debug, Copy, Clone)]
+pub struct Ports {
+    pub first: bool,
+    pub second: bool,
+}
+
+/// A port of the controller, which a device is attached to. The keyboard is usually attached to the
+/// first port and the mouse to the second.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DevicePort {
+    First,
+    Second,
+}
+
+/// Initializes the controller and the keyboard and mouse attached to it. Interrupts must be
+/// enabled, as the PIT is used for timeouts.
+pub fn init() {
+    info!("ps2: initializing");
+
+    // SAFETY: the controller is only initialized once, before its IRQs are enabled
+    let ports = match unsafe { init_controller() } {
+        Ok(ports) => ports,
+        Err(e) => {
+            warn!("ps2: couldn't initialize controller: {:?}", e);
+            return;
+        }
+    };
+
+    debug!("ps2: controller initialized, ports: {:?}", ports);
+
+    // Both devices are initialized before either's IRQ is enabled, so that neither IRQ handler
+    // takes the other device's responses
+    let keyboard = ports.first
+        && match keyboard::init(translation_enabled()) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("keyboard: couldn't initialize: {:?}", e);
+                false
+            }
+        };
+
+    let mouse = ports.second
+        && match mouse::init() {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("mouse: couldn't initialize: {:?}", e);
+                false
+            }
+        };
+
+    if keyboard {
+        interrupts::enable_irq(Irq::Ps2Keyboard);
+        info!("keyboard: ready");
+    }
+
+    if mouse {
+        interrupts::enable_irq(Irq::Ps2Mouse);
+        info!("mouse: ready");
+    }
+}
+
+/// # Safety
+///
+/// Must only be called once, with the controller's IRQs masked.
+unsafe fn init_controller() -> Result<Ports, Ps2Error> {
+    // Disable the devices so they don't send data in the middle of initialization
+    write_command(COMMAND_DISABLE_FIRST_PORT)?;
+    write_command(COMMAND_DISABLE_SECOND_PORT)?;
+    flush();
+
+    // Disable IRQs while testing. Translation is left as the firmware set it up, as some
+    // keyboards only support scancode set 1.
+    let config = read_config()?;
+    write_config(config & !(CONFIG_FIRST_PORT_IRQ | CONFIG_SECOND_PORT_IRQ))?;
+
+    write_command(COMMAND_SELF_TEST)?;
+    match read_data()? {
+        SELF_TEST_PASSED => (),
+        response => return Err(Ps2Error::SelfTestFailed(response)),
+    }
+
+    // The self test may reset the controller, so the configuration is restored
+    write_config(config & !(CONFIG_FIRST_PORT_IRQ | CONFIG_SECOND_PORT_IRQ))?;
+
+    // If the second port's clock is still disabled after enabling it, there is no second port
+    write_command(COMMAND_ENABLE_SECOND_PORT)?;
+    let dual_channel = read_config()? & CONFIG_SECOND_PORT_CLOCK_DISABLED == 0;
+    if dual_channel {
+        write_command(COMMAND_DISABLE_SECOND_PORT)?;
+    }
+
+    let ports = Ports {
+        first: test_port(COMMAND_TEST_FIRST_PORT)?,
+        second: dual_channel && test_port(COMMAND_TEST_SECOND_PORT)?,
+    };
+
+    if !ports.first && !ports.second {
+        return Ok(ports);
+    }
+
+    let mut config = read_config()?;
+
+    if ports.first {
+        write_command(COMMAND_ENABLE_FIRST_PORT)?;
+        config |= CONFIG_FIRST_PORT_IRQ;
+    }
+
+    if ports.second {
+        write_command(COMMAND_ENABLE_SECOND_PORT)?;
+        config |= CONFIG_SECOND_PORT_IRQ;
+    }
+
+    write_config(config)?;
+
+    Ok(ports)
+}
+
+unsafe fn test_port(command: u8) -> Result<bool, Ps2Error> {
+    write_command(command)?;
+
+    match read_data()? {
+        PORT_TEST_PASSED => Ok(true),
+        response => {
+            warn!("ps2: port test failed: 0x{:x}", response);
+            Ok(false)
+        }
+    }
+}
+
+fn translation_enabled() -> bool {
+    // SAFETY: reading the configuration has no side effects
+    unsafe { read_config() }
+        .map(|config| config & CONFIG_TRANSLATION != 0)
+        .unwrap_or(false)
+}
+
+/// Sends a command to the device on the given port, resending it if the device asks to. Returns
+/// once the device acknowledges it.
+///
+/// # Safety
+///
+/// The device's IRQ must be masked, or its handler must expect the acknowledgement.
+pub unsafe fn send_command(port: DevicePort, byte: u8) -> Result<(), Ps2Error> {
+    for _ in 0..MAX_RESENDS {
+        write_device(port, byte)?;
+
+        match read_data()? {
+            DEVICE_ACK => return Ok(()),
+            DEVICE_RESEND => continue,
+            response => return Err(Ps2Error::UnexpectedResponse(response)),
+        }
+    }
+
+    Err(Ps2Error::Resend)
+}
+
+/// Resets the device on the given port and waits for it to pass its self test.
+///
+/// # Safety
+///
+/// The device's IRQ must be masked.
+pub unsafe fn reset_device(port: DevicePort) -> Result<(), Ps2Error> {
+    send_command(port, DEVICE_RESET)?;
+
+    match read_data_timeout(RESET_TIMEOUT_MS)? {
+        DEVICE_SELF_TEST_PASSED => Ok(()),
+        response => Err(Ps2Error::SelfTestFailed(response)),
+    }
+}
+
+unsafe fn read_config() -> Result<u8, Ps2Error> {
+    write_command(COMMAND_READ_CONFIG)?;
+    read_data()
+}
+
+unsafe fn write_config(config: u8) -> Result<(), Ps2Error> {
+    write_command(COMMAND_WRITE_CONFIG)?;
+    write_data(config)
+}
+
+/// Discards any data waiting to be read
+unsafe fn flush() {
+    while status() & STATUS_OUTPUT_FULL != 0 {
+        Port::<u8>::new(DATA_PORT).read();
+    }
+}
+
+fn status() -> u8 {
+    // SAFETY: reading the status register has no side effects
+    unsafe { Port::<u8>::new(COMMAND_PORT).read() }
+}
+
+/// Waits until `condition` holds for the status register
+fn wait_for(condition: impl Fn(u8) -> bool, timeout_ms: usize) -> Result<(), Ps2Error> {
+    let deadline = pit::time_ms() + timeout_ms;
+
+    for _ in 0..MAX_POLLS {
+        if condition(status()) {
+            return Ok(());
+        }
+
+        if pit::time_ms() >= deadline {
+            break;
+        }
+    }
+
+    Err(Ps2Error::Timeout)
+}
+
+unsafe fn write_command(command: u8) -> Result<(), Ps2Error> {
+    wait_for(|status| status & STATUS_INPUT_FULL == 0, TIMEOUT_MS)?;
+    Port::<u8>::new(COMMAND_PORT).write(command);
+    Ok(())
+}
+
+unsafe fn write_data(data: u8) -> Result<(), Ps2Error> {
+    wait_for(|status| status & STATUS_INPUT_FULL == 0, TIMEOUT_MS)?;
+    Port::<u8>::new(DATA_PORT).write(data);
+    Ok(())
+}
+
+/// Writes a byte to the device on the given port
+pub unsafe fn write_device(port: DevicePort, data: u8) -> Result<(), Ps2Error> {
+    if port == DevicePort::Second {
+        write_command(COMMAND_WRITE_SECOND_PORT)?;
+    }
+
+    write_data(data)
+}
+
+pub unsafe fn read_data() -> Result<u8, Ps2Error> {
+    read_data_timeout(TIMEOUT_MS)
+}
+
+unsafe fn read_data_timeout(timeout_ms: usize) -> Result<u8, Ps2Error> {
+    wait_for(|status| status & STATUS_OUTPUT_FULL != 0, timeout_ms)?;
+    Ok(Port::<u8>::new(DATA_PORT).read())
+}
+
+/// Reads the byte which raised an IRQ. Must only be called from the IRQ handler.
+pub fn read_irq_data() -> u8 {
+    // SAFETY: the controller raised the IRQ because there is data to read
+    unsafe { Port::<u8>::new(DATA_PORT).read() }
+}
@@ -0,0 +1,162 @@
+//! The PS/2 keyboard on the controller's first port. Key presses are decoded in its IRQ handler
+//! and queued as input events.
+
+use super::scancode::{Decoder, KeyChange, ScancodeSet};
+use crate::input::{self, keys, InputEvent, KeyCode, KeyEvent, Modifiers};
+use crate::interrupts::{self, Irq};
+use crate::ps2::{self, DevicePort, Ps2Error, DEVICE_ACK, DEVICE_ENABLE_SCANNING, DEVICE_RESEND};
+use crate::vga;
+use spin::Mutex;
+
+const COMMAND_SET_LEDS: u8 = 0xED;
+
+const LED_SCROLL_LOCK: u8 = 1;
+const LED_NUM_LOCK: u8 = 1 << 1;
+const LED_CAPS_LOCK: u8 = 1 << 2;
+
+/// Sent by the keyboard on a buffer overrun or other error
+const ERROR: u8 = 0x00;
+const ERROR_ALTERNATE: u8 = 0xFF;
+const ECHO: u8 = 0xEE;
+
+/// Only locked from the IRQ handler once the keyboard is initialized
+static KEYBOARD: Mutex<Keyboard> = Mutex::new(Keyboard::new(ScancodeSet::Set1));
+
+struct Keyboard {
+    decoder: Decoder,
+    modifiers: Modifiers,
+    /// Lock keys which are held down, so that they are only toggled once when they repeat
+    held_locks: Modifiers,
+    /// LED state to send once the keyboard acknowledges the set LEDs command
+    pending_leds: Option<u8>,
+}
+
+/// Resets the keyboard and registers its IRQ handler. If `translation` is set, the controller
+/// translates scancodes to set 1, and otherwise the keyboard's default, set 2, is used.
+pub fn init(translation: bool) -> Result<(), Ps2Error> {
+    // SAFETY: the keyboard's IRQ has not been enabled yet
+    unsafe {
+        ps2::reset_device(DevicePort::First)?;
+        ps2::send_command(DevicePort::First, DEVICE_ENABLE_SCANNING)?;
+    }
+
+    let set = if translation {
+        ScancodeSet::Set1
+    } else {
+        ScancodeSet::Set2
+    };
+
+    debug!("keyboard: using scancode {:?}", set);
+    *KEYBOARD.lock() = Keyboard::new(set);
+
+    interrupts::listen(Irq::Ps2Keyboard, handle_irq);
+
+    Ok(())
+}
+
+fn handle_irq() {
+    let byte = ps2::read_irq_data();
+    KEYBOARD.lock().handle_byte(byte);
+}
+
+impl Keyboard {
+    const fn new(set: ScancodeSet) -> Keyboard {
+        Keyboard {
+            decoder: Decoder::new(set),
+            modifiers: Modifiers::empty(),
+            held_locks: Modifiers::empty(),
+            pending_leds: None,
+        }
+    }
+
+    fn handle_byte(&mut self, byte: u8) {
+        match byte {
+            DEVICE_ACK if self.pending_leds.is_some() => {
+                let leds = self.pending_leds.take().unwrap();
+
+                // SAFETY: the keyboard is waiting for the LED state
+                let _ = unsafe { ps2::write_device(DevicePort::First, leds) };
+            }
+            // Acknowledgements of the LED state itself, and other responses which aren't keys
+            DEVICE_ACK | DEVICE_RESEND | ERROR | ERROR_ALTERNATE | ECHO => (),
+            _ => {
+                if let Some(change) = self.decoder.feed(byte) {
+                    self.handle_key(change);
+                }
+            }
+        }
+    }
+
+    fn handle_key(&mut self, change: KeyChange) {
+        let KeyChange { key, pressed } = change;
+
+        if let Some(modifier) = key.modifier() {
+            self.modifiers.set(modifier, pressed);
+        }
+
+        if let Some(lock) = key.lock() {
+            if pressed && !self.held_locks.contains(lock) {
+                self.modifiers.toggle(lock);
+                self.update_leds();
+            }
+
+            self.held_locks.set(lock, pressed);
+        }
+
+        // Shift+PageUp and Shift+PageDown scroll the screen rather than going to userspace
+        if self.modifiers.intersects(Modifiers::SHIFT) {
+            match key {
+                KeyCode::PageUp if pressed => return vga::request_scroll(1),
+                KeyCode::PageDown if pressed => return vga::request_scroll(-1),
+                KeyCode::PageUp | KeyCode::PageDown => return,
+                _ => (),
+            }
+        }
+
+        let character = if pressed {
+            keys::us_layout(key, self.modifiers)
+        } else {
+            None
+        };
+
+        input::push(InputEvent::Key(KeyEvent {
+            key,
+            pressed,
+            modifiers: self.modifiers,
+            character,
+        }));
+
+        // The pause key only sends a press
+        if key == KeyCode::Pause {
+            input::push(InputEvent::Key(KeyEvent {
+                key,
+                pressed: false,
+                modifiers: self.modifiers,
+                character: None,
+            }));
+        }
+    }
+
+    /// Sets the keyboard's LEDs to the state of the lock keys. The state is sent once the keyboard
+    /// acknowledges the command.
+    fn update_leds(&mut self) {
+        let mut leds = 0;
+
+        if self.modifiers.contains(Modifiers::SCROLL_LOCK) {
+            leds |= LED_SCROLL_LOCK;
+        }
+
+        if self.modifiers.contains(Modifiers::NUM_LOCK) {
+            leds |= LED_NUM_LOCK;
+        }
+
+        if self.modifiers.contains(Modifiers::CAPS_LOCK) {
+            leds |= LED_CAPS_LOCK;
+        }
+
+        self.pending_leds = Some(leds);
+
+        // SAFETY: the acknowledgement is handled by the IRQ handler
+        let _ = unsafe { ps2::write_device(DevicePort::First, COMMAND_SET_LEDS) };
+    }
+}
@@ -0,0 +1,151 @@
+//! The PS/2 mouse on the controller's second port. Movement packets are decoded in its IRQ handler
+//! and queued as input events.
+//! From: [OsDev Wiki](https://wiki.osdev.org/PS/2_Mouse)
+
+use crate::input::{self, InputEvent, MouseButtons, MouseEvent};
+use crate::interrupts::{self, Irq};
+use crate::ps2::{self, DevicePort, Ps2Error, DEVICE_ENABLE_SCANNING};
+use spin::Mutex;
+
+const COMMAND_GET_ID: u8 = 0xF2;
+const COMMAND_SET_SAMPLE_RATE: u8 = 0xF3;
+const COMMAND_SET_DEFAULTS: u8 = 0xF6;
+
+const ID_STANDARD: u8 = 0x00;
+/// Sent by IntelliMouse compatible mice, which send a fourth byte with the scroll wheel's movement
+const ID_WHEEL: u8 = 0x03;
+
+/// Setting these sample rates in turn enables the scroll wheel of an IntelliMouse compatible mouse
+const WHEEL_SAMPLE_RATE_SEQUENCE: [u8; 3] = [200, 100, 80];
+const SAMPLE_RATE: u8 = 100;
+
+const PACKET_LEFT_BUTTON: u8 = 1;
+const PACKET_RIGHT_BUTTON: u8 = 1 << 1;
+const PACKET_MIDDLE_BUTTON: u8 = 1 << 2;
+/// Always set in the first byte of a packet, used to find the start of packets
+const PACKET_ALWAYS_ONE: u8 = 1 << 3;
+const PACKET_X_SIGN: u8 = 1 << 4;
+const PACKET_Y_SIGN: u8 = 1 << 5;
+const PACKET_X_OVERFLOW: u8 = 1 << 6;
+const PACKET_Y_OVERFLOW: u8 = 1 << 7;
+
+/// Only locked from the IRQ handler once the mouse is initialized
+static MOUSE: Mutex<Mouse> = Mutex::new(Mouse::new(false));
+
+struct Mouse {
+    packet: [u8; 4],
+    received: usize,
+    has_wheel: bool,
+}
+
+/// Resets the mouse, enables its scroll wheel if it has one, and registers its IRQ handler
+pub fn init() -> Result<(), Ps2Error> {
+    // SAFETY: the mouse's IRQ has not been enabled yet
+    let has_wheel = unsafe {
+        ps2::reset_device(DevicePort::Second)?;
+
+        // The mouse sends its id after passing its self test
+        match ps2::read_data()? {
+            ID_STANDARD | ID_WHEEL => (),
+            id => return Err(Ps2Error::UnexpectedResponse(id)),
+        }
+
+        ps2::send_command(DevicePort::Second, COMMAND_SET_DEFAULTS)?;
+
+        for rate in WHEEL_SAMPLE_RATE_SEQUENCE.iter() {
+            set_sample_rate(*rate)?;
+        }
+
+        ps2::send_command(DevicePort::Second, COMMAND_GET_ID)?;
+        let has_wheel = ps2::read_data()? == ID_WHEEL;
+
+        set_sample_rate(SAMPLE_RATE)?;
+        ps2::send_command(DevicePort::Second, DEVICE_ENABLE_SCANNING)?;
+
+        has_wheel
+    };
+
+    debug!("mouse: scroll wheel: {}", has_wheel);
+    *MOUSE.lock() = Mouse::new(has_wheel);
+
+    interrupts::listen(Irq::Ps2Mouse, handle_irq);
+
+    Ok(())
+}
+
+unsafe fn set_sample_rate(rate: u8) -> Result<(), Ps2Error> {
+    ps2::send_command(DevicePort::Second, COMMAND_SET_SAMPLE_RATE)?;
+    ps2::send_command(DevicePort::Second, rate)
+}
+
+fn handle_irq() {
+    let byte = ps2::read_irq_data();
+    MOUSE.lock().handle_byte(byte);
+}
+
+impl Mouse {
+    const fn new(has_wheel: bool) -> Mouse {
+        Mouse {
+            packet: [0; 4],
+            received: 0,
+            has_wheel,
+        }
+    }
+
+    fn packet_len(&self) -> usize {
+        if self.has_wheel {
+            4
+        } else {
+            3
+        }
+    }
+
+    fn handle_byte(&mut self, byte: u8) {
+        // Resynchronise if a byte was lost, by discarding bytes until one could start a packet
+        if self.received == 0 && byte & PACKET_ALWAYS_ONE == 0 {
+            return;
+        }
+
+        self.packet[self.received] = byte;
+        self.received += 1;
+
+        if self.received == self.packet_len() {
+            self.received = 0;
+
+            if let Some(event) = self.decode_packet() {
+                input::push(InputEvent::Mouse(event));
+            }
+        }
+    }
+
+    /// Decodes the packet, unless its movement overflowed
+    fn decode_packet(&self) -> Option<MouseEvent> {
+        let flags = self.packet[0];
+
+        if flags & (PACKET_X_OVERFLOW | PACKET_Y_OVERFLOW) != 0 {
+            return None;
+        }
+
+        let mut buttons = MouseButtons::empty();
+        buttons.set(MouseButtons::LEFT, flags & PACKET_LEFT_BUTTON != 0);
+        buttons.set(MouseButtons::RIGHT, flags & PACKET_RIGHT_BUTTON != 0);
+        buttons.set(MouseButtons::MIDDLE, flags & PACKET_MIDDLE_BUTTON != 0);
+
+        // The movement is 9 bit two's complement, with the sign bit in the first byte
+        let dx = self.packet[1] as i32 - if flags & PACKET_X_SIGN != 0 { 256 } else { 0 };
+        let dy = self.packet[2] as i32 - if flags & PACKET_Y_SIGN != 0 { 256 } else { 0 };
+        let wheel = if self.has_wheel {
+            self.packet[3] as i8 as i32
+        } else {
+            0
+        };
+
+        Some(MouseEvent {
+            buttons,
+            dx,
+            // The mouse counts upwards movement as positive, but the screen counts downwards
+            dy: -dy,
+            wheel,
+        })
+    }
+}
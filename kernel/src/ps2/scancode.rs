@@ -0,0 +1,354 @@
+//! Decoding of scancode sets 1 and 2 into key codes.
+//! From: [OsDev Wiki](https://wiki.osdev.org/PS/2_Keyboard)
+
+use crate::input::KeyCode;
+
+const PREFIX_EXTENDED: u8 = 0xE0;
+/// Starts the pause key's sequence, which has no release
+const PREFIX_PAUSE: u8 = 0xE1;
+/// Scancode set 2's prefix for a key release
+const SET_2_PREFIX_RELEASE: u8 = 0xF0;
+/// Scancode set 1 releases are the press with this bit set
+const SET_1_RELEASE: u8 = 0x80;
+
+/// The number of bytes following `PREFIX_PAUSE` in each set
+const SET_1_PAUSE_LEN: u8 = 5;
+const SET_2_PAUSE_LEN: u8 = 7;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ScancodeSet {
+    Set1,
+    Set2,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct KeyChange {
+    pub key: KeyCode,
+    pub pressed: bool,
+}
+
+/// Turns a stream of scancode bytes into key presses and releases
+pub struct Decoder {
+    set: ScancodeSet,
+    extended: bool,
+    release: bool,
+    /// The bytes left to skip of the pause key's sequence
+    pause_remaining: u8,
+}
+
+impl Decoder {
+    pub const fn new(set: ScancodeSet) -> Decoder {
+        Decoder {
+            set,
+            extended: false,
+            release: false,
+            pause_remaining: 0,
+        }
+    }
+
+    /// Feeds the next byte from the keyboard to the decoder. Returns the key change once a whole
+    /// scancode has been read, if it is one which is understood.
+    pub fn feed(&mut self, byte: u8) -> Option<KeyChange> {
+        if self.pause_remaining > 0 {
+            self.pause_remaining -= 1;
+
+            return if self.pause_remaining == 0 {
+                Some(KeyChange {
+                    key: KeyCode::Pause,
+                    pressed: true,
+                })
+            } else {
+                None
+            };
+        }
+
+        match byte {
+            PREFIX_EXTENDED => {
+                self.extended = true;
+                return None;
+            }
+            PREFIX_PAUSE => {
+                self.pause_remaining = match self.set {
+                    ScancodeSet::Set1 => SET_1_PAUSE_LEN,
+                    ScancodeSet::Set2 => SET_2_PAUSE_LEN,
+                };
+                return None;
+            }
+            SET_2_PREFIX_RELEASE if self.set == ScancodeSet::Set2 => {
+                self.release = true;
+                return None;
+            }
+            _ => (),
+        }
+
+        let (extended, release) = (self.extended, self.release);
+        self.extended = false;
+        self.release = false;
+
+        let (code, pressed) = match self.set {
+            ScancodeSet::Set1 => (byte & !SET_1_RELEASE, byte & SET_1_RELEASE == 0),
+            ScancodeSet::Set2 => (byte, !release),
+        };
+
+        let key = match (self.set, extended) {
+            (ScancodeSet::Set1, false) => set_1(code),
+            (ScancodeSet::Set1, true) => set_1_extended(code),
+            (ScancodeSet::Set2, false) => set_2(code),
+            (ScancodeSet::Set2, true) => set_2_extended(code),
+        }?;
+
+        Some(KeyChange { key, pressed })
+    }
+}
+
+fn set_1(code: u8) -> Option<KeyCode> {
+    use crate::input::KeyCode::*;
+
+    let key = match code {
+        0x01 => Escape,
+        0x02 => Digit1,
+        0x03 => Digit2,
+        0x04 => Digit3,
+        0x05 => Digit4,
+        0x06 => Digit5,
+        0x07 => Digit6,
+        0x08 => Digit7,
+        0x09 => Digit8,
+        0x0A => Digit9,
+        0x0B => Digit0,
+        0x0C => Minus,
+        0x0D => Equals,
+        0x0E => Backspace,
+        0x0F => Tab,
+        0x10 => Q,
+        0x11 => W,
+        0x12 => E,
+        0x13 => R,
+        0x14 => T,
+        0x15 => Y,
+        0x16 => U,
+        0x17 => I,
+        0x18 => O,
+        0x19 => P,
+        0x1A => LeftBracket,
+        0x1B => RightBracket,
+        0x1C => Enter,
+        0x1D => LeftCtrl,
+        0x1E => A,
+        0x1F => S,
+        0x20 => D,
+        0x21 => F,
+        0x22 => G,
+        0x23 => H,
+        0x24 => J,
+        0x25 => K,
+        0x26 => L,
+        0x27 => Semicolon,
+        0x28 => Quote,
+        0x29 => Backtick,
+        0x2A => LeftShift,
+        0x2B => Backslash,
+        0x2C => Z,
+        0x2D => X,
+        0x2E => C,
+        0x2F => V,
+        0x30 => B,
+        0x31 => N,
+        0x32 => M,
+        0x33 => Comma,
+        0x34 => Period,
+        0x35 => Slash,
+        0x36 => RightShift,
+        0x37 => KeypadAsterisk,
+        0x38 => LeftAlt,
+        0x39 => Space,
+        0x3A => CapsLock,
+        0x3B => F1,
+        0x3C => F2,
+        0x3D => F3,
+        0x3E => F4,
+        0x3F => F5,
+        0x40 => F6,
+        0x41 => F7,
+        0x42 => F8,
+        0x43 => F9,
+        0x44 => F10,
+        0x45 => NumLock,
+        0x46 => ScrollLock,
+        0x47 => Keypad7,
+        0x48 => Keypad8,
+        0x49 => Keypad9,
+        0x4A => KeypadMinus,
+        0x4B => Keypad4,
+        0x4C => Keypad5,
+        0x4D => Keypad6,
+        0x4E => KeypadPlus,
+        0x4F => Keypad1,
+        0x50 => Keypad2,
+        0x51 => Keypad3,
+        0x52 => Keypad0,
+        0x53 => KeypadPeriod,
+        0x56 => NonUsBackslash,
+        0x57 => F11,
+        0x58 => F12,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+/// Keys prefixed by `PREFIX_EXTENDED` in set 1. The fake shifts sent around some of these keys
+/// (0x2A and 0x36) are deliberately not mapped, and so are ignored.
+fn set_1_extended(code: u8) -> Option<KeyCode> {
+    use crate::input::KeyCode::*;
+
+    let key = match code {
+        0x1C => KeypadEnter,
+        0x1D => RightCtrl,
+        0x35 => KeypadSlash,
+        0x37 => PrintScreen,
+        0x38 => RightAlt,
+        0x47 => Home,
+        0x48 => Up,
+        0x49 => PageUp,
+        0x4B => Left,
+        0x4D => Right,
+        0x4F => End,
+        0x50 => Down,
+        0x51 => PageDown,
+        0x52 => Insert,
+        0x53 => Delete,
+        0x5B => LeftGui,
+        0x5C => RightGui,
+        0x5D => Menu,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+fn set_2(code: u8) -> Option<KeyCode> {
+    use crate::input::KeyCode::*;
+
+    let key = match code {
+        0x01 => F9,
+        0x03 => F5,
+        0x04 => F3,
+        0x05 => F1,
+        0x06 => F2,
+        0x07 => F12,
+        0x09 => F10,
+        0x0A => F8,
+        0x0B => F6,
+        0x0C => F4,
+        0x0D => Tab,
+        0x0E => Backtick,
+        0x11 => LeftAlt,
+        0x12 => LeftShift,
+        0x14 => LeftCtrl,
+        0x15 => Q,
+        0x16 => Digit1,
+        0x1A => Z,
+        0x1B => S,
+        0x1C => A,
+        0x1D => W,
+        0x1E => Digit2,
+        0x21 => C,
+        0x22 => X,
+        0x23 => D,
+        0x24 => E,
+        0x25 => Digit4,
+        0x26 => Digit3,
+        0x29 => Space,
+        0x2A => V,
+        0x2B => F,
+        0x2C => T,
+        0x2D => R,
+        0x2E => Digit5,
+        0x31 => N,
+        0x32 => B,
+        0x33 => H,
+        0x34 => G,
+        0x35 => Y,
+        0x36 => Digit6,
+        0x3A => M,
+        0x3B => J,
+        0x3C => U,
+        0x3D => Digit7,
+        0x3E => Digit8,
+        0x41 => Comma,
+        0x42 => K,
+        0x43 => I,
+        0x44 => O,
+        0x45 => Digit0,
+        0x46 => Digit9,
+        0x49 => Period,
+        0x4A => Slash,
+        0x4B => L,
+        0x4C => Semicolon,
+        0x4D => P,
+        0x4E => Minus,
+        0x52 => Quote,
+        0x54 => LeftBracket,
+        0x55 => Equals,
+        0x58 => CapsLock,
+        0x59 => RightShift,
+        0x5A => Enter,
+        0x5B => RightBracket,
+        0x5D => Backslash,
+        0x61 => NonUsBackslash,
+        0x66 => Backspace,
+        0x69 => Keypad1,
+        0x6B => Keypad4,
+        0x6C => Keypad7,
+        0x70 => Keypad0,
+        0x71 => KeypadPeriod,
+        0x72 => Keypad2,
+        0x73 => Keypad5,
+        0x74 => Keypad6,
+        0x75 => Keypad8,
+        0x76 => Escape,
+        0x77 => NumLock,
+        0x78 => F11,
+        0x79 => KeypadPlus,
+        0x7A => Keypad3,
+        0x7B => KeypadMinus,
+        0x7C => KeypadAsterisk,
+        0x7D => Keypad9,
+        0x7E => ScrollLock,
+        0x83 => F7,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+/// Keys prefixed by `PREFIX_EXTENDED` in set 2. The fake shifts sent around some of these keys
+/// (0x12 and 0x59) are deliberately not mapped, and so are ignored.
+fn set_2_extended(code: u8) -> Option<KeyCode> {
+    use crate::input::KeyCode::*;
+
+    let key = match code {
+        0x11 => RightAlt,
+        0x14 => RightCtrl,
+        0x1F => LeftGui,
+        0x27 => RightGui,
+        0x2F => Menu,
+        0x4A => KeypadSlash,
+        0x5A => KeypadEnter,
+        0x69 => End,
+        0x6B => Left,
+        0x6C => Home,
+        0x70 => Insert,
+        0x71 => Delete,
+        0x72 => Down,
+        0x74 => Right,
+        0x75 => Up,
+        0x7A => PageDown,
+        0x7C => PrintScreen,
+        0x7D => PageUp,
+        _ => return None,
+    };
+
+    Some(key)
+}
@@ -0,0 +1,182 @@
+//! CMOS real-time clock driver, used to find out the wall-clock time at boot.
+//! From: [OsDev Wiki](https://wiki.osdev.org/CMOS)
+
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+pub static RTC: Mutex<Rtc> = unsafe { Mutex::new(Rtc::new()) };
+
+const REGISTER_SECONDS: u8 = 0x00;
+const REGISTER_MINUTES: u8 = 0x02;
+const REGISTER_HOURS: u8 = 0x04;
+const REGISTER_DAY_OF_MONTH: u8 = 0x07;
+const REGISTER_MONTH: u8 = 0x08;
+const REGISTER_YEAR: u8 = 0x09;
+const REGISTER_STATUS_A: u8 = 0x0A;
+const REGISTER_STATUS_B: u8 = 0x0B;
+
+/// Set in status register A while the RTC is updating its registers
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// Set in status register B if the hours are in 24 hour format
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+/// Set in status register B if values are in binary instead of BCD
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// Set in the hours register in 12 hour mode if it is PM
+const HOUR_PM: u8 = 1 << 7;
+
+/// Used when the FADT does not specify a century register
+const DEFAULT_CENTURY: u64 = 20;
+
+/// A date and time as read from the RTC. Assumed to be UTC.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DateTime {
+    pub year: u64,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Seconds since the unix epoch (1970-01-01T00:00:00Z), or None if this isn't a valid date and
+    /// time since then, as happens if the RTC was never set
+    pub fn unix_timestamp(&self) -> Option<u64> {
+        if self.hour >= 24 || self.minute >= 60 || self.second >= 60 {
+            return None;
+        }
+
+        let days = days_since_epoch(self.year, self.month as u64, self.day as u64)?;
+
+        Some(days * 86400 + self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64)
+    }
+}
+
+/// Days between the unix epoch and a given date in the proleptic Gregorian calendar, or None if the
+/// date is before the epoch, or the month or day is out of range.
+/// From: [Howard Hinnant's date algorithms](http://howardhinnant.github.io/date_algorithms.html)
+fn days_since_epoch(year: u64, month: u64, day: u64) -> Option<u64> {
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12; // March = 0
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    Some(era * 146097 + day_of_era - 719468)
+}
+
+/// The raw register values read from the CMOS, before any format conversion
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: Option<u8>,
+}
+
+pub struct Rtc {
+    address_port: Port<u8>,
+    data_port: Port<u8>,
+    /// The CMOS register holding the century, as given by the ACPI FADT
+    century_register: Option<u8>,
+}
+
+impl Rtc {
+    const unsafe fn new() -> Rtc {
+        Rtc {
+            address_port: Port::new(0x70),
+            data_port: Port::new(0x71),
+            century_register: None,
+        }
+    }
+
+    /// Sets the CMOS register which holds the century. A register of zero means that there is none.
+    pub fn set_century_register(&mut self, register: u8) {
+        self.century_register = if register != 0 { Some(register) } else { None };
+    }
+
+    fn read_register(&mut self, register: u8) -> u8 {
+        unsafe {
+            self.address_port.write(register);
+            self.data_port.read()
+        }
+    }
+
+    fn update_in_progress(&mut self) -> bool {
+        self.read_register(REGISTER_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0
+    }
+
+    fn read_raw(&mut self) -> RawTime {
+        while self.update_in_progress() {}
+
+        RawTime {
+            second: self.read_register(REGISTER_SECONDS),
+            minute: self.read_register(REGISTER_MINUTES),
+            hour: self.read_register(REGISTER_HOURS),
+            day: self.read_register(REGISTER_DAY_OF_MONTH),
+            month: self.read_register(REGISTER_MONTH),
+            year: self.read_register(REGISTER_YEAR),
+            century: self.century_register.map(|reg| self.read_register(reg)),
+        }
+    }
+
+    /// Reads the current date and time from the RTC
+    pub fn read(&mut self) -> DateTime {
+        // Keep reading until two consecutive reads agree, so that an update happening midway
+        // through a read cannot give us an inconsistent time
+        let mut last = self.read_raw();
+        let raw = loop {
+            let current = self.read_raw();
+            if current == last {
+                break current;
+            }
+            last = current;
+        };
+
+        let status_b = self.read_register(REGISTER_STATUS_B);
+        let binary = status_b & STATUS_B_BINARY != 0;
+        let to_binary = |value: u8| {
+            if binary {
+                value
+            } else {
+                bcd_to_binary(value)
+            }
+        };
+
+        let mut hour = to_binary(raw.hour & !HOUR_PM);
+
+        // 12 hour mode: 12am is 0h and 12pm is 12h
+        if status_b & STATUS_B_24_HOUR == 0 {
+            let pm = raw.hour & HOUR_PM != 0;
+            hour %= 12;
+
+            if pm {
+                hour += 12;
+            }
+        }
+
+        let century = raw.century.map(|c| to_binary(c) as u64);
+        let year = century.unwrap_or(DEFAULT_CENTURY) * 100 + to_binary(raw.year) as u64;
+
+        DateTime {
+            year,
+            month: to_binary(raw.month),
+            day: to_binary(raw.day),
+            hour,
+            minute: to_binary(raw.minute),
+            second: to_binary(raw.second),
+        }
+    }
+}
+
+const fn bcd_to_binary(bcd: u8) -> u8 {
+    (bcd & 0x0F) + ((bcd >> 4) * 10)
+}
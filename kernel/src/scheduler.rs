@@ -0,0 +1,154 @@
+//! A round-robin scheduler. The running process is switched out when it blocks in a system call,
+//! or is preempted by the PIT's interrupt once its time slice is used up or a timer has expired.
+
+use crate::process::{self, Delivery, ExitStatus, Process, ProcessId, PROCESSES};
+use crate::syscall::{self, UserContext};
+use crate::timer::{self, Deadline};
+use crate::{block, console, input, interrupts, net, pit, vga};
+use alloc::collections::VecDeque;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+/// How long a process may run in user mode before it is preempted
+const TIME_SLICE_MS: u64 = 10;
+
+lazy_static::lazy_static! {
+    static ref RUN_QUEUE: Mutex<VecDeque<ProcessId>> = Mutex::new(VecDeque::new());
+}
+
+// TODO(SMP): per-cpu
+static CURRENT: Mutex<Option<ProcessId>> = Mutex::new(None);
+/// When the running process's time slice started, on the monotonic clock
+static SLICE_START_MS: AtomicU64 = AtomicU64::new(0);
+
+/// The process which is currently running, if any
+pub fn current() -> Option<ProcessId> {
+    *CURRENT.lock()
+}
+
+/// Adds a runnable process to the back of the run queue
+pub fn enqueue(pid: ProcessId) {
+    RUN_QUEUE.lock().push_back(pid);
+}
+
+/// Blocks the current process in its system call until it is woken with [wake], or until the
+/// deadline passes. The system call returns the value given to [wake], or the deadline's result.
+///
+/// No locks may be held when calling this, as it never returns.
+pub fn block_current(context: &UserContext, deadline: Option<Deadline>) -> ! {
+    block_current_on(context, deadline, |_, _| ())
+}
+
+/// Like [block_current], but first gives the process id and block id to `register`, so that they
+/// can be kept somewhere to wake the process from, e.g in a `WaitQueue`.
+pub fn block_current_on<F>(context: &UserContext, deadline: Option<Deadline>, register: F) -> !
+where
+    F: FnOnce(ProcessId, u64),
+{
+    let pid = current().expect("No process is running");
+    let block_id = PROCESSES.get_mut(&pid).unwrap().block(*context);
+    register(pid, block_id);
+
+    if let Some(deadline) = deadline {
+        timer::add(deadline, pid, block_id);
+    }
+
+    *CURRENT.lock() = None;
+    run_next()
+}
+
+/// Wakes a process which is blocked with the given block id, making its system call return
+/// `result`. Does nothing if the process has already been woken from that block.
+pub fn wake(pid: ProcessId, block_id: u64, result: i64) {
+    let woken = match PROCESSES.get_mut(&pid) {
+        Some(mut process) => process.wake(block_id, result),
+        None => false,
+    };
+
+    if woken {
+        enqueue(pid);
+    }
+}
+
+/// Like [wake], but also copies data into the process's buffer once it is next run, e.g a message
+/// which it was blocked waiting to receive. Returns whether the process was woken, and so will get
+/// the data.
+pub fn wake_with(pid: ProcessId, block_id: u64, result: i64, delivery: Delivery) -> bool {
+    let woken = match PROCESSES.get_mut(&pid) {
+        Some(mut process) => process.wake_with(block_id, result, delivery),
+        None => false,
+    };
+
+    if woken {
+        enqueue(pid);
+    }
+
+    woken
+}
+
+/// Whether the current process should be preempted, as its time slice is used up or a timer has
+/// expired. Timers are only fired between processes, so that their wakes can take the process
+/// table's locks.
+///
+/// Must only be called when the current process was interrupted in user mode, so that no locks are
+/// held.
+pub fn should_preempt() -> bool {
+    let ran_for = (pit::time_ms() as u64).saturating_sub(SLICE_START_MS.load(Ordering::SeqCst));
+    ran_for >= TIME_SLICE_MS || timer::any_expired()
+}
+
+/// Switches out the current process, which was interrupted in user mode with the given context,
+/// putting it at the back of the run queue, and runs the next.
+///
+/// No locks may be held when calling this, as it never returns.
+pub fn preempt_current(context: &UserContext) -> ! {
+    let pid = current().expect("No process is running");
+    PROCESSES.get_mut(&pid).unwrap().preempt(*context);
+
+    *CURRENT.lock() = None;
+    enqueue(pid);
+    run_next()
+}
+
+/// Ends the current process and runs the next.
+///
+/// No locks may be held when calling this, as it never returns.
+pub fn exit_current(status: ExitStatus) -> ! {
+    let pid = current().expect("No process is running");
+    *CURRENT.lock() = None;
+    process::exit(pid, status);
+    run_next()
+}
+
+/// Runs the next runnable process, idling until there is one.
+pub fn run_next() -> ! {
+    // Whichever system call was being handled has blocked or ended its process
+    syscall::end_call();
+
+    loop {
+        timer::fire_expired();
+
+        // Interrupt handlers can't wake processes themselves, as they may have interrupted code
+        // holding the process table's locks, so it is done here instead
+        input::wake_readers();
+        interrupts::user::poll();
+        block::poll();
+        net::poll();
+        console::poll();
+        vga::poll();
+
+        let next = RUN_QUEUE.lock().pop_front();
+
+        match next {
+            // The process may have been killed since it was queued
+            Some(pid) if PROCESSES.get(&pid).is_none() => (),
+            Some(pid) => {
+                *CURRENT.lock() = Some(pid);
+                SLICE_START_MS.store(pit::time_ms() as u64, Ordering::SeqCst);
+                Process::run_by_pid(&pid).expect("Out of physical memory")
+            }
+            // Wait for the next interrupt (at worst, the next PIT tick)
+            None => unsafe { asm!("sti; hlt") },
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Receiving from the first serial port (COM1). Bytes are read in its IRQ handler into a fixed size
+//! ring buffer, which the kernel console reads lines from. Output still goes through
+//! `SERIAL_WRITER`.
+
+use crate::interrupts::{self, Irq};
+use crate::SERIAL_WRITER;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::port::Port;
+
+const COM1_DATA_PORT: u16 = 0x3F8;
+const COM1_LINE_STATUS_PORT: u16 = 0x3F8 + 5;
+
+/// Set in the line status register if there is a received byte to read
+const LINE_STATUS_DATA_READY: u8 = 1;
+
+/// Bytes which arrive while the buffer is full are dropped
+const RECEIVE_BUFFER_CAPACITY: usize = 1024;
+
+static RECEIVED: Mutex<ReceiveBuffer> = Mutex::new(ReceiveBuffer::new());
+
+struct ReceiveBuffer {
+    bytes: [u8; RECEIVE_BUFFER_CAPACITY],
+    head: usize,
+    len: usize,
+}
+
+impl ReceiveBuffer {
+    const fn new() -> ReceiveBuffer {
+        ReceiveBuffer {
+            bytes: [0; RECEIVE_BUFFER_CAPACITY],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len == RECEIVE_BUFFER_CAPACITY {
+            return;
+        }
+
+        self.bytes[(self.head + self.len) % RECEIVE_BUFFER_CAPACITY] = byte;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let byte = self.bytes[self.head];
+        self.head = (self.head + 1) % RECEIVE_BUFFER_CAPACITY;
+        self.len -= 1;
+
+        Some(byte)
+    }
+}
+
+/// Configures COM1 and enables its received data interrupt
+pub fn init() {
+    // Also enables the received data available interrupt and the IRQ line output (OUT2)
+    SERIAL_WRITER.lock().init();
+
+    interrupts::listen(Irq::Serial1, handle_irq);
+    interrupts::enable_irq(Irq::Serial1);
+}
+
+fn handle_irq() {
+    let mut line_status = Port::<u8>::new(COM1_LINE_STATUS_PORT);
+    let mut data = Port::<u8>::new(COM1_DATA_PORT);
+    let mut received = RECEIVED.lock();
+
+    // The UART's FIFO may hold several bytes. Reading them all also acknowledges the interrupt.
+    // SAFETY: only the data ready bit is relied on, and reading data only consumes received bytes
+    while unsafe { line_status.read() } & LINE_STATUS_DATA_READY != 0 {
+        received.push(unsafe { data.read() });
+    }
+}
+
+/// Takes the next received byte, if there is one
+pub fn read_byte() -> Option<u8> {
+    // Interrupts are disabled so that the IRQ handler can't try to push while the lock is held
+    without_interrupts(|| RECEIVED.lock().pop())
+}
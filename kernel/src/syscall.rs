@@ -3,14 +3,38 @@ use crate::tss::TSS;
 use core::cell::UnsafeCell;
 use x86_64::registers::model_specific::{Efer, EferFlags, LStar, SFMask, Star};
 
+use crate::block::{self, BlockDeviceInfo, BlockError, Direction, Request};
+use crate::clock::{self, Clock};
+use crate::fd::{self, File, FileTable};
 use crate::halt;
-use crate::memory::buffer::BorrowedKernelBuffer;
+use crate::initrd;
+use crate::input::{self, RawInputEvent};
+use crate::interrupts::{self, user::ListenError};
+use crate::ipc;
+use crate::memory;
+use crate::memory::buffer::{BorrowedKernelBuffer, BorrowedKernelBufferMut};
+use crate::memory::dma::DmaRegion;
 use crate::memory::paging::{EntryFlags, InvalidateTlb, Page, ZeroPage, ACTIVE_PAGE_TABLES};
+use crate::memory::physical_allocator::PHYSICAL_ALLOCATOR;
+use crate::net::{self, AttachError, NetDeviceInfo};
+use crate::notification;
+use crate::page_cache;
+use crate::pci::{self, Bar, ClaimError, CommandFlags, PciAddress, PciDeviceInfo};
+use crate::pipe;
+use crate::power;
+use crate::process::{self, Capabilities, ExitStatus, Process, ProcessId, ProcessInfo, PROCESSES};
+use crate::scheduler;
+use crate::timer::Deadline;
 use crate::vga::VGA_WRITER;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::convert::TryInto;
+use core::mem;
 use core::ptr::NonNull;
+use spin::Mutex;
 use x86_64::registers::rflags::RFlags;
-use x86_64::VirtAddr;
+use x86_64::{PhysAddr, VirtAddr};
 
 // TODO(SMP): use gs/swapgs
 /// SAFETY: always used from asm, one at a time.
@@ -19,6 +43,12 @@ static mut USER_RSP: AsmCell<u64> = AsmCell(UnsafeCell::new(0));
 #[no_mangle]
 static SYSCALL_STACK: AsmCell<u64> = AsmCell(UnsafeCell::new(0));
 
+/// The context of the system call being handled, with its instruction pointer moved back to the
+/// `syscall` instruction. Blocking with it makes the call again once the process is woken, which is
+/// how a call waits for a page of one of its buffers to be read.
+// TODO(SMP): per-cpu
+static RESTART_CONTEXT: Mutex<Option<UserContext>> = Mutex::new(None);
+
 #[repr(transparent)]
 struct AsmCell<T>(UnsafeCell<T>);
 unsafe impl<T> Send for AsmCell<T> {}
@@ -50,9 +80,55 @@ pub unsafe fn setup_syscall() {
     SFMask::write(RFlags::INTERRUPT_FLAG);
 }
 
+/// The user's registers, as saved on system call, page fault or timer interrupt entry. The result of
+/// a system call is returned in `rax`. `syscall` clobbers `rcx` and `r11` with the user's
+/// instruction pointer and flags, but a page fault or interrupt may happen anywhere, so they are
+/// kept too.
+///
+/// The layout must be kept in sync with `syscall_callback`, `exceptions::page_fault`,
+/// `preempt::tick` and `process::jump_usermode`.
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub struct UserContext {
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub r10: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub rax: u64,
+    pub rbx: u64,
+    pub rbp: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rsp: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub rcx: u64,
+    pub r11: u64,
+}
+
+impl UserContext {
+    /// The context of a process which has never been run
+    pub fn new(stack_ptr: VirtAddr, instruction_ptr: VirtAddr) -> Self {
+        UserContext {
+            rsp: stack_ptr.as_u64(),
+            rip: instruction_ptr.as_u64(),
+            rflags: RFlags::INTERRUPT_FLAG.bits(),
+            ..UserContext::default()
+        }
+    }
+
+    pub fn args(&self) -> [u64; 6] {
+        [self.rdi, self.rsi, self.rdx, self.r10, self.r8, self.r9]
+    }
+}
+
 /// # Syscall ABI
 ///
-/// Modified cdecl. Arguments are passed in `rdi, rsi, rdx, rcx, r8, r9`. `rcx` and `r11` are
+/// Modified cdecl. Arguments are passed in `rdi, rsi, rdx, r10, r8, r9`. `rcx` and `r11` are
 /// clobbered. The system call number is passed in `rax`, and the return is from `rax` too.
 #[naked]
 #[no_mangle]
@@ -64,10 +140,22 @@ pub extern "C" fn syscall_callback() {
             mov [USER_RSP], rsp // Save RSP
             mov rsp, SYSCALL_STACK
 
-            push rcx // RCX = userland IP,
+            // Push the user's context (reverse order because of struct layout)
+            push r11
+            push rcx
             push r11 // R11 = userland EFLAGS
-
-            // Push arguments (reverse order because of slice)
+            push rcx // RCX = userland IP
+            push qword ptr [USER_RSP]
+            push r15
+            push r14
+            push r13
+            push r12
+            push rbp
+            push rbx
+            push rax
+            push r9
+            push r8
+            push r10
             push rdx
             push rsi
             push rdi
@@ -75,20 +163,32 @@ pub extern "C" fn syscall_callback() {
             // Re-enable interrupts
             sti
 
-            // Make a slice out of the arguments
-            mov rsi, rsp // ptr
-            mov rdx, 3 // len
-            mov rdi, rax // syscall number
+            mov rdi, rsp // &mut UserContext
             call syscall_handler
 
-            // Pop arguments
+            // If the system call blocked, it will not return here, and the process will instead be
+            // resumed by the scheduler
+
             pop rdi
             pop rsi
             pop rdx
+            pop r10
+            pop r8
+            pop r9
+            add rsp, 8 // Skip RAX -- it holds the return value
+            pop rbx
+            pop rbp
+            pop r12
+            pop r13
+            pop r14
+            pop r15
+            add rsp, 8 // Skip RSP -- it is restored from USER_RSP below
+            pop rcx // RCX = userland IP
+            pop r11 // R11 = userland EFLAGS
+            add rsp, 16 // Skip RCX and R11 -- they were the same as RIP and RFLAGS
 
-            pop r11 // RCX = userland IP,
-            pop rcx // R11 = userland EFLAGS
-
+            // Don't take interrupts on the user's stack
+            cli
             mov rsp, [USER_RSP] // Restore user's rsp
 
             sysretq",
@@ -96,13 +196,40 @@ pub extern "C" fn syscall_callback() {
     }
 }
 
+/// The largest region which `DmaMap` maps at once
+const MAX_DMA_REGION: u64 = 4 * 1024 * 1024;
+
+/// The most data which `Write` copies from the user at once
+const MAX_WRITE: usize = 64 * 1024;
+
+/// The largest ELF file which `Spawn` loads
+const MAX_ELF: usize = 4 * 1024 * 1024;
+
 #[repr(i64)]
-enum Error {
+pub enum Error {
     InvalidBuffer = -1,
     InvalidUtf8 = -2,
     InvalidPage = -3,
     InvalidPagesLength = -4,
     OutOfMemory = -5,
+    InvalidArgument = -6,
+    PermissionDenied = -7,
+    NotFound = -8,
+    Busy = -9,
+    IoError = -10,
+    BrokenPipe = -11,
+    TimedOut = -12,
+}
+
+impl From<BlockError> for Error {
+    fn from(err: BlockError) -> Self {
+        match err {
+            BlockError::OutOfRange => Error::InvalidArgument,
+            BlockError::ReadOnly => Error::PermissionDenied,
+            BlockError::Busy => Error::Busy,
+            BlockError::Io => Error::IoError,
+        }
+    }
 }
 
 bitflags::bitflags! {
@@ -129,17 +256,41 @@ impl From<UserPageFlags> for EntryFlags {
 }
 
 #[no_mangle]
-pub extern "C" fn syscall_handler(id: u64, argv: *const u64, argc: u64) -> i64 {
-    let syscall = Syscall::from_u64(id).unwrap();
-    // SAFETY: this is correct (see asm above)
-    let args: &[u64] = unsafe { core::slice::from_raw_parts(argv, argc as usize) };
+pub extern "C" fn syscall_handler(context: &mut UserContext) -> i64 {
+    // `syscall` is two bytes long
+    let restart = UserContext {
+        rip: context.rip - 2,
+        ..*context
+    };
+
+    *RESTART_CONTEXT.lock() = Some(restart);
+    let res = dispatch(context);
+    end_call();
+    res
+}
+
+/// The context which blocks the current system call until it should be made again, or None if no
+/// system call is being handled
+pub fn restart_context() -> Option<UserContext> {
+    *RESTART_CONTEXT.lock()
+}
+
+/// Marks the current system call as having returned, or having blocked and switched to another
+/// process
+pub fn end_call() {
+    *RESTART_CONTEXT.lock() = None;
+}
+
+fn dispatch(context: &mut UserContext) -> i64 {
+    let syscall = Syscall::from_u64(context.rax).unwrap();
+    let args = context.args();
     match syscall {
         Syscall::Halt => {
             info!("Got system call halt");
             halt()
         }
         Syscall::Map => {
-            let [addr_begin, len, flags]: [u64; 3] = args[0..3].try_into().unwrap();
+            let [addr_begin, len, flags, object, offset]: [u64; 5] = args[0..5].try_into().unwrap();
 
             if addr_begin & 0xfff != 0 {
                 return Error::InvalidPage as i64;
@@ -149,9 +300,14 @@ pub extern "C" fn syscall_handler(id: u64, argv: *const u64, argc: u64) -> i64 {
                 return Error::InvalidPagesLength as i64;
             }
 
+            let flags = UserPageFlags::from_bits_truncate(flags).into();
+
+            if object != 0 {
+                return map_object(addr_begin, len, flags, object, offset);
+            }
+
             let page_begin = Page::containing_address(addr_begin);
             let page_end = page_begin + (len - 1) as usize;
-            let flags = UserPageFlags::from_bits_truncate(flags).into();
             let mut tables = ACTIVE_PAGE_TABLES.lock();
 
             // SAFETY: we are in the user's page tables
@@ -167,7 +323,7 @@ pub extern "C" fn syscall_handler(id: u64, argv: *const u64, argc: u64) -> i64 {
 
             res.map(|_| 0).unwrap_or(Error::InvalidPage as i64)
         }
-        Syscall::Unmap => {
+        Syscall::Unmap | Syscall::Msync => {
             let [addr_begin, len]: [u64; 2] = args[0..2].try_into().unwrap();
 
             if addr_begin & 0xfff != 0 {
@@ -178,7 +334,12 @@ pub extern "C" fn syscall_handler(id: u64, argv: *const u64, argc: u64) -> i64 {
                 return Error::InvalidPagesLength as i64;
             }
 
-            todo!()
+            let res = match syscall {
+                Syscall::Unmap => page_cache::unmap(context, addr_begin / 0x1000, len),
+                _ => page_cache::sync(context, addr_begin / 0x1000, len),
+            };
+
+            res.map(|_| 0).unwrap_or_else(|err| err as i64)
         }
         Syscall::Print => {
             // SAFETY: we are in the user's page tables
@@ -200,7 +361,1024 @@ pub extern "C" fn syscall_handler(id: u64, argv: *const u64, argc: u64) -> i64 {
 
             0
         }
+        Syscall::ClockGet => match Clock::from_u64(args[0]) {
+            Some(clock) => clock::now_ms(clock) as i64,
+            None => Error::InvalidArgument as i64,
+        },
+        Syscall::Sleep => {
+            let now = clock::now_ms(Clock::Monotonic);
+            sleep_until(context, now.saturating_add(args[0]))
+        }
+        Syscall::SleepUntil => match Clock::from_u64(args[0]) {
+            Some(clock) => sleep_until(context, clock::to_monotonic_ms(clock, args[1])),
+            None => Error::InvalidArgument as i64,
+        },
+        Syscall::Shutdown => {
+            if !current_has_capability(Capabilities::POWER) {
+                return Error::PermissionDenied as i64;
+            }
+
+            power::shutdown()
+        }
+        Syscall::Reboot => {
+            if !current_has_capability(Capabilities::POWER) {
+                return Error::PermissionDenied as i64;
+            }
+
+            power::reboot()
+        }
+        Syscall::PciDevices => pci_devices(args[0], args[1]),
+        Syscall::PciClaim => match PciAddress::from_u64(args[0]) {
+            Some(address) => pci_claim(address),
+            None => Error::InvalidArgument as i64,
+        },
+        Syscall::PciMapBar => match PciAddress::from_u64(args[0]) {
+            Some(address) => pci_map_bar(address, args[1], args[2]),
+            None => Error::InvalidArgument as i64,
+        },
+        Syscall::InputRead => input_read(args[0], args[1]),
+        Syscall::InputWait => {
+            if !current_has_capability(Capabilities::INPUT) {
+                return Error::PermissionDenied as i64;
+            }
+
+            input::wait(context)
+        }
+        Syscall::InitrdMap => initrd_map(args[0]),
+        Syscall::BlockDevices => block_devices(args[0], args[1]),
+        Syscall::BlockRead => block_transfer(context, Direction::Read, args),
+        Syscall::BlockWrite => block_transfer(context, Direction::Write, args),
+        Syscall::NetDevices => net_devices(args[0], args[1]),
+        Syscall::NetAttach => net_attach(args[0], args[1], args[2]),
+        Syscall::NetTransmit => net_transmit(args[0]),
+        Syscall::NotificationWait => {
+            if args[0] & notification::BITS == 0 {
+                return Error::InvalidArgument as i64;
+            }
+
+            notification::wait(context, args[0])
+        }
+        Syscall::IrqListen => irq_listen(args[0], args[1]),
+        Syscall::IrqAck => {
+            let pid = scheduler::current().unwrap();
+
+            if args[0] < 16 && interrupts::user::acknowledge(args[0] as u8, pid) {
+                0
+            } else {
+                Error::PermissionDenied as i64
+            }
+        }
+        Syscall::DmaMap => dma_map(args[0], args[1]),
+        Syscall::EndpointCreate => endpoint_create(args[0], args[1]),
+        Syscall::EndpointLookup => endpoint_lookup(args[0], args[1]),
+        Syscall::IpcCall => ipc_call(context, args),
+        Syscall::IpcReceive => {
+            let timeout_ms = Some(args[3]).filter(|&timeout| timeout != u64::max_value());
+            ipc::receive(context, args[0], args[1], args[2], timeout_ms)
+        }
+        Syscall::IpcReply => ipc_reply(args[0], args[1], args[2]),
+        Syscall::ObjectCreate => {
+            let pid = scheduler::current().unwrap();
+            let res = page_cache::create(pid, args[0]);
+            res.map(|id| id as i64).unwrap_or_else(|err| err as i64)
+        }
+        Syscall::ObjectFromFile => {
+            let pid = scheduler::current().unwrap();
+            let res = page_cache::from_file(pid, args[0], args[1]);
+            res.map(|id| id as i64).unwrap_or_else(|err| err as i64)
+        }
+        Syscall::ObjectRelease => {
+            let pid = scheduler::current().unwrap();
+            let res = page_cache::release(pid, args[0]);
+            res.map(|_| 0).unwrap_or_else(|err| err as i64)
+        }
+        Syscall::Read => read(context, args[0], args[1], args[2]),
+        Syscall::Write => write(context, args[0], args[1], args[2]),
+        Syscall::Close => {
+            let pid = scheduler::current().unwrap();
+            let file = PROCESSES.get_mut(&pid).unwrap().files().remove(args[0]);
+
+            // The file is dropped here, closing its pipe end if this was its last descriptor
+            match file {
+                Some(_) => 0,
+                None => Error::NotFound as i64,
+            }
+        }
+        Syscall::Pipe => pipe(args[0]),
+        Syscall::Spawn => spawn(args),
+        Syscall::Exit => scheduler::exit_current(ExitStatus::Exited(args[0] as u32)),
+        Syscall::Kill => kill(ProcessId::from_u64(args[0])),
+        Syscall::Wait => wait(context, ProcessId::from_u64(args[0])),
+        Syscall::ProcessList => process_list(args[0], args[1]),
+        Syscall::MemoryInfo => memory_info(args[0]),
+        Syscall::WaitAny => wait_any(context, args[0]),
+        Syscall::IoPortGrant => io_port_grant(ProcessId::from_u64(args[0]), args[1], args[2]),
+        Syscall::IrqGrant => irq_grant(ProcessId::from_u64(args[0]), args[1]),
+    }
+}
+
+fn current_has_capability(capability: Capabilities) -> bool {
+    scheduler::current()
+        .and_then(|pid| PROCESSES.get(&pid))
+        .map(|process| process.capabilities().contains(capability))
+        .unwrap_or(false)
+}
+
+/// Copies information about up to `len` PCI functions into the user's buffer. Returns the total
+/// number of functions, which may be more than `len`.
+fn pci_devices(ptr: u64, len: u64) -> i64 {
+    let devices = pci::devices();
+
+    if len == 0 {
+        return devices.len() as i64;
+    }
+
+    let size = mem::size_of::<PciDeviceInfo>() as u64;
+    let bytes = match len.checked_mul(size) {
+        Some(bytes) => bytes,
+        None => return Error::InvalidBuffer as i64,
+    };
+
+    // SAFETY: we are in the user's page tables
+    let res = unsafe {
+        BorrowedKernelBufferMut::<u8>::try_from_user(NonNull::new(ptr as *mut u8), bytes)
+    };
+
+    let buf = match res {
+        Ok(buf) => buf,
+        Err(_) => return Error::InvalidBuffer as i64,
+    };
+
+    for (device, dst) in devices.iter().zip(buf.0.chunks_exact_mut(size as usize)) {
+        dst.copy_from_slice(PciDeviceInfo::from(device).as_bytes());
+    }
+
+    devices.len() as i64
+}
+
+/// Claims a PCI function for the calling process, enabling it and granting the process its io
+/// ports, including the legacy ones of an IDE controller in compatibility mode. Returns the legacy
+/// IRQ routed to the function, or 0xff if it has none.
+fn pci_claim(address: PciAddress) -> i64 {
+    if !current_has_capability(Capabilities::DEVICES) {
+        return Error::PermissionDenied as i64;
+    }
+
+    let pid = scheduler::current().unwrap();
+    let device = match pci::claim(address, pid) {
+        Ok(device) => device,
+        Err(ClaimError::NoSuchDevice) => return Error::NotFound as i64,
+        Err(ClaimError::AlreadyClaimed) => return Error::Busy as i64,
+    };
+
+    let mut command = device.command() | CommandFlags::BUS_MASTER;
+    let mut process = PROCESSES.get_mut(&pid).unwrap();
+
+    for bar in device.bars.iter().flatten() {
+        match *bar {
+            Bar::Memory { .. } => command |= CommandFlags::MEMORY_SPACE,
+            Bar::Io { port, size } => {
+                command |= CommandFlags::IO_SPACE;
+                process.grant_io_ports(port..=port.saturating_add(size - 1));
+            }
+        }
+    }
+
+    for ports in device.legacy_io_ports() {
+        command |= CommandFlags::IO_SPACE;
+        process.grant_io_ports(ports);
+    }
+
+    process.apply_io_ports();
+    device.set_command(command);
+
+    if device.interrupt_pin != 0 {
+        device.interrupt_line as i64
+    } else {
+        0xff
+    }
+}
+
+/// Maps a memory BAR of a PCI function which the calling process has claimed at the given page
+/// aligned address. Returns the size of the BAR.
+fn pci_map_bar(address: PciAddress, bar: u64, addr_begin: u64) -> i64 {
+    if pci::owner(address) != scheduler::current() {
+        return Error::PermissionDenied as i64;
+    }
+
+    if addr_begin & 0xfff != 0 {
+        return Error::InvalidPage as i64;
+    }
+
+    let device = pci::device(address).unwrap();
+    let (physical_address, size) = match device.bars.get(bar as usize) {
+        Some(Some(Bar::Memory { address, size, .. })) => (*address, *size),
+        _ => return Error::InvalidArgument as i64,
+    };
+
+    // The BAR may be smaller than a page, and so not page aligned
+    let physical_begin = physical_address & !0xfff;
+    let pages = (physical_address + size - physical_begin + 0xfff) / 0x1000;
+
+    let page_begin = Page::containing_address(addr_begin);
+    let page_end = page_begin + (pages - 1) as usize;
+    let flags = EntryFlags::PRESENT
+        | EntryFlags::USER_ACCESSIBLE
+        | EntryFlags::WRITABLE
+        | EntryFlags::NO_CACHE
+        | EntryFlags::NO_EXECUTE;
+
+    // SAFETY: we are in the user's page tables, and the BAR is device memory
+    let res = unsafe {
+        ACTIVE_PAGE_TABLES.lock().try_map_user_range_to(
+            page_begin..=page_end,
+            PhysAddr::new(physical_begin),
+            flags,
+            InvalidateTlb::Invalidate,
+        )
+    };
+
+    res.map(|_| size as i64)
+        .unwrap_or(Error::InvalidPage as i64)
+}
+
+/// Sends a legacy IRQ to the calling process as the given notification bits, if it was granted the
+/// IRQ or it is raised by a PCI function which the process has claimed. The IRQ is masked each
+/// time it fires, until it is acknowledged.
+fn irq_listen(irq: u64, notify: u64) -> i64 {
+    if irq >= 16 || notify & notification::BITS == 0 {
+        return Error::InvalidArgument as i64;
+    }
+
+    let pid = scheduler::current().unwrap();
+    let granted = PROCESSES.get(&pid).unwrap().is_irq_granted(irq as u8);
+    let claimed = current_has_capability(Capabilities::DEVICES) && pci::raises_irq(pid, irq as u8);
+
+    if !granted && !claimed {
+        return Error::PermissionDenied as i64;
+    }
+
+    match interrupts::user::listen(irq as u8, pid, notify) {
+        Ok(()) => 0,
+        Err(ListenError::NoSuchIrq) => Error::InvalidArgument as i64,
+        Err(ListenError::AlreadyListened) => Error::Busy as i64,
+    }
+}
+
+/// Maps `len` bytes of zeroed, physically contiguous memory at the given page aligned address, for
+/// the calling process's devices to access directly. Returns its physical address.
+fn dma_map(addr_begin: u64, len: u64) -> i64 {
+    if !current_has_capability(Capabilities::DEVICES) {
+        return Error::PermissionDenied as i64;
+    }
+
+    if addr_begin & 0xfff != 0 {
+        return Error::InvalidPage as i64;
+    }
+
+    if len == 0 || len > MAX_DMA_REGION {
+        return Error::InvalidPagesLength as i64;
+    }
+
+    let region = match DmaRegion::allocate(len as usize) {
+        Some(region) => region,
+        None => return Error::OutOfMemory as i64,
+    };
+
+    let pages = (len + 0xfff) / 0x1000;
+    let page_begin = Page::containing_address(addr_begin);
+    let page_end = page_begin + (pages - 1) as usize;
+    let flags = EntryFlags::PRESENT
+        | EntryFlags::USER_ACCESSIBLE
+        | EntryFlags::WRITABLE
+        | EntryFlags::NO_EXECUTE;
+
+    // SAFETY: we are in the user's page tables, and the region is kept until the process exits
+    let res = unsafe {
+        ACTIVE_PAGE_TABLES.lock().try_map_user_range_to(
+            page_begin..=page_end,
+            PhysAddr::new(region.physical(0)),
+            flags,
+            InvalidateTlb::Invalidate,
+        )
+    };
+
+    if res.is_err() {
+        return Error::InvalidPage as i64;
+    }
+
+    let physical = region.physical(0);
+    let pid = scheduler::current().unwrap();
+    PROCESSES.get_mut(&pid).unwrap().add_dma_region(region);
+
+    physical as i64
+}
+
+/// Maps pages of a memory object into the process, to be faulted in when they are first accessed.
+/// The offset into the object must be page-aligned.
+fn map_object(addr_begin: u64, pages: u64, flags: EntryFlags, object: u64, offset: u64) -> i64 {
+    if offset & 0xfff != 0 {
+        return Error::InvalidArgument as i64;
+    }
+
+    match page_cache::map(addr_begin / 0x1000, pages, flags, object, offset / 0x1000) {
+        Ok(()) => 0,
+        Err(err) => err as i64,
+    }
+}
+
+/// Copies a slice of the user's memory into the kernel, if it is no longer than `max`
+fn copy_from_user(ptr: u64, len: u64, max: usize) -> Result<Vec<u8>, Error> {
+    if len > max as u64 {
+        return Err(Error::InvalidArgument);
+    }
+
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+
+    // SAFETY: we are in the user's page tables
+    let res = unsafe { BorrowedKernelBuffer::try_from_user(NonNull::new(ptr as *mut u8), len) };
+
+    match res {
+        Ok(buf) => Ok(buf.0.to_vec()),
+        Err(_) => Err(Error::InvalidBuffer),
+    }
+}
+
+/// Reads an endpoint name from the user's memory
+fn endpoint_name(ptr: u64, len: u64) -> Result<String, Error> {
+    let bytes = copy_from_user(ptr, len, ipc::MAX_NAME)?;
+    String::from_utf8(bytes).map_err(|_| Error::InvalidUtf8)
+}
+
+/// Creates an IPC endpoint owned by the calling process, under the given name unless it is empty.
+/// Returns the endpoint's id.
+fn endpoint_create(name_ptr: u64, name_len: u64) -> i64 {
+    let name = match endpoint_name(name_ptr, name_len) {
+        Ok(name) => name,
+        Err(err) => return err as i64,
+    };
+
+    let name = if name.is_empty() { None } else { Some(&*name) };
+    let pid = scheduler::current().unwrap();
+
+    match ipc::create(pid, name) {
+        Ok(id) => id as i64,
+        Err(err) => Error::from(err) as i64,
+    }
+}
+
+/// Returns the id of the IPC endpoint with the given name
+fn endpoint_lookup(name_ptr: u64, name_len: u64) -> i64 {
+    let name = match endpoint_name(name_ptr, name_len) {
+        Ok(name) => name,
+        Err(err) => return err as i64,
+    };
+
+    match ipc::lookup(&name) {
+        Some(id) => id as i64,
+        None => Error::NotFound as i64,
+    }
+}
+
+/// Calls an IPC endpoint with a request, blocking until the call is replied to. Returns the length
+/// of the reply, which is truncated to fit the reply buffer.
+fn ipc_call(context: &UserContext, args: [u64; 6]) -> i64 {
+    let [endpoint, request_ptr, request_len, reply_ptr, reply_len]: [u64; 5] =
+        args[0..5].try_into().unwrap();
+
+    let request = match copy_from_user(request_ptr, request_len, ipc::MAX_MESSAGE) {
+        Ok(request) => request,
+        Err(err) => return err as i64,
+    };
+
+    if reply_len > 0 {
+        // SAFETY: we are in the user's page tables
+        let res = unsafe {
+            BorrowedKernelBufferMut::<u8>::try_from_user(
+                NonNull::new(reply_ptr as *mut u8),
+                reply_len,
+            )
+        };
+
+        if res.is_err() {
+            return Error::InvalidBuffer as i64;
+        }
     }
+
+    ipc::call(context, endpoint, request, reply_ptr, reply_len)
+}
+
+/// Replies to a call received on one of the calling process's IPC endpoints
+fn ipc_reply(call: u64, ptr: u64, len: u64) -> i64 {
+    let reply = match copy_from_user(ptr, len, ipc::MAX_MESSAGE) {
+        Ok(reply) => reply,
+        Err(err) => return err as i64,
+    };
+
+    let pid = scheduler::current().unwrap();
+
+    match ipc::reply(pid, call, reply) {
+        Ok(()) => 0,
+        Err(err) => Error::from(err) as i64,
+    }
+}
+
+/// Maps the initrd read-only at the given page aligned address. Returns its length in bytes.
+fn initrd_map(addr_begin: u64) -> i64 {
+    if !current_has_capability(Capabilities::INITRD) {
+        return Error::PermissionDenied as i64;
+    }
+
+    if addr_begin & 0xfff != 0 {
+        return Error::InvalidPage as i64;
+    }
+
+    let initrd = match initrd::get() {
+        Some(initrd) if !initrd.is_empty() => initrd,
+        _ => return Error::NotFound as i64,
+    };
+
+    let pages = (initrd.len() + 0xfff) / 0x1000;
+    let page_begin = Page::containing_address(addr_begin);
+    let page_end = page_begin + (pages - 1) as usize;
+    let flags = EntryFlags::PRESENT | EntryFlags::USER_ACCESSIBLE | EntryFlags::NO_EXECUTE;
+
+    // SAFETY: we are in the user's page tables, and the initrd is never freed or written to
+    let res = unsafe {
+        ACTIVE_PAGE_TABLES.lock().try_map_user_range_to(
+            page_begin..=page_end,
+            PhysAddr::new(initrd.physical_start()),
+            flags,
+            InvalidateTlb::Invalidate,
+        )
+    };
+
+    res.map(|_| initrd.len() as i64)
+        .unwrap_or(Error::InvalidPage as i64)
+}
+
+/// Copies information about up to `len` block devices into the user's buffer. Returns the total
+/// number of devices, which may be more than `len`.
+fn block_devices(ptr: u64, len: u64) -> i64 {
+    if !current_has_capability(Capabilities::STORAGE) {
+        return Error::PermissionDenied as i64;
+    }
+
+    let devices = block::devices();
+
+    if len == 0 {
+        return devices.len() as i64;
+    }
+
+    let size = mem::size_of::<BlockDeviceInfo>() as u64;
+    let bytes = match len.checked_mul(size) {
+        Some(bytes) => bytes,
+        None => return Error::InvalidBuffer as i64,
+    };
+
+    // SAFETY: we are in the user's page tables
+    let res = unsafe {
+        BorrowedKernelBufferMut::<u8>::try_from_user(NonNull::new(ptr as *mut u8), bytes)
+    };
+
+    let buf = match res {
+        Ok(buf) => buf,
+        Err(_) => return Error::InvalidBuffer as i64,
+    };
+
+    for (device, dst) in devices.iter().zip(buf.0.chunks_exact_mut(size as usize)) {
+        dst.copy_from_slice(device.as_bytes());
+    }
+
+    devices.len() as i64
+}
+
+/// Reads or writes whole sectors of a block device, starting at the given sector, by DMA to or
+/// from the user's buffer. Blocks until the transfer completes, then returns its length in bytes.
+fn block_transfer(context: &UserContext, direction: Direction, args: [u64; 6]) -> i64 {
+    let [device, sector, ptr, len]: [u64; 4] = args[0..4].try_into().unwrap();
+
+    if !current_has_capability(Capabilities::STORAGE) {
+        return Error::PermissionDenied as i64;
+    }
+
+    if len == 0 || len > block::MAX_TRANSFER as u64 {
+        return Error::InvalidArgument as i64;
+    }
+
+    // The device writes to the buffer when reading
+    // SAFETY: we are in the user's page tables
+    let res = unsafe {
+        match direction {
+            Direction::Read => {
+                BorrowedKernelBufferMut::<u8>::try_from_user(NonNull::new(ptr as *mut u8), len)
+                    .map(|buf| &*buf.0)
+            }
+            Direction::Write => {
+                BorrowedKernelBuffer::<u8>::try_from_user(NonNull::new(ptr as *mut u8), len)
+                    .map(|buf| buf.0)
+            }
+        }
+    };
+
+    let segments = match res.ok().and_then(block::segments_of) {
+        Some(segments) => segments,
+        None => return Error::InvalidBuffer as i64,
+    };
+
+    let request = Request {
+        direction,
+        sector,
+        segments: &segments,
+    };
+
+    let tag = match block::submit(device as usize, &request) {
+        Ok(tag) => tag,
+        Err(err) => return Error::from(err) as i64,
+    };
+
+    mem::drop(segments);
+    block::wait(context, device as usize, tag, len)
+}
+
+/// Copies information about up to `len` network devices into the user's buffer. Returns the total
+/// number of devices, which may be more than `len`.
+fn net_devices(ptr: u64, len: u64) -> i64 {
+    if !current_has_capability(Capabilities::NETWORK) {
+        return Error::PermissionDenied as i64;
+    }
+
+    let devices = net::devices();
+
+    if len == 0 {
+        return devices.len() as i64;
+    }
+
+    let size = mem::size_of::<NetDeviceInfo>() as u64;
+    let bytes = match len.checked_mul(size) {
+        Some(bytes) => bytes,
+        None => return Error::InvalidBuffer as i64,
+    };
+
+    // SAFETY: we are in the user's page tables
+    let res = unsafe {
+        BorrowedKernelBufferMut::<u8>::try_from_user(NonNull::new(ptr as *mut u8), bytes)
+    };
+
+    let buf = match res {
+        Ok(buf) => buf,
+        Err(_) => return Error::InvalidBuffer as i64,
+    };
+
+    for (device, dst) in devices.iter().zip(buf.0.chunks_exact_mut(size as usize)) {
+        dst.copy_from_slice(device.as_bytes());
+    }
+
+    devices.len() as i64
+}
+
+/// Attaches the calling process to a network device, mapping the rings it shares with the kernel
+/// at the given page aligned address. The given notification bits are signalled when frames
+/// arrive. Returns the length of the mapping.
+fn net_attach(device: u64, addr_begin: u64, notify: u64) -> i64 {
+    if !current_has_capability(Capabilities::NETWORK) {
+        return Error::PermissionDenied as i64;
+    }
+
+    if addr_begin & 0xfff != 0 {
+        return Error::InvalidPage as i64;
+    }
+
+    if notify & notification::BITS == 0 {
+        return Error::InvalidArgument as i64;
+    }
+
+    let pid = scheduler::current().unwrap();
+
+    // SAFETY: we are in the user's page tables
+    match unsafe { net::attach(device as usize, pid, addr_begin, notify) } {
+        Ok(len) => len as i64,
+        Err(AttachError::NoSuchDevice) => Error::NotFound as i64,
+        Err(AttachError::AlreadyAttached) => Error::Busy as i64,
+        Err(AttachError::OutOfMemory) => Error::OutOfMemory as i64,
+        Err(AttachError::InvalidAddress) => Error::InvalidPage as i64,
+    }
+}
+
+/// Sends the frames waiting in the transmit ring of a network device which the calling process is
+/// attached to. Returns how many were sent.
+fn net_transmit(device: u64) -> i64 {
+    let pid = scheduler::current().unwrap();
+
+    match net::transmit(device as usize, pid) {
+        Some(sent) => sent as i64,
+        None => Error::PermissionDenied as i64,
+    }
+}
+
+/// Moves up to `len` queued input events into the user's buffer without blocking. Returns how many
+/// were moved.
+fn input_read(ptr: u64, len: u64) -> i64 {
+    if !current_has_capability(Capabilities::INPUT) {
+        return Error::PermissionDenied as i64;
+    }
+
+    // SAFETY: we are in the user's page tables
+    let res = unsafe {
+        BorrowedKernelBufferMut::<RawInputEvent>::try_from_user(NonNull::new(ptr as *mut u8), len)
+    };
+
+    match res {
+        Ok(buf) => input::read(buf.0) as i64,
+        Err(_) => Error::InvalidBuffer as i64,
+    }
+}
+
+/// Looks up one of the calling process's file descriptors
+fn current_file(fd: u64) -> Result<File, Error> {
+    let pid = scheduler::current().unwrap();
+    let mut process = PROCESSES.get_mut(&pid).unwrap();
+    process.files().get(fd).ok_or(Error::NotFound)
+}
+
+/// Reads up to `len` bytes from a file descriptor into the user's buffer, blocking until there is
+/// data. Returns how many bytes were read, which is 0 at the end of the file.
+fn read(context: &UserContext, fd: u64, ptr: u64, len: u64) -> i64 {
+    if len > 0 {
+        // SAFETY: we are in the user's page tables
+        let res = unsafe {
+            BorrowedKernelBufferMut::<u8>::try_from_user(NonNull::new(ptr as *mut u8), len)
+        };
+
+        if res.is_err() {
+            return Error::InvalidBuffer as i64;
+        }
+    }
+
+    let file = match current_file(fd) {
+        Ok(file) => file,
+        Err(err) => return err as i64,
+    };
+
+    let data = match file.read(context, ptr, len as usize) {
+        Ok(data) => data,
+        Err(err) => return err as i64,
+    };
+
+    // SAFETY: we are in the user's page tables, and the buffer was checked above
+    let res =
+        unsafe { BorrowedKernelBufferMut::<u8>::try_from_user(NonNull::new(ptr as *mut u8), len) };
+
+    match res {
+        Ok(buf) => {
+            buf.0[..data.len()].copy_from_slice(&data);
+            data.len() as i64
+        }
+        Err(_) if data.is_empty() => 0,
+        Err(_) => Error::InvalidBuffer as i64,
+    }
+}
+
+/// Writes the user's buffer to a file descriptor, blocking until all of it is written. Returns its
+/// length.
+fn write(context: &UserContext, fd: u64, ptr: u64, len: u64) -> i64 {
+    let data = match copy_from_user(ptr, len, MAX_WRITE) {
+        Ok(data) => data,
+        Err(err) => return err as i64,
+    };
+
+    let file = match current_file(fd) {
+        Ok(file) => file,
+        Err(err) => return err as i64,
+    };
+
+    match file.write(context, data) {
+        Ok(len) => len as i64,
+        Err(err) => err as i64,
+    }
+}
+
+/// Creates a pipe, writing the descriptors of its read and write ends to the user's `[u64; 2]`
+fn pipe(fds_ptr: u64) -> i64 {
+    // SAFETY: we are in the user's page tables
+    let res = unsafe {
+        BorrowedKernelBufferMut::<u64>::try_from_user(NonNull::new(fds_ptr as *mut u8), 2)
+    };
+
+    let buf = match res {
+        Ok(buf) => buf,
+        Err(_) => return Error::InvalidBuffer as i64,
+    };
+
+    let pid = scheduler::current().unwrap();
+    let mut process = PROCESSES.get_mut(&pid).unwrap();
+    let (reader, writer) = pipe::new();
+
+    let read_fd = match process.files().insert(File::PipeReader(Arc::new(reader))) {
+        Ok(fd) => fd,
+        Err(err) => return err as i64,
+    };
+
+    let write_fd = match process.files().insert(File::PipeWriter(Arc::new(writer))) {
+        Ok(fd) => fd,
+        Err(err) => {
+            process.files().remove(read_fd);
+            return err as i64;
+        }
+    };
+
+    buf.0[0] = read_fd;
+    buf.0[1] = write_fd;
+    0
+}
+
+/// Spawns a process from the ELF file in the user's buffer, under the given name and with a subset
+/// of the calling process's capabilities. Its standard input, output and error are the given
+/// descriptors of the calling process, from the user's `[u64; 3]`, or the caller's own if the
+/// pointer is null. Returns the new process's id.
+fn spawn(args: [u64; 6]) -> i64 {
+    let [elf_ptr, elf_len, name_ptr, name_len, capabilities, stdio_ptr] = args;
+
+    let name = match copy_from_user(name_ptr, name_len, process::MAX_NAME) {
+        Ok(name) => name,
+        Err(err) => return err as i64,
+    };
+
+    let name = match String::from_utf8(name) {
+        Ok(name) => name,
+        Err(_) => return Error::InvalidUtf8 as i64,
+    };
+
+    let capabilities = match Capabilities::from_bits(capabilities) {
+        Some(capabilities) => capabilities,
+        None => return Error::InvalidArgument as i64,
+    };
+
+    if !current_has_capability(capabilities) {
+        return Error::PermissionDenied as i64;
+    }
+
+    let stdio = if stdio_ptr == 0 {
+        [fd::STDIN, fd::STDOUT, fd::STDERR]
+    } else {
+        // SAFETY: we are in the user's page tables
+        let res = unsafe {
+            BorrowedKernelBuffer::<u64>::try_from_user(NonNull::new(stdio_ptr as *mut u8), 3)
+        };
+
+        match res {
+            Ok(buf) => [buf.0[0], buf.0[1], buf.0[2]],
+            Err(_) => return Error::InvalidBuffer as i64,
+        }
+    };
+
+    // The ELF must be in kernel memory, as the new process's page tables are switched to while it
+    // is loaded
+    let elf = match copy_from_user(elf_ptr, elf_len, MAX_ELF) {
+        Ok(elf) => elf,
+        Err(err) => return err as i64,
+    };
+
+    let pid = scheduler::current().unwrap();
+    let files = {
+        let mut process = PROCESSES.get_mut(&pid).unwrap();
+        let table = process.files();
+
+        match (
+            table.get(stdio[0]),
+            table.get(stdio[1]),
+            table.get(stdio[2]),
+        ) {
+            (Some(stdin), Some(stdout), Some(stderr)) => {
+                FileTable::with_standard(stdin, stdout, stderr)
+            }
+            _ => return Error::NotFound as i64,
+        }
+    };
+
+    match Process::spawn_from_elf(&elf, &name, Some(pid), capabilities, files) {
+        Ok(child) => {
+            scheduler::enqueue(child);
+            child.as_u64() as i64
+        }
+        Err(err) => {
+            warn!("Failed to spawn process: {:?}", err);
+            Error::InvalidArgument as i64
+        }
+    }
+}
+
+/// Kills the calling process or one of its descendants. Processes with the `KILL` capability can
+/// kill any other process which has no capabilities which they don't.
+fn kill(pid: ProcessId) -> i64 {
+    let capabilities = match PROCESSES.get(&pid) {
+        Some(process) => process.capabilities(),
+        None => return Error::NotFound as i64,
+    };
+
+    let allowed = is_descendant(pid, scheduler::current().unwrap())
+        || current_has_capability(capabilities | Capabilities::KILL);
+
+    if !allowed {
+        return Error::PermissionDenied as i64;
+    }
+
+    if scheduler::current() == Some(pid) {
+        scheduler::exit_current(ExitStatus::Killed);
+    }
+
+    process::exit(pid, ExitStatus::Killed);
+    0
+}
+
+/// Whether a process is the ancestor, or was spawned by it or by one of its descendants. A process
+/// whose parent has exited is no longer anyone's descendant.
+fn is_descendant(pid: ProcessId, ancestor: ProcessId) -> bool {
+    let mut next = Some(pid);
+
+    // Parents are always older than their children, so this can't loop
+    while let Some(pid) = next {
+        if pid == ancestor {
+            return true;
+        }
+
+        next = PROCESSES.get(&pid).and_then(|process| process.parent());
+    }
+
+    false
+}
+
+/// Blocks the calling process until one of its children exits, unless it already has. Returns how
+/// the child ended, as encoded by `ExitStatus::as_u64`.
+fn wait(context: &UserContext, child: ProcessId) -> i64 {
+    let pid = scheduler::current().unwrap();
+    let exited = PROCESSES.get_mut(&pid).unwrap().take_exited_child(child);
+
+    if let Some(status) = exited {
+        return status.as_u64() as i64;
+    }
+
+    let is_child = match PROCESSES.get(&child) {
+        Some(process) => process.parent() == Some(pid),
+        None => false,
+    };
+
+    if !is_child {
+        return Error::NotFound as i64;
+    }
+
+    scheduler::block_current_on(context, None, move |pid, block_id| {
+        PROCESSES.get_mut(&pid).unwrap().wait_on(child, block_id);
+    })
+}
+
+/// Blocks the calling process until any of its children exits, unless one already has. Returns the
+/// child's id, and copies how it ended to the user's `u64`.
+fn wait_any(context: &UserContext, status_ptr: u64) -> i64 {
+    // The buffer is checked now, as it is only written to once the process is woken
+    // SAFETY: we are in the user's page tables
+    let res = unsafe {
+        BorrowedKernelBufferMut::<u64>::try_from_user(NonNull::new(status_ptr as *mut u8), 1)
+    };
+
+    let buf = match res {
+        Ok(buf) => buf,
+        Err(_) => return Error::InvalidBuffer as i64,
+    };
+
+    let pid = scheduler::current().unwrap();
+    let exited = PROCESSES.get_mut(&pid).unwrap().take_any_exited_child();
+
+    if let Some((child, status)) = exited {
+        buf.0[0] = status.as_u64();
+        return child.as_u64() as i64;
+    }
+
+    let has_children = PROCESSES
+        .iter()
+        .any(|entry| entry.value().parent() == Some(pid));
+
+    if !has_children {
+        return Error::NotFound as i64;
+    }
+
+    scheduler::block_current_on(context, None, move |pid, block_id| {
+        PROCESSES
+            .get_mut(&pid)
+            .unwrap()
+            .wait_on_any(status_ptr, block_id);
+    })
+}
+
+/// Grants something to a child of the calling process, which must have the `DEVICES` capability.
+/// The child doesn't run until the caller next blocks, so it can be granted io ports and IRQs
+/// straight after it is spawned.
+fn grant_to_child<F>(child: ProcessId, grant: F) -> i64
+where
+    F: FnOnce(&mut Process),
+{
+    if !current_has_capability(Capabilities::DEVICES) {
+        return Error::PermissionDenied as i64;
+    }
+
+    let pid = scheduler::current().unwrap();
+
+    match PROCESSES.get_mut(&child) {
+        Some(mut process) if process.parent() == Some(pid) => {
+            grant(&mut *process);
+            0
+        }
+        _ => Error::NotFound as i64,
+    }
+}
+
+/// Allows a child of the calling process to access `count` io ports, starting from `first`
+fn io_port_grant(child: ProcessId, first: u64, count: u64) -> i64 {
+    if count == 0 || first > 0xffff || count > 0x1_0000 - first {
+        return Error::InvalidArgument as i64;
+    }
+
+    let ports = first as u16..=(first + count - 1) as u16;
+    grant_to_child(child, |process| process.grant_io_ports(ports))
+}
+
+/// Allows a child of the calling process to listen to a legacy IRQ
+fn irq_grant(child: ProcessId, irq: u64) -> i64 {
+    if irq >= 16 {
+        return Error::InvalidArgument as i64;
+    }
+
+    grant_to_child(child, |process| process.grant_irq(irq as u8))
+}
+
+/// Copies information about up to `len` processes into the user's buffer, in order of their ids.
+/// Returns the total number of processes, which may be more than `len`.
+fn process_list(ptr: u64, len: u64) -> i64 {
+    let mut processes: Vec<ProcessInfo> = PROCESSES
+        .iter()
+        .map(|entry| entry.value().info(*entry.key()))
+        .collect();
+    processes.sort_by_key(|info| info.pid);
+
+    if len == 0 {
+        return processes.len() as i64;
+    }
+
+    let size = mem::size_of::<ProcessInfo>() as u64;
+    let bytes = match len.checked_mul(size) {
+        Some(bytes) => bytes,
+        None => return Error::InvalidBuffer as i64,
+    };
+
+    // SAFETY: we are in the user's page tables
+    let res = unsafe {
+        BorrowedKernelBufferMut::<u8>::try_from_user(NonNull::new(ptr as *mut u8), bytes)
+    };
+
+    let buf = match res {
+        Ok(buf) => buf,
+        Err(_) => return Error::InvalidBuffer as i64,
+    };
+
+    for (info, dst) in processes.iter().zip(buf.0.chunks_exact_mut(size as usize)) {
+        dst.copy_from_slice(info.as_bytes());
+    }
+
+    processes.len() as i64
+}
+
+/// Writes the amount of usable physical memory, how much of it is allocated and how much of the
+/// kernel heap is in use, in bytes, to the user's `[u64; 3]`
+fn memory_info(ptr: u64) -> i64 {
+    // SAFETY: we are in the user's page tables
+    let res =
+        unsafe { BorrowedKernelBufferMut::<u64>::try_from_user(NonNull::new(ptr as *mut u8), 3) };
+
+    let buf = match res {
+        Ok(buf) => buf,
+        Err(_) => return Error::InvalidBuffer as i64,
+    };
+
+    buf.0[0] = memory::usable_bytes();
+    buf.0[1] = PHYSICAL_ALLOCATOR.allocated_bytes();
+    buf.0[2] = crate::HEAP.allocated_bytes();
+    0
+}
+
+/// Blocks the calling process until the given time on the monotonic clock
+fn sleep_until(context: &UserContext, deadline_ms: u64) -> i64 {
+    if deadline_ms <= clock::now_ms(Clock::Monotonic) {
+        return 0;
+    }
+
+    let deadline = Deadline {
+        at_ms: deadline_ms,
+        result: 0,
+    };
+
+    scheduler::block_current(context, Some(deadline))
 }
 
 #[repr(u64)]
@@ -209,6 +1387,49 @@ pub enum Syscall {
     Map = 1,
     Unmap = 2,
     Print = 3,
+    ClockGet = 4,
+    Sleep = 5,
+    SleepUntil = 6,
+    Shutdown = 7,
+    Reboot = 8,
+    PciDevices = 9,
+    PciClaim = 10,
+    PciMapBar = 11,
+    InputRead = 12,
+    InputWait = 13,
+    InitrdMap = 14,
+    BlockDevices = 15,
+    BlockRead = 16,
+    BlockWrite = 17,
+    NetDevices = 18,
+    NetAttach = 19,
+    NetTransmit = 20,
+    NotificationWait = 21,
+    IrqListen = 22,
+    IrqAck = 23,
+    DmaMap = 24,
+    EndpointCreate = 25,
+    EndpointLookup = 26,
+    IpcCall = 27,
+    IpcReceive = 28,
+    IpcReply = 29,
+    ObjectCreate = 30,
+    ObjectFromFile = 31,
+    ObjectRelease = 32,
+    Msync = 33,
+    Read = 34,
+    Write = 35,
+    Close = 36,
+    Pipe = 37,
+    Spawn = 38,
+    Exit = 39,
+    Kill = 40,
+    Wait = 41,
+    ProcessList = 42,
+    MemoryInfo = 43,
+    WaitAny = 44,
+    IoPortGrant = 45,
+    IrqGrant = 46,
 }
 
 impl Syscall {
@@ -218,6 +1439,49 @@ impl Syscall {
             1 => Some(Syscall::Map),
             2 => Some(Syscall::Unmap),
             3 => Some(Syscall::Print),
+            4 => Some(Syscall::ClockGet),
+            5 => Some(Syscall::Sleep),
+            6 => Some(Syscall::SleepUntil),
+            7 => Some(Syscall::Shutdown),
+            8 => Some(Syscall::Reboot),
+            9 => Some(Syscall::PciDevices),
+            10 => Some(Syscall::PciClaim),
+            11 => Some(Syscall::PciMapBar),
+            12 => Some(Syscall::InputRead),
+            13 => Some(Syscall::InputWait),
+            14 => Some(Syscall::InitrdMap),
+            15 => Some(Syscall::BlockDevices),
+            16 => Some(Syscall::BlockRead),
+            17 => Some(Syscall::BlockWrite),
+            18 => Some(Syscall::NetDevices),
+            19 => Some(Syscall::NetAttach),
+            20 => Some(Syscall::NetTransmit),
+            21 => Some(Syscall::NotificationWait),
+            22 => Some(Syscall::IrqListen),
+            23 => Some(Syscall::IrqAck),
+            24 => Some(Syscall::DmaMap),
+            25 => Some(Syscall::EndpointCreate),
+            26 => Some(Syscall::EndpointLookup),
+            27 => Some(Syscall::IpcCall),
+            28 => Some(Syscall::IpcReceive),
+            29 => Some(Syscall::IpcReply),
+            30 => Some(Syscall::ObjectCreate),
+            31 => Some(Syscall::ObjectFromFile),
+            32 => Some(Syscall::ObjectRelease),
+            33 => Some(Syscall::Msync),
+            34 => Some(Syscall::Read),
+            35 => Some(Syscall::Write),
+            36 => Some(Syscall::Close),
+            37 => Some(Syscall::Pipe),
+            38 => Some(Syscall::Spawn),
+            39 => Some(Syscall::Exit),
+            40 => Some(Syscall::Kill),
+            41 => Some(Syscall::Wait),
+            42 => Some(Syscall::ProcessList),
+            43 => Some(Syscall::MemoryInfo),
+            44 => Some(Syscall::WaitAny),
+            45 => Some(Syscall::IoPortGrant),
+            46 => Some(Syscall::IrqGrant),
             _ => None,
         }
     }
@@ -0,0 +1,67 @@
+//! Kernel timers, keyed on the monotonic clock. When a timer expires, the process blocked on it is
+//! woken.
+
+use crate::clock::{self, Clock};
+use crate::process::ProcessId;
+use crate::scheduler;
+use alloc::collections::BinaryHeap;
+use core::cmp::Reverse;
+use spin::Mutex;
+
+lazy_static::lazy_static! {
+    static ref TIMERS: Mutex<BinaryHeap<Reverse<Timer>>> = Mutex::new(BinaryHeap::new());
+}
+
+/// A point in time on the monotonic clock after which a blocked system call gives up, along with
+/// the value it should then return.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Deadline {
+    pub at_ms: u64,
+    pub result: i64,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+struct Timer {
+    deadline: Deadline,
+    pid: ProcessId,
+    /// Identifies which time the process blocked, so that a stale timer cannot wake it up later
+    block_id: u64,
+}
+
+/// Arms a timer which wakes the given process from the given block once the deadline has passed
+pub fn add(deadline: Deadline, pid: ProcessId, block_id: u64) {
+    TIMERS.lock().push(Reverse(Timer {
+        deadline,
+        pid,
+        block_id,
+    }));
+}
+
+/// Whether any timer has expired and is waiting to be fired
+pub fn any_expired() -> bool {
+    let now = clock::now_ms(Clock::Monotonic);
+
+    match TIMERS.lock().peek() {
+        Some(Reverse(timer)) => timer.deadline.at_ms <= now,
+        None => false,
+    }
+}
+
+/// Wakes the processes waiting on all timers which have expired
+pub fn fire_expired() {
+    let now = clock::now_ms(Clock::Monotonic);
+
+    loop {
+        // Pop one at a time so that the lock isn't held while waking processes
+        let timer = {
+            let mut timers = TIMERS.lock();
+
+            match timers.peek() {
+                Some(Reverse(timer)) if timer.deadline.at_ms <= now => timers.pop().unwrap().0,
+                _ => break,
+            }
+        };
+
+        scheduler::wake(timer.pid, timer.block_id, timer.deadline.result);
+    }
+}
@@ -0,0 +1,652 @@
+//! A filesystem held in memory, for scratch data which doesn't need to outlive a boot. The contents
+//! of its files are kept in frames of physical memory, which are allocated as they are first
+//! written, and which are mapped straight into processes which map the files.
+//!
+//! The kernel serves the tmpfs itself over the VFS protocol, on the `tmpfs` endpoint, so that it can
+//! be mounted and used like any other filesystem. Its handles belong to the process which opened
+//! them.
+
+use crate::ipc;
+use crate::memory;
+use crate::memory::frame::{Frame, FRAME_SIZE};
+use crate::page_cache;
+use crate::process::ProcessId;
+use crate::vfs::{
+    path_of, Error, Op, OpenFlags, Reply, Request, DIR_ENTRY_HEADER_SIZE, KIND_DIRECTORY,
+    KIND_FILE, MAX_NAME, MAX_TRANSFER, REPLY_SIZE, REQUEST_SIZE, STAT_SIZE,
+};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+use spin::{Mutex, Once};
+
+/// The name of the endpoint which the tmpfs is served on
+pub const ENDPOINT: &str = "tmpfs";
+
+/// The root directory's node, which can't be unlinked
+const ROOT: u64 = 0;
+
+lazy_static::lazy_static! {
+    static ref TMPFS: Mutex<Tmpfs> = Mutex::new(Tmpfs::new());
+}
+
+/// The id of the tmpfs's endpoint, once it has been created
+static ENDPOINT_ID: Once<u64> = Once::new();
+
+#[derive(Debug)]
+enum Contents {
+    /// The frames of a file, by their index within it. Pages which have never been written are
+    /// holes, and read as zeroes.
+    File {
+        pages: BTreeMap<u64, Arc<Frame>>,
+        size: u64,
+    },
+    Directory(BTreeMap<String, u64>),
+}
+
+#[derive(Debug)]
+struct Node {
+    contents: Contents,
+    /// Whether the node is still in a directory. An unlinked node lives on until it is closed.
+    linked: bool,
+    /// How many handles to the node are open
+    open: usize,
+}
+
+#[derive(Debug)]
+struct Handle {
+    owner: ProcessId,
+    node: u64,
+    writable: bool,
+}
+
+#[derive(Debug)]
+struct Tmpfs {
+    nodes: BTreeMap<u64, Node>,
+    handles: BTreeMap<u64, Handle>,
+    next_node: u64,
+    next_handle: u64,
+    /// How many frames the files hold
+    pages: u64,
+    /// The most frames which the files can hold, which is half of physical memory
+    max_pages: u64,
+    /// The frames which files have been truncated past while handling a request, which memory
+    /// objects must drop once the tmpfs is unlocked
+    truncated: Vec<Arc<Frame>>,
+}
+
+impl Tmpfs {
+    fn new() -> Tmpfs {
+        let mut nodes = BTreeMap::new();
+        let root = Node {
+            contents: Contents::Directory(BTreeMap::new()),
+            linked: true,
+            open: 0,
+        };
+
+        nodes.insert(ROOT, root);
+
+        Tmpfs {
+            nodes,
+            handles: BTreeMap::new(),
+            next_node: ROOT + 1,
+            next_handle: 0,
+            pages: 0,
+            max_pages: memory::usable_bytes() / 2 / FRAME_SIZE as u64,
+            truncated: Vec::new(),
+        }
+    }
+
+    /// Returns the handle, if it is open and belongs to the process
+    fn handle(&self, pid: ProcessId, handle: u64) -> Result<&Handle, Error> {
+        match self.handles.get(&handle) {
+            Some(handle) if handle.owner == pid => Ok(handle),
+            _ => Err(Error::BadHandle),
+        }
+    }
+
+    fn contents(&self, node: u64) -> &Contents {
+        &self.nodes[&node].contents
+    }
+
+    /// Finds the node at a path, which has no leading or trailing `/`
+    fn lookup(&self, path: &str) -> Result<u64, Error> {
+        let mut node = ROOT;
+
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            node = match self.contents(node) {
+                Contents::Directory(entries) => *entries.get(name).ok_or(Error::NotFound)?,
+                Contents::File { .. } => return Err(Error::NotADirectory),
+            };
+        }
+
+        Ok(node)
+    }
+
+    fn open(&mut self, pid: ProcessId, path: &str, flags: OpenFlags) -> Result<u64, Error> {
+        let wants_dir = flags.contains(OpenFlags::DIRECTORY);
+        let writable = flags.intersects(OpenFlags::WRITE | OpenFlags::TRUNCATE);
+
+        let node = match self.lookup(path) {
+            // Creating a directory which exists fails, but creating a file opens it
+            Ok(_) if wants_dir && flags.contains(OpenFlags::CREATE) => {
+                return Err(Error::AlreadyExists)
+            }
+            Ok(node) => node,
+            Err(Error::NotFound) if flags.contains(OpenFlags::CREATE) => {
+                self.create(path, wants_dir)?
+            }
+            Err(err) => return Err(err),
+        };
+
+        match self.contents(node) {
+            Contents::File { .. } if wants_dir => return Err(Error::NotADirectory),
+            Contents::Directory(_) if writable => return Err(Error::IsADirectory),
+            _ => (),
+        }
+
+        if flags.contains(OpenFlags::TRUNCATE) {
+            self.resize(node, 0);
+        }
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.nodes.get_mut(&node).unwrap().open += 1;
+
+        self.handles.insert(
+            handle,
+            Handle {
+                owner: pid,
+                node,
+                writable,
+            },
+        );
+
+        Ok(handle)
+    }
+
+    /// Creates an empty file or directory at the path, whose parent must exist
+    fn create(&mut self, path: &str, dir: bool) -> Result<u64, Error> {
+        let (parent, name) = match path.rfind('/') {
+            Some(slash) => (&path[..slash], &path[slash + 1..]),
+            None => ("", path),
+        };
+
+        if name.is_empty() || name == "." || name == ".." || name.len() > MAX_NAME {
+            return Err(Error::InvalidArgument);
+        }
+
+        let parent = self.lookup(parent)?;
+        let contents = if dir {
+            Contents::Directory(BTreeMap::new())
+        } else {
+            Contents::File {
+                pages: BTreeMap::new(),
+                size: 0,
+            }
+        };
+
+        let node = self.next_node;
+        self.next_node += 1;
+
+        match &mut self.nodes.get_mut(&parent).unwrap().contents {
+            Contents::Directory(entries) => entries.insert(name.to_string(), node),
+            Contents::File { .. } => return Err(Error::NotADirectory),
+        };
+
+        let node_data = Node {
+            contents,
+            linked: true,
+            open: 0,
+        };
+
+        self.nodes.insert(node, node_data);
+        Ok(node)
+    }
+
+    fn read(
+        &self,
+        pid: ProcessId,
+        handle: u64,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        let node = self.handle(pid, handle)?.node;
+        let (pages, size) = match self.contents(node) {
+            Contents::File { pages, size } => (pages, *size),
+            Contents::Directory(_) => return Err(Error::IsADirectory),
+        };
+
+        let len = buf.len().min(size.saturating_sub(offset) as usize);
+        let mut done = 0;
+
+        while done < len {
+            let position = offset + done as u64;
+            let within = (position % FRAME_SIZE as u64) as usize;
+            let piece = (FRAME_SIZE - within).min(len - done);
+            let dst = &mut buf[done..done + piece];
+
+            match pages.get(&(position / FRAME_SIZE as u64)) {
+                Some(frame) => frame.with_bytes(|bytes| {
+                    dst.copy_from_slice(&bytes[within..within + piece]);
+                }),
+                None => dst.iter_mut().for_each(|byte| *byte = 0),
+            }
+
+            done += piece;
+        }
+
+        Ok(len)
+    }
+
+    fn write(
+        &mut self,
+        pid: ProcessId,
+        handle: u64,
+        offset: u64,
+        buf: &[u8],
+    ) -> Result<usize, Error> {
+        let handle = self.handle(pid, handle)?;
+        let node = handle.node;
+
+        if let Contents::Directory(_) = self.contents(node) {
+            return Err(Error::IsADirectory);
+        }
+
+        if !handle.writable {
+            return Err(Error::ReadOnly);
+        }
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .ok_or(Error::InvalidArgument)?;
+        let first = offset / FRAME_SIZE as u64;
+        let last = (end - 1) / FRAME_SIZE as u64;
+        self.allocate_pages(node, first..=last)?;
+
+        if end > self.size(node) {
+            self.resize(node, end);
+        }
+
+        let pages = match &self.nodes[&node].contents {
+            Contents::File { pages, .. } => pages,
+            Contents::Directory(_) => unreachable!(),
+        };
+
+        let mut done = 0;
+
+        while done < buf.len() {
+            let position = offset + done as u64;
+            let within = (position % FRAME_SIZE as u64) as usize;
+            let piece = (FRAME_SIZE - within).min(buf.len() - done);
+            let src = &buf[done..done + piece];
+
+            pages[&(position / FRAME_SIZE as u64)].with_bytes(|bytes| {
+                bytes[within..within + piece].copy_from_slice(src);
+            });
+
+            done += piece;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn size(&self, node: u64) -> u64 {
+        match self.contents(node) {
+            Contents::File { size, .. } => *size,
+            Contents::Directory(_) => 0,
+        }
+    }
+
+    /// Allocates the frames of a range of a file's pages which are holes. Either all are
+    /// allocated, or none are.
+    fn allocate_pages(
+        &mut self,
+        node: u64,
+        range: core::ops::RangeInclusive<u64>,
+    ) -> Result<(), Error> {
+        let pages = match &mut self.nodes.get_mut(&node).unwrap().contents {
+            Contents::File { pages, .. } => pages,
+            Contents::Directory(_) => return Err(Error::IsADirectory),
+        };
+
+        let holes: Vec<u64> = range.filter(|page| !pages.contains_key(page)).collect();
+
+        if self.pages + holes.len() as u64 > self.max_pages {
+            return Err(Error::NoSpace);
+        }
+
+        let mut frames = Vec::with_capacity(holes.len());
+
+        for _ in &holes {
+            frames.push(Frame::allocate().ok_or(Error::NoSpace)?);
+        }
+
+        self.pages += frames.len() as u64;
+
+        for (page, frame) in holes.into_iter().zip(frames) {
+            pages.insert(page, Arc::new(frame));
+        }
+
+        Ok(())
+    }
+
+    /// Changes the length of a file, freeing the frames past its end. The rest of the last page is
+    /// zeroed, so that nothing written past the old end through a mapping shows up in the file.
+    /// The freed frames are kept in `truncated` until memory objects have dropped them.
+    fn resize(&mut self, node: u64, len: u64) {
+        let (pages, size) = match &mut self.nodes.get_mut(&node).unwrap().contents {
+            Contents::File { pages, size } => (pages, size),
+            Contents::Directory(_) => return,
+        };
+
+        let end = len.min(*size);
+        let kept = (len + FRAME_SIZE as u64 - 1) / FRAME_SIZE as u64;
+        let cut = pages.split_off(&kept);
+        self.pages -= cut.len() as u64;
+        self.truncated.extend(cut.into_iter().map(|(_, page)| page));
+
+        let within = (end % FRAME_SIZE as u64) as usize;
+
+        if within != 0 {
+            if let Some(frame) = pages.get(&(end / FRAME_SIZE as u64)) {
+                frame.with_bytes(|bytes| bytes[within..].iter_mut().for_each(|byte| *byte = 0));
+            }
+        }
+
+        *size = len;
+    }
+
+    fn truncate(&mut self, pid: ProcessId, handle: u64, len: u64) -> Result<(), Error> {
+        let handle = self.handle(pid, handle)?;
+        let node = handle.node;
+
+        if let Contents::Directory(_) = self.contents(node) {
+            return Err(Error::IsADirectory);
+        }
+
+        if !handle.writable {
+            return Err(Error::ReadOnly);
+        }
+
+        self.resize(node, len);
+        Ok(())
+    }
+
+    fn stat(&self, pid: ProcessId, handle: u64, buf: &mut Vec<u8>) -> Result<(), Error> {
+        let node = self.handle(pid, handle)?.node;
+        let kind = match self.contents(node) {
+            Contents::File { .. } => KIND_FILE,
+            Contents::Directory(_) => KIND_DIRECTORY,
+        };
+
+        let mut stat = [0; STAT_SIZE];
+        stat[0..4].copy_from_slice(&kind.to_le_bytes());
+        stat[8..16].copy_from_slice(&self.size(node).to_le_bytes());
+        buf.extend_from_slice(&stat);
+        Ok(())
+    }
+
+    /// Appends the directory entry at the position, which is its index, to the buffer. Returns
+    /// the position of the next, or 0 at the end of the directory.
+    fn read_dir(
+        &self,
+        pid: ProcessId,
+        handle: u64,
+        position: u64,
+        buf: &mut Vec<u8>,
+    ) -> Result<u64, Error> {
+        let node = self.handle(pid, handle)?.node;
+        let entries = match self.contents(node) {
+            Contents::Directory(entries) => entries,
+            Contents::File { .. } => return Err(Error::NotADirectory),
+        };
+
+        let (name, child) = match entries.iter().nth(position as usize) {
+            Some(entry) => entry,
+            None => return Ok(0),
+        };
+
+        let kind = match self.contents(*child) {
+            Contents::File { .. } => KIND_FILE,
+            Contents::Directory(_) => KIND_DIRECTORY,
+        };
+
+        let mut header = [0; DIR_ENTRY_HEADER_SIZE];
+        header[0..4].copy_from_slice(&kind.to_le_bytes());
+        header[4..8].copy_from_slice(&(name.len() as u32).to_le_bytes());
+        header[8..16].copy_from_slice(&self.size(*child).to_le_bytes());
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(name.as_bytes());
+
+        Ok(position + 1)
+    }
+
+    /// Closes the handles which an exiting process had open
+    fn close_all(&mut self, pid: ProcessId) {
+        let handles: Vec<u64> = self
+            .handles
+            .iter()
+            .filter(|(_, handle)| handle.owner == pid)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for handle in handles {
+            self.close(pid, handle).unwrap();
+        }
+    }
+
+    fn close(&mut self, pid: ProcessId, handle: u64) -> Result<(), Error> {
+        let node = self.handle(pid, handle)?.node;
+        self.handles.remove(&handle);
+
+        let data = self.nodes.get_mut(&node).unwrap();
+        data.open -= 1;
+
+        if data.open == 0 && !data.linked {
+            self.free(node);
+        }
+
+        Ok(())
+    }
+
+    /// Removes a file or empty directory from its directory. Its contents are freed once it is
+    /// no longer open.
+    fn unlink(&mut self, path: &str) -> Result<(), Error> {
+        let (parent, name) = match path.rfind('/') {
+            Some(slash) => (&path[..slash], &path[slash + 1..]),
+            None => ("", path),
+        };
+
+        if name.is_empty() {
+            return Err(Error::InvalidArgument);
+        }
+
+        let parent = self.lookup(parent)?;
+        let node = match self.contents(parent) {
+            Contents::Directory(entries) => *entries.get(name).ok_or(Error::NotFound)?,
+            Contents::File { .. } => return Err(Error::NotADirectory),
+        };
+
+        if let Contents::Directory(entries) = self.contents(node) {
+            if !entries.is_empty() {
+                return Err(Error::NotEmpty);
+            }
+        }
+
+        if let Contents::Directory(entries) = &mut self.nodes.get_mut(&parent).unwrap().contents {
+            entries.remove(name);
+        }
+
+        let data = self.nodes.get_mut(&node).unwrap();
+        data.linked = false;
+
+        if data.open == 0 {
+            self.free(node);
+        }
+
+        Ok(())
+    }
+
+    fn free(&mut self, node: u64) {
+        if let Some(Contents::File { pages, .. }) = self.nodes.remove(&node).map(|n| n.contents) {
+            self.pages -= pages.len() as u64;
+        }
+    }
+
+    /// Checks that a range of pages of a file can be mapped. Returns the file's node.
+    fn check_mapping(
+        &self,
+        pid: ProcessId,
+        handle: u64,
+        first_page: u64,
+        pages: u64,
+        writable: bool,
+    ) -> Result<u64, Error> {
+        let handle = self.handle(pid, handle)?;
+        let node = handle.node;
+
+        if let Contents::Directory(_) = self.contents(node) {
+            return Err(Error::IsADirectory);
+        }
+
+        if writable && !handle.writable {
+            return Err(Error::ReadOnly);
+        }
+
+        let size_pages = (self.size(node) + FRAME_SIZE as u64 - 1) / FRAME_SIZE as u64;
+        let end = first_page
+            .checked_add(pages)
+            .ok_or(Error::InvalidArgument)?;
+
+        if pages == 0 || end > size_pages {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(node)
+    }
+
+    /// Answers a request, appending the reply's payload to the buffer. Returns the reply's value.
+    fn handle_request(
+        &mut self,
+        pid: ProcessId,
+        request: Request,
+        payload: &[u8],
+        reply: &mut Vec<u8>,
+    ) -> Result<u64, Error> {
+        match Op::from_u32(request.op).ok_or(Error::InvalidArgument)? {
+            Op::Open => {
+                let flags = OpenFlags::from_bits_truncate(request.flags);
+                self.open(pid, path_of(payload)?, flags)
+            }
+            Op::Read => {
+                let len = (request.len as usize).min(MAX_TRANSFER);
+                let start = reply.len();
+                reply.resize(start + len, 0);
+
+                let res = self.read(pid, request.handle, request.offset, &mut reply[start..]);
+                reply.truncate(start + *res.as_ref().unwrap_or(&0));
+                res.map(|len| len as u64)
+            }
+            Op::Write => {
+                let res = self.write(pid, request.handle, request.offset, payload);
+                res.map(|len| len as u64)
+            }
+            Op::Stat => self.stat(pid, request.handle, reply).map(|_| 0),
+            Op::ReadDir => self.read_dir(pid, request.handle, request.offset, reply),
+            Op::Close => self.close(pid, request.handle).map(|_| 0),
+            Op::Truncate => {
+                let res = self.truncate(pid, request.handle, request.offset);
+                res.map(|_| 0)
+            }
+            Op::Unlink => self.unlink(path_of(payload)?).map(|_| 0),
+            // Filesystems don't mount each other
+            Op::Mount | Op::Resolve => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Answers a call to the tmpfs's endpoint
+fn serve(pid: ProcessId, message: &[u8]) -> Vec<u8> {
+    let mut reply = vec![0; REPLY_SIZE];
+
+    let res = match Request::decode(message) {
+        Some(request) => {
+            let payload = &message[REQUEST_SIZE..];
+            let mut tmpfs = TMPFS.lock();
+            let res = tmpfs.handle_request(pid, request, payload, &mut reply);
+            let truncated = mem::take(&mut tmpfs.truncated);
+            drop(tmpfs);
+
+            page_cache::evict_truncated(&truncated);
+            res
+        }
+        None => Err(Error::InvalidArgument),
+    };
+
+    let header = match res {
+        Ok(value) => Reply { status: 0, value },
+        Err(err) => {
+            reply.truncate(REPLY_SIZE);
+            Reply {
+                status: err as i64,
+                value: 0,
+            }
+        }
+    };
+
+    header.encode(&mut reply);
+    reply
+}
+
+/// Creates the tmpfs and its endpoint
+pub fn init() {
+    let id = ipc::create_kernel(ENDPOINT, serve).expect("The tmpfs endpoint already exists");
+    ENDPOINT_ID.call_once(|| id);
+}
+
+/// Whether the endpoint is the tmpfs's
+pub fn is_endpoint(endpoint: u64) -> bool {
+    ENDPOINT_ID.wait() == Some(&endpoint)
+}
+
+/// Closes the files which an exiting process had open
+pub fn release(pid: ProcessId) {
+    TMPFS.lock().close_all(pid);
+}
+
+/// Checks that a range of pages of a file which the process has open can be mapped. The pages must
+/// be within the file's length, rounded up to a whole page, and the handle must be writable for
+/// them to be mapped writable.
+pub fn check_mapping(
+    pid: ProcessId,
+    handle: u64,
+    first_page: u64,
+    pages: u64,
+    writable: bool,
+) -> Result<(), Error> {
+    TMPFS
+        .lock()
+        .check_mapping(pid, handle, first_page, pages, writable)
+        .map(|_| ())
+}
+
+/// Returns the frame of a page of a file which the process has open, allocating it if it is a
+/// hole. The same checks as `check_mapping` apply.
+pub fn frame(pid: ProcessId, handle: u64, page: u64, writable: bool) -> Result<Arc<Frame>, Error> {
+    let mut tmpfs = TMPFS.lock();
+    let node = tmpfs.check_mapping(pid, handle, page, 1, writable)?;
+    tmpfs.allocate_pages(node, page..=page)?;
+
+    match tmpfs.contents(node) {
+        Contents::File { pages, .. } => Ok(pages[&page].clone()),
+        Contents::Directory(_) => unreachable!(),
+    }
+}
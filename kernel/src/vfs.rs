@@ -0,0 +1,168 @@
+//! The parts of the VFS protocol which the kernel speaks, to serve the tmpfs and to read and write
+//! the pages of mapped files. All of this must be kept in sync with `libwolffia::vfs`.
+
+use crate::syscall;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::str;
+
+/// The most data read or written by one request
+pub const MAX_TRANSFER: usize = 4096;
+/// The longest path which can be opened
+pub const MAX_PATH: usize = 256;
+/// The longest name of a directory entry
+pub const MAX_NAME: usize = 255;
+
+pub const REQUEST_SIZE: usize = 32;
+pub const REPLY_SIZE: usize = 16;
+pub const STAT_SIZE: usize = 16;
+pub const DIR_ENTRY_HEADER_SIZE: usize = 16;
+
+pub const KIND_FILE: u32 = 0;
+pub const KIND_DIRECTORY: u32 = 1;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum Op {
+    Open = 0,
+    Read = 1,
+    Write = 2,
+    Stat = 3,
+    ReadDir = 4,
+    Close = 5,
+    Mount = 6,
+    Resolve = 7,
+    Truncate = 8,
+    Unlink = 9,
+}
+
+impl Op {
+    pub fn from_u32(v: u32) -> Option<Op> {
+        match v {
+            0 => Some(Op::Open),
+            1 => Some(Op::Read),
+            2 => Some(Op::Write),
+            3 => Some(Op::Stat),
+            4 => Some(Op::ReadDir),
+            5 => Some(Op::Close),
+            6 => Some(Op::Mount),
+            7 => Some(Op::Resolve),
+            8 => Some(Op::Truncate),
+            9 => Some(Op::Unlink),
+            _ => None,
+        }
+    }
+}
+
+bitflags::bitflags! {
+    pub struct OpenFlags: u32 {
+        const READ = 1;
+        const WRITE = 1 << 1;
+        const CREATE = 1 << 2;
+        const TRUNCATE = 1 << 3;
+        const DIRECTORY = 1 << 4;
+        const NO_FOLLOW = 1 << 5;
+    }
+}
+
+/// The errors of the VFS protocol, as their codes
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(i64)]
+pub enum Error {
+    NotFound = -1,
+    AlreadyExists = -2,
+    NotADirectory = -3,
+    IsADirectory = -4,
+    NotEmpty = -5,
+    ReadOnly = -6,
+    NoSpace = -7,
+    InvalidArgument = -8,
+    BadHandle = -9,
+}
+
+impl From<Error> for syscall::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::NotFound | Error::BadHandle => syscall::Error::NotFound,
+            Error::ReadOnly => syscall::Error::PermissionDenied,
+            Error::NoSpace => syscall::Error::OutOfMemory,
+            _ => syscall::Error::InvalidArgument,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Request {
+    pub op: u32,
+    pub flags: u32,
+    pub handle: u64,
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl Request {
+    pub fn new(op: Op) -> Request {
+        Request {
+            op: op as u32,
+            ..Request::default()
+        }
+    }
+
+    /// Encodes the request, followed by its payload
+    pub fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(REQUEST_SIZE + payload.len());
+        buf.extend_from_slice(&self.op.to_le_bytes());
+        buf.extend_from_slice(&self.flags.to_le_bytes());
+        buf.extend_from_slice(&self.handle.to_le_bytes());
+        buf.extend_from_slice(&self.offset.to_le_bytes());
+        buf.extend_from_slice(&self.len.to_le_bytes());
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Request> {
+        let buf = buf.get(..REQUEST_SIZE)?;
+
+        Some(Request {
+            op: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+            flags: u32::from_le_bytes(buf[4..8].try_into().unwrap()),
+            handle: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+            offset: u64::from_le_bytes(buf[16..24].try_into().unwrap()),
+            len: u64::from_le_bytes(buf[24..32].try_into().unwrap()),
+        })
+    }
+}
+
+/// The header of a reply. A negative status is an error's code, and otherwise the value is the
+/// result of the request.
+#[derive(Debug, Copy, Clone)]
+pub struct Reply {
+    pub status: i64,
+    pub value: u64,
+}
+
+impl Reply {
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0..8].copy_from_slice(&self.status.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.value.to_le_bytes());
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Reply> {
+        let buf = buf.get(..REPLY_SIZE)?;
+
+        Some(Reply {
+            status: i64::from_le_bytes(buf[0..8].try_into().unwrap()),
+            value: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+        })
+    }
+}
+
+/// Parses the path which a request carries, stripping any leading or trailing `/`
+pub fn path_of(payload: &[u8]) -> Result<&str, Error> {
+    if payload.len() > MAX_PATH {
+        return Err(Error::InvalidArgument);
+    }
+
+    let path = str::from_utf8(payload).map_err(|_| Error::InvalidArgument)?;
+    Ok(path.trim_matches('/'))
+}
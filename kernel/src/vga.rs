@@ -1,8 +1,22 @@
 use crate::memory::KERNEL_MAPPING_BEGIN;
+use alloc::vec::Vec;
 use core::fmt::{self, Debug, Write};
+use core::ops::Range;
 use core::ptr::NonNull;
-use core::{cmp, ptr};
+use core::sync::atomic::{AtomicIsize, Ordering};
+use core::{cmp, mem, ptr};
 use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+mod ansi;
+mod font;
+mod framebuffer;
+mod scrollback;
+
+use self::ansi::{Action, ControlSequence, Parser};
+use self::framebuffer::FramebufferConsole;
+pub use self::framebuffer::FramebufferError;
+use self::scrollback::Scrollback;
 
 /// Represents colours, based off of VGA's colour set
 #[allow(dead_code)] // dead variants for completeness
@@ -74,6 +88,66 @@ pub fn stdout_print(args: fmt::Arguments) {
 
 pub static VGA_WRITER: Mutex<VgaWriter> = Mutex::new(unsafe { VgaWriter::new() });
 
+/// Pages to scroll the view back by, or forward by if negative, requested by the keyboard's IRQ
+/// handler and applied by `poll`
+static PENDING_SCROLL: AtomicIsize = AtomicIsize::new(0);
+
+/// Starts keeping the lines which scroll off the top of the screen. Must be called once the heap
+/// is initialised.
+pub fn init_scrollback() {
+    VGA_WRITER.lock().scrollback = Some(Scrollback::new());
+}
+
+/// Requests that the view be scrolled back through the scrollback by the given number of pages,
+/// or forward if negative. May be called from interrupt handlers.
+pub fn request_scroll(pages: isize) {
+    PENDING_SCROLL.fetch_add(pages, Ordering::Relaxed);
+}
+
+/// Applies the scroll requests made since the last call. Must not be called from an interrupt
+/// handler, as the writer may be locked.
+pub fn poll() {
+    let pages = PENDING_SCROLL.swap(0, Ordering::Relaxed);
+
+    if pages == 0 {
+        return;
+    }
+
+    match VGA_WRITER.try_lock() {
+        Some(mut writer) => {
+            let page = writer.resolution().y as isize / 2;
+            writer.scroll_view(pages * page);
+        }
+        None => request_scroll(pages),
+    }
+}
+
+/// Switches the VGA writer to drawing onto the framebuffer, if the bootloader set up a graphical
+/// one, and returns its resolution in pixels. Must be called after the boot information is
+/// initialised.
+pub fn init_framebuffer() -> Result<Resolution, FramebufferError> {
+    let console = FramebufferConsole::from_boot_info()?;
+    let pixels = console.pixel_resolution();
+
+    let mut writer = VGA_WRITER.lock();
+
+    // Keep what was written in text mode in the scrollback, as the screen is about to be cleared
+    writer.return_to_live();
+    let written_rows = writer.resolution().y - writer.cursor.1;
+    for row in 0..written_rows {
+        let line = writer.row(row);
+        if let Some(scrollback) = &mut writer.scrollback {
+            scrollback.push(line);
+        }
+    }
+
+    writer.framebuffer = Some(console);
+    writer.cursor = (0, writer.resolution().y - 1);
+    writer.clear();
+
+    Ok(pixels)
+}
+
 /// Represents a vga's resolution.
 ///
 /// # Note
@@ -86,14 +160,37 @@ pub struct Resolution {
 
 pub const VIRTUAL_VGA_PTR: u64 = KERNEL_MAPPING_BEGIN + 0xb8000;
 
-/// The resolution of VGA
+/// The resolution of VGA text mode
 pub const RESOLUTION: Resolution = Resolution { x: 80, y: 25 };
 
-/// Interface to VGA, allowing write
+const CRTC_ADDRESS_PORT: u16 = 0x3D4;
+const CRTC_DATA_PORT: u16 = 0x3D5;
+const CRTC_CURSOR_START: u8 = 0x0A;
+const CRTC_CURSOR_LOCATION_HIGH: u8 = 0x0E;
+const CRTC_CURSOR_LOCATION_LOW: u8 = 0x0F;
+/// Set in the cursor start register to hide the cursor
+const CURSOR_DISABLE: u8 = 1 << 5;
+/// The scanline the cursor starts on, so that it is drawn as an underline
+const CURSOR_START_SCANLINE: u8 = 14;
+
+/// Interface to VGA, allowing write. Understands the ANSI escape sequences for colours, moving
+/// the cursor and erasing.
 pub struct VgaWriter {
     buffer: NonNull<VgaBuffer>,
+    /// Drawn onto instead of the text buffer if the bootloader set up a graphical framebuffer
+    framebuffer: Option<FramebufferConsole>,
+    /// The x position, and the y position counted from the bottom row
     cursor: (usize, usize),
+    /// Saved by `ESC [ s` and restored by `ESC [ u`
+    saved_cursor: (usize, usize),
+    /// Whether the hardware cursor is shown. It is only drawn in text mode.
+    cursor_visible: bool,
+    /// Lines scrolled off the top of the screen. None until the heap is initialised.
+    scrollback: Option<Scrollback>,
     pub colour: ColourPair,
+    /// Whether the foreground colours selected by escape sequences are bright
+    bold: bool,
+    parser: Parser,
 }
 
 impl fmt::Debug for VgaWriter {
@@ -120,50 +217,261 @@ impl VgaWriter {
     pub const unsafe fn new() -> Self {
         VgaWriter {
             buffer: NonNull::new_unchecked(VIRTUAL_VGA_PTR as *mut _),
+            framebuffer: None,
             cursor: (0, RESOLUTION.y - 1),
+            saved_cursor: (0, RESOLUTION.y - 1),
+            cursor_visible: true,
+            scrollback: None,
             colour: colour!(White on Black),
+            bold: false,
+            parser: Parser::new(),
         }
     }
 
     fn buffer(&mut self) -> &mut VgaBuffer {
         unsafe { self.buffer.as_mut() }
     }
+
+    /// The size of the screen in characters
+    pub fn resolution(&self) -> Resolution {
+        match &self.framebuffer {
+            Some(framebuffer) => framebuffer.resolution(),
+            None => RESOLUTION,
+        }
+    }
 }
 
 impl VgaWriter {
     fn set_char(&mut self, character: char, colour: ColourPair, point: (usize, usize)) {
-        self.buffer().set_char(
-            point.0,
-            RESOLUTION.y - 1 - point.1,
-            VgaChar::new(colour.into(), character as u8),
-        );
+        let y = self.resolution().y - 1 - point.1;
+        self.put(point.0, y, VgaChar::new(colour.into(), character as u8));
     }
 
-    pub fn write_str(&mut self, txt: &str) {
-        self.write_str_coloured(txt, self.colour)
+    /// Puts the character in the cell, with y counted from the top of the screen
+    fn put(&mut self, x: usize, y: usize, value: VgaChar) {
+        match &mut self.framebuffer {
+            Some(framebuffer) => framebuffer.set_char(x, y, value),
+            None => self.buffer().set_char(x, y, value),
+        }
     }
 
-    pub fn write_str_coloured(&mut self, txt: &str, colour: ColourPair) {
+    /// Returns the characters in the row, counted from the top of the screen
+    fn row(&mut self, y: usize) -> Vec<VgaChar> {
+        match &self.framebuffer {
+            Some(framebuffer) => framebuffer.row(y).to_vec(),
+            None => self.buffer().row(y),
+        }
+    }
+
+    pub fn write_str(&mut self, txt: &str) {
+        self.return_to_live();
+
         for c in txt.chars() {
-            self.write_coloured(c, colour)
+            self.feed(c);
         }
+
+        self.update_hardware_cursor();
+    }
+
+    /// Writes the string in the given colour. Escape sequences in the string may still change the
+    /// colour, until the end of the string.
+    pub fn write_str_coloured(&mut self, txt: &str, colour: ColourPair) {
+        let previous = mem::replace(&mut self.colour, colour);
+        self.write_str(txt);
+        self.colour = previous;
     }
 
     pub fn write_coloured(&mut self, character: char, colour: ColourPair) {
+        self.return_to_live();
+
+        let previous = mem::replace(&mut self.colour, colour);
+        self.feed(character);
+        self.colour = previous;
+
+        self.update_hardware_cursor();
+    }
+
+    fn feed(&mut self, character: char) {
+        match self.parser.feed(character) {
+            Some(Action::Print(character)) => self.print(character),
+            Some(Action::ControlSequence(sequence)) => self.control_sequence(sequence),
+            Some(Action::Reset) => {
+                self.colour = ColourPair::default();
+                self.bold = false;
+                self.cursor_visible = true;
+                self.clear();
+                self.cursor = (0, self.resolution().y - 1);
+            }
+            None => (),
+        }
+    }
+
+    fn print(&mut self, character: char) {
         match character {
             '\n' => self.new_line(),
+            '\r' => self.cursor.0 = 0,
+            '\x08' => self.cursor.0 = self.cursor.0.saturating_sub(1),
+            '\t' => {
+                let next_stop = (self.cursor.0 / 8 + 1) * 8;
+                self.cursor.0 = cmp::min(next_stop, self.resolution().x - 1);
+            }
+            // The bell
+            '\x07' => (),
             _ => {
-                self.set_char(character, colour, self.cursor);
+                self.set_char(character, self.colour, self.cursor);
                 self.cursor.0 += 1;
 
                 // If the x point went out of bounds, wrap
-                if self.cursor.0 >= RESOLUTION.x {
+                if self.cursor.0 >= self.resolution().x {
                     self.new_line();
                 }
             }
         }
     }
 
+    fn control_sequence(&mut self, sequence: ControlSequence) {
+        let resolution = self.resolution();
+        let amount = sequence.param_or(0, 1) as usize;
+
+        match (sequence.private, sequence.command) {
+            (false, 'm') => self.select_graphic_rendition(sequence.params()),
+            // Cursor up, down, forward and back
+            (false, 'A') => self.cursor.1 = cmp::min(self.cursor.1 + amount, resolution.y - 1),
+            (false, 'B') => self.cursor.1 = self.cursor.1.saturating_sub(amount),
+            (false, 'C') => self.cursor.0 = cmp::min(self.cursor.0 + amount, resolution.x - 1),
+            (false, 'D') => self.cursor.0 = self.cursor.0.saturating_sub(amount),
+            // Cursor position, with the row and column counted from 1
+            (false, 'H') | (false, 'f') => {
+                let row = cmp::min(sequence.param_or(0, 1) as usize, resolution.y);
+                let column = cmp::min(sequence.param_or(1, 1) as usize, resolution.x);
+                self.cursor = (column - 1, resolution.y - row);
+            }
+            (false, 'J') => self.erase_screen(sequence.params().first().copied().unwrap_or(0)),
+            (false, 'K') => self.erase_line(sequence.params().first().copied().unwrap_or(0)),
+            (false, 's') => self.saved_cursor = self.cursor,
+            (false, 'u') => self.cursor = self.saved_cursor,
+            // Show and hide the cursor
+            (true, 'h') if sequence.params() == [25] => self.cursor_visible = true,
+            (true, 'l') if sequence.params() == [25] => self.cursor_visible = false,
+            _ => trace!("vga: ignoring control sequence {:?}", sequence),
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        // No parameters is the same as a reset
+        if params.is_empty() {
+            self.select_graphic_rendition(&[0]);
+            return;
+        }
+
+        for &param in params {
+            match param {
+                0 => {
+                    self.colour = ColourPair::default();
+                    self.bold = false;
+                }
+                1 => {
+                    self.bold = true;
+                    self.colour.foreground = ansi::brighten(self.colour.foreground);
+                }
+                22 => {
+                    self.bold = false;
+                    self.colour.foreground = ansi::dim(self.colour.foreground);
+                }
+                30..=37 => self.colour.foreground = ansi::colour(param - 30, self.bold),
+                39 => self.colour.foreground = ColourPair::default().foreground,
+                40..=47 => self.colour.background = ansi::colour(param - 40, false),
+                49 => self.colour.background = ColourPair::default().background,
+                90..=97 => self.colour.foreground = ansi::colour(param - 90, true),
+                100..=107 => self.colour.background = ansi::colour(param - 100, true),
+                // 256 colour and RGB colours take further parameters, which shouldn't be mistaken
+                // for other attributes
+                38 | 48 => break,
+                _ => (),
+            }
+        }
+    }
+
+    /// Erases part of the screen: 0 from the cursor to the end, 1 from the start to the cursor,
+    /// and 2 or 3 all of it
+    fn erase_screen(&mut self, mode: u16) {
+        let resolution = self.resolution();
+        let (x, y) = self.cursor;
+
+        match mode {
+            0 => {
+                self.erase_cells(y, x..resolution.x);
+                for row in 0..y {
+                    self.erase_cells(row, 0..resolution.x);
+                }
+            }
+            1 => {
+                self.erase_cells(y, 0..x + 1);
+                for row in y + 1..resolution.y {
+                    self.erase_cells(row, 0..resolution.x);
+                }
+            }
+            2 | 3 => {
+                for row in 0..resolution.y {
+                    self.erase_cells(row, 0..resolution.x);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Erases part of the cursor's line: 0 from the cursor to the end, 1 from the start to the
+    /// cursor, and 2 all of it
+    fn erase_line(&mut self, mode: u16) {
+        let width = self.resolution().x;
+        let (x, y) = self.cursor;
+
+        match mode {
+            0 => self.erase_cells(y, x..width),
+            1 => self.erase_cells(y, 0..x + 1),
+            2 => self.erase_cells(y, 0..width),
+            _ => (),
+        }
+    }
+
+    /// Erases the cells in the row, counted from the bottom, with the current background colour
+    fn erase_cells(&mut self, y: usize, xs: Range<usize>) {
+        let colour = self.colour;
+
+        for x in xs {
+            self.set_char(' ', colour, (x, y));
+        }
+    }
+
+    /// Moves the hardware cursor to the writer's cursor. Does nothing when drawing onto a
+    /// framebuffer, as it has no cursor.
+    fn update_hardware_cursor(&mut self) {
+        if self.framebuffer.is_some() {
+            return;
+        }
+
+        let mut address: Port<u8> = Port::new(CRTC_ADDRESS_PORT);
+        let mut data: Port<u8> = Port::new(CRTC_DATA_PORT);
+
+        let start = if self.cursor_visible {
+            CURSOR_START_SCANLINE
+        } else {
+            CURSOR_DISABLE
+        };
+
+        let position = (RESOLUTION.y - 1 - self.cursor.1) * RESOLUTION.x + self.cursor.0;
+
+        // SAFETY: these are the VGA's CRT controller registers, which only the VGA writer uses
+        unsafe {
+            address.write(CRTC_CURSOR_START);
+            data.write(start);
+            address.write(CRTC_CURSOR_LOCATION_HIGH);
+            data.write((position >> 8) as u8);
+            address.write(CRTC_CURSOR_LOCATION_LOW);
+            data.write(position as u8);
+        }
+    }
+
     /// Writes a newline to this terminal, resetting cursor position
     fn new_line(&mut self) {
         self.cursor.0 = 0;
@@ -176,13 +484,94 @@ impl VgaWriter {
 
     fn scroll_down(&mut self, amount: usize) {
         let background = self.colour.background;
-        self.buffer().scroll_down(amount, background);
+
+        if self.scrollback.is_some() {
+            for row in 0..cmp::min(amount, self.resolution().y) {
+                let line = self.row(row);
+                self.scrollback.as_mut().unwrap().push(line);
+            }
+        }
+
+        match &mut self.framebuffer {
+            Some(framebuffer) => framebuffer.scroll_down(amount, background),
+            None => self.buffer().scroll_down(amount, background),
+        }
     }
 
     pub fn clear(&mut self) {
         let background = self.colour.background;
-        for line in 0..RESOLUTION.y {
-            self.buffer().clear_row(line, background);
+
+        for line in 0..self.resolution().y {
+            match &mut self.framebuffer {
+                Some(framebuffer) => framebuffer.clear_row(line, background),
+                None => self.buffer().clear_row(line, background),
+            }
+        }
+    }
+
+    /// Scrolls the view back through the scrollback by the given number of lines, or forward if
+    /// negative. The view returns to the live screen when anything is written.
+    pub fn scroll_view(&mut self, lines: isize) {
+        let mut scrollback = match self.scrollback.take() {
+            Some(scrollback) => scrollback,
+            None => return,
+        };
+
+        let previous = scrollback.offset();
+        let offset = cmp::max(previous as isize + lines, 0) as usize;
+
+        if previous == 0 && offset > 0 {
+            let live = (0..self.resolution().y).map(|row| self.row(row)).collect();
+            scrollback.save_live(live);
+        }
+
+        scrollback.set_offset(offset);
+
+        if scrollback.offset() != previous {
+            let resolution = self.resolution();
+
+            for y in 0..resolution.y {
+                for x in 0..resolution.x {
+                    // Lines from before a change of resolution may be shorter than the screen
+                    let value = scrollback
+                        .view_line(y)
+                        .and_then(|line| line.get(x).copied())
+                        .unwrap_or_else(|| VgaChar::blank(Colour::Black));
+
+                    self.put(x, y, value);
+                }
+            }
+        }
+
+        if scrollback.offset() == 0 {
+            scrollback.discard_live();
+        }
+
+        self.scrollback = Some(scrollback);
+    }
+
+    /// Returns the view to the live screen if it is scrolled back
+    fn return_to_live(&mut self) {
+        let offset = self.scrollback.as_ref().map_or(0, Scrollback::offset);
+
+        if offset > 0 {
+            self.scroll_view(-(offset as isize));
+        }
+    }
+
+    /// Calls `write_line` with each line in the scrollback, oldest first, and then each line on
+    /// the screen
+    pub fn dump_history(&mut self, mut write_line: impl FnMut(&[VgaChar])) {
+        self.return_to_live();
+
+        if let Some(scrollback) = &self.scrollback {
+            for line in scrollback.lines() {
+                write_line(line);
+            }
+        }
+
+        for row in 0..self.resolution().y {
+            write_line(&self.row(row));
         }
     }
 }
@@ -196,6 +585,13 @@ impl VgaBuffer {
         unsafe { ptr::write_volatile(&mut self.0[y][x] as *mut _, value) }
     }
 
+    pub fn row(&self, y: usize) -> Vec<VgaChar> {
+        self.0[y]
+            .iter()
+            .map(|cell| unsafe { ptr::read_volatile(cell as *const _) })
+            .collect()
+    }
+
     pub fn scroll_down(&mut self, amount: usize, background_colour: Colour) {
         // Shift lines left (up) by amount only if amount < Y resolution
         // If amount is any more then the data will be cleared anyway
@@ -210,7 +606,7 @@ impl VgaBuffer {
     }
 
     pub fn clear_row(&mut self, y: usize, colour: Colour) {
-        let blank = VgaChar::new(VgaColour::new(Colour::Black, colour), b' ');
+        let blank = VgaChar::blank(colour);
 
         for x in 0..RESOLUTION.x {
             self.set_char(x, y, blank);
@@ -219,7 +615,7 @@ impl VgaBuffer {
 }
 
 /// Represents a full character in the VGA buffer, with a character code, foreground and background
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Eq, PartialEq)]
 #[repr(C)]
 pub struct VgaChar {
     pub character: u8,
@@ -230,10 +626,15 @@ impl VgaChar {
     fn new(colour: VgaColour, character: u8) -> Self {
         VgaChar { colour, character }
     }
+
+    /// A space with the given background colour
+    fn blank(background: Colour) -> Self {
+        VgaChar::new(VgaColour::new(Colour::Black, background), b' ')
+    }
 }
 
 /// Represents a VGA colour, with both a foreground and background
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Eq, PartialEq)]
 #[repr(C)]
 pub struct VgaColour(u8);
 
@@ -242,6 +643,11 @@ impl VgaColour {
     pub const fn new(foreground: Colour, background: Colour) -> Self {
         VgaColour((background as u8) << 4 | (foreground as u8))
     }
+
+    /// Returns the foreground and background colour indices
+    pub fn split(self) -> (u8, u8) {
+        (self.0 & 0xf, self.0 >> 4)
+    }
 }
 
 impl From<ColourPair> for VgaColour {
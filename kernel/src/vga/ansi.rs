@@ -0,0 +1,182 @@
+//! A parser for the subset of ANSI/VT100 escape sequences which the VGA writer understands.
+//! From: [ECMA-48](https://www.ecma-international.org/publications-and-standards/standards/ecma-48/)
+
+use super::Colour;
+
+const ESCAPE: char = '\x1b';
+/// Parameters past this are ignored
+const MAX_PARAMS: usize = 8;
+
+/// The colours selected by SGR parameters 30 to 37 and 40 to 47
+const COLOURS: [Colour; 8] = [
+    Colour::Black,
+    Colour::Red,
+    Colour::Green,
+    Colour::Brown,
+    Colour::Blue,
+    Colour::Magenta,
+    Colour::Cyan,
+    Colour::LightGray,
+];
+
+/// The colours selected by SGR parameters 90 to 97 and 100 to 107, or by the normal ones when bold
+const BRIGHT_COLOURS: [Colour; 8] = [
+    Colour::DarkGray,
+    Colour::LightRed,
+    Colour::LightGreen,
+    Colour::Yellow,
+    Colour::LightBlue,
+    Colour::Pink,
+    Colour::LightCyan,
+    Colour::White,
+];
+
+/// Returns the ANSI colour with the given index, which must be less than 8
+pub fn colour(index: u16, bright: bool) -> Colour {
+    if bright {
+        BRIGHT_COLOURS[index as usize]
+    } else {
+        COLOURS[index as usize]
+    }
+}
+
+/// Returns the bright version of a colour, or the colour itself if it has none
+pub fn brighten(colour: Colour) -> Colour {
+    match COLOURS.iter().position(|&c| c == colour) {
+        Some(index) => BRIGHT_COLOURS[index],
+        None => colour,
+    }
+}
+
+/// Returns the normal version of a bright colour, or the colour itself if it has none
+pub fn dim(colour: Colour) -> Colour {
+    match BRIGHT_COLOURS.iter().position(|&c| c == colour) {
+        Some(index) => COLOURS[index],
+        None => colour,
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum State {
+    Ground,
+    /// After an escape
+    Escape,
+    /// After an escape and `[`
+    Csi,
+}
+
+/// A complete control sequence introduced by `ESC [`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ControlSequence {
+    params: [u16; MAX_PARAMS],
+    param_count: usize,
+    /// Set if the parameters began with `?`, as in the DEC private modes
+    pub private: bool,
+    pub command: char,
+}
+
+impl ControlSequence {
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.param_count]
+    }
+
+    /// Returns the parameter at the index, or the default if it is missing or zero
+    pub fn param_or(&self, index: usize, default: u16) -> u16 {
+        match self.params().get(index) {
+            Some(&param) if param != 0 => param,
+            _ => default,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Action {
+    /// A character to be printed, or a control character to be handled
+    Print(char),
+    ControlSequence(ControlSequence),
+    /// `ESC c`, which resets the terminal to its initial state
+    Reset,
+}
+
+#[derive(Debug)]
+pub struct Parser {
+    state: State,
+    sequence: ControlSequence,
+}
+
+impl Parser {
+    pub const fn new() -> Parser {
+        Parser {
+            state: State::Ground,
+            sequence: ControlSequence {
+                params: [0; MAX_PARAMS],
+                param_count: 0,
+                private: false,
+                command: '\0',
+            },
+        }
+    }
+
+    /// Feeds the next character to the parser, returning the action to take, if any. Malformed
+    /// or unsupported sequences are dropped.
+    pub fn feed(&mut self, character: char) -> Option<Action> {
+        match self.state {
+            State::Ground if character == ESCAPE => {
+                self.state = State::Escape;
+                None
+            }
+            State::Ground => Some(Action::Print(character)),
+            State::Escape => {
+                self.state = State::Ground;
+
+                match character {
+                    '[' => {
+                        self.state = State::Csi;
+                        self.sequence = Parser::new().sequence;
+                        None
+                    }
+                    'c' => Some(Action::Reset),
+                    _ => None,
+                }
+            }
+            State::Csi => self.feed_csi(character),
+        }
+    }
+
+    fn feed_csi(&mut self, character: char) -> Option<Action> {
+        let sequence = &mut self.sequence;
+
+        match character {
+            '0'..='9' => {
+                if sequence.param_count == 0 {
+                    sequence.param_count = 1;
+                }
+
+                let digit = character as u16 - '0' as u16;
+                let param = &mut sequence.params[sequence.param_count - 1];
+                *param = param.saturating_mul(10).saturating_add(digit);
+            }
+            ';' => {
+                // An empty first parameter still counts as one
+                if sequence.param_count == 0 {
+                    sequence.param_count = 1;
+                }
+
+                if sequence.param_count < MAX_PARAMS {
+                    sequence.param_count += 1;
+                }
+            }
+            '?' if sequence.param_count == 0 => sequence.private = true,
+            // Intermediate bytes, which none of the supported sequences use
+            ' '..='/' => (),
+            '@'..='~' => {
+                sequence.command = character;
+                self.state = State::Ground;
+                return Some(Action::ControlSequence(*sequence));
+            }
+            _ => self.state = State::Ground,
+        }
+
+        None
+    }
+}
@@ -0,0 +1,103 @@
+//! Parsing of PC Screen Font (PSF) bitmap fonts, versions 1 and 2.
+//! From: [OsDev Wiki](https://wiki.osdev.org/PC_Screen_Font)
+
+use core::convert::TryInto;
+
+/// The font drawn for the framebuffer console, generated by `kernel/font/mkfont.py`
+pub static DEFAULT_FONT: &[u8] = include_bytes!("font.psf");
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_HEADER_SIZE: usize = 4;
+/// Set in the PSF1 mode if the font has 512 glyphs rather than 256
+const PSF1_MODE_512: u8 = 1;
+
+const PSF2_MAGIC: u32 = 0x864a_b572;
+const PSF2_MIN_HEADER_SIZE: usize = 32;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FontError {
+    UnknownFormat,
+    /// The header describes more glyph data than there is
+    Truncated,
+    /// The glyphs are too wide for their rows to fit in a byte
+    UnsupportedWidth(u32),
+}
+
+/// A bitmap font. Each glyph is a row-major bitmap, with the most significant bit of a row's first
+/// byte being its leftmost pixel.
+pub struct Font {
+    glyphs: &'static [u8],
+    glyph_count: usize,
+    bytes_per_glyph: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Font {
+    pub fn parse(data: &'static [u8]) -> Result<Font, FontError> {
+        if data.starts_with(&PSF1_MAGIC) && data.len() >= PSF1_HEADER_SIZE {
+            let glyph_count = if data[2] & PSF1_MODE_512 != 0 {
+                512
+            } else {
+                256
+            };
+            let height = data[3] as usize;
+
+            return Font::new(&data[PSF1_HEADER_SIZE..], glyph_count, height, 8, height);
+        }
+
+        if data.len() < PSF2_MIN_HEADER_SIZE || read_u32(data, 0) != PSF2_MAGIC {
+            return Err(FontError::UnknownFormat);
+        }
+
+        let header_size = read_u32(data, 8) as usize;
+        let glyph_count = read_u32(data, 16) as usize;
+        let bytes_per_glyph = read_u32(data, 20) as usize;
+        let height = read_u32(data, 24) as usize;
+        let width = read_u32(data, 28);
+
+        // Only fonts whose rows are a single byte are supported
+        if width == 0 || width > 8 {
+            return Err(FontError::UnsupportedWidth(width));
+        }
+
+        let glyphs = data.get(header_size..).ok_or(FontError::Truncated)?;
+        Font::new(glyphs, glyph_count, bytes_per_glyph, width as usize, height)
+    }
+
+    fn new(
+        glyphs: &'static [u8],
+        glyph_count: usize,
+        bytes_per_glyph: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<Font, FontError> {
+        if bytes_per_glyph < height || glyphs.len() < glyph_count * bytes_per_glyph {
+            return Err(FontError::Truncated);
+        }
+
+        Ok(Font {
+            glyphs,
+            glyph_count,
+            bytes_per_glyph,
+            width,
+            height,
+        })
+    }
+
+    /// Returns the rows of the glyph for the given character, or of `?` if the font has none
+    pub fn glyph(&self, character: u8) -> &[u8] {
+        let index = if (character as usize) < self.glyph_count {
+            character as usize
+        } else {
+            b'?' as usize
+        };
+
+        let start = index * self.bytes_per_glyph;
+        &self.glyphs[start..start + self.height]
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
@@ -0,0 +1,235 @@
+//! A text console drawn onto the linear framebuffer which the bootloader set up, for when there is
+//! no VGA text mode, e.g when booted through UEFI.
+
+use super::font::{Font, FontError, DEFAULT_FONT};
+use super::{Colour, Resolution, VgaChar};
+use crate::boot_info;
+use crate::memory::physical_mapping::{self, PhysicalMapping};
+use alloc::vec::Vec;
+use core::ptr;
+use multiboot2::{FramebufferField, FramebufferType};
+
+/// The standard VGA palette, indexed by `Colour`
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0xAA),
+    (0x00, 0xAA, 0x00),
+    (0x00, 0xAA, 0xAA),
+    (0xAA, 0x00, 0x00),
+    (0xAA, 0x00, 0xAA),
+    (0xAA, 0x55, 0x00),
+    (0xAA, 0xAA, 0xAA),
+    (0x55, 0x55, 0x55),
+    (0x55, 0x55, 0xFF),
+    (0x55, 0xFF, 0x55),
+    (0x55, 0xFF, 0xFF),
+    (0xFF, 0x55, 0x55),
+    (0xFF, 0x55, 0xFF),
+    (0xFF, 0xFF, 0x55),
+    (0xFF, 0xFF, 0xFF),
+];
+
+#[derive(Debug)]
+pub enum FramebufferError {
+    /// The bootloader did not give the kernel a framebuffer
+    NoFramebuffer,
+    /// The framebuffer is in text mode, so the VGA text buffer should be used instead
+    TextMode,
+    /// Only direct colour framebuffers with 2 to 4 bytes per pixel are supported
+    UnsupportedFormat,
+    Font(FontError),
+}
+
+/// The position and size in bits of a colour channel in a pixel
+#[derive(Debug, Copy, Clone)]
+struct Channel {
+    position: u8,
+    size: u8,
+}
+
+impl Channel {
+    fn pack(self, value: u8) -> u32 {
+        // Keep the most significant bits if the channel is narrower than 8 bits
+        let value = (value as u32) >> 8u8.saturating_sub(self.size);
+        value << self.position
+    }
+}
+
+/// A console of character cells drawn onto the framebuffer
+pub struct FramebufferConsole {
+    _mapping: PhysicalMapping<u8>,
+    pixels: *mut u8,
+    pitch: usize,
+    bytes_per_pixel: usize,
+    red: Channel,
+    green: Channel,
+    blue: Channel,
+    font: Font,
+    resolution: Resolution,
+    /// The characters drawn in each cell, so that unchanged cells are not redrawn
+    cells: Vec<VgaChar>,
+}
+
+impl FramebufferConsole {
+    /// Maps the framebuffer described by the boot information. Must be called after the boot
+    /// information is initialised.
+    pub fn from_boot_info() -> Result<FramebufferConsole, FramebufferError> {
+        let tag = boot_info::boot_info()
+            .framebuffer_tag()
+            .ok_or(FramebufferError::NoFramebuffer)?;
+
+        let (red, green, blue) = match tag.buffer_type {
+            FramebufferType::RGB { red, green, blue } => (red, green, blue),
+            FramebufferType::Text => return Err(FramebufferError::TextMode),
+            FramebufferType::Indexed { .. } => return Err(FramebufferError::UnsupportedFormat),
+        };
+
+        let bytes_per_pixel = (tag.bpp as usize + 7) / 8;
+        if bytes_per_pixel < 2 || bytes_per_pixel > 4 {
+            return Err(FramebufferError::UnsupportedFormat);
+        }
+
+        let font = Font::parse(DEFAULT_FONT).map_err(FramebufferError::Font)?;
+        let resolution = Resolution {
+            x: tag.width as usize / font.width,
+            y: tag.height as usize / font.height,
+        };
+
+        let size = tag.pitch as u64 * tag.height as u64;
+
+        // SAFETY: the bootloader describes this as the framebuffer
+        let mut mapping =
+            unsafe { physical_mapping::map_physical_region::<u8>(tag.address, size, true) };
+        let pixels = mapping.deref_mut().unwrap() as *mut u8;
+
+        let channel = |field: FramebufferField| Channel {
+            position: field.position,
+            size: field.size,
+        };
+
+        let mut console = FramebufferConsole {
+            _mapping: mapping,
+            pixels,
+            pitch: tag.pitch as usize,
+            bytes_per_pixel,
+            red: channel(red),
+            green: channel(green),
+            blue: channel(blue),
+            font,
+            resolution,
+            cells: Vec::new(),
+        };
+
+        console.cells = vec![VgaChar::blank(Colour::Black); resolution.x * resolution.y];
+        console.redraw();
+
+        Ok(console)
+    }
+
+    /// The size of the console in characters
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// The size of the framebuffer in pixels
+    pub fn pixel_resolution(&self) -> Resolution {
+        Resolution {
+            x: self.resolution.x * self.font.width,
+            y: self.resolution.y * self.font.height,
+        }
+    }
+
+    pub fn set_char(&mut self, x: usize, y: usize, value: VgaChar) {
+        let index = y * self.resolution.x + x;
+
+        if self.cells[index] != value {
+            self.cells[index] = value;
+            self.draw_cell(x, y);
+        }
+    }
+
+    /// Returns the characters in the row
+    pub fn row(&self, y: usize) -> &[VgaChar] {
+        let width = self.resolution.x;
+        &self.cells[y * width..(y + 1) * width]
+    }
+
+    /// Moves all rows up by `amount`, clearing the rows at the bottom
+    pub fn scroll_down(&mut self, amount: usize, background_colour: Colour) {
+        let amount = amount.min(self.resolution.y);
+        let blank = VgaChar::blank(background_colour);
+        let width = self.resolution.x;
+
+        let mut cells = self.cells.clone();
+        cells.rotate_left(amount * width);
+
+        let len = cells.len();
+        for cell in &mut cells[len - amount * width..] {
+            *cell = blank;
+        }
+
+        // Only the cells which changed are redrawn, as most of the screen is usually blank
+        for (index, cell) in cells.into_iter().enumerate() {
+            self.set_char(index % width, index / width, cell);
+        }
+    }
+
+    pub fn clear_row(&mut self, y: usize, colour: Colour) {
+        for x in 0..self.resolution.x {
+            self.set_char(x, y, VgaChar::blank(colour));
+        }
+    }
+
+    fn redraw(&mut self) {
+        for y in 0..self.resolution.y {
+            for x in 0..self.resolution.x {
+                self.draw_cell(x, y);
+            }
+        }
+    }
+
+    fn draw_cell(&mut self, x: usize, y: usize) {
+        let cell = self.cells[y * self.resolution.x + x];
+        let (foreground, background) = cell.colour.split();
+        let foreground = self.pixel(foreground);
+        let background = self.pixel(background);
+
+        let (width, height) = (self.font.width, self.font.height);
+
+        for row in 0..height {
+            let bits = self.font.glyph(cell.character)[row];
+            let pixel_y = y * height + row;
+
+            for column in 0..width {
+                let set = bits & (0x80 >> column) != 0;
+                let value = if set { foreground } else { background };
+                self.put_pixel(x * width + column, pixel_y, value);
+            }
+        }
+    }
+
+    fn pixel(&self, colour: u8) -> u32 {
+        let (r, g, b) = PALETTE[colour as usize & 0xf];
+        self.red.pack(r) | self.green.pack(g) | self.blue.pack(b)
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, value: u32) {
+        let offset = y * self.pitch + x * self.bytes_per_pixel;
+
+        // SAFETY: the pixel is within the mapped framebuffer, as the console's resolution is
+        // rounded down to whole characters
+        unsafe {
+            let ptr = self.pixels.add(offset);
+
+            match self.bytes_per_pixel {
+                4 => ptr::write_volatile(ptr as *mut u32, value),
+                3 => {
+                    ptr::write_volatile(ptr, value as u8);
+                    ptr::write_volatile(ptr.add(1), (value >> 8) as u8);
+                    ptr::write_volatile(ptr.add(2), (value >> 16) as u8);
+                }
+                _ => ptr::write_volatile(ptr as *mut u16, value as u16),
+            }
+        }
+    }
+}
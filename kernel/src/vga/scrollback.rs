@@ -0,0 +1,74 @@
+//! The history of lines which have scrolled off the top of the screen, which can be scrolled back
+//! through with Shift+PageUp and Shift+PageDown.
+
+use super::VgaChar;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// The oldest lines are dropped past this
+const CAPACITY: usize = 1000;
+
+pub struct Scrollback {
+    lines: VecDeque<Vec<VgaChar>>,
+    /// How many lines back the view is, or 0 if it shows the live screen
+    offset: usize,
+    /// The live screen as it was when the view was scrolled back, to be drawn below the history
+    /// and restored once the view returns to it
+    live: Vec<Vec<VgaChar>>,
+}
+
+impl Scrollback {
+    pub fn new() -> Scrollback {
+        Scrollback {
+            lines: VecDeque::new(),
+            offset: 0,
+            live: Vec::new(),
+        }
+    }
+
+    /// Adds a line which has scrolled off the top of the screen
+    pub fn push(&mut self, line: Vec<VgaChar>) {
+        if self.lines.len() == CAPACITY {
+            self.lines.pop_front();
+        }
+
+        self.lines.push_back(line);
+    }
+
+    /// The lines in the history, oldest first
+    pub fn lines(&self) -> impl Iterator<Item = &[VgaChar]> {
+        self.lines.iter().map(Vec::as_slice)
+    }
+
+    /// How many lines back the view is, or 0 if it shows the live screen
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Sets how many lines back the view is, up to the length of the history. The live screen
+    /// must have been saved if the view is leaving it.
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = offset.min(self.lines.len());
+    }
+
+    /// Saves the live screen, to be drawn below the history while the view is scrolled back
+    pub fn save_live(&mut self, live: Vec<Vec<VgaChar>>) {
+        self.live = live;
+    }
+
+    /// Drops the saved live screen once the view has returned to it
+    pub fn discard_live(&mut self) {
+        self.live = Vec::new();
+    }
+
+    /// Returns the line shown on the given row of the view, counted from the top
+    pub fn view_line(&self, row: usize) -> Option<&[VgaChar]> {
+        let index = self.lines.len() - self.offset + row;
+
+        if index < self.lines.len() {
+            self.lines.get(index).map(Vec::as_slice)
+        } else {
+            self.live.get(index - self.lines.len()).map(Vec::as_slice)
+        }
+    }
+}
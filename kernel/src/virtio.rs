@@ -0,0 +1,532 @@
+//! Virtio devices on the PCI bus, as emulated by QEMU and other hypervisors. Both the legacy
+//! transport, which is a block of io ports in BAR 0, and the modern one, which is spread across
+//! memory BARs described by vendor specific capabilities, are supported.
+//! From: [Virtio 1.1](https://docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.html), 4.1
+
+use crate::interrupts;
+use crate::memory::physical_mapping::{self, PhysicalMapping};
+use crate::pci::{self, config, Bar, CommandFlags, PciDevice};
+use alloc::vec::Vec;
+use core::{mem, ptr};
+use spin::RwLock;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::port::Port;
+
+pub mod blk;
+pub mod net;
+mod queue;
+
+pub use self::queue::{Buffer, VirtQueue};
+
+pub const VENDOR_ID: u16 = 0x1af4;
+/// Transitional devices have ids from here, in the order of their device types
+const TRANSITIONAL_DEVICE_ID_BASE: u16 = 0x1000;
+const TRANSITIONAL_DEVICE_ID_END: u16 = 0x103f;
+/// Modern devices have the device type added to this
+const MODERN_DEVICE_ID_BASE: u16 = 0x1040;
+const MODERN_DEVICE_ID_END: u16 = 0x107f;
+
+pub const DEVICE_TYPE_NET: u16 = 1;
+pub const DEVICE_TYPE_BLOCK: u16 = 2;
+
+/// Transitional device ids which don't follow the order of the device types
+const TRANSITIONAL_DEVICE_TYPES: [(u16, u16); 2] =
+    [(0x1000, DEVICE_TYPE_NET), (0x1001, DEVICE_TYPE_BLOCK)];
+
+pub const STATUS_ACKNOWLEDGE: u8 = 1;
+pub const STATUS_DRIVER: u8 = 2;
+pub const STATUS_DRIVER_OK: u8 = 4;
+pub const STATUS_FEATURES_OK: u8 = 8;
+pub const STATUS_FAILED: u8 = 128;
+
+/// Set by devices which support the modern interface
+pub const FEATURE_VERSION_1: u64 = 1 << 32;
+
+/// The transports of the devices whose interrupts are listened to, which need acknowledging
+static INTERRUPTING: RwLock<Vec<&'static Transport>> = RwLock::new(Vec::new());
+
+/// Written as an MSI-X vector to not use one
+const NO_VECTOR: u16 = 0xffff;
+
+const LEGACY_DEVICE_FEATURES: u16 = 0x00;
+const LEGACY_DRIVER_FEATURES: u16 = 0x04;
+const LEGACY_QUEUE_PFN: u16 = 0x08;
+const LEGACY_QUEUE_SIZE: u16 = 0x0C;
+const LEGACY_QUEUE_SELECT: u16 = 0x0E;
+const LEGACY_QUEUE_NOTIFY: u16 = 0x10;
+const LEGACY_DEVICE_STATUS: u16 = 0x12;
+const LEGACY_ISR_STATUS: u16 = 0x13;
+/// These only exist while MSI-X is enabled, and push the device config back by 4 bytes
+const LEGACY_CONFIG_VECTOR: u16 = 0x14;
+const LEGACY_QUEUE_VECTOR: u16 = 0x16;
+const LEGACY_DEVICE_CONFIG: u16 = 0x14;
+const LEGACY_DEVICE_CONFIG_MSI_X: u16 = 0x18;
+
+const CAPABILITY_VENDOR: u8 = 0x09;
+const CFG_TYPE_COMMON: u8 = 1;
+const CFG_TYPE_NOTIFY: u8 = 2;
+const CFG_TYPE_ISR: u8 = 3;
+const CFG_TYPE_DEVICE: u8 = 4;
+
+const COMMON_DEVICE_FEATURE_SELECT: usize = 0x00;
+const COMMON_DEVICE_FEATURE: usize = 0x04;
+const COMMON_DRIVER_FEATURE_SELECT: usize = 0x08;
+const COMMON_DRIVER_FEATURE: usize = 0x0C;
+const COMMON_MSIX_CONFIG: usize = 0x10;
+const COMMON_DEVICE_STATUS: usize = 0x14;
+const COMMON_QUEUE_SELECT: usize = 0x16;
+const COMMON_QUEUE_SIZE: usize = 0x18;
+const COMMON_QUEUE_MSIX_VECTOR: usize = 0x1A;
+const COMMON_QUEUE_ENABLE: usize = 0x1C;
+const COMMON_QUEUE_NOTIFY_OFF: usize = 0x1E;
+const COMMON_QUEUE_DESC: usize = 0x20;
+const COMMON_QUEUE_DRIVER: usize = 0x28;
+const COMMON_QUEUE_DEVICE: usize = 0x30;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VirtioError {
+    /// The device has neither an io BAR 0 nor the capabilities of the modern interface
+    NoTransport,
+    /// The PCI function has already been claimed, e.g by a userspace driver
+    AlreadyClaimed,
+    /// The device did not accept the features the driver selected
+    FeaturesRejected,
+    NoSuchQueue(u16),
+    OutOfMemory,
+}
+
+/// Returns the virtio device type of a PCI function, or None if it isn't a virtio device
+pub fn device_type(device: &PciDevice) -> Option<u16> {
+    if device.vendor_id != VENDOR_ID {
+        return None;
+    }
+
+    match device.device_id {
+        id @ TRANSITIONAL_DEVICE_ID_BASE..=TRANSITIONAL_DEVICE_ID_END => TRANSITIONAL_DEVICE_TYPES
+            .iter()
+            .find(|(transitional, _)| *transitional == id)
+            .map(|(_, device_type)| *device_type),
+        id @ MODERN_DEVICE_ID_BASE..=MODERN_DEVICE_ID_END => Some(id - MODERN_DEVICE_ID_BASE),
+        _ => None,
+    }
+}
+
+/// Finds and initialises the virtio devices which there are drivers for
+pub fn init() {
+    for device in pci::devices() {
+        let result = match device_type(device) {
+            Some(DEVICE_TYPE_BLOCK) => blk::init(device),
+            Some(DEVICE_TYPE_NET) => net::init(device),
+            _ => continue,
+        };
+
+        if let Err(e) = result {
+            warn!("virtio: failed to initialise {} ({:?})", device.address, e);
+        }
+    }
+}
+
+/// Listens to the device's interrupts, through its first MSI-X vector if it was enabled and its
+/// legacy IRQ otherwise. Completed buffers are found when the driver is next polled, which the
+/// interrupt wakes the scheduler to do.
+pub fn listen(device: &PciDevice, transport: &'static Transport, msi_x_vectors: Option<Vec<u8>>) {
+    without_interrupts(|| INTERRUPTING.write().push(transport));
+
+    match msi_x_vectors {
+        Some(vectors) => interrupts::listen_vector(vectors[0], handle_interrupt),
+        None if device.interrupt_pin != 0 => {
+            interrupts::listen(device.interrupt_line, handle_interrupt);
+            interrupts::enable_irq(device.interrupt_line);
+        }
+        // Buffers are still completed when the scheduler next polls, e.g on the next timer tick
+        None => warn!("virtio: {} has no interrupts", device.address),
+    }
+}
+
+/// Acknowledges the devices' interrupts. The legacy IRQs may be shared, so every device's is
+/// acknowledged.
+fn handle_interrupt() {
+    for transport in INTERRUPTING.read().iter() {
+        transport.read_isr();
+    }
+}
+
+/// A region of a memory BAR
+struct Mmio {
+    base: *mut u8,
+    len: usize,
+    _mapping: PhysicalMapping<u8>,
+}
+
+impl Mmio {
+    /// # Safety
+    ///
+    /// The region must be device memory which is not otherwise mapped
+    unsafe fn new(physical_address: u64, len: usize) -> Mmio {
+        let mut mapping = physical_mapping::map_physical_region(physical_address, len as u64, true);
+
+        Mmio {
+            base: mapping.deref_mut().unwrap() as *mut u8,
+            len,
+            _mapping: mapping,
+        }
+    }
+
+    fn read<T: Copy>(&self, offset: usize) -> T {
+        assert!(offset + mem::size_of::<T>() <= self.len);
+
+        // SAFETY: the offset was checked to be within the region
+        unsafe { ptr::read_volatile(self.base.add(offset) as *const T) }
+    }
+
+    fn write<T: Copy>(&self, offset: usize, value: T) {
+        assert!(offset + mem::size_of::<T>() <= self.len);
+
+        // SAFETY: the offset was checked to be within the region
+        unsafe { ptr::write_volatile(self.base.add(offset) as *mut T, value) }
+    }
+}
+
+struct ModernTransport {
+    common: Mmio,
+    notify: Mmio,
+    notify_offset_multiplier: u32,
+    isr: Mmio,
+    device: Mmio,
+}
+
+enum TransportKind {
+    Legacy {
+        port: u16,
+        /// Whether MSI-X is enabled, which moves the device config
+        msi_x: bool,
+    },
+    Modern(ModernTransport),
+}
+
+/// The registers of a virtio device, through which it is configured
+pub struct Transport {
+    kind: TransportKind,
+}
+
+// Safety: the registers are only accessed through volatile reads and writes, and drivers
+// serialise their use
+unsafe impl Send for Transport {}
+unsafe impl Sync for Transport {}
+
+impl Transport {
+    /// Finds the transport of the device, preferring the modern one, and enables it
+    pub fn new(device: &PciDevice) -> Result<Transport, VirtioError> {
+        let kind = match modern_transport(device) {
+            Some(modern) => {
+                device.set_command(
+                    device.command() | CommandFlags::MEMORY_SPACE | CommandFlags::BUS_MASTER,
+                );
+                TransportKind::Modern(modern)
+            }
+            None => match device.bars[0] {
+                Some(Bar::Io { port, .. }) => {
+                    device.set_command(
+                        device.command() | CommandFlags::IO_SPACE | CommandFlags::BUS_MASTER,
+                    );
+                    TransportKind::Legacy { port, msi_x: false }
+                }
+                _ => return Err(VirtioError::NoTransport),
+            },
+        };
+
+        Ok(Transport { kind })
+    }
+
+    pub fn is_modern(&self) -> bool {
+        matches!(self.kind, TransportKind::Modern(_))
+    }
+
+    /// Resets the device and acknowledges it, then negotiates features, accepting those of
+    /// `supported` which the device offers. Returns the accepted features.
+    pub fn begin_init(&self, supported: u64) -> Result<u64, VirtioError> {
+        self.set_status(0);
+        while self.status() != 0 {}
+
+        self.set_status(STATUS_ACKNOWLEDGE);
+        self.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        let mut supported = supported;
+        if self.is_modern() {
+            supported |= FEATURE_VERSION_1;
+        }
+
+        let features = self.device_features() & supported;
+        self.set_driver_features(features);
+
+        if self.is_modern() {
+            let status = STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK;
+            self.set_status(status);
+
+            if self.status() & STATUS_FEATURES_OK == 0 {
+                self.set_status(status | STATUS_FAILED);
+                return Err(VirtioError::FeaturesRejected);
+            }
+        }
+
+        Ok(features)
+    }
+
+    /// Tells the device that the driver is ready, once its queues have been set up
+    pub fn finish_init(&self) {
+        self.set_status(self.status() | STATUS_DRIVER_OK);
+    }
+
+    pub fn fail(&self) {
+        self.set_status(self.status() | STATUS_FAILED);
+    }
+
+    pub fn status(&self) -> u8 {
+        match &self.kind {
+            TransportKind::Legacy { port, .. } => read_port(port + LEGACY_DEVICE_STATUS),
+            TransportKind::Modern(modern) => modern.common.read(COMMON_DEVICE_STATUS),
+        }
+    }
+
+    fn set_status(&self, status: u8) {
+        match &self.kind {
+            TransportKind::Legacy { port, .. } => write_port(port + LEGACY_DEVICE_STATUS, status),
+            TransportKind::Modern(modern) => modern.common.write(COMMON_DEVICE_STATUS, status),
+        }
+    }
+
+    fn device_features(&self) -> u64 {
+        match &self.kind {
+            TransportKind::Legacy { port, .. } => {
+                read_port::<u32>(port + LEGACY_DEVICE_FEATURES) as u64
+            }
+            TransportKind::Modern(modern) => {
+                let mut features = 0;
+
+                for select in 0..2 {
+                    modern
+                        .common
+                        .write(COMMON_DEVICE_FEATURE_SELECT, select as u32);
+                    let half = modern.common.read::<u32>(COMMON_DEVICE_FEATURE) as u64;
+                    features |= half << (select * 32);
+                }
+
+                features
+            }
+        }
+    }
+
+    fn set_driver_features(&self, features: u64) {
+        match &self.kind {
+            TransportKind::Legacy { port, .. } => {
+                write_port(port + LEGACY_DRIVER_FEATURES, features as u32)
+            }
+            TransportKind::Modern(modern) => {
+                for select in 0..2 {
+                    modern
+                        .common
+                        .write(COMMON_DRIVER_FEATURE_SELECT, select as u32);
+                    modern
+                        .common
+                        .write(COMMON_DRIVER_FEATURE, (features >> (select * 32)) as u32);
+                }
+            }
+        }
+    }
+
+    /// Enables MSI-X with a vector for each queue, returning the vectors. The device's config
+    /// change interrupt is not used. Must be called before reading the device config.
+    pub fn enable_msi_x(&mut self, device: &PciDevice, queues: u16) -> Option<Vec<u8>> {
+        let vectors = pci::msi::enable_msi_x(device, queues).ok()?;
+
+        match &mut self.kind {
+            TransportKind::Legacy { port, msi_x } => {
+                *msi_x = true;
+                write_port(*port + LEGACY_CONFIG_VECTOR, NO_VECTOR);
+            }
+            TransportKind::Modern(modern) => modern.common.write(COMMON_MSIX_CONFIG, NO_VECTOR),
+        }
+
+        Some(vectors)
+    }
+
+    /// Returns the size of the given queue, or None if the device doesn't have it
+    pub fn queue_size(&self, queue: u16) -> Option<u16> {
+        let size = match &self.kind {
+            TransportKind::Legacy { port, .. } => {
+                write_port(port + LEGACY_QUEUE_SELECT, queue);
+                read_port(port + LEGACY_QUEUE_SIZE)
+            }
+            TransportKind::Modern(modern) => {
+                modern.common.write(COMMON_QUEUE_SELECT, queue);
+                modern.common.read(COMMON_QUEUE_SIZE)
+            }
+        };
+
+        if size == 0 {
+            None
+        } else {
+            Some(size)
+        }
+    }
+
+    /// Gives the device a queue, which must be the size it reported. If `vector` is given, the
+    /// queue's interrupts are sent to that MSI-X message number.
+    pub fn set_queue(&self, queue: &VirtQueue, vector: Option<u16>) {
+        let index = queue.index();
+
+        match &self.kind {
+            TransportKind::Legacy { port, msi_x } => {
+                write_port(port + LEGACY_QUEUE_SELECT, index);
+
+                if *msi_x {
+                    write_port(port + LEGACY_QUEUE_VECTOR, vector.unwrap_or(NO_VECTOR));
+                }
+
+                write_port(
+                    port + LEGACY_QUEUE_PFN,
+                    (queue.descriptors_physical() / 4096) as u32,
+                );
+            }
+            TransportKind::Modern(modern) => {
+                let common = &modern.common;
+                common.write(COMMON_QUEUE_SELECT, index);
+                common.write(COMMON_QUEUE_SIZE, queue.size());
+                common.write(COMMON_QUEUE_MSIX_VECTOR, vector.unwrap_or(NO_VECTOR));
+                write_u64(common, COMMON_QUEUE_DESC, queue.descriptors_physical());
+                write_u64(common, COMMON_QUEUE_DRIVER, queue.available_physical());
+                write_u64(common, COMMON_QUEUE_DEVICE, queue.used_physical());
+                common.write(COMMON_QUEUE_ENABLE, 1u16);
+            }
+        }
+    }
+
+    /// Tells the device that there are new buffers in the given queue
+    pub fn notify(&self, queue: u16) {
+        match &self.kind {
+            TransportKind::Legacy { port, .. } => write_port(port + LEGACY_QUEUE_NOTIFY, queue),
+            TransportKind::Modern(modern) => {
+                modern.common.write(COMMON_QUEUE_SELECT, queue);
+                let offset = modern.common.read::<u16>(COMMON_QUEUE_NOTIFY_OFF) as usize;
+                let offset = offset * modern.notify_offset_multiplier as usize;
+                modern.notify.write(offset, queue);
+            }
+        }
+    }
+
+    /// Reads and clears the interrupt status. Bit 0 is set if a queue was used, and bit 1 if the
+    /// device config changed. Must be read to deassert legacy interrupts.
+    pub fn read_isr(&self) -> u8 {
+        match &self.kind {
+            TransportKind::Legacy { port, .. } => read_port(port + LEGACY_ISR_STATUS),
+            TransportKind::Modern(modern) => modern.isr.read(0),
+        }
+    }
+
+    pub fn config_u8(&self, offset: u16) -> u8 {
+        match &self.kind {
+            TransportKind::Legacy { .. } => read_port(self.legacy_config_port() + offset),
+            TransportKind::Modern(modern) => modern.device.read(offset as usize),
+        }
+    }
+
+    pub fn config_u16(&self, offset: u16) -> u16 {
+        match &self.kind {
+            TransportKind::Legacy { .. } => read_port(self.legacy_config_port() + offset),
+            TransportKind::Modern(modern) => modern.device.read(offset as usize),
+        }
+    }
+
+    pub fn config_u32(&self, offset: u16) -> u32 {
+        match &self.kind {
+            TransportKind::Legacy { .. } => read_port(self.legacy_config_port() + offset),
+            TransportKind::Modern(modern) => modern.device.read(offset as usize),
+        }
+    }
+
+    pub fn config_u64(&self, offset: u16) -> u64 {
+        self.config_u32(offset) as u64 | (self.config_u32(offset + 4) as u64) << 32
+    }
+
+    fn legacy_config_port(&self) -> u16 {
+        match self.kind {
+            TransportKind::Legacy { port, msi_x: true } => port + LEGACY_DEVICE_CONFIG_MSI_X,
+            TransportKind::Legacy { port, msi_x: false } => port + LEGACY_DEVICE_CONFIG,
+            TransportKind::Modern(_) => unreachable!(),
+        }
+    }
+}
+
+/// Finds the regions of the modern interface from the device's vendor specific capabilities
+fn modern_transport(device: &PciDevice) -> Option<ModernTransport> {
+    let mut common = None;
+    let mut notify = None;
+    let mut isr = None;
+    let mut device_config = None;
+
+    let vendor_capabilities = device
+        .capabilities
+        .iter()
+        .filter(|cap| cap.id == CAPABILITY_VENDOR);
+
+    for capability in vendor_capabilities {
+        let offset = capability.offset as u16;
+        let cfg_type = config::read::<u8>(device.address, offset + 3);
+        let bar = config::read::<u8>(device.address, offset + 4);
+        let region_offset = config::read::<u32>(device.address, offset + 8) as u64;
+        let len = config::read::<u32>(device.address, offset + 12) as usize;
+
+        let bar_address = match device.bars.get(bar as usize) {
+            Some(Some(Bar::Memory { address, .. })) => *address,
+            _ => continue,
+        };
+
+        // SAFETY: the region is in a memory BAR of the device
+        let region = || unsafe { Mmio::new(bar_address + region_offset, len) };
+
+        // Only the first capability of each type is used
+        match cfg_type {
+            CFG_TYPE_COMMON if common.is_none() => common = Some(region()),
+            CFG_TYPE_NOTIFY if notify.is_none() => {
+                let multiplier = config::read::<u32>(device.address, offset + 16);
+                notify = Some((region(), multiplier));
+            }
+            CFG_TYPE_ISR if isr.is_none() => isr = Some(region()),
+            CFG_TYPE_DEVICE if device_config.is_none() => device_config = Some(region()),
+            _ => (),
+        }
+    }
+
+    let (notify, notify_offset_multiplier) = notify?;
+
+    Some(ModernTransport {
+        common: common?,
+        notify,
+        notify_offset_multiplier,
+        isr: isr?,
+        device: device_config?,
+    })
+}
+
+/// 64 bit registers are written as two halves, low first
+fn write_u64(mmio: &Mmio, offset: usize, value: u64) {
+    mmio.write(offset, value as u32);
+    mmio.write(offset + 4, (value >> 32) as u32);
+}
+
+fn read_port<T: PortValue>(port: u16) -> T {
+    // SAFETY: the port is one of the device's registers
+    unsafe { Port::new(port).read() }
+}
+
+fn write_port<T: PortValue>(port: u16, value: T) {
+    // SAFETY: the port is one of the device's registers
+    unsafe { Port::new(port).write(value) }
+}
+
+/// The widths which io ports can be accessed with
+trait PortValue: x86_64::instructions::port::PortRead + x86_64::instructions::port::PortWrite {}
+
+impl PortValue for u8 {}
+impl PortValue for u16 {}
+impl PortValue for u32 {}
@@ -0,0 +1,187 @@
+//! Virtio block devices, i.e disks. Each has a single request queue, through which sectors are
+//! read and written by DMA.
+//! From: [Virtio 1.1](https://docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.html), 5.2
+
+use super::{Buffer, Transport, VirtQueue, VirtioError};
+use crate::block::{self, BlockDevice, BlockDeviceInfo, BlockError, Direction, Request};
+use crate::memory::dma::DmaRegion;
+use crate::pci::{self, PciDevice};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ptr;
+use spin::Mutex;
+
+/// The device can't be written to
+const FEATURE_READ_ONLY: u64 = 1 << 5;
+
+/// The capacity of the device in sectors, which are always 512 bytes
+const CONFIG_CAPACITY: u16 = 0x00;
+const SECTOR_SIZE: u32 = 512;
+
+const REQUEST_IN: u32 = 0;
+const REQUEST_OUT: u32 = 1;
+const REQUEST_STATUS_OK: u8 = 0;
+
+/// The most requests which can be in flight on a device at once
+const SLOTS: usize = 8;
+/// The headers of each slot's request, followed by their status bytes, are kept in a DMA region
+const HEADER_SIZE: usize = 16;
+const STATUS_OFFSET: usize = SLOTS * HEADER_SIZE;
+
+#[repr(C)]
+struct RequestHeader {
+    kind: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+pub struct VirtioBlk {
+    transport: Transport,
+    info: BlockDeviceInfo,
+    state: Mutex<State>,
+}
+
+struct State {
+    queue: VirtQueue,
+    /// The headers and statuses of the requests
+    slots: DmaRegion,
+    /// The first descriptor of the request in each slot, if the slot is in use
+    in_flight: [Option<u16>; SLOTS],
+}
+
+/// Initialises the device and registers it as a block device. The device is claimed by the kernel.
+pub fn init(device: &'static PciDevice) -> Result<(), VirtioError> {
+    pci::claim_for_kernel(device.address).map_err(|_| VirtioError::AlreadyClaimed)?;
+
+    let mut transport = Transport::new(device)?;
+    let features = transport.begin_init(FEATURE_READ_ONLY)?;
+    let vectors = transport.enable_msi_x(device, 1);
+
+    let size = transport.queue_size(0).ok_or(VirtioError::NoSuchQueue(0))?;
+    let queue = VirtQueue::new(0, size)?;
+    transport.set_queue(&queue, vectors.as_ref().map(|_| 0));
+
+    let slots = DmaRegion::allocate(STATUS_OFFSET + SLOTS).ok_or(VirtioError::OutOfMemory)?;
+
+    let info = BlockDeviceInfo {
+        sector_size: SECTOR_SIZE,
+        flags: if features & FEATURE_READ_ONLY != 0 {
+            BlockDeviceInfo::FLAG_READ_ONLY
+        } else {
+            0
+        },
+        sector_count: transport.config_u64(CONFIG_CAPACITY),
+    };
+
+    let blk: &'static VirtioBlk = Box::leak(Box::new(VirtioBlk {
+        transport,
+        info,
+        state: Mutex::new(State {
+            queue,
+            slots,
+            in_flight: [None; SLOTS],
+        }),
+    }));
+
+    super::listen(device, &blk.transport, vectors);
+    blk.transport.finish_init();
+
+    info!(
+        "virtio: block device at {} using the {} interface",
+        device.address,
+        if blk.transport.is_modern() {
+            "modern"
+        } else {
+            "legacy"
+        }
+    );
+    block::register(blk);
+
+    Ok(())
+}
+
+impl BlockDevice for VirtioBlk {
+    fn info(&self) -> BlockDeviceInfo {
+        self.info
+    }
+
+    fn submit(&self, request: &Request) -> Result<u16, BlockError> {
+        let mut state = self.state.lock();
+        let state = &mut *state;
+
+        let slot = state
+            .in_flight
+            .iter()
+            .position(Option::is_none)
+            .ok_or(BlockError::Busy)?;
+
+        let header = RequestHeader {
+            kind: match request.direction {
+                Direction::Read => REQUEST_IN,
+                Direction::Write => REQUEST_OUT,
+            },
+            reserved: 0,
+            sector: request.sector,
+        };
+
+        // SAFETY: the header and status are within the slots' region
+        unsafe {
+            ptr::write_volatile(
+                state.slots.ptr(slot * HEADER_SIZE) as *mut RequestHeader,
+                header,
+            );
+            ptr::write_volatile(state.slots.ptr(STATUS_OFFSET + slot), 0xff);
+        }
+
+        let mut buffers = Vec::with_capacity(request.segments.len() + 2);
+        buffers.push(Buffer {
+            physical: state.slots.physical(slot * HEADER_SIZE),
+            len: HEADER_SIZE as u32,
+            device_writable: false,
+        });
+
+        for segment in request.segments {
+            buffers.push(Buffer {
+                physical: segment.physical,
+                len: segment.len,
+                device_writable: request.direction == Direction::Read,
+            });
+        }
+
+        buffers.push(Buffer {
+            physical: state.slots.physical(STATUS_OFFSET + slot),
+            len: 1,
+            device_writable: true,
+        });
+
+        let head = state.queue.add(&buffers).ok_or(BlockError::Busy)?;
+        state.in_flight[slot] = Some(head);
+        self.transport.notify(state.queue.index());
+
+        Ok(slot as u16)
+    }
+
+    fn poll(&self, complete: &mut dyn FnMut(u16, Result<(), BlockError>)) {
+        let mut state = self.state.lock();
+        let state = &mut *state;
+
+        while let Some((head, _)) = state.queue.pop_used() {
+            let slot = match state.in_flight.iter().position(|&s| s == Some(head)) {
+                Some(slot) => slot,
+                None => continue,
+            };
+
+            state.in_flight[slot] = None;
+
+            // SAFETY: the status is within the slots' region
+            let status = unsafe { ptr::read_volatile(state.slots.ptr(STATUS_OFFSET + slot)) };
+            let result = if status == REQUEST_STATUS_OK {
+                Ok(())
+            } else {
+                Err(BlockError::Io)
+            };
+
+            complete(slot as u16, result);
+        }
+    }
+}
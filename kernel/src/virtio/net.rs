@@ -0,0 +1,237 @@
+//! Virtio network devices. Frames are received into buffers kept in the receive queue, and copied
+//! into buffers of the transmit queue to be sent.
+//! From: [Virtio 1.1](https://docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.html), 5.1
+
+use super::{Buffer, Transport, VirtQueue, VirtioError};
+use crate::memory::dma::DmaRegion;
+use crate::net::{self, NetDevice, NetDeviceInfo, NetError, MAX_FRAME_SIZE};
+use crate::pci::{self, PciDevice};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::{ptr, slice};
+use spin::Mutex;
+
+/// The device has a MAC address in its config
+const FEATURE_MAC: u64 = 1 << 5;
+/// The device reports whether its link is up in its config
+const FEATURE_STATUS: u64 = 1 << 16;
+
+const CONFIG_MAC: u16 = 0x00;
+const CONFIG_STATUS: u16 = 0x06;
+const STATUS_LINK_UP: u16 = 1;
+
+const RX_QUEUE: u16 = 0;
+const TX_QUEUE: u16 = 1;
+
+/// Each frame is preceded by a header, which is 2 bytes longer with the modern interface. As no
+/// offloads are negotiated, it is always zeroed.
+const HEADER_SIZE_LEGACY: usize = 10;
+const HEADER_SIZE_MODERN: usize = 12;
+/// Big enough for a header and the largest frame
+const BUFFER_SIZE: usize = 2048;
+const RX_BUFFERS: usize = 32;
+const TX_BUFFERS: usize = 32;
+
+pub struct VirtioNet {
+    transport: Transport,
+    mac: [u8; 6],
+    has_status: bool,
+    header_size: usize,
+    state: Mutex<State>,
+}
+
+struct State {
+    rx: VirtQueue,
+    tx: VirtQueue,
+    rx_buffers: DmaRegion,
+    tx_buffers: DmaRegion,
+    /// The receive buffer which each descriptor of the receive queue points to
+    rx_buffer_of: Vec<usize>,
+    /// The transmit buffer which each descriptor of the transmit queue points to, if it is in use
+    tx_buffer_of: Vec<Option<usize>>,
+    tx_free: Vec<usize>,
+}
+
+/// Initialises the device and registers it as a network device. The device is claimed by the
+/// kernel.
+pub fn init(device: &'static PciDevice) -> Result<(), VirtioError> {
+    pci::claim_for_kernel(device.address).map_err(|_| VirtioError::AlreadyClaimed)?;
+
+    let mut transport = Transport::new(device)?;
+    let features = transport.begin_init(FEATURE_MAC | FEATURE_STATUS)?;
+    let vectors = transport.enable_msi_x(device, 1);
+    let vector = vectors.as_ref().map(|_| 0);
+
+    let rx_size = transport
+        .queue_size(RX_QUEUE)
+        .ok_or(VirtioError::NoSuchQueue(RX_QUEUE))?;
+    let tx_size = transport
+        .queue_size(TX_QUEUE)
+        .ok_or(VirtioError::NoSuchQueue(TX_QUEUE))?;
+    let mut rx = VirtQueue::new(RX_QUEUE, rx_size)?;
+    let tx = VirtQueue::new(TX_QUEUE, tx_size)?;
+    transport.set_queue(&rx, vector);
+    transport.set_queue(&tx, vector);
+
+    let rx_buffers =
+        DmaRegion::allocate(RX_BUFFERS * BUFFER_SIZE).ok_or(VirtioError::OutOfMemory)?;
+    let tx_buffers =
+        DmaRegion::allocate(TX_BUFFERS * BUFFER_SIZE).ok_or(VirtioError::OutOfMemory)?;
+
+    // Fill the receive queue before the device is told that the driver is ready
+    let mut rx_buffer_of = vec![0; rx_size as usize];
+    for buffer in 0..RX_BUFFERS.min(rx_size as usize) {
+        let head = rx.add(&[rx_buffer(&rx_buffers, buffer)]).unwrap();
+        rx_buffer_of[head as usize] = buffer;
+    }
+
+    let mac = if features & FEATURE_MAC != 0 {
+        let mut mac = [0; 6];
+        for (i, byte) in mac.iter_mut().enumerate() {
+            *byte = transport.config_u8(CONFIG_MAC + i as u16);
+        }
+        mac
+    } else {
+        // A locally administered address, made unique by the device's location
+        let address = device.address;
+        [0x02, 0, 0, address.bus, address.device, address.function]
+    };
+
+    let header_size = if transport.is_modern() {
+        HEADER_SIZE_MODERN
+    } else {
+        HEADER_SIZE_LEGACY
+    };
+
+    let net: &'static VirtioNet = Box::leak(Box::new(VirtioNet {
+        transport,
+        mac,
+        has_status: features & FEATURE_STATUS != 0,
+        header_size,
+        state: Mutex::new(State {
+            rx,
+            tx,
+            rx_buffers,
+            tx_buffers,
+            rx_buffer_of,
+            tx_buffer_of: vec![None; tx_size as usize],
+            tx_free: (0..TX_BUFFERS.min(tx_size as usize)).collect(),
+        }),
+    }));
+
+    super::listen(device, &net.transport, vectors);
+    net.transport.finish_init();
+    net.transport.notify(RX_QUEUE);
+
+    info!(
+        "virtio: network device at {} using the {} interface",
+        device.address,
+        if net.transport.is_modern() {
+            "modern"
+        } else {
+            "legacy"
+        }
+    );
+    net::register(net);
+
+    Ok(())
+}
+
+fn rx_buffer(rx_buffers: &DmaRegion, buffer: usize) -> Buffer {
+    Buffer {
+        physical: rx_buffers.physical(buffer * BUFFER_SIZE),
+        len: BUFFER_SIZE as u32,
+        device_writable: true,
+    }
+}
+
+impl NetDevice for VirtioNet {
+    fn info(&self) -> NetDeviceInfo {
+        let link_up =
+            !self.has_status || self.transport.config_u16(CONFIG_STATUS) & STATUS_LINK_UP != 0;
+        let flags = if link_up {
+            NetDeviceInfo::FLAG_LINK_UP
+        } else {
+            0
+        };
+
+        NetDeviceInfo::new(self.mac, MAX_FRAME_SIZE as u32, flags)
+    }
+
+    fn transmit(&self, frame: &[u8]) -> Result<(), NetError> {
+        if frame.len() > MAX_FRAME_SIZE {
+            return Err(NetError::TooLarge);
+        }
+
+        let mut state = self.state.lock();
+        let state = &mut *state;
+        let buffer = state.tx_free.pop().ok_or(NetError::Busy)?;
+        let offset = buffer * BUFFER_SIZE;
+
+        // SAFETY: the header and frame fit in the buffer
+        unsafe {
+            let start = state.tx_buffers.ptr(offset);
+            ptr::write_bytes(start, 0, self.header_size);
+            ptr::copy_nonoverlapping(frame.as_ptr(), start.add(self.header_size), frame.len());
+        }
+
+        let descriptor = Buffer {
+            physical: state.tx_buffers.physical(offset),
+            len: (self.header_size + frame.len()) as u32,
+            device_writable: false,
+        };
+
+        match state.tx.add(&[descriptor]) {
+            Some(head) => {
+                state.tx_buffer_of[head as usize] = Some(buffer);
+                self.transport.notify(TX_QUEUE);
+                Ok(())
+            }
+            None => {
+                state.tx_free.push(buffer);
+                Err(NetError::Busy)
+            }
+        }
+    }
+
+    fn poll(&self, receive: &mut dyn FnMut(&[u8])) {
+        let mut state = self.state.lock();
+        let state = &mut *state;
+
+        while let Some((head, _)) = state.tx.pop_used() {
+            if let Some(buffer) = state.tx_buffer_of[head as usize].take() {
+                state.tx_free.push(buffer);
+            }
+        }
+
+        let mut refilled = false;
+
+        while let Some((head, len)) = state.rx.pop_used() {
+            let buffer = state.rx_buffer_of[head as usize];
+            let len = (len as usize).min(BUFFER_SIZE);
+
+            if len > self.header_size {
+                // SAFETY: the frame is within the buffer
+                let frame = unsafe {
+                    let start = state
+                        .rx_buffers
+                        .ptr(buffer * BUFFER_SIZE + self.header_size);
+                    slice::from_raw_parts(start, len - self.header_size)
+                };
+
+                receive(frame);
+            }
+
+            let head = state
+                .rx
+                .add(&[rx_buffer(&state.rx_buffers, buffer)])
+                .unwrap();
+            state.rx_buffer_of[head as usize] = buffer;
+            refilled = true;
+        }
+
+        if refilled {
+            self.transport.notify(RX_QUEUE);
+        }
+    }
+}
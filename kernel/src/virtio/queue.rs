@@ -0,0 +1,227 @@
+//! Split virtqueues, through which buffers are passed to and from a device. The driver adds chains
+//! of descriptors to the available ring, and the device returns them through the used ring once it
+//! is done with them.
+//! From: [Virtio 1.1](https://docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.html), 2.6
+
+use super::VirtioError;
+use crate::memory::dma::DmaRegion;
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
+
+/// The descriptor continues in its `next` field
+const DESCRIPTOR_NEXT: u16 = 1;
+/// The device writes to the buffer, rather than reading from it
+const DESCRIPTOR_WRITE: u16 = 2;
+
+const DESCRIPTOR_SIZE: usize = 16;
+/// The flags and index which come before each ring
+const RING_HEADER_SIZE: usize = 4;
+/// Legacy devices expect the used ring to be on the next page after the available ring
+const USED_RING_ALIGN: usize = 4096;
+const USED_ELEMENT_SIZE: usize = 8;
+
+/// Marks the end of the free list
+const NONE: u16 = 0xffff;
+
+/// A buffer to be passed to the device
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Buffer {
+    pub physical: u64,
+    pub len: u32,
+    /// Whether the device writes to the buffer, rather than reading from it
+    pub device_writable: bool,
+}
+
+pub struct VirtQueue {
+    index: u16,
+    size: u16,
+    region: DmaRegion,
+    used_offset: usize,
+    /// The first descriptor which isn't in use, each of which links to the next
+    free_head: u16,
+    free_count: u16,
+    /// The next entry of the available ring to write
+    available_index: u16,
+    /// The next entry of the used ring to read
+    last_used_index: u16,
+}
+
+impl VirtQueue {
+    /// Allocates the queue with the given index, which must have the size that the device reported
+    pub fn new(index: u16, size: u16) -> Result<VirtQueue, VirtioError> {
+        let available_len = RING_HEADER_SIZE + 2 * size as usize + 2;
+        let used_offset = round_up(
+            DESCRIPTOR_SIZE * size as usize + available_len,
+            USED_RING_ALIGN,
+        );
+        let used_len = RING_HEADER_SIZE + USED_ELEMENT_SIZE * size as usize + 2;
+
+        let region = DmaRegion::allocate(used_offset + used_len).ok_or(VirtioError::OutOfMemory)?;
+
+        let mut queue = VirtQueue {
+            index,
+            size,
+            region,
+            used_offset,
+            free_head: 0,
+            free_count: size,
+            available_index: 0,
+            last_used_index: 0,
+        };
+
+        for i in 0..size {
+            let next = if i + 1 < size { i + 1 } else { NONE };
+            queue.write_descriptor(i, 0, 0, 0, next);
+        }
+
+        Ok(queue)
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    pub fn descriptors_physical(&self) -> u64 {
+        self.region.physical(0)
+    }
+
+    pub fn available_physical(&self) -> u64 {
+        self.region.physical(self.available_offset())
+    }
+
+    pub fn used_physical(&self) -> u64 {
+        self.region.physical(self.used_offset)
+    }
+
+    /// Adds a chain of buffers to the available ring, returning the index of its first descriptor.
+    /// Returns None if there aren't enough free descriptors. The device must then be notified.
+    pub fn add(&mut self, buffers: &[Buffer]) -> Option<u16> {
+        if buffers.is_empty() || buffers.len() > self.free_count as usize {
+            return None;
+        }
+
+        let head = self.free_head;
+        let mut descriptor = head;
+
+        for (i, buffer) in buffers.iter().enumerate() {
+            let next = self.descriptor_next(descriptor);
+            let mut flags = 0;
+
+            if buffer.device_writable {
+                flags |= DESCRIPTOR_WRITE;
+            }
+
+            if i + 1 < buffers.len() {
+                flags |= DESCRIPTOR_NEXT;
+            }
+
+            self.write_descriptor(descriptor, buffer.physical, buffer.len, flags, next);
+
+            if i + 1 < buffers.len() {
+                descriptor = next;
+            } else {
+                self.free_head = next;
+            }
+        }
+
+        self.free_count -= buffers.len() as u16;
+
+        let slot = self.available_offset()
+            + RING_HEADER_SIZE
+            + 2 * (self.available_index % self.size) as usize;
+        self.write(slot, head);
+
+        // The device must see the ring entry before the index which makes it available
+        atomic::fence(Ordering::SeqCst);
+        self.available_index = self.available_index.wrapping_add(1);
+        self.write(self.available_offset() + 2, self.available_index);
+        atomic::fence(Ordering::SeqCst);
+
+        Some(head)
+    }
+
+    /// Takes the next chain which the device has finished with from the used ring, returning the
+    /// index of its first descriptor and how many bytes the device wrote to it
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let used_index: u16 = self.read(self.used_offset + 2);
+
+        if used_index == self.last_used_index {
+            return None;
+        }
+
+        // The element must not be read before the index which says that it is there
+        atomic::fence(Ordering::SeqCst);
+
+        let element = self.used_offset
+            + RING_HEADER_SIZE
+            + USED_ELEMENT_SIZE * (self.last_used_index % self.size) as usize;
+        let head = self.read::<u32>(element) as u16;
+        let len = self.read::<u32>(element + 4);
+        self.last_used_index = self.last_used_index.wrapping_add(1);
+
+        self.free_chain(head);
+
+        Some((head, len))
+    }
+
+    /// Returns the descriptors of a chain to the free list
+    fn free_chain(&mut self, head: u16) {
+        let mut descriptor = head;
+
+        loop {
+            let flags: u16 = self.read(descriptor as usize * DESCRIPTOR_SIZE + 12);
+            let next = self.descriptor_next(descriptor);
+            self.free_count += 1;
+
+            if flags & DESCRIPTOR_NEXT == 0 {
+                self.write_descriptor(descriptor, 0, 0, 0, self.free_head);
+                break;
+            }
+
+            descriptor = next;
+        }
+
+        self.free_head = head;
+    }
+
+    fn available_offset(&self) -> usize {
+        DESCRIPTOR_SIZE * self.size as usize
+    }
+
+    fn descriptor_next(&mut self, descriptor: u16) -> u16 {
+        self.read(descriptor as usize * DESCRIPTOR_SIZE + 14)
+    }
+
+    fn write_descriptor(
+        &mut self,
+        descriptor: u16,
+        physical: u64,
+        len: u32,
+        flags: u16,
+        next: u16,
+    ) {
+        let offset = descriptor as usize * DESCRIPTOR_SIZE;
+        self.write(offset, physical);
+        self.write(offset + 8, len);
+        self.write(offset + 12, flags);
+        self.write(offset + 14, next);
+    }
+
+    fn read<T: Copy>(&mut self, offset: usize) -> T {
+        // SAFETY: offsets are within the queue's region, and aligned to the field's size
+        unsafe { ptr::read_volatile(self.region.ptr(offset) as *const T) }
+    }
+
+    fn write<T: Copy>(&mut self, offset: usize, value: T) {
+        // SAFETY: offsets are within the queue's region, and aligned to the field's size
+        unsafe { ptr::write_volatile(self.region.ptr(offset) as *mut T, value) }
+    }
+}
+
+fn round_up(value: usize, multiple: usize) -> usize {
+    (value + multiple - 1) / multiple * multiple
+}
@@ -0,0 +1,47 @@
+//! Queues of processes blocked waiting for something to happen, e.g for input to arrive.
+
+use crate::process::ProcessId;
+use crate::scheduler;
+use crate::syscall::UserContext;
+use crate::timer::Deadline;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+pub struct WaitQueue {
+    /// The blocked processes and the ids of their blocks
+    waiters: Mutex<Vec<(ProcessId, u64)>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> WaitQueue {
+        WaitQueue {
+            waiters: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Blocks the current process in its system call until the queue is woken, or until the
+    /// deadline passes.
+    ///
+    /// No locks may be held when calling this, as it never returns.
+    pub fn wait(&self, context: &UserContext, deadline: Option<Deadline>) -> ! {
+        scheduler::block_current_on(context, deadline, |pid, block_id| {
+            self.waiters.lock().push((pid, block_id))
+        })
+    }
+
+    /// Wakes every process waiting on the queue, making their system calls return `result`.
+    ///
+    /// This must not be called from an interrupt handler, as it may have interrupted code which
+    /// holds the process table's locks.
+    pub fn wake_all(&self, result: i64) {
+        let waiters: Vec<_> = self.waiters.lock().drain(..).collect();
+
+        for (pid, block_id) in waiters {
+            scheduler::wake(pid, block_id, result);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.lock().is_empty()
+    }
+}
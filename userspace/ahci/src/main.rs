@@ -0,0 +1,83 @@
+//! Drives the SATA disks on the first AHCI controller, and serves them on the `ahci` endpoint,
+//! numbered by port. The controller is found and claimed over PCI, which gives this its IRQ, so it
+//! needs the `devices` capability rather than any grants of io ports or IRQs.
+
+#![no_std]
+#![no_main]
+
+use libwolffia::block::ahci::{self, Controller};
+use libwolffia::block::{server, BlockDevice};
+use libwolffia::ipc::Endpoint;
+use libwolffia::prelude::*;
+use libwolffia::syscall::{self, PciDeviceInfo};
+
+/// Where the controller's registers, and each port's control page and buffer, are mapped
+const CONTROLLER_ADDRESS: u64 = 0x4000_0000_0000;
+/// The notification bits which the controller's IRQ is sent as
+const NOTIFY: u64 = 1;
+/// The most PCI functions which are searched for a controller
+const MAX_PCI_DEVICES: usize = 64;
+
+#[libwolffia::main]
+fn main() {
+    // Created before looking for disks, so that services which start after this one can call it
+    // straight away, and are answered once the disks have been found
+    let endpoint = match Endpoint::create(ahci::ENDPOINT) {
+        Ok(endpoint) => endpoint,
+        Err(err) => {
+            eprintln!("ahci: couldn't create the endpoint: {:?}", err);
+            syscall::exit(1);
+        }
+    };
+
+    let mut devices = [PciDeviceInfo::default(); MAX_PCI_DEVICES];
+    let count = match syscall::pci_devices(&mut devices) {
+        Ok(count) => count.min(MAX_PCI_DEVICES),
+        Err(err) => {
+            eprintln!("ahci: couldn't list PCI devices: {:?}", err);
+            syscall::exit(1);
+        }
+    };
+
+    let controller = devices[..count]
+        .iter()
+        .find(|device| ahci::is_controller(device));
+    let device = match controller {
+        Some(device) => device,
+        None => {
+            println!("ahci: no controller found");
+            return;
+        }
+    };
+
+    let ports = syscall::pci_claim(device.address())
+        .and_then(|_| Controller::new(device, CONTROLLER_ADDRESS as *mut u8, NOTIFY))
+        .and_then(Controller::into_ports);
+
+    let mut ports = match ports {
+        Ok(ports) => ports,
+        Err(err) => {
+            eprintln!("ahci: couldn't start the controller: {:?}", err);
+            syscall::exit(1);
+        }
+    };
+
+    if ports.iter().all(Option::is_none) {
+        println!("ahci: no disks found");
+        return;
+    }
+
+    for (index, port) in ports.iter().enumerate() {
+        if let Some(port) = port {
+            println!(
+                "ahci: disk {} has {} sectors",
+                index,
+                port.info().sector_count
+            );
+        }
+    }
+
+    let err = server::serve(&endpoint, &mut ports);
+    eprintln!("ahci: serving disks failed: {:?}", err);
+    syscall::exit(1);
+}
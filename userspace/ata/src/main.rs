@@ -0,0 +1,59 @@
+//! Drives the ATA disks on the legacy IDE channels with programmed IO, and serves them on the `ata`
+//! endpoint. It needs no capabilities, only the channels' io ports and IRQs, which `init` grants it
+//! as listed in the service manifest.
+
+#![no_std]
+#![no_main]
+
+use libwolffia::block::ata::{self, AtaDrive, Channel};
+use libwolffia::block::{server, BlockDevice};
+use libwolffia::ipc::Endpoint;
+use libwolffia::prelude::*;
+use libwolffia::syscall;
+
+/// The notification bits which each channel's IRQ is sent as
+const CHANNEL_NOTIFY: [u64; 2] = [1, 1 << 1];
+
+#[libwolffia::main]
+fn main() {
+    // Created before looking for disks, so that services which start after this one can call it
+    // straight away, and are answered once the disks have been found
+    let endpoint = match Endpoint::create(ata::ENDPOINT) {
+        Ok(endpoint) => endpoint,
+        Err(err) => {
+            eprintln!("ata: couldn't create the endpoint: {:?}", err);
+            syscall::exit(1);
+        }
+    };
+
+    let mut drives: [Option<AtaDrive>; ata::MAX_DRIVES] = [None; ata::MAX_DRIVES];
+
+    for (index, &notify) in CHANNEL_NOTIFY.iter().enumerate() {
+        match Channel::legacy(index, notify).drives() {
+            Ok([master, slave]) => {
+                drives[index * 2] = master;
+                drives[index * 2 + 1] = slave;
+            }
+            Err(err) => eprintln!("ata: couldn't use channel {}: {:?}", index, err),
+        }
+    }
+
+    if drives.iter().all(Option::is_none) {
+        println!("ata: no disks found");
+        return;
+    }
+
+    for (disk, drive) in drives.iter().enumerate() {
+        if let Some(drive) = drive {
+            println!(
+                "ata: disk {} has {} sectors",
+                disk,
+                drive.info().sector_count
+            );
+        }
+    }
+
+    let err = server::serve(&endpoint, &mut drives);
+    eprintln!("ata: serving disks failed: {:?}", err);
+    syscall::exit(1);
+}
@@ -0,0 +1,61 @@
+//! Serves the first ext2 filesystem on a virtio disk as the root filesystem, mounted at `/`, which
+//! needs the `storage` capability. It is read-only, and the mounts beneath it, such as `/initrd`,
+//! are still served by their own filesystems.
+
+#![no_std]
+#![no_main]
+
+use libwolffia::block::KernelBlockDevice;
+use libwolffia::ipc::Endpoint;
+use libwolffia::prelude::*;
+use libwolffia::syscall::{self, BlockDeviceInfo};
+use libwolffia::vfs::{self, ext2::Ext2Fs, server};
+
+const MOUNT_POINT: &str = "/";
+/// The most of the kernel's disks which are looked at
+const MAX_DISKS: usize = 32;
+
+#[libwolffia::main]
+fn main() {
+    let mut devices = [BlockDeviceInfo::default(); MAX_DISKS];
+    let count = match syscall::block_devices(&mut devices) {
+        Ok(count) => count.min(MAX_DISKS),
+        Err(err) => {
+            eprintln!("ext2fs: couldn't list disks: {:?}", err);
+            syscall::exit(1);
+        }
+    };
+
+    let fs = devices[..count]
+        .iter()
+        .enumerate()
+        .filter_map(|(id, info)| Ext2Fs::new(KernelBlockDevice::new(id, *info)).ok())
+        .next();
+
+    let mut fs = match fs {
+        Some(fs) => fs,
+        None => {
+            println!("ext2fs: no ext2 filesystem found");
+            return;
+        }
+    };
+
+    let endpoint = match Endpoint::create_anonymous() {
+        Ok(endpoint) => endpoint,
+        Err(err) => {
+            eprintln!("ext2fs: couldn't create the endpoint: {:?}", err);
+            syscall::exit(1);
+        }
+    };
+
+    if let Err(err) = vfs::mount(MOUNT_POINT, &endpoint) {
+        eprintln!("ext2fs: couldn't mount at {}: {:?}", MOUNT_POINT, err);
+        syscall::exit(1);
+    }
+
+    println!("ext2fs: mounted the root filesystem");
+
+    let err = server::serve(&endpoint, &mut fs);
+    eprintln!("ext2fs: serving the filesystem failed: {:?}", err);
+    syscall::exit(1);
+}
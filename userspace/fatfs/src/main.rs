@@ -0,0 +1,72 @@
+//! Serves the first FAT filesystem found on a disk, mounted at `/mnt`. Disks served by the ATA and
+//! AHCI drivers are looked at first, then those which the kernel drives, which needs the `storage`
+//! capability.
+
+#![no_std]
+#![no_main]
+
+use libwolffia::block::{ahci, ata, BlockDevice, KernelBlockDevice, RemoteBlockDevice};
+use libwolffia::ipc::Endpoint;
+use libwolffia::prelude::*;
+use libwolffia::syscall::{self, BlockDeviceInfo};
+use libwolffia::vfs::{self, fat::FatFs, server};
+
+const MOUNT_POINT: &str = "/mnt";
+/// The most disks which are looked at of each driver, and of the kernel
+const MAX_DISKS: usize = ahci::MAX_PORTS;
+
+#[libwolffia::main]
+fn main() {
+    for &driver in &[ata::ENDPOINT, ahci::ENDPOINT] {
+        let endpoint = match Endpoint::lookup(driver) {
+            Ok(endpoint) => endpoint,
+            Err(_) => continue,
+        };
+
+        for disk in 0..MAX_DISKS as u32 {
+            if let Ok(device) = RemoteBlockDevice::open(endpoint, disk) {
+                serve_if_fat(device);
+            }
+        }
+    }
+
+    let mut devices = [BlockDeviceInfo::default(); MAX_DISKS];
+    let count = syscall::block_devices(&mut devices).unwrap_or(0);
+
+    for (id, info) in devices[..count.min(MAX_DISKS)].iter().enumerate() {
+        serve_if_fat(KernelBlockDevice::new(id, *info));
+    }
+
+    println!("fatfs: no FAT filesystem found");
+}
+
+/// Mounts and serves the filesystem on the device forever, if it has one. Otherwise returns.
+fn serve_if_fat<D: BlockDevice>(device: D) {
+    let mut fs = match FatFs::new(device) {
+        Ok(fs) => fs,
+        Err(_) => return,
+    };
+
+    let endpoint = match Endpoint::create_anonymous() {
+        Ok(endpoint) => endpoint,
+        Err(err) => {
+            eprintln!("fatfs: couldn't create the endpoint: {:?}", err);
+            syscall::exit(1);
+        }
+    };
+
+    if let Err(err) = vfs::mount(MOUNT_POINT, &endpoint) {
+        eprintln!("fatfs: couldn't mount at {}: {:?}", MOUNT_POINT, err);
+        syscall::exit(1);
+    }
+
+    println!(
+        "fatfs: mounted a {:?} filesystem at {}",
+        fs.kind(),
+        MOUNT_POINT
+    );
+
+    let err = server::serve(&endpoint, &mut fs);
+    eprintln!("fatfs: serving the filesystem failed: {:?}", err);
+    syscall::exit(1);
+}
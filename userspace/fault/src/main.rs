@@ -0,0 +1,29 @@
+//! Writes to memory which it may only read, to check that the kernel ends a process which faults
+//! rather than going down with it. Run from the shell, it should be reported as killed, and the
+//! shell should carry on.
+
+#![no_std]
+#![no_main]
+
+use libwolffia::memory::MemoryObject;
+use libwolffia::prelude::*;
+use libwolffia::syscall::{self, UserPageFlags};
+
+/// Where the read-only page is mapped
+const MAPPING_ADDRESS: u64 = 0x5000_0000_0000;
+
+#[libwolffia::main]
+fn main() {
+    let object = MemoryObject::create(1).expect("Error creating a memory object");
+    let mapping = object
+        .map(MAPPING_ADDRESS as *mut u8, 1, 0, UserPageFlags::empty())
+        .expect("Error mapping the memory object");
+
+    println!("fault: writing to a read-only page, which should end this process");
+
+    // SAFETY: the page is mapped, so the only thing which can go wrong is the fault this is for
+    unsafe { mapping.as_ptr().write_volatile(1) };
+
+    eprintln!("fault: the write succeeded, but it should have faulted");
+    syscall::exit(1);
+}
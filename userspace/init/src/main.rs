@@ -1,13 +1,232 @@
-#![feature(asm)]
-
 #![no_std]
 #![no_main]
 
+mod manifest;
+
+use libwolffia::initrd::Archive;
 use libwolffia::prelude::*;
+use libwolffia::syscall::{self, Clock, SyscallError};
+use manifest::{Manifest, Restart, Service, MAX_SERVICES};
+
+/// The service manifest's path in the initrd
+const MANIFEST: &str = "etc/services";
+/// A service which exits sooner than this after starting is counted as failing to start
+const MIN_UPTIME_MS: u64 = 1000;
+/// How many times in a row a service may fail to start before it is no longer restarted
+const MAX_QUICK_EXITS: u32 = 5;
+
+#[derive(Debug, Copy, Clone)]
+struct State {
+    /// The process running the service, if it is running
+    pid: Option<u64>,
+    started_at_ms: u64,
+    /// How many times in a row the service has exited sooner than `MIN_UPTIME_MS`
+    quick_exits: u32,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Mark {
+    Unvisited,
+    Visiting,
+    Ordered,
+    /// One of the service's dependencies is missing, or they form a cycle
+    Broken,
+}
 
 #[libwolffia::main]
 fn main() {
     println!("Hello, world!");
-    unsafe { asm!("xor rax, rax", lateout("rax") _); }
-    println!("Halting...");
+
+    let initrd = match libwolffia::initrd::map() {
+        Ok(initrd) => initrd,
+        Err(_) => shut_down("No initrd was loaded"),
+    };
+
+    let motd = initrd.find("etc/motd");
+    if let Some(motd) = motd.and_then(|file| core::str::from_utf8(file.data).ok()) {
+        print!("{}", motd);
+    }
+
+    let text = match initrd.find(MANIFEST) {
+        Some(file) => core::str::from_utf8(file.data).ok(),
+        None => shut_down("There is no service manifest in the initrd"),
+    };
+
+    let manifest = match text.map(Manifest::parse) {
+        Some(Ok(manifest)) => manifest,
+        Some(Err(err)) => {
+            eprintln!("init: {}: {}", MANIFEST, err);
+            shut_down("The service manifest is invalid")
+        }
+        None => shut_down("The service manifest isn't valid UTF-8"),
+    };
+
+    let mut order = [0; MAX_SERVICES];
+    let mut order_len = 0;
+    let mut marks = [Mark::Unvisited; MAX_SERVICES];
+
+    for index in 0..manifest.services().len() {
+        visit(&manifest, index, &mut marks, &mut order, &mut order_len);
+    }
+
+    let mut states = [State {
+        pid: None,
+        started_at_ms: 0,
+        quick_exits: 0,
+    }; MAX_SERVICES];
+
+    // Services only run once init blocks, so they start in this order
+    for &index in &order[..order_len] {
+        start(initrd, &manifest.services()[index], &mut states[index]);
+    }
+
+    supervise(initrd, &manifest, &mut states)
+}
+
+/// Adds a service to the start order after its dependencies. Returns false if it can't be started.
+fn visit(
+    manifest: &Manifest,
+    index: usize,
+    marks: &mut [Mark; MAX_SERVICES],
+    order: &mut [usize; MAX_SERVICES],
+    order_len: &mut usize,
+) -> bool {
+    let service = &manifest.services()[index];
+
+    match marks[index] {
+        Mark::Unvisited => (),
+        Mark::Visiting => {
+            eprintln!("init: {} depends on itself", service.name);
+            return false;
+        }
+        Mark::Ordered => return true,
+        Mark::Broken => return false,
+    }
+
+    marks[index] = Mark::Visiting;
+    let mut startable = true;
+
+    for &dependency in service.after() {
+        match manifest.find(dependency) {
+            Some(dependency) => startable &= visit(manifest, dependency, marks, order, order_len),
+            None => {
+                eprintln!(
+                    "init: {} depends on {}, which isn't a service",
+                    service.name, dependency
+                );
+                startable = false;
+            }
+        }
+    }
+
+    if startable {
+        marks[index] = Mark::Ordered;
+        order[*order_len] = index;
+        *order_len += 1;
+    } else {
+        marks[index] = Mark::Broken;
+        eprintln!("init: not starting {}", service.name);
+    }
+
+    startable
+}
+
+/// Waits for services to exit, restarting them according to their restart policies
+fn supervise(initrd: Archive, manifest: &Manifest, states: &mut [State; MAX_SERVICES]) -> ! {
+    loop {
+        let (pid, status) = match syscall::wait_any() {
+            Ok(exited) => exited,
+            Err(SyscallError::NotFound) => shut_down("No services are running"),
+            Err(err) => panic!("Error waiting for services: {:?}", err),
+        };
+
+        let index = match states.iter().position(|state| state.pid == Some(pid)) {
+            Some(index) => index,
+            None => continue,
+        };
+
+        let service = &manifest.services()[index];
+        let state = &mut states[index];
+        state.pid = None;
+
+        let failed = !status.success();
+        if failed {
+            eprintln!("init: {} ended with {:?}", service.name, status);
+        }
+
+        let restart = match service.restart {
+            Restart::Always => true,
+            Restart::OnFailure => failed,
+            Restart::Never => false,
+        };
+
+        if !restart {
+            continue;
+        }
+
+        if now_ms().saturating_sub(state.started_at_ms) < MIN_UPTIME_MS {
+            state.quick_exits += 1;
+        } else {
+            state.quick_exits = 0;
+        }
+
+        if state.quick_exits >= MAX_QUICK_EXITS {
+            eprintln!(
+                "init: {} keeps exiting as soon as it starts, so it won't be restarted",
+                service.name
+            );
+            continue;
+        }
+
+        start(initrd, service, state);
+    }
+}
+
+fn start(initrd: Archive, service: &Service, state: &mut State) {
+    match spawn(initrd, service) {
+        Ok(pid) => {
+            state.pid = Some(pid);
+            state.started_at_ms = now_ms();
+        }
+        Err(err) => eprintln!("init: couldn't start {}: {:?}", service.name, err),
+    }
+}
+
+/// Spawns a service and grants it its io ports and IRQs, which it has by the time it first runs
+fn spawn(initrd: Archive, service: &Service) -> Result<u64, SyscallError> {
+    let file = initrd.find(service.path).ok_or(SyscallError::NotFound)?;
+    let pid = syscall::spawn(file.data, service.name, service.capabilities, None)?;
+
+    if let Err(err) = grant(pid, service) {
+        let _ = syscall::kill(pid);
+        let _ = syscall::wait(pid);
+        return Err(err);
+    }
+
+    Ok(pid)
+}
+
+fn grant(pid: u64, service: &Service) -> Result<(), SyscallError> {
+    for &(first, last) in service.io_ports() {
+        syscall::io_port_grant(pid, first..=last)?;
+    }
+
+    for irq in 0..16 {
+        if service.irqs & (1 << irq) != 0 {
+            syscall::irq_grant(pid, irq)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn now_ms() -> u64 {
+    syscall::clock_get(Clock::Monotonic).unwrap_or(0)
+}
+
+fn shut_down(reason: &str) -> ! {
+    println!("{}", reason);
+    println!("Shutting down...");
+    syscall::shutdown();
+    syscall::halt()
 }
@@ -0,0 +1,266 @@
+//! Parsing the service manifest, which lists the drivers and servers that `init` starts. It is made
+//! of sections like the following, where everything but `path` may be left out:
+//!
+//! ```text
+//! # Comments start with a hash
+//! [serial]
+//! path = bin/serial
+//! capabilities = devices initrd
+//! after = logger
+//! io_ports = 0x2f8-0x2ff 0x3e8
+//! irqs = 3
+//! restart = on-failure
+//! ```
+//!
+//! `capabilities` is any of `power`, `devices`, `input`, `initrd`, `storage`, `network` and
+//! `kill`, or `all`. `after` names the services which must be started first. `restart` is
+//! `always`, `on-failure` (the default), which restarts the service unless it exits successfully,
+//! or `never`.
+
+use core::fmt;
+use libwolffia::syscall::Capabilities;
+
+pub const MAX_SERVICES: usize = 32;
+pub const MAX_DEPENDENCIES: usize = 8;
+pub const MAX_IO_PORT_RANGES: usize = 8;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Restart {
+    Always,
+    OnFailure,
+    Never,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Service {
+    pub name: &'static str,
+    /// The line of the manifest which the service's section starts on
+    line: usize,
+    /// The program's path in the initrd
+    pub path: &'static str,
+    pub capabilities: Capabilities,
+    after: [&'static str; MAX_DEPENDENCIES],
+    after_len: usize,
+    /// The first and last ports of each range
+    io_ports: [(u16, u16); MAX_IO_PORT_RANGES],
+    io_ports_len: usize,
+    /// One bit per legacy IRQ
+    pub irqs: u16,
+    pub restart: Restart,
+}
+
+impl Service {
+    const EMPTY: Service = Service {
+        name: "",
+        line: 0,
+        path: "",
+        capabilities: Capabilities::empty(),
+        after: [""; MAX_DEPENDENCIES],
+        after_len: 0,
+        io_ports: [(0, 0); MAX_IO_PORT_RANGES],
+        io_ports_len: 0,
+        irqs: 0,
+        restart: Restart::OnFailure,
+    };
+
+    fn new(name: &'static str, line: usize) -> Service {
+        Service {
+            name,
+            line,
+            ..Service::EMPTY
+        }
+    }
+
+    /// The names of the services which must be started first
+    pub fn after(&self) -> &[&'static str] {
+        &self.after[..self.after_len]
+    }
+
+    /// The first and last ports of each range of io ports which the service is granted
+    pub fn io_ports(&self) -> &[(u16, u16)] {
+        &self.io_ports[..self.io_ports_len]
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ParseError {
+    /// Counting from 1
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// The services listed in a manifest, in the order they are listed
+pub struct Manifest {
+    services: [Service; MAX_SERVICES],
+    len: usize,
+}
+
+impl Manifest {
+    pub fn parse(text: &'static str) -> Result<Manifest, ParseError> {
+        let mut manifest = Manifest {
+            services: [Service::EMPTY; MAX_SERVICES],
+            len: 0,
+        };
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message| ParseError {
+                line: index + 1,
+                message,
+            };
+
+            manifest.parse_line(line.trim(), index + 1).map_err(error)?;
+        }
+
+        if let Some(service) = manifest
+            .services()
+            .iter()
+            .find(|service| service.path.is_empty())
+        {
+            return Err(ParseError {
+                line: service.line,
+                message: "the service has no path",
+            });
+        }
+
+        Ok(manifest)
+    }
+
+    fn parse_line(&mut self, line: &'static str, number: usize) -> Result<(), &'static str> {
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err("expected `]`");
+            }
+
+            let name = line[1..line.len() - 1].trim();
+
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err("expected a service name without spaces");
+            }
+
+            if self.find(name).is_some() {
+                return Err("there is already a service with this name");
+            }
+
+            if self.len == MAX_SERVICES {
+                return Err("too many services");
+            }
+
+            self.services[self.len] = Service::new(name, number);
+            self.len += 1;
+            return Ok(());
+        }
+
+        let mut parts = line.splitn(2, '=');
+        let key = parts.next().unwrap().trim();
+        let value = match parts.next() {
+            Some(value) => value.trim(),
+            None => return Err("expected `key = value` or `[service]`"),
+        };
+
+        let service = match self.len {
+            0 => return Err("expected `[service]` before its settings"),
+            len => &mut self.services[len - 1],
+        };
+
+        match key {
+            "path" => service.path = value,
+            "capabilities" => service.capabilities = parse_capabilities(value)?,
+            "after" => {
+                for name in value.split_whitespace() {
+                    if service.after_len == MAX_DEPENDENCIES {
+                        return Err("too many dependencies");
+                    }
+
+                    service.after[service.after_len] = name;
+                    service.after_len += 1;
+                }
+            }
+            "io_ports" => {
+                for range in value.split_whitespace() {
+                    if service.io_ports_len == MAX_IO_PORT_RANGES {
+                        return Err("too many io port ranges");
+                    }
+
+                    service.io_ports[service.io_ports_len] = parse_port_range(range)?;
+                    service.io_ports_len += 1;
+                }
+            }
+            "irqs" => {
+                for irq in value.split_whitespace() {
+                    match parse_number(irq) {
+                        Some(irq) if irq < 16 => service.irqs |= 1 << irq,
+                        _ => return Err("expected IRQs from 0 to 15"),
+                    }
+                }
+            }
+            "restart" => {
+                service.restart = match value {
+                    "always" => Restart::Always,
+                    "on-failure" => Restart::OnFailure,
+                    "never" => Restart::Never,
+                    _ => return Err("expected `always`, `on-failure` or `never`"),
+                }
+            }
+            _ => return Err("unknown setting"),
+        }
+
+        Ok(())
+    }
+
+    pub fn services(&self) -> &[Service] {
+        &self.services[..self.len]
+    }
+
+    /// Returns the index of the service with the given name
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.services()
+            .iter()
+            .position(|service| service.name == name)
+    }
+}
+
+fn parse_capabilities(value: &str) -> Result<Capabilities, &'static str> {
+    let mut capabilities = Capabilities::empty();
+
+    for name in value.split_whitespace() {
+        capabilities |= Capabilities::from_name(name).ok_or("unknown capability")?;
+    }
+
+    Ok(capabilities)
+}
+
+/// Parses `first-last`, or a single port
+fn parse_port_range(range: &str) -> Result<(u16, u16), &'static str> {
+    let mut ports = range.splitn(2, '-');
+    let first = ports.next().and_then(parse_number);
+    let last = match ports.next() {
+        Some(last) => parse_number(last),
+        None => first,
+    };
+
+    match (first, last) {
+        (Some(first), Some(last)) if first <= last && last <= 0xffff => {
+            Ok((first as u16, last as u16))
+        }
+        _ => Err("expected io ports like `0x60` or `0x60-0x64`"),
+    }
+}
+
+/// Parses a decimal number, or a hexadecimal one starting with `0x`
+fn parse_number(number: &str) -> Option<u32> {
+    if number.starts_with("0x") {
+        u32::from_str_radix(&number[2..], 16).ok()
+    } else {
+        number.parse().ok()
+    }
+}
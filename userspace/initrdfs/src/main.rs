@@ -0,0 +1,41 @@
+//! Serves the initrd as a read-only filesystem, mounted at `/initrd`. It needs the `initrd`
+//! capability to map it, and the mount table server to be running.
+
+#![no_std]
+#![no_main]
+
+use libwolffia::initrd;
+use libwolffia::ipc::Endpoint;
+use libwolffia::prelude::*;
+use libwolffia::syscall;
+use libwolffia::vfs::{self, initrd::InitrdFs, server};
+
+const MOUNT_POINT: &str = "/initrd";
+
+#[libwolffia::main]
+fn main() {
+    let archive = match initrd::map() {
+        Ok(archive) => archive,
+        Err(err) => {
+            eprintln!("initrdfs: couldn't map the initrd: {:?}", err);
+            syscall::exit(1);
+        }
+    };
+
+    let endpoint = match Endpoint::create_anonymous() {
+        Ok(endpoint) => endpoint,
+        Err(err) => {
+            eprintln!("initrdfs: couldn't create the endpoint: {:?}", err);
+            syscall::exit(1);
+        }
+    };
+
+    if let Err(err) = vfs::mount(MOUNT_POINT, &endpoint) {
+        eprintln!("initrdfs: couldn't mount at {}: {:?}", MOUNT_POINT, err);
+        syscall::exit(1);
+    }
+
+    let err = server::serve(&endpoint, &mut InitrdFs::new(archive));
+    eprintln!("initrdfs: serving the initrd failed: {:?}", err);
+    syscall::exit(1);
+}
@@ -14,7 +14,7 @@ pub fn main(_args: TokenStream, input: TokenStream) -> TokenStream {
         #[no_mangle]
         pub fn _start() {
             __main();
-            ::libwolffia::syscall::halt();
+            ::libwolffia::syscall::exit(0);
         }
 
         #[inline(always)]
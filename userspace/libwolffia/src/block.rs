@@ -0,0 +1,320 @@
+//! Block devices, such as disks. Those driven by the kernel are accessed through system calls,
+//! while ATA and AHCI disks are driven from userspace, but all of them are read and written through
+//! the `BlockDevice` trait.
+//!
+//! A driver process serves its disks to other processes on an endpoint with `server::serve`, and
+//! they open each as a `RemoteBlockDevice`. Every request is a `Request` header followed by the
+//! data to write, if any, and every reply is an i64 status, which is negative for an error's
+//! `SyscallError` code, followed by the data read or the disk's information. The headers are
+//! little endian.
+
+pub mod ahci;
+pub mod ata;
+pub mod server;
+
+use crate::ipc::Endpoint;
+use crate::syscall::{self, BlockDeviceInfo, SyscallError};
+use core::convert::TryInto;
+
+/// The most which the kernel transfers at once
+const KERNEL_MAX_TRANSFER: usize = 64 * 1024;
+
+/// The most data read or written by one request to a driver process
+pub const MAX_TRANSFER: usize = 4096;
+pub const REQUEST_SIZE: usize = 24;
+pub const REPLY_SIZE: usize = 8;
+/// Large enough for any request or reply
+pub const MESSAGE_SIZE: usize = REQUEST_SIZE + MAX_TRANSFER;
+
+const INFO_SIZE: usize = 16;
+
+const MBR_SIGNATURE_OFFSET: usize = 510;
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_PARTITIONS_OFFSET: usize = 446;
+const MBR_PARTITION_SIZE: usize = 16;
+const MBR_PARTITIONS: usize = 4;
+
+pub trait BlockDevice {
+    fn info(&self) -> BlockDeviceInfo;
+
+    /// Reads whole sectors into the buffer, starting at the given sector
+    fn read(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), SyscallError>;
+
+    /// Writes whole sectors from the buffer, starting at the given sector. Fails with
+    /// `SyscallError::PermissionDenied` if the device is read-only.
+    fn write(&mut self, sector: u64, buf: &[u8]) -> Result<(), SyscallError>;
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum Op {
+    /// Returns the disk's `BlockDeviceInfo` in the reply payload
+    Info = 0,
+    /// Reads `len` bytes of whole sectors from the sector into the reply payload
+    Read = 1,
+    /// Writes the payload to the disk from the sector
+    Write = 2,
+}
+
+impl Op {
+    pub fn from_u32(v: u32) -> Option<Op> {
+        match v {
+            0 => Some(Op::Info),
+            1 => Some(Op::Read),
+            2 => Some(Op::Write),
+            _ => None,
+        }
+    }
+}
+
+/// The header of a request to a driver process
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Request {
+    pub op: u32,
+    /// Which of the driver's disks the request is for
+    pub disk: u32,
+    pub sector: u64,
+    pub len: u64,
+}
+
+impl Request {
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0..4].copy_from_slice(&self.op.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.disk.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.sector.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.len.to_le_bytes());
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Request> {
+        let buf = buf.get(..REQUEST_SIZE)?;
+
+        Some(Request {
+            op: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+            disk: u32::from_le_bytes(buf[4..8].try_into().unwrap()),
+            sector: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+            len: u64::from_le_bytes(buf[16..24].try_into().unwrap()),
+        })
+    }
+}
+
+fn encode_info(info: &BlockDeviceInfo, buf: &mut [u8]) -> usize {
+    buf[0..4].copy_from_slice(&info.sector_size.to_le_bytes());
+    buf[4..8].copy_from_slice(&info.flags.to_le_bytes());
+    buf[8..16].copy_from_slice(&info.sector_count.to_le_bytes());
+    INFO_SIZE
+}
+
+fn decode_info(buf: &[u8]) -> Option<BlockDeviceInfo> {
+    let buf = buf.get(..INFO_SIZE)?;
+
+    Some(BlockDeviceInfo {
+        sector_size: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+        flags: u32::from_le_bytes(buf[4..8].try_into().unwrap()),
+        sector_count: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+    })
+}
+
+/// Checks that a transfer is of whole sectors within the device
+fn check_transfer(info: &BlockDeviceInfo, sector: u64, len: usize) -> Result<u64, SyscallError> {
+    let sector_size = info.sector_size as usize;
+
+    if len == 0 || len % sector_size != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let sectors = (len / sector_size) as u64;
+
+    match sector.checked_add(sectors) {
+        Some(end) if end <= info.sector_count => Ok(sectors),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+/// Finds the first partition of one of the given types in a master boot record, the first sector of
+/// a device. Returns the sector which it starts at, or None if the sector isn't an MBR or has no
+/// such partition.
+pub fn mbr_partition(mbr: &[u8], types: &[u8]) -> Option<u64> {
+    if mbr.get(MBR_SIGNATURE_OFFSET..MBR_SIGNATURE_OFFSET + 2) != Some(&MBR_SIGNATURE[..]) {
+        return None;
+    }
+
+    mbr[MBR_PARTITIONS_OFFSET..MBR_PARTITIONS_OFFSET + MBR_PARTITIONS * MBR_PARTITION_SIZE]
+        .chunks_exact(MBR_PARTITION_SIZE)
+        .find(|partition| types.contains(&partition[4]))
+        .map(|partition| u32::from_le_bytes(partition[8..12].try_into().unwrap()) as u64)
+}
+
+/// A block device driven by the kernel
+pub struct KernelBlockDevice {
+    id: usize,
+    info: BlockDeviceInfo,
+}
+
+impl KernelBlockDevice {
+    /// Opens the device with the given id, whose information is as returned by
+    /// `syscall::block_devices`
+    pub fn new(id: usize, info: BlockDeviceInfo) -> KernelBlockDevice {
+        KernelBlockDevice { id, info }
+    }
+
+    /// Splits a transfer into pieces which the kernel accepts, retrying those which it is too
+    /// busy for
+    fn transfer<F>(&mut self, sector: u64, len: usize, mut transfer: F) -> Result<(), SyscallError>
+    where
+        F: FnMut(u64, usize, usize) -> Result<(), SyscallError>,
+    {
+        let sectors_per_chunk = (KERNEL_MAX_TRANSFER / self.info.sector_size as usize) as u64;
+        let chunk_len = sectors_per_chunk as usize * self.info.sector_size as usize;
+        let mut sector = sector;
+        let mut offset = 0;
+
+        while offset < len {
+            let end = (offset + chunk_len).min(len);
+
+            loop {
+                match transfer(sector, offset, end) {
+                    Err(SyscallError::Busy) => syscall::sleep(1)?,
+                    res => break res?,
+                }
+            }
+
+            sector += sectors_per_chunk;
+            offset = end;
+        }
+
+        Ok(())
+    }
+}
+
+impl BlockDevice for KernelBlockDevice {
+    fn info(&self) -> BlockDeviceInfo {
+        self.info
+    }
+
+    fn read(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), SyscallError> {
+        let id = self.id;
+        self.transfer(sector, buf.len(), |sector, start, end| {
+            syscall::block_read(id, sector, &mut buf[start..end])
+        })
+    }
+
+    fn write(&mut self, sector: u64, buf: &[u8]) -> Result<(), SyscallError> {
+        let id = self.id;
+        self.transfer(sector, buf.len(), |sector, start, end| {
+            syscall::block_write(id, sector, &buf[start..end])
+        })
+    }
+}
+
+/// A disk served by a driver process
+#[derive(Debug)]
+pub struct RemoteBlockDevice {
+    endpoint: Endpoint,
+    disk: u32,
+    info: BlockDeviceInfo,
+}
+
+impl RemoteBlockDevice {
+    /// Opens one of the disks served on the endpoint, asking the driver for its information. Fails
+    /// with `SyscallError::NotFound` if the driver has no such disk.
+    pub fn open(endpoint: Endpoint, disk: u32) -> Result<RemoteBlockDevice, SyscallError> {
+        let mut device = RemoteBlockDevice {
+            endpoint,
+            disk,
+            info: BlockDeviceInfo::default(),
+        };
+
+        let mut buf = [0; INFO_SIZE];
+        let len = device.call(Op::Info, 0, &[], &mut buf)?;
+        let info = decode_info(&buf[..len]).ok_or(SyscallError::IoError)?;
+
+        // Each request must be of whole sectors
+        if info.sector_size == 0 || info.sector_size as usize > MAX_TRANSFER {
+            return Err(SyscallError::InvalidArgument);
+        }
+
+        device.info = info;
+        Ok(device)
+    }
+
+    /// Makes a request of the driver, receiving the reply's payload into the buffer. Returns the
+    /// length of the payload.
+    fn call(
+        &self,
+        op: Op,
+        sector: u64,
+        payload: &[u8],
+        reply_payload: &mut [u8],
+    ) -> Result<usize, SyscallError> {
+        let request = Request {
+            op: op as u32,
+            disk: self.disk,
+            sector,
+            len: payload.len().max(reply_payload.len()) as u64,
+        };
+
+        let mut request_buf = [0; MESSAGE_SIZE];
+        request.encode(&mut request_buf);
+        request_buf[REQUEST_SIZE..REQUEST_SIZE + payload.len()].copy_from_slice(payload);
+
+        let mut reply_buf = [0; MESSAGE_SIZE];
+        let request_len = REQUEST_SIZE + payload.len();
+        let len = self
+            .endpoint
+            .call(&request_buf[..request_len], &mut reply_buf)?;
+
+        if len < REPLY_SIZE {
+            return Err(SyscallError::IoError);
+        }
+
+        let status = i64::from_le_bytes(reply_buf[..REPLY_SIZE].try_into().unwrap());
+        syscall::res_from_code(status)?;
+
+        let reply_len = (len - REPLY_SIZE).min(reply_payload.len());
+        reply_payload[..reply_len].copy_from_slice(&reply_buf[REPLY_SIZE..REPLY_SIZE + reply_len]);
+        Ok(reply_len)
+    }
+
+    /// The length of the largest request of whole sectors
+    fn chunk_len(&self) -> usize {
+        let sector_size = self.info.sector_size as usize;
+        MAX_TRANSFER / sector_size * sector_size
+    }
+}
+
+impl BlockDevice for RemoteBlockDevice {
+    fn info(&self) -> BlockDeviceInfo {
+        self.info
+    }
+
+    fn read(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), SyscallError> {
+        check_transfer(&self.info, sector, buf.len())?;
+
+        let chunk_len = self.chunk_len();
+        let sectors_per_chunk = (chunk_len / self.info.sector_size as usize) as u64;
+
+        for (i, chunk) in buf.chunks_mut(chunk_len).enumerate() {
+            let sector = sector + i as u64 * sectors_per_chunk;
+
+            if self.call(Op::Read, sector, &[], chunk)? < chunk.len() {
+                return Err(SyscallError::IoError);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, sector: u64, buf: &[u8]) -> Result<(), SyscallError> {
+        check_transfer(&self.info, sector, buf.len())?;
+
+        let chunk_len = self.chunk_len();
+        let sectors_per_chunk = (chunk_len / self.info.sector_size as usize) as u64;
+
+        for (i, chunk) in buf.chunks(chunk_len).enumerate() {
+            let sector = sector + i as u64 * sectors_per_chunk;
+            self.call(Op::Write, sector, chunk, &mut [])?;
+        }
+
+        Ok(())
+    }
+}
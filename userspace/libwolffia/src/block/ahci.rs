@@ -0,0 +1,438 @@
+//! SATA disks on AHCI controllers. Each port has a single command slot, and transfers go through a
+//! buffer which the controller accesses by DMA. The controller must have been claimed with
+//! `syscall::pci_claim`.
+//! From: Serial ATA AHCI 1.3.1 specification, sections 3 to 5
+
+use super::{check_transfer, BlockDevice};
+use crate::syscall::{self, BlockDeviceInfo, PciDeviceInfo, SyscallError};
+use core::ptr;
+
+/// The PCI class, subclass and programming interface of AHCI controllers
+const CLASS_MASS_STORAGE: u8 = 0x01;
+const SUBCLASS_SATA: u8 = 0x06;
+const PROG_IF_AHCI: u8 = 0x01;
+
+/// The BAR which holds the HBA's registers
+const ABAR: u8 = 5;
+
+/// Offsets of the HBA's global registers
+const HBA_GLOBAL_CONTROL: usize = 0x04;
+const HBA_INTERRUPT_STATUS: usize = 0x08;
+const HBA_PORTS_IMPLEMENTED: usize = 0x0c;
+
+const GLOBAL_CONTROL_INTERRUPTS: u32 = 1 << 1;
+const GLOBAL_CONTROL_AHCI: u32 = 1 << 31;
+
+/// The name of the endpoint which the AHCI driver serves its disks on, numbered by port
+pub const ENDPOINT: &str = "ahci";
+
+const PORTS_OFFSET: usize = 0x100;
+const PORT_SIZE: usize = 0x80;
+pub const MAX_PORTS: usize = 32;
+
+/// Offsets of each port's registers
+const PORT_COMMAND_LIST: usize = 0x00;
+const PORT_COMMAND_LIST_UPPER: usize = 0x04;
+const PORT_FIS: usize = 0x08;
+const PORT_FIS_UPPER: usize = 0x0c;
+const PORT_INTERRUPT_STATUS: usize = 0x10;
+const PORT_INTERRUPT_ENABLE: usize = 0x14;
+const PORT_COMMAND: usize = 0x18;
+const PORT_TASK_FILE: usize = 0x20;
+const PORT_SIGNATURE: usize = 0x24;
+const PORT_SATA_STATUS: usize = 0x28;
+const PORT_SATA_ERROR: usize = 0x30;
+const PORT_COMMAND_ISSUE: usize = 0x38;
+
+const COMMAND_START: u32 = 1;
+const COMMAND_FIS_RECEIVE: u32 = 1 << 4;
+const COMMAND_FIS_RUNNING: u32 = 1 << 14;
+const COMMAND_LIST_RUNNING: u32 = 1 << 15;
+
+const TASK_FILE_ERROR: u32 = 1;
+const TASK_FILE_DATA_REQUEST: u32 = 1 << 3;
+const TASK_FILE_BUSY: u32 = 1 << 7;
+
+/// Raised when a device to host register FIS is received, i.e a command completes
+const INTERRUPT_REGISTER_FIS: u32 = 1;
+const INTERRUPT_TASK_FILE_ERROR: u32 = 1 << 30;
+
+const SATA_STATUS_DETECTION_MASK: u32 = 0xf;
+/// A device is present and communication is established
+const SATA_STATUS_DETECTION_PRESENT: u32 = 3;
+const SIGNATURE_ATA: u32 = 0x0000_0101;
+
+/// The port's command list, received FIS area and command table are in one page
+const CONTROL_SIZE: usize = 4096;
+const COMMAND_LIST_OFFSET: usize = 0;
+const FIS_OFFSET: usize = 1024;
+const COMMAND_TABLE_OFFSET: usize = 2048;
+const PRDT_OFFSET: usize = 0x80;
+
+/// The length in dwords of a register FIS
+const FIS_REGISTER_LEN: u32 = 5;
+const FIS_TYPE_REGISTER_HOST_TO_DEVICE: u8 = 0x27;
+/// Set in a register FIS which holds a command
+const FIS_COMMAND: u8 = 0x80;
+const HEADER_WRITE: u32 = 1 << 6;
+const PRD_INTERRUPT: u32 = 1 << 31;
+
+const ATA_DEVICE_LBA: u8 = 1 << 6;
+const ATA_READ_DMA_EXT: u8 = 0x25;
+const ATA_WRITE_DMA_EXT: u8 = 0x35;
+const ATA_FLUSH_CACHE_EXT: u8 = 0xea;
+const ATA_IDENTIFY: u8 = 0xec;
+
+/// Words of the identify data
+const IDENTIFY_SECTORS_28: usize = 60;
+const IDENTIFY_SECTORS_48: usize = 100;
+
+const SECTOR_SIZE: usize = 512;
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// The HBA's registers are mapped first, in at most four pages as its BAR may not be page aligned
+const REGISTERS_SIZE: usize = 16 * 1024;
+
+/// How much address space the controller needs: its registers, then each port's control page and
+/// buffer
+pub const ADDRESS_SPACE: usize = REGISTERS_SIZE + MAX_PORTS * (CONTROL_SIZE + BUFFER_SIZE);
+
+/// An AHCI controller, with its registers mapped
+pub struct Controller {
+    hba: *mut u8,
+    at: *mut u8,
+    irq: u8,
+    notify: u64,
+}
+
+/// Whether a PCI function is an AHCI controller
+pub fn is_controller(device: &PciDeviceInfo) -> bool {
+    device.class == CLASS_MASS_STORAGE
+        && device.subclass == SUBCLASS_SATA
+        && device.prog_if == PROG_IF_AHCI
+}
+
+impl Controller {
+    /// Maps the registers of a claimed controller at the given page aligned address, where there
+    /// must be `ADDRESS_SPACE` bytes free, and listens to its IRQ as the given notification bits
+    pub fn new(
+        controller: &PciDeviceInfo,
+        at: *mut u8,
+        notify: u64,
+    ) -> Result<Controller, SyscallError> {
+        let bar = controller.bars[ABAR as usize];
+        let offset = bar.address as usize & 0xfff;
+
+        if offset + bar.size as usize > REGISTERS_SIZE {
+            return Err(SyscallError::InvalidArgument);
+        }
+
+        syscall::pci_map_bar(controller.address(), ABAR, at)?;
+
+        // SAFETY: the BAR was mapped at the page containing its start
+        let hba = unsafe { at.add(offset) };
+
+        let irq = controller.interrupt_line;
+        syscall::irq_listen(irq, notify)?;
+
+        let controller = Controller {
+            hba,
+            at,
+            irq,
+            notify,
+        };
+
+        let control = controller.read_register(HBA_GLOBAL_CONTROL);
+        controller.write_register(
+            HBA_GLOBAL_CONTROL,
+            control | GLOBAL_CONTROL_AHCI | GLOBAL_CONTROL_INTERRUPTS,
+        );
+
+        Ok(controller)
+    }
+
+    /// Starts the ports which have SATA disks attached, indexed by port number. ATAPI drives, e.g
+    /// CD drives, are skipped.
+    pub fn into_ports(self) -> Result<[Option<AhciPort>; MAX_PORTS], SyscallError> {
+        let mut ports: [Option<AhciPort>; MAX_PORTS] = Default::default();
+        let implemented = self.read_register(HBA_PORTS_IMPLEMENTED);
+
+        for (index, port) in ports.iter_mut().enumerate() {
+            if implemented & (1 << index) == 0 {
+                continue;
+            }
+
+            // SAFETY: the port's registers are within the HBA's
+            let registers = unsafe { self.hba.add(PORTS_OFFSET + index * PORT_SIZE) };
+            let status = read(registers, PORT_SATA_STATUS);
+
+            if status & SATA_STATUS_DETECTION_MASK != SATA_STATUS_DETECTION_PRESENT
+                || read(registers, PORT_SIGNATURE) != SIGNATURE_ATA
+            {
+                continue;
+            }
+
+            *port = Some(self.start_port(index, registers)?);
+        }
+
+        Ok(ports)
+    }
+
+    fn start_port(&self, index: usize, registers: *mut u8) -> Result<AhciPort, SyscallError> {
+        // SAFETY: within the address space reserved for the controller
+        let control = unsafe {
+            self.at
+                .add(REGISTERS_SIZE + index * (CONTROL_SIZE + BUFFER_SIZE))
+        };
+        // SAFETY: as above
+        let buffer = unsafe { control.add(CONTROL_SIZE) };
+
+        let control_physical = syscall::dma_map(control, CONTROL_SIZE)?;
+        let buffer_physical = syscall::dma_map(buffer, BUFFER_SIZE)?;
+
+        let mut port = AhciPort {
+            hba: self.hba,
+            registers,
+            index,
+            irq: self.irq,
+            notify: self.notify,
+            control,
+            control_physical,
+            buffer,
+            buffer_physical,
+            info: BlockDeviceInfo::default(),
+        };
+
+        port.stop();
+
+        let command_list = control_physical + COMMAND_LIST_OFFSET as u64;
+        let fis = control_physical + FIS_OFFSET as u64;
+        port.write_register(PORT_COMMAND_LIST, command_list as u32);
+        port.write_register(PORT_COMMAND_LIST_UPPER, (command_list >> 32) as u32);
+        port.write_register(PORT_FIS, fis as u32);
+        port.write_register(PORT_FIS_UPPER, (fis >> 32) as u32);
+
+        port.write_register(PORT_SATA_ERROR, !0);
+        port.write_register(PORT_INTERRUPT_STATUS, !0);
+        port.write_register(
+            PORT_INTERRUPT_ENABLE,
+            INTERRUPT_REGISTER_FIS | INTERRUPT_TASK_FILE_ERROR,
+        );
+
+        port.start();
+        port.identify()?;
+
+        Ok(port)
+    }
+
+    fn read_register(&self, register: usize) -> u32 {
+        read(self.hba, register)
+    }
+
+    fn write_register(&self, register: usize, value: u32) {
+        write(self.hba, register, value)
+    }
+}
+
+/// A port of an AHCI controller with a SATA disk attached, as found by `Controller::into_ports`
+pub struct AhciPort {
+    hba: *mut u8,
+    registers: *mut u8,
+    index: usize,
+    irq: u8,
+    notify: u64,
+    /// The port's command list, received FIS area and command table
+    control: *mut u8,
+    control_physical: u64,
+    buffer: *mut u8,
+    buffer_physical: u64,
+    info: BlockDeviceInfo,
+}
+
+impl AhciPort {
+    fn stop(&mut self) {
+        let command = self.read_register(PORT_COMMAND) & !(COMMAND_START | COMMAND_FIS_RECEIVE);
+        self.write_register(PORT_COMMAND, command);
+
+        let running = COMMAND_LIST_RUNNING | COMMAND_FIS_RUNNING;
+        while self.read_register(PORT_COMMAND) & running != 0 {}
+    }
+
+    fn start(&mut self) {
+        let command = self.read_register(PORT_COMMAND);
+        self.write_register(PORT_COMMAND, command | COMMAND_FIS_RECEIVE);
+
+        while self.read_register(PORT_TASK_FILE) & (TASK_FILE_BUSY | TASK_FILE_DATA_REQUEST) != 0 {}
+
+        let command = self.read_register(PORT_COMMAND);
+        self.write_register(PORT_COMMAND, command | COMMAND_START);
+    }
+
+    fn identify(&mut self) -> Result<(), SyscallError> {
+        self.issue(ATA_IDENTIFY, 0, 0, SECTOR_SIZE, false)?;
+
+        let word = |index: usize| {
+            // SAFETY: the identify data is at the start of the buffer
+            unsafe { ptr::read_volatile(self.buffer.add(index * 2) as *const u16) as u64 }
+        };
+
+        let sectors_48 = (0..4)
+            .rev()
+            .fold(0, |count, i| count << 16 | word(IDENTIFY_SECTORS_48 + i));
+        let sectors_28 = word(IDENTIFY_SECTORS_28) | word(IDENTIFY_SECTORS_28 + 1) << 16;
+
+        self.info = BlockDeviceInfo {
+            sector_size: SECTOR_SIZE as u32,
+            flags: 0,
+            sector_count: if sectors_48 != 0 {
+                sectors_48
+            } else {
+                sectors_28
+            },
+        };
+
+        Ok(())
+    }
+
+    /// Issues a command which transfers `len` bytes to the buffer, or from it if writing, and
+    /// blocks until it completes
+    fn issue(
+        &mut self,
+        command: u8,
+        sector: u64,
+        count: u16,
+        len: usize,
+        writing: bool,
+    ) -> Result<(), SyscallError> {
+        let table = self.control_physical + COMMAND_TABLE_OFFSET as u64;
+        let prdt_len = if len == 0 { 0 } else { 1 };
+        let write_flag = if writing { HEADER_WRITE } else { 0 };
+
+        // The command header of slot 0
+        self.write_control(
+            COMMAND_LIST_OFFSET,
+            FIS_REGISTER_LEN | write_flag | prdt_len << 16,
+        );
+        self.write_control(COMMAND_LIST_OFFSET + 4, 0);
+        self.write_control(COMMAND_LIST_OFFSET + 8, table as u32);
+        self.write_control(COMMAND_LIST_OFFSET + 12, (table >> 32) as u32);
+
+        let fis: [u8; 20] = [
+            FIS_TYPE_REGISTER_HOST_TO_DEVICE,
+            FIS_COMMAND,
+            command,
+            0,
+            sector as u8,
+            (sector >> 8) as u8,
+            (sector >> 16) as u8,
+            ATA_DEVICE_LBA,
+            (sector >> 24) as u8,
+            (sector >> 32) as u8,
+            (sector >> 40) as u8,
+            0,
+            count as u8,
+            (count >> 8) as u8,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+
+        for (i, dword) in fis.chunks_exact(4).enumerate() {
+            let dword = u32::from_le_bytes([dword[0], dword[1], dword[2], dword[3]]);
+            self.write_control(COMMAND_TABLE_OFFSET + i * 4, dword);
+        }
+
+        let prd = COMMAND_TABLE_OFFSET + PRDT_OFFSET;
+        self.write_control(prd, self.buffer_physical as u32);
+        self.write_control(prd + 4, (self.buffer_physical >> 32) as u32);
+        self.write_control(prd + 8, 0);
+        self.write_control(prd + 12, (len as u32).wrapping_sub(1) | PRD_INTERRUPT);
+
+        self.write_register(PORT_COMMAND_ISSUE, 1);
+
+        loop {
+            syscall::notification_wait(self.notify)?;
+
+            // The IRQ is shared by every port, and so may have been another's
+            let status = self.read_register(PORT_INTERRUPT_STATUS);
+            self.write_register(PORT_INTERRUPT_STATUS, status);
+            write(self.hba, HBA_INTERRUPT_STATUS, 1 << self.index);
+            syscall::irq_ack(self.irq)?;
+
+            if status & INTERRUPT_TASK_FILE_ERROR != 0 {
+                return Err(SyscallError::IoError);
+            }
+
+            if self.read_register(PORT_COMMAND_ISSUE) & 1 == 0 {
+                break;
+            }
+        }
+
+        if self.read_register(PORT_TASK_FILE) & TASK_FILE_ERROR != 0 {
+            Err(SyscallError::IoError)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read_register(&self, register: usize) -> u32 {
+        read(self.registers, register)
+    }
+
+    fn write_register(&self, register: usize, value: u32) {
+        write(self.registers, register, value)
+    }
+
+    fn write_control(&self, offset: usize, value: u32) {
+        write(self.control, offset, value)
+    }
+}
+
+impl BlockDevice for AhciPort {
+    fn info(&self) -> BlockDeviceInfo {
+        self.info
+    }
+
+    fn read(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), SyscallError> {
+        check_transfer(&self.info, sector, buf.len())?;
+
+        for (i, chunk) in buf.chunks_mut(BUFFER_SIZE).enumerate() {
+            let sector = sector + (i * BUFFER_SIZE / SECTOR_SIZE) as u64;
+            let count = (chunk.len() / SECTOR_SIZE) as u16;
+            self.issue(ATA_READ_DMA_EXT, sector, count, chunk.len(), false)?;
+
+            // SAFETY: the chunk fits in the buffer
+            unsafe { ptr::copy_nonoverlapping(self.buffer, chunk.as_mut_ptr(), chunk.len()) };
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, sector: u64, buf: &[u8]) -> Result<(), SyscallError> {
+        check_transfer(&self.info, sector, buf.len())?;
+
+        for (i, chunk) in buf.chunks(BUFFER_SIZE).enumerate() {
+            let sector = sector + (i * BUFFER_SIZE / SECTOR_SIZE) as u64;
+            let count = (chunk.len() / SECTOR_SIZE) as u16;
+
+            // SAFETY: the chunk fits in the buffer
+            unsafe { ptr::copy_nonoverlapping(chunk.as_ptr(), self.buffer, chunk.len()) };
+            self.issue(ATA_WRITE_DMA_EXT, sector, count, chunk.len(), true)?;
+        }
+
+        self.issue(ATA_FLUSH_CACHE_EXT, 0, 0, 0, false)
+    }
+}
+
+fn read(base: *mut u8, offset: usize) -> u32 {
+    // SAFETY: registers and control structures are aligned, and within their mappings
+    unsafe { ptr::read_volatile(base.add(offset) as *const u32) }
+}
+
+fn write(base: *mut u8, offset: usize, value: u32) {
+    // SAFETY: registers and control structures are aligned, and within their mappings
+    unsafe { ptr::write_volatile(base.add(offset) as *mut u32, value) }
+}
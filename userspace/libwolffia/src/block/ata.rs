@@ -0,0 +1,380 @@
+//! ATA disks on IDE channels, transferred to and from with programmed IO. The controller must have
+//! been claimed with `syscall::pci_claim`, which grants its ports, including the legacy ones of
+//! channels in compatibility mode. Otherwise, a process which has been granted the legacy ports and
+//! IRQs can drive the channels at them without claiming anything.
+//! From: [OsDev Wiki](https://wiki.osdev.org/ATA_PIO_Mode)
+
+use super::{check_transfer, BlockDevice};
+use crate::port::Port;
+use crate::syscall::{self, BarInfo, BlockDeviceInfo, PciDeviceInfo, SyscallError};
+
+/// Offsets of the command block registers
+const DATA: u16 = 0;
+const SECTOR_COUNT: u16 = 2;
+const LBA_LOW: u16 = 3;
+const LBA_MID: u16 = 4;
+const LBA_HIGH: u16 = 5;
+const DEVICE: u16 = 6;
+const STATUS: u16 = 7;
+const COMMAND: u16 = 7;
+
+const STATUS_ERROR: u8 = 1;
+const STATUS_DATA_REQUEST: u8 = 1 << 3;
+const STATUS_DEVICE_FAULT: u8 = 1 << 5;
+const STATUS_BUSY: u8 = 1 << 7;
+
+/// Stops the device raising interrupts
+const CONTROL_NO_INTERRUPTS: u8 = 1 << 1;
+
+const DEVICE_LBA: u8 = 1 << 6;
+const DEVICE_SLAVE: u8 = 1 << 4;
+
+const COMMAND_READ_SECTORS: u8 = 0x20;
+const COMMAND_READ_SECTORS_EXT: u8 = 0x24;
+const COMMAND_WRITE_SECTORS: u8 = 0x30;
+const COMMAND_WRITE_SECTORS_EXT: u8 = 0x34;
+const COMMAND_FLUSH_CACHE: u8 = 0xe7;
+const COMMAND_FLUSH_CACHE_EXT: u8 = 0xea;
+const COMMAND_IDENTIFY: u8 = 0xec;
+
+/// Words of the identify data
+const IDENTIFY_GENERAL: usize = 0;
+const IDENTIFY_SECTORS_28: usize = 60;
+const IDENTIFY_FEATURES: usize = 83;
+const IDENTIFY_SECTORS_48: usize = 100;
+const GENERAL_NOT_ATA: u16 = 1 << 15;
+const FEATURES_LBA_48: u16 = 1 << 10;
+
+const SECTOR_SIZE: usize = 512;
+/// A sector count of 0 means 256
+const MAX_SECTORS: u64 = 256;
+const LBA_28_LIMIT: u64 = 1 << 28;
+
+/// The legacy ports and IRQ of the primary and secondary channels in compatibility mode
+const LEGACY_CHANNELS: [(u16, u16, u8); 2] = [(0x1f0, 0x3f6, 14), (0x170, 0x376, 15)];
+
+/// The name of the endpoint which the ATA driver serves its disks on. Each disk is numbered by its
+/// channel and then whether it is the slave, so the primary master is 0 and the secondary slave 3.
+pub const ENDPOINT: &str = "ata";
+/// The most disks which the legacy channels can have
+pub const MAX_DRIVES: usize = 4;
+
+/// One of the two channels of an IDE controller, each of which has up to two drives
+#[derive(Debug, Copy, Clone)]
+pub struct Channel {
+    /// The first of the command block ports
+    command: u16,
+    /// The device control and alternate status port
+    control: u16,
+    irq: u8,
+    /// The notification bits which the channel's IRQ is sent as
+    notify: u64,
+}
+
+impl Channel {
+    /// The primary (0) or secondary (1) channel of a claimed IDE controller. Its IRQ is sent as the
+    /// given notification bits.
+    pub fn of_controller(controller: &PciDeviceInfo, index: usize, notify: u64) -> Channel {
+        let native_mode = controller.prog_if & (1 << (index * 2)) != 0;
+        let command_bar = controller.bars[index * 2];
+        let control_bar = controller.bars[index * 2 + 1];
+
+        if native_mode && command_bar.kind == BarInfo::KIND_IO {
+            Channel {
+                command: command_bar.address as u16,
+                // The device control register is the third port of the BAR
+                control: control_bar.address as u16 + 2,
+                irq: controller.interrupt_line,
+                notify,
+            }
+        } else {
+            Channel::legacy(index, notify)
+        }
+    }
+
+    /// The primary (0) or secondary (1) channel at its legacy ports and IRQ, whose ports and IRQ
+    /// must have been granted to this process. Its IRQ is sent as the given notification bits.
+    pub fn legacy(index: usize, notify: u64) -> Channel {
+        let (command, control, irq) = LEGACY_CHANNELS[index];
+
+        Channel {
+            command,
+            control,
+            irq,
+            notify,
+        }
+    }
+
+    /// Listens to the channel's IRQ and finds the ATA drives on it. ATAPI drives, e.g CD drives,
+    /// are skipped.
+    pub fn drives(&self) -> Result<[Option<AtaDrive>; 2], SyscallError> {
+        syscall::irq_listen(self.irq, self.notify)?;
+
+        // Identify by polling, as a missing drive would never raise an interrupt
+        self.write_control(CONTROL_NO_INTERRUPTS);
+        let master = self.identify(false);
+        let slave = self.identify(true);
+        self.write_control(0);
+
+        Ok([master, slave])
+    }
+
+    fn identify(&self, slave: bool) -> Option<AtaDrive> {
+        self.select(slave, 0);
+        self.write(SECTOR_COUNT, 0);
+        self.write(LBA_LOW, 0);
+        self.write(LBA_MID, 0);
+        self.write(LBA_HIGH, 0);
+        self.write(COMMAND, COMMAND_IDENTIFY);
+
+        // Floating, so there is no drive
+        let status = self.read(STATUS);
+        if status == 0 || status == 0xff {
+            return None;
+        }
+
+        while self.read(STATUS) & STATUS_BUSY != 0 {}
+
+        // ATAPI and SATA drives set these to their signature, rather than identifying
+        if self.read(LBA_MID) != 0 || self.read(LBA_HIGH) != 0 {
+            return None;
+        }
+
+        let status = loop {
+            let status = self.read(STATUS);
+
+            if status & (STATUS_DATA_REQUEST | STATUS_ERROR) != 0 {
+                break status;
+            }
+        };
+
+        if status & STATUS_ERROR != 0 {
+            return None;
+        }
+
+        let mut identify = [0u16; 256];
+        let data = Port::<u16>::new(self.command + DATA);
+
+        for word in identify.iter_mut() {
+            // SAFETY: the data port was granted to this process
+            *word = unsafe { data.read() };
+        }
+
+        if identify[IDENTIFY_GENERAL] & GENERAL_NOT_ATA != 0 {
+            return None;
+        }
+
+        let lba_48 = identify[IDENTIFY_FEATURES] & FEATURES_LBA_48 != 0;
+        let sector_count = if lba_48 {
+            identify[IDENTIFY_SECTORS_48..IDENTIFY_SECTORS_48 + 4]
+                .iter()
+                .rev()
+                .fold(0, |count, word| count << 16 | *word as u64)
+        } else {
+            identify[IDENTIFY_SECTORS_28] as u64 | (identify[IDENTIFY_SECTORS_28 + 1] as u64) << 16
+        };
+
+        Some(AtaDrive {
+            channel: *self,
+            slave,
+            lba_48,
+            info: BlockDeviceInfo {
+                sector_size: SECTOR_SIZE as u32,
+                flags: 0,
+                sector_count,
+            },
+        })
+    }
+
+    /// Selects a drive, with the top bits of a 28 bit LBA, and waits for it to respond
+    fn select(&self, slave: bool, lba_top: u8) {
+        let slave = if slave { DEVICE_SLAVE } else { 0 };
+        self.write(DEVICE, DEVICE_LBA | slave | (lba_top & 0xf));
+
+        // Each read of the alternate status takes 100ns, and the drive needs 400ns to respond
+        for _ in 0..4 {
+            self.read_alternate_status();
+        }
+    }
+
+    /// Blocks until the selected drive raises an interrupt, then returns its status. Fails if the
+    /// drive reported an error.
+    fn wait(&self) -> Result<u8, SyscallError> {
+        syscall::notification_wait(self.notify)?;
+
+        // The interrupt may have been another drive's, if the IRQ is shared
+        while self.read_alternate_status() & STATUS_BUSY != 0 {}
+
+        // Reading the status acknowledges the interrupt
+        let status = self.read(STATUS);
+        syscall::irq_ack(self.irq)?;
+
+        if status & (STATUS_ERROR | STATUS_DEVICE_FAULT) != 0 {
+            Err(SyscallError::IoError)
+        } else {
+            Ok(status)
+        }
+    }
+
+    fn read(&self, register: u16) -> u8 {
+        // SAFETY: the command block was granted to this process
+        unsafe { Port::new(self.command + register).read() }
+    }
+
+    fn write(&self, register: u16, value: u8) {
+        // SAFETY: the command block was granted to this process
+        unsafe { Port::new(self.command + register).write(value) }
+    }
+
+    fn read_alternate_status(&self) -> u8 {
+        // SAFETY: the control port was granted to this process
+        unsafe { Port::new(self.control).read() }
+    }
+
+    fn write_control(&self, value: u8) {
+        // SAFETY: the control port was granted to this process
+        unsafe { Port::new(self.control).write(value) }
+    }
+}
+
+/// An ATA drive, as found by `Channel::drives`
+#[derive(Debug, Copy, Clone)]
+pub struct AtaDrive {
+    channel: Channel,
+    slave: bool,
+    /// Whether the drive supports 48 bit LBAs, for sectors past 128 GiB
+    lba_48: bool,
+    info: BlockDeviceInfo,
+}
+
+impl AtaDrive {
+    /// Issues a read or write of up to 256 sectors
+    fn command(&self, sector: u64, count: u64, write: bool) {
+        let channel = &self.channel;
+
+        if sector + count > LBA_28_LIMIT {
+            channel.select(self.slave, 0);
+
+            // The high bytes are written first, and then pushed back by the low bytes
+            channel.write(SECTOR_COUNT, (count >> 8) as u8);
+            channel.write(LBA_LOW, (sector >> 24) as u8);
+            channel.write(LBA_MID, (sector >> 32) as u8);
+            channel.write(LBA_HIGH, (sector >> 40) as u8);
+            self.write_lba_low(sector, count);
+
+            let command = if write {
+                COMMAND_WRITE_SECTORS_EXT
+            } else {
+                COMMAND_READ_SECTORS_EXT
+            };
+            channel.write(COMMAND, command);
+        } else {
+            channel.select(self.slave, (sector >> 24) as u8);
+            self.write_lba_low(sector, count);
+
+            let command = if write {
+                COMMAND_WRITE_SECTORS
+            } else {
+                COMMAND_READ_SECTORS
+            };
+            channel.write(COMMAND, command);
+        }
+    }
+
+    fn write_lba_low(&self, sector: u64, count: u64) {
+        // A count of 256 wraps to 0, which means 256
+        self.channel.write(SECTOR_COUNT, count as u8);
+        self.channel.write(LBA_LOW, sector as u8);
+        self.channel.write(LBA_MID, (sector >> 8) as u8);
+        self.channel.write(LBA_HIGH, (sector >> 16) as u8);
+    }
+
+    /// Waits for the drive to be ready for the next sector's data
+    fn wait_for_data_request(&self) -> Result<(), SyscallError> {
+        loop {
+            let status = self.channel.read_alternate_status();
+
+            if status & (STATUS_ERROR | STATUS_DEVICE_FAULT) != 0 {
+                return Err(SyscallError::IoError);
+            }
+
+            if status & (STATUS_BUSY | STATUS_DATA_REQUEST) == STATUS_DATA_REQUEST {
+                return Ok(());
+            }
+        }
+    }
+
+    fn flush(&self) -> Result<(), SyscallError> {
+        let command = if self.lba_48 {
+            COMMAND_FLUSH_CACHE_EXT
+        } else {
+            COMMAND_FLUSH_CACHE
+        };
+
+        self.channel.select(self.slave, 0);
+        self.channel.write(COMMAND, command);
+        self.channel.wait().map(|_| ())
+    }
+}
+
+impl BlockDevice for AtaDrive {
+    fn info(&self) -> BlockDeviceInfo {
+        self.info
+    }
+
+    fn read(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), SyscallError> {
+        check_transfer(&self.info, sector, buf.len())?;
+
+        let data = Port::<u16>::new(self.channel.command + DATA);
+
+        for (i, chunk) in buf
+            .chunks_mut(MAX_SECTORS as usize * SECTOR_SIZE)
+            .enumerate()
+        {
+            let count = (chunk.len() / SECTOR_SIZE) as u64;
+            self.command(sector + i as u64 * MAX_SECTORS, count, false);
+
+            // The drive interrupts once each sector is ready to be read
+            for sector in chunk.chunks_exact_mut(SECTOR_SIZE) {
+                let status = self.channel.wait()?;
+
+                if status & STATUS_DATA_REQUEST == 0 {
+                    return Err(SyscallError::IoError);
+                }
+
+                for word in sector.chunks_exact_mut(2) {
+                    // SAFETY: the data port was granted to this process
+                    word.copy_from_slice(&unsafe { data.read() }.to_le_bytes());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, sector: u64, buf: &[u8]) -> Result<(), SyscallError> {
+        check_transfer(&self.info, sector, buf.len())?;
+
+        let data = Port::<u16>::new(self.channel.command + DATA);
+
+        for (i, chunk) in buf.chunks(MAX_SECTORS as usize * SECTOR_SIZE).enumerate() {
+            let count = (chunk.len() / SECTOR_SIZE) as u64;
+            self.command(sector + i as u64 * MAX_SECTORS, count, true);
+
+            // The drive asks for the first sector straight away, and interrupts once each sector
+            // has been written
+            for sector in chunk.chunks_exact(SECTOR_SIZE) {
+                self.wait_for_data_request()?;
+
+                for word in sector.chunks_exact(2) {
+                    // SAFETY: the data port was granted to this process
+                    unsafe { data.write(u16::from_le_bytes([word[0], word[1]])) };
+                }
+
+                self.channel.wait()?;
+            }
+        }
+
+        self.flush()
+    }
+}
@@ -0,0 +1,71 @@
+//! Serving disks to other processes, as a driver process does with those it finds. The disks are
+//! numbered by their places in the slice handed to `serve`, which may have gaps, e.g so that an
+//! AHCI controller's disks are numbered by port.
+
+use super::{
+    encode_info, BlockDevice, Op, Request, MAX_TRANSFER, MESSAGE_SIZE, REPLY_SIZE, REQUEST_SIZE,
+};
+use crate::ipc::{Endpoint, RECEIVE_HEADER_SIZE};
+use crate::syscall::SyscallError;
+
+/// Receives requests on the endpoint and answers them from the disks, forever. Only returns if
+/// receiving or replying fails.
+pub fn serve<D: BlockDevice>(endpoint: &Endpoint, disks: &mut [Option<D>]) -> SyscallError {
+    let mut request_buf = [0; RECEIVE_HEADER_SIZE + MESSAGE_SIZE];
+    let mut reply_buf = [0; MESSAGE_SIZE];
+
+    loop {
+        let received = match endpoint.receive(&mut request_buf) {
+            Ok(received) => received,
+            Err(err) => return err,
+        };
+
+        let payload = &mut reply_buf[REPLY_SIZE..];
+        let res = match Request::decode(received.request) {
+            Some(request) => handle(disks, request, &received.request[REQUEST_SIZE..], payload),
+            None => Err(SyscallError::InvalidArgument),
+        };
+
+        let (status, payload_len) = match res {
+            Ok(len) => (0, len),
+            Err(err) => (err.code(), 0),
+        };
+
+        reply_buf[..REPLY_SIZE].copy_from_slice(&status.to_le_bytes());
+
+        if let Err(err) = received.call.reply(&reply_buf[..REPLY_SIZE + payload_len]) {
+            return err;
+        }
+    }
+}
+
+/// Answers one request. Returns the length of the payload written to the buffer.
+fn handle<D: BlockDevice>(
+    disks: &mut [Option<D>],
+    request: Request,
+    payload: &[u8],
+    reply_payload: &mut [u8],
+) -> Result<usize, SyscallError> {
+    let op = Op::from_u32(request.op).ok_or(SyscallError::InvalidArgument)?;
+    let disk = disks
+        .get_mut(request.disk as usize)
+        .and_then(Option::as_mut);
+    let disk = disk.ok_or(SyscallError::NotFound)?;
+
+    match op {
+        Op::Info => Ok(encode_info(&disk.info(), reply_payload)),
+        Op::Read => {
+            if request.len > MAX_TRANSFER as u64 {
+                return Err(SyscallError::InvalidArgument);
+            }
+
+            let len = request.len as usize;
+            disk.read(request.sector, &mut reply_payload[..len])?;
+            Ok(len)
+        }
+        Op::Write => {
+            disk.write(request.sector, payload)?;
+            Ok(0)
+        }
+    }
+}
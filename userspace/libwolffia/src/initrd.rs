@@ -0,0 +1,216 @@
+//! Reading the files in the initial ramdisk, a ustar or newc cpio archive which the kernel maps
+//! read-only into processes allowed to see it.
+
+use crate::syscall::{self, SyscallError};
+use core::slice;
+use core::str;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Where `map` maps the initrd
+pub const INITRD_ADDRESS: u64 = 0x4000_0000_0000;
+
+/// The length of the initrd once it has been mapped by this process
+static MAPPED_LEN: AtomicU64 = AtomicU64::new(0);
+
+const TAR_BLOCK_SIZE: usize = 512;
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_MAGIC_OFFSET: usize = 257;
+const TAR_TYPE_REGULAR: u8 = b'0';
+/// Regular files in archives from before ustar have no type
+const TAR_TYPE_REGULAR_OLD: u8 = 0;
+
+const CPIO_MAGIC: &[u8] = b"070701";
+/// The same format, but with checksums, which are not checked
+const CPIO_MAGIC_CRC: &[u8] = b"070702";
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+const CPIO_MODE_TYPE_MASK: u32 = 0o170_000;
+const CPIO_MODE_REGULAR: u32 = 0o100_000;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Format {
+    Tar,
+    Cpio,
+}
+
+/// An archive of files, such as the initrd
+#[derive(Debug, Copy, Clone)]
+pub struct Archive<'a> {
+    data: &'a [u8],
+    format: Format,
+}
+
+/// A regular file in an archive
+#[derive(Debug, Copy, Clone)]
+pub struct File<'a> {
+    pub name: &'a str,
+    pub data: &'a [u8],
+}
+
+/// Maps the initrd at `INITRD_ADDRESS`, or returns it if it is already mapped. Fails with
+/// `SyscallError::InvalidArgument` if it is not an archive.
+pub fn map() -> Result<Archive<'static>, SyscallError> {
+    let mut len = MAPPED_LEN.load(Ordering::Acquire);
+
+    if len == 0 {
+        len = syscall::initrd_map(INITRD_ADDRESS as *mut u8)?;
+        MAPPED_LEN.store(len, Ordering::Release);
+    }
+
+    // SAFETY: the kernel mapped the initrd here, and it is never unmapped or written to
+    let data = unsafe { slice::from_raw_parts(INITRD_ADDRESS as *const u8, len as usize) };
+    Archive::new(data).ok_or(SyscallError::InvalidArgument)
+}
+
+impl<'a> Archive<'a> {
+    /// Returns None if the data isn't a ustar or newc cpio archive
+    pub fn new(data: &'a [u8]) -> Option<Archive<'a>> {
+        let format = if data.starts_with(CPIO_MAGIC) || data.starts_with(CPIO_MAGIC_CRC) {
+            Format::Cpio
+        } else if data.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len()) == Some(TAR_MAGIC)
+        {
+            Format::Tar
+        } else {
+            return None;
+        };
+
+        Some(Archive { data, format })
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Iterates over the regular files in the archive. Stops at the end of the archive, or at the
+    /// first malformed entry.
+    pub fn files(&self) -> Files<'a> {
+        Files {
+            data: self.data,
+            offset: 0,
+            format: self.format,
+        }
+    }
+
+    /// Finds the file with the given name. A leading `/` or `./` is ignored in both.
+    pub fn find(&self, name: &str) -> Option<File<'a>> {
+        let name = strip_root(name);
+        self.files().find(|file| strip_root(file.name) == name)
+    }
+}
+
+/// Removes a leading `/` or `./` from a file's name
+pub fn strip_root(name: &str) -> &str {
+    name.trim_start_matches("./").trim_start_matches('/')
+}
+
+pub struct Files<'a> {
+    data: &'a [u8],
+    offset: usize,
+    format: Format,
+}
+
+impl<'a> Iterator for Files<'a> {
+    type Item = File<'a>;
+
+    fn next(&mut self) -> Option<File<'a>> {
+        loop {
+            let (file, next) = match self.format {
+                Format::Tar => self.next_tar()?,
+                Format::Cpio => self.next_cpio()?,
+            };
+
+            self.offset = next;
+
+            if let Some(file) = file {
+                return Some(file);
+            }
+        }
+    }
+}
+
+impl<'a> Files<'a> {
+    /// Parses the entry at the offset, returning it if it is a regular file, and the offset of the
+    /// next entry. Returns None at the end of the archive.
+    fn next_tar(&self) -> Option<(Option<File<'a>>, usize)> {
+        let header = self.data.get(self.offset..self.offset + TAR_BLOCK_SIZE)?;
+
+        // The archive ends with zeroed blocks
+        if header.iter().all(|&b| b == 0) {
+            return None;
+        }
+
+        let name = c_str(&header[0..100])?;
+        let size = parse_number(&header[124..136], 8)? as usize;
+        let kind = header[156];
+
+        // Sizes come from the archive, so are checked not to overflow
+        let data_start = self.offset + TAR_BLOCK_SIZE;
+        let data = self.data.get(data_start..data_start.checked_add(size)?)?;
+        let next = data_start.checked_add(round_up(size, TAR_BLOCK_SIZE)?)?;
+
+        let file = match kind {
+            TAR_TYPE_REGULAR | TAR_TYPE_REGULAR_OLD => Some(File { name, data }),
+            _ => None,
+        };
+
+        Some((file, next))
+    }
+
+    fn next_cpio(&self) -> Option<(Option<File<'a>>, usize)> {
+        let header = self.data.get(self.offset..self.offset + CPIO_HEADER_SIZE)?;
+
+        if !header.starts_with(CPIO_MAGIC) && !header.starts_with(CPIO_MAGIC_CRC) {
+            return None;
+        }
+
+        // Each field is 8 hex digits, after the 6 byte magic
+        let field = |index: usize| parse_number(&header[6 + index * 8..6 + (index + 1) * 8], 16);
+        let mode = field(1)? as u32;
+        let size = field(6)? as usize;
+        let name_size = field(11)? as usize;
+
+        let name_start = self.offset + CPIO_HEADER_SIZE;
+        let name_end = name_start.checked_add(name_size)?;
+        let name = c_str(self.data.get(name_start..name_end)?)?;
+
+        if name == CPIO_TRAILER {
+            return None;
+        }
+
+        // The header and name, and the data, are each padded to 4 bytes
+        let data_start = round_up(name_end, 4)?;
+        let data_end = data_start.checked_add(size)?;
+        let data = self.data.get(data_start..data_end)?;
+        let next = round_up(data_end, 4)?;
+
+        let file = if mode & CPIO_MODE_TYPE_MASK == CPIO_MODE_REGULAR {
+            Some(File { name, data })
+        } else {
+            None
+        };
+
+        Some((file, next))
+    }
+}
+
+/// Parses a string which is either NUL terminated or fills the whole field
+fn c_str(field: &[u8]) -> Option<&str> {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    str::from_utf8(&field[..len]).ok()
+}
+
+/// Parses a number in the given radix, ignoring the NULs and spaces which pad tar fields
+fn parse_number(field: &[u8], radix: u32) -> Option<u64> {
+    let digits = c_str(field)?.trim_matches(' ');
+
+    if digits.is_empty() {
+        return Some(0);
+    }
+
+    u64::from_str_radix(digits, radix).ok()
+}
+
+/// Returns None if the result would overflow
+fn round_up(value: usize, multiple: usize) -> Option<usize> {
+    Some(value.checked_add(multiple - 1)? / multiple * multiple)
+}
@@ -0,0 +1,108 @@
+//! Streams of bytes read and written through file descriptors. Every process has a standard input,
+//! output and error, which it inherits from the process which spawned it unless it was given
+//! others, e.g the ends of pipes. Those of `init` are the console, which has nothing to read.
+
+use crate::syscall::{self, SyscallError};
+use core::fmt;
+use core::mem;
+
+pub const STDIN: u64 = 0;
+pub const STDOUT: u64 = 1;
+pub const STDERR: u64 = 2;
+
+/// The standard input of this process
+#[derive(Debug, Copy, Clone)]
+pub struct Stdin;
+
+/// The standard output of this process, which `print!` writes to
+#[derive(Debug, Copy, Clone)]
+pub struct Stdout;
+
+/// The standard error of this process, which `eprint!` and panics write to
+#[derive(Debug, Copy, Clone)]
+pub struct Stderr;
+
+impl Stdin {
+    /// Reads into the buffer, blocking until there is data. Returns how many bytes were read,
+    /// which is 0 at the end of the input.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, SyscallError> {
+        syscall::read(STDIN, buf)
+    }
+}
+
+impl Stdout {
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize, SyscallError> {
+        syscall::write(STDOUT, buf)
+    }
+}
+
+impl Stderr {
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize, SyscallError> {
+        syscall::write(STDERR, buf)
+    }
+}
+
+impl fmt::Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes()).map(|_| ()).map_err(|_| fmt::Error)
+    }
+}
+
+impl fmt::Write for Stderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes()).map(|_| ()).map_err(|_| fmt::Error)
+    }
+}
+
+/// A file descriptor which this process owns. It is closed when it is dropped.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Fd(u64);
+
+impl Fd {
+    /// Takes ownership of one of this process's file descriptors
+    pub fn from_raw(fd: u64) -> Fd {
+        Fd(fd)
+    }
+
+    pub fn as_raw(&self) -> u64 {
+        self.0
+    }
+
+    /// Gives up ownership of the descriptor without closing it
+    pub fn into_raw(self) -> u64 {
+        let fd = self.0;
+        mem::forget(self);
+        fd
+    }
+
+    /// Reads into the buffer, blocking until there is data. Returns how many bytes were read,
+    /// which is 0 at the end of the file.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, SyscallError> {
+        syscall::read(self.0, buf)
+    }
+
+    /// Writes all of the buffer, blocking until it is written. Fails with
+    /// `SyscallError::BrokenPipe` if the descriptor is a pipe which nothing reads.
+    pub fn write(&self, buf: &[u8]) -> Result<usize, SyscallError> {
+        syscall::write(self.0, buf)
+    }
+}
+
+impl fmt::Write for Fd {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes()).map(|_| ()).map_err(|_| fmt::Error)
+    }
+}
+
+impl Drop for Fd {
+    fn drop(&mut self) {
+        let _ = syscall::close(self.0);
+    }
+}
+
+/// Creates a pipe, returning its read and write ends. Reads see the end of the file once every
+/// descriptor of the write end is closed, including those held by spawned processes.
+pub fn pipe() -> Result<(Fd, Fd), SyscallError> {
+    let [read, write] = syscall::pipe()?;
+    Ok((Fd(read), Fd(write)))
+}
@@ -0,0 +1,97 @@
+//! Synchronous message passing. A server creates an endpoint and receives calls on it, replying to
+//! each, while clients call it and block until they get the reply. Messages are copied by the
+//! kernel, so neither side needs to share memory with the other.
+
+use crate::syscall::{self, SyscallError};
+use core::convert::TryInto;
+
+/// The longest request or reply
+pub const MAX_MESSAGE: usize = 64 * 1024;
+/// The longest name which an endpoint can be given
+pub const MAX_NAME: usize = 64;
+/// The size of the call id and process id which precede a received request
+pub const RECEIVE_HEADER_SIZE: usize = 16;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Endpoint(u64);
+
+/// A call which has been received, and must be replied to
+#[derive(Debug, Eq, PartialEq)]
+pub struct Call(u64);
+
+/// A request received on an endpoint
+#[derive(Debug)]
+pub struct Received<'a> {
+    pub call: Call,
+    /// The id of the calling process
+    pub sender: u64,
+    pub request: &'a [u8],
+}
+
+impl Endpoint {
+    /// Creates an endpoint which other processes can look up by the given name. Fails with
+    /// `SyscallError::Busy` if another endpoint has the name.
+    pub fn create(name: &str) -> Result<Endpoint, SyscallError> {
+        if name.is_empty() {
+            return Err(SyscallError::InvalidArgument);
+        }
+
+        syscall::endpoint_create(name).map(Endpoint)
+    }
+
+    /// Creates an endpoint without a name, which other processes must be told the id of
+    pub fn create_anonymous() -> Result<Endpoint, SyscallError> {
+        syscall::endpoint_create("").map(Endpoint)
+    }
+
+    pub fn lookup(name: &str) -> Result<Endpoint, SyscallError> {
+        syscall::endpoint_lookup(name).map(Endpoint)
+    }
+
+    pub fn from_id(id: u64) -> Endpoint {
+        Endpoint(id)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+
+    /// Calls the endpoint, blocking until it replies. Returns the length of the reply, which is
+    /// truncated to fit the buffer.
+    pub fn call(&self, request: &[u8], reply: &mut [u8]) -> Result<usize, SyscallError> {
+        syscall::ipc_call(self.0, request, reply)
+    }
+
+    /// Blocks until a call is made to the endpoint, which must have been created by this process.
+    /// The request is received into the buffer after a `RECEIVE_HEADER_SIZE` byte header, and is
+    /// truncated to fit.
+    pub fn receive<'a>(&self, buf: &'a mut [u8]) -> Result<Received<'a>, SyscallError> {
+        self.receive_within(buf, None)
+    }
+
+    /// Like `receive`, but gives up with `SyscallError::TimedOut` if no call is made within
+    /// `timeout_ms` milliseconds, or waits forever given `None`
+    pub fn receive_within<'a>(
+        &self,
+        buf: &'a mut [u8],
+        timeout_ms: Option<u64>,
+    ) -> Result<Received<'a>, SyscallError> {
+        let len = syscall::ipc_receive(self.0, buf, timeout_ms)?;
+        let call = u64::from_le_bytes(buf[0..8].try_into().unwrap());
+        let sender = u64::from_le_bytes(buf[8..16].try_into().unwrap());
+
+        Ok(Received {
+            call: Call(call),
+            sender,
+            request: &buf[RECEIVE_HEADER_SIZE..RECEIVE_HEADER_SIZE + len],
+        })
+    }
+}
+
+impl Call {
+    /// Replies to the call, waking the caller. The reply is truncated to the length of the caller's
+    /// buffer.
+    pub fn reply(self, reply: &[u8]) -> Result<(), SyscallError> {
+        syscall::ipc_reply(self.0, reply)
+    }
+}
@@ -1,11 +1,20 @@
 #![feature(asm, lang_items, panic_info_message)]
 #![no_std]
 
+pub mod block;
+pub mod initrd;
+pub mod io;
+pub mod ipc;
+pub mod memory;
+pub mod net;
+pub mod port;
 pub mod syscall;
+pub mod vfs;
 
 use core::panic::PanicInfo;
-use core::fmt::{self, Write};
+use core::fmt::Write;
 
+pub use io::{Stderr, Stdin, Stdout};
 pub use libwolffia_macros::*;
 
 #[macro_export]
@@ -18,12 +27,28 @@ macro_rules! print {
 
 #[macro_export]
 macro_rules! println {
+    () => (print!("\n"));
     ($fmt:expr) => (print!(concat!($fmt, "\n")));
     ($fmt:expr, $($arg:tt)*) => (print!(concat!($fmt, "\n"), $($arg)*));
 }
 
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => ({
+        use ::core::fmt::Write;
+        write!(&mut $crate::Stderr, $($arg)*).unwrap();
+    });
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    () => (eprint!("\n"));
+    ($fmt:expr) => (eprint!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => (eprint!(concat!($fmt, "\n"), $($arg)*));
+}
+
 pub mod prelude {
-    pub use crate::{print, println};
+    pub use crate::{eprint, eprintln, print, println};
 }
 
 #[lang = "eh_personality"]
@@ -39,7 +64,7 @@ fn panic_fmt(info: &PanicInfo) -> ! {
 
     if let Some(loc) = info.location() {
         let _ = write!(
-            &mut Stdout,
+            &mut Stderr,
             "Panicked at \"{}\", {file}:{line}",
             arguments,
             file = loc.file(),
@@ -47,19 +72,11 @@ fn panic_fmt(info: &PanicInfo) -> ! {
         );
     } else {
         let _ = write!(
-            &mut Stdout,
+            &mut Stderr,
             "Panicked at \"{}\" at an undefined location",
             arguments
         );
     }
 
-    syscall::halt()
-}
-
-pub struct Stdout;
-
-impl Write for Stdout {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        syscall::print(s).map_err(|_| fmt::Error)
-    }
+    syscall::exit(101)
 }
@@ -0,0 +1,86 @@
+//! Memory objects, whose pages the kernel maps lazily, faulting each in when it is first accessed.
+//! An object is either zeroed memory, or an open file, whose pages are read through the kernel's
+//! page cache and written back to the file when they are unmapped or synced.
+//!
+//! Buffers in mappings can be passed to system calls before they have been touched, as the kernel
+//! faults their pages in as it checks them.
+
+use crate::syscall::{self, SyscallError, UserPageFlags};
+use core::mem;
+
+/// A memory object which this process created. It is released when it is dropped, but its pages
+/// stay mapped until their mappings are dropped too.
+#[derive(Debug, Eq, PartialEq)]
+pub struct MemoryObject(u64);
+
+/// Pages of a memory object which are mapped into this process. They are unmapped when it is
+/// dropped.
+#[derive(Debug)]
+pub struct Mapping {
+    at: *mut u8,
+    pages: usize,
+}
+
+impl MemoryObject {
+    /// Creates an object of zeroed pages
+    pub fn create(pages: usize) -> Result<MemoryObject, SyscallError> {
+        syscall::object_create(pages).map(MemoryObject)
+    }
+
+    /// Takes ownership of an object which this process created, by its id
+    pub fn from_id(id: u64) -> MemoryObject {
+        MemoryObject(id)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+
+    /// Maps pages of the object at the page aligned address, starting at the page aligned offset
+    /// into the object. The pages must not already be mapped.
+    pub fn map(
+        &self,
+        at: *mut u8,
+        pages: usize,
+        offset: u64,
+        flags: UserPageFlags,
+    ) -> Result<Mapping, SyscallError> {
+        syscall::map_object(at, pages, flags, self.0, offset)?;
+        Ok(Mapping { at, pages })
+    }
+}
+
+impl Drop for MemoryObject {
+    fn drop(&mut self) {
+        let _ = syscall::object_release(self.0);
+    }
+}
+
+impl Mapping {
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.at
+    }
+
+    pub fn pages(&self) -> usize {
+        self.pages
+    }
+
+    /// Writes back the pages which have been written to, if the object is a file
+    pub fn sync(&self) -> Result<(), SyscallError> {
+        syscall::msync(self.at, self.pages)
+    }
+
+    /// Unmaps the pages, returning whether they could be written back. Dropping the mapping unmaps
+    /// them too, but ignores any error.
+    pub fn unmap(self) -> Result<(), SyscallError> {
+        let res = syscall::unmap(self.at, self.pages);
+        mem::forget(self);
+        res
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        let _ = syscall::unmap(self.at, self.pages);
+    }
+}
@@ -0,0 +1,135 @@
+//! Sending and receiving raw Ethernet frames on a network device, through a pair of frame rings
+//! which the kernel maps into the process when it attaches to the device.
+
+use crate::syscall::{self, SyscallError};
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Indices of the shared header's fields, which are each a u32
+const RX_HEAD: usize = 0;
+const RX_TAIL: usize = 1;
+const TX_HEAD: usize = 2;
+const TX_TAIL: usize = 3;
+const SLOT_COUNT: usize = 4;
+const SLOT_SIZE: usize = 5;
+
+/// The header is in its own page, followed by the receive ring's slots and then the transmit ring's
+const HEADER_SIZE: usize = 4096;
+
+/// A network device which this process is attached to
+pub struct Interface {
+    device: usize,
+    base: *mut u8,
+    notify: u64,
+    slot_count: u32,
+    slot_size: u32,
+}
+
+impl Interface {
+    /// Attaches to a device, mapping its rings at the given page aligned address. `notify` is the
+    /// notification bits which are signalled when frames arrive.
+    pub fn attach(device: usize, at: *mut u8, notify: u64) -> Result<Interface, SyscallError> {
+        syscall::net_attach(device, at, notify)?;
+
+        let mut interface = Interface {
+            device,
+            base: at,
+            notify,
+            slot_count: 0,
+            slot_size: 0,
+        };
+
+        interface.slot_count = interface.field(SLOT_COUNT).load(Ordering::Relaxed);
+        interface.slot_size = interface.field(SLOT_SIZE).load(Ordering::Relaxed);
+
+        Ok(interface)
+    }
+
+    /// The largest frame which can be sent
+    pub fn max_frame_size(&self) -> usize {
+        self.slot_size as usize - 4
+    }
+
+    /// Copies the next received frame into the buffer, returning its length, or None if no frames
+    /// have arrived. Frames longer than the buffer are truncated.
+    pub fn receive(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let tail = self.field(RX_TAIL).load(Ordering::Relaxed);
+        let head = self.field(RX_HEAD).load(Ordering::Acquire);
+
+        if tail == head {
+            return None;
+        }
+
+        let slot = self.slot(0, tail);
+
+        // SAFETY: the kernel only writes lengths which fit in the slot
+        let len = unsafe {
+            let len = ptr::read_volatile(slot as *const u32) as usize;
+            let copied = len.min(buf.len());
+            ptr::copy_nonoverlapping(slot.add(4), buf.as_mut_ptr(), copied);
+            copied
+        };
+
+        self.field(RX_TAIL)
+            .store(tail.wrapping_add(1), Ordering::Release);
+        Some(len)
+    }
+
+    /// Blocks until a frame arrives, then copies it into the buffer as with `receive`
+    pub fn receive_blocking(&mut self, buf: &mut [u8]) -> Result<usize, SyscallError> {
+        loop {
+            match self.receive(buf) {
+                Some(len) => return Ok(len),
+                None => self.wait()?,
+            }
+        }
+    }
+
+    /// Queues a frame and asks the kernel to send it. Fails with `SyscallError::Busy` if the
+    /// transmit ring is full, or `SyscallError::InvalidArgument` if the frame is too large.
+    pub fn send(&mut self, frame: &[u8]) -> Result<(), SyscallError> {
+        if frame.len() > self.max_frame_size() {
+            return Err(SyscallError::InvalidArgument);
+        }
+
+        let head = self.field(TX_HEAD).load(Ordering::Relaxed);
+        let tail = self.field(TX_TAIL).load(Ordering::Acquire);
+
+        if head.wrapping_sub(tail) >= self.slot_count {
+            return Err(SyscallError::Busy);
+        }
+
+        let slot = self.slot(1, head);
+
+        // SAFETY: the frame fits in the slot after its length
+        unsafe {
+            ptr::write_volatile(slot as *mut u32, frame.len() as u32);
+            ptr::copy_nonoverlapping(frame.as_ptr(), slot.add(4), frame.len());
+        }
+
+        self.field(TX_HEAD)
+            .store(head.wrapping_add(1), Ordering::Release);
+        syscall::net_transmit(self.device).map(|_| ())
+    }
+
+    /// Blocks until frames arrive. Other notification bits are left alone.
+    pub fn wait(&self) -> Result<(), SyscallError> {
+        syscall::notification_wait(self.notify).map(|_| ())
+    }
+
+    fn field(&self, field: usize) -> &AtomicU32 {
+        // SAFETY: the header is mapped for as long as the process lives, and its fields are aligned
+        unsafe { &*(self.base.add(field * 4) as *const AtomicU32) }
+    }
+
+    /// Returns the slot of the receive ring (0) or the transmit ring (1) with the given index
+    fn slot(&self, ring: u32, index: u32) -> *mut u8 {
+        let slot = ring * self.slot_count + index % self.slot_count;
+
+        // SAFETY: the slot is within the mapped rings
+        unsafe {
+            self.base
+                .add(HEADER_SIZE + slot as usize * self.slot_size as usize)
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Access to io ports which the kernel has granted this process, e.g by claiming a PCI function.
+//! Accessing any other port faults.
+
+use core::marker::PhantomData;
+
+/// The widths which io ports can be accessed with
+pub trait PortValue: Copy {
+    /// # Safety
+    ///
+    /// The port must have been granted to the process
+    unsafe fn read_from(port: u16) -> Self;
+
+    /// # Safety
+    ///
+    /// The port must have been granted to the process
+    unsafe fn write_to(port: u16, value: Self);
+}
+
+impl PortValue for u8 {
+    unsafe fn read_from(port: u16) -> u8 {
+        let value: u8;
+        asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack));
+        value
+    }
+
+    unsafe fn write_to(port: u16, value: u8) {
+        asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack));
+    }
+}
+
+impl PortValue for u16 {
+    unsafe fn read_from(port: u16) -> u16 {
+        let value: u16;
+        asm!("in ax, dx", out("ax") value, in("dx") port, options(nomem, nostack));
+        value
+    }
+
+    unsafe fn write_to(port: u16, value: u16) {
+        asm!("out dx, ax", in("dx") port, in("ax") value, options(nomem, nostack));
+    }
+}
+
+impl PortValue for u32 {
+    unsafe fn read_from(port: u16) -> u32 {
+        let value: u32;
+        asm!("in eax, dx", out("eax") value, in("dx") port, options(nomem, nostack));
+        value
+    }
+
+    unsafe fn write_to(port: u16, value: u32) {
+        asm!("out dx, eax", in("dx") port, in("eax") value, options(nomem, nostack));
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Port<T: PortValue> {
+    port: u16,
+    _value: PhantomData<T>,
+}
+
+impl<T: PortValue> Port<T> {
+    pub fn new(port: u16) -> Port<T> {
+        Port {
+            port,
+            _value: PhantomData,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The port must have been granted to the process, and reading it must not break any of the
+    /// device's invariants
+    pub unsafe fn read(&self) -> T {
+        T::read_from(self.port)
+    }
+
+    /// # Safety
+    ///
+    /// The port must have been granted to the process, and writing it must not break any of the
+    /// device's invariants
+    pub unsafe fn write(&self, value: T) {
+        T::write_to(self.port, value)
+    }
+}
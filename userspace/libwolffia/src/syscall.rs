@@ -5,17 +5,89 @@ pub enum Syscall {
     Map = 1,
     Unmap = 2,
     Print = 3,
+    ClockGet = 4,
+    Sleep = 5,
+    SleepUntil = 6,
+    Shutdown = 7,
+    Reboot = 8,
+    PciDevices = 9,
+    PciClaim = 10,
+    PciMapBar = 11,
+    InputRead = 12,
+    InputWait = 13,
+    InitrdMap = 14,
+    BlockDevices = 15,
+    BlockRead = 16,
+    BlockWrite = 17,
+    NetDevices = 18,
+    NetAttach = 19,
+    NetTransmit = 20,
+    NotificationWait = 21,
+    IrqListen = 22,
+    IrqAck = 23,
+    DmaMap = 24,
+    EndpointCreate = 25,
+    EndpointLookup = 26,
+    IpcCall = 27,
+    IpcReceive = 28,
+    IpcReply = 29,
+    ObjectCreate = 30,
+    ObjectFromFile = 31,
+    ObjectRelease = 32,
+    Msync = 33,
+    Read = 34,
+    Write = 35,
+    Close = 36,
+    Pipe = 37,
+    Spawn = 38,
+    Exit = 39,
+    Kill = 40,
+    Wait = 41,
+    ProcessList = 42,
+    MemoryInfo = 43,
+    WaitAny = 44,
+    IoPortGrant = 45,
+    IrqGrant = 46,
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum SyscallError {
     InvalidBuffer,
     InvalidUtf8,
     InvalidPage,
     InvalidPagesLength,
     OutOfMemory,
+    InvalidArgument,
+    PermissionDenied,
+    NotFound,
+    Busy,
+    IoError,
+    BrokenPipe,
+    TimedOut,
     UnknownError(i64),
 }
 
+impl SyscallError {
+    /// The code which the kernel returns for this error, as read by `res_from_code`
+    pub fn code(self) -> i64 {
+        match self {
+            SyscallError::InvalidBuffer => -1,
+            SyscallError::InvalidUtf8 => -2,
+            SyscallError::InvalidPage => -3,
+            SyscallError::InvalidPagesLength => -4,
+            SyscallError::OutOfMemory => -5,
+            SyscallError::InvalidArgument => -6,
+            SyscallError::PermissionDenied => -7,
+            SyscallError::NotFound => -8,
+            SyscallError::Busy => -9,
+            SyscallError::IoError => -10,
+            SyscallError::BrokenPipe => -11,
+            SyscallError::TimedOut => -12,
+            SyscallError::UnknownError(code) => code,
+        }
+    }
+}
+
 bitflags::bitflags! {
      pub struct UserPageFlags: u64 {
         const WRITABLE = 1;
@@ -23,13 +95,454 @@ bitflags::bitflags! {
      }
 }
 
+bitflags::bitflags! {
+    /// Privileged operations which a process is allowed to perform. A process can only give a
+    /// process which it spawns capabilities which it has itself.
+    pub struct Capabilities: u64 {
+        /// Shutting down and rebooting the machine
+        const POWER = 1;
+        /// Claiming PCI devices, accessing their BARs, handling their interrupts and mapping memory
+        /// for them to access directly
+        const DEVICES = 1 << 1;
+        /// Reading keyboard and mouse input
+        const INPUT = 1 << 2;
+        /// Mapping the initial ramdisk
+        const INITRD = 1 << 3;
+        /// Reading and writing block devices, e.g disks
+        const STORAGE = 1 << 4;
+        /// Sending and receiving raw frames on network devices
+        const NETWORK = 1 << 5;
+        /// Killing processes which it didn't spawn, directly or otherwise
+        const KILL = 1 << 6;
+    }
+}
+
+impl Capabilities {
+    /// Looks up a capability by its name in lowercase, e.g `devices` for `DEVICES`, or `all` for
+    /// every capability
+    pub fn from_name(name: &str) -> Option<Capabilities> {
+        match name {
+            "power" => Some(Capabilities::POWER),
+            "devices" => Some(Capabilities::DEVICES),
+            "input" => Some(Capabilities::INPUT),
+            "initrd" => Some(Capabilities::INITRD),
+            "storage" => Some(Capabilities::STORAGE),
+            "network" => Some(Capabilities::NETWORK),
+            "kill" => Some(Capabilities::KILL),
+            "all" => Some(Capabilities::all()),
+            _ => None,
+        }
+    }
+}
+
+#[repr(u64)]
+pub enum Clock {
+    /// Milliseconds since the unix epoch
+    Realtime = 0,
+    /// Milliseconds since boot
+    Monotonic = 1,
+}
+
+/// The location of a PCI function, packed as `segment:16 bus:8 device:5 function:3`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct PciAddress(pub u32);
+
+impl PciAddress {
+    pub fn segment(self) -> u16 {
+        (self.0 >> 16) as u16
+    }
+
+    pub fn bus(self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    pub fn device(self) -> u8 {
+        (self.0 >> 3) as u8 & 0b11111
+    }
+
+    pub fn function(self) -> u8 {
+        self.0 as u8 & 0b111
+    }
+}
+
+/// A PCI function, as described by the kernel
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct PciDeviceInfo {
+    pub address: u32,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    /// The legacy IRQ routed to the function, or 0xff if it has none
+    pub interrupt_line: u8,
+    pub interrupt_pin: u8,
+    _reserved: [u8; 2],
+    pub bars: [BarInfo; 6],
+}
+
+impl PciDeviceInfo {
+    pub fn address(&self) -> PciAddress {
+        PciAddress(self.address)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct BarInfo {
+    /// The physical address of a memory BAR, or the first port of an io BAR
+    pub address: u64,
+    pub size: u64,
+    pub kind: u32,
+    pub flags: u32,
+}
+
+impl BarInfo {
+    pub const KIND_NONE: u32 = 0;
+    pub const KIND_MEMORY: u32 = 1;
+    pub const KIND_IO: u32 = 2;
+
+    pub const FLAG_PREFETCHABLE: u32 = 1;
+    pub const FLAG_64_BIT: u32 = 1 << 1;
+}
+
+/// A block device, such as a disk, as described by the kernel
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct BlockDeviceInfo {
+    pub sector_size: u32,
+    pub flags: u32,
+    pub sector_count: u64,
+}
+
+impl BlockDeviceInfo {
+    pub const FLAG_READ_ONLY: u32 = 1;
+
+    pub fn is_read_only(&self) -> bool {
+        self.flags & BlockDeviceInfo::FLAG_READ_ONLY != 0
+    }
+}
+
+/// A network device, as described by the kernel
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct NetDeviceInfo {
+    pub mac: [u8; 6],
+    _reserved: [u8; 2],
+    /// The largest frame which can be sent, without its frame check sequence
+    pub max_frame_size: u32,
+    pub flags: u32,
+}
+
+impl NetDeviceInfo {
+    pub const FLAG_LINK_UP: u32 = 1;
+
+    pub fn is_link_up(&self) -> bool {
+        self.flags & NetDeviceInfo::FLAG_LINK_UP != 0
+    }
+}
+
+/// A process, as described by the kernel
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct ProcessInfo {
+    pub pid: u64,
+    /// The process which spawned it, or `u64::max_value()` if none did
+    pub parent: u64,
+    pub capabilities: u64,
+    pub state: u32,
+    name_len: u32,
+    name: [u8; MAX_PROCESS_NAME],
+}
+
+/// The longest name which a process can be given. Longer names are cut off.
+pub const MAX_PROCESS_NAME: usize = 32;
+
+impl ProcessInfo {
+    pub const STATE_RUNNABLE: u32 = 0;
+    pub const STATE_BLOCKED: u32 = 1;
+
+    pub fn name(&self) -> &str {
+        let len = (self.name_len as usize).min(MAX_PROCESS_NAME);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+
+    pub fn parent(&self) -> Option<u64> {
+        if self.parent == u64::max_value() {
+            None
+        } else {
+            Some(self.parent)
+        }
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::from_bits_truncate(self.capabilities)
+    }
+}
+
+impl Default for ProcessInfo {
+    fn default() -> Self {
+        ProcessInfo {
+            pid: 0,
+            parent: 0,
+            capabilities: 0,
+            state: 0,
+            name_len: 0,
+            name: [0; MAX_PROCESS_NAME],
+        }
+    }
+}
+
+/// Physical memory usage, in bytes
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct MemoryInfo {
+    pub usable: u64,
+    pub allocated: u64,
+    pub kernel_heap: u64,
+}
+
+/// How a process ended
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ExitStatus {
+    /// The process exited by itself, with the given code
+    Exited(u32),
+    /// Another process killed it, or the kernel did after a fault which it couldn't recover from
+    Killed,
+    Unknown(u64),
+}
+
+impl ExitStatus {
+    /// Decodes the status as `Wait` returns it
+    pub fn from_u64(status: u64) -> ExitStatus {
+        match status {
+            code if code <= u32::max_value() as u64 => ExitStatus::Exited(code as u32),
+            0x1_0000_0000 => ExitStatus::Killed,
+            unknown => ExitStatus::Unknown(unknown),
+        }
+    }
+
+    pub fn success(self) -> bool {
+        self == ExitStatus::Exited(0)
+    }
+}
+
+/// A keyboard or mouse event
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct InputEvent {
+    pub kind: u32,
+    /// For key events, the modifiers. For mouse events, the buttons.
+    pub state: u32,
+    /// For key events, the key code and the character typed, or 0 if there is none. For mouse
+    /// events, the x, y and wheel movement: positive towards the right, the bottom of the screen
+    /// and the user.
+    pub data: [i32; 3],
+}
+
+impl InputEvent {
+    pub const KIND_NONE: u32 = 0;
+    pub const KIND_KEY_PRESS: u32 = 1;
+    pub const KIND_KEY_RELEASE: u32 = 2;
+    pub const KIND_MOUSE: u32 = 3;
+
+    pub fn is_key(&self) -> bool {
+        self.kind == InputEvent::KIND_KEY_PRESS || self.kind == InputEvent::KIND_KEY_RELEASE
+    }
+
+    /// The key pressed or released, if this is a key event
+    pub fn key(&self) -> Option<KeyCode> {
+        let code = self.data[0] as u32;
+
+        if self.is_key() && code >= KeyCode::Escape as u32 && code <= KeyCode::Keypad9 as u32 {
+            // SAFETY: the key codes are contiguous, and the code is in range
+            Some(unsafe { core::mem::transmute::<u32, KeyCode>(code) })
+        } else {
+            None
+        }
+    }
+
+    /// The modifiers held when the key event happened
+    pub fn modifiers(&self) -> Modifiers {
+        Modifiers::from_bits_truncate(self.state)
+    }
+
+    /// The buttons held after a mouse event
+    pub fn mouse_buttons(&self) -> MouseButtons {
+        MouseButtons::from_bits_truncate(self.state)
+    }
+
+    /// The character typed by a key press, if any
+    pub fn character(&self) -> Option<char> {
+        match self.kind {
+            InputEvent::KIND_KEY_PRESS => {
+                core::char::from_u32(self.data[1] as u32).filter(|c| *c != '\0')
+            }
+            _ => None,
+        }
+    }
+}
+
+bitflags::bitflags! {
+    pub struct MouseButtons: u32 {
+        const LEFT = 1;
+        const RIGHT = 1 << 1;
+        const MIDDLE = 1 << 2;
+    }
+}
+
+/// A key, named by its position on a US keyboard
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum KeyCode {
+    Escape = 1,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Backtick,
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+    Digit0,
+    Minus,
+    Equals,
+    Backspace,
+    Tab,
+    Q,
+    W,
+    E,
+    R,
+    T,
+    Y,
+    U,
+    I,
+    O,
+    P,
+    LeftBracket,
+    RightBracket,
+    Backslash,
+    CapsLock,
+    A,
+    S,
+    D,
+    F,
+    G,
+    H,
+    J,
+    K,
+    L,
+    Semicolon,
+    Quote,
+    Enter,
+    LeftShift,
+    /// The extra key next to left shift on non-US keyboards
+    NonUsBackslash,
+    Z,
+    X,
+    C,
+    V,
+    B,
+    N,
+    M,
+    Comma,
+    Period,
+    Slash,
+    RightShift,
+    LeftCtrl,
+    LeftGui,
+    LeftAlt,
+    Space,
+    RightAlt,
+    RightGui,
+    Menu,
+    RightCtrl,
+    PrintScreen,
+    ScrollLock,
+    Pause,
+    Insert,
+    Home,
+    PageUp,
+    Delete,
+    End,
+    PageDown,
+    Up,
+    Left,
+    Down,
+    Right,
+    NumLock,
+    KeypadSlash,
+    KeypadAsterisk,
+    KeypadMinus,
+    KeypadPlus,
+    KeypadEnter,
+    KeypadPeriod,
+    Keypad0,
+    Keypad1,
+    Keypad2,
+    Keypad3,
+    Keypad4,
+    Keypad5,
+    Keypad6,
+    Keypad7,
+    Keypad8,
+    Keypad9,
+}
+
+bitflags::bitflags! {
+    /// The modifier keys held and lock keys toggled on when a key event happened
+    pub struct Modifiers: u32 {
+        const LEFT_SHIFT = 1;
+        const RIGHT_SHIFT = 1 << 1;
+        const LEFT_CTRL = 1 << 2;
+        const RIGHT_CTRL = 1 << 3;
+        const LEFT_ALT = 1 << 4;
+        const RIGHT_ALT = 1 << 5;
+        const LEFT_GUI = 1 << 6;
+        const RIGHT_GUI = 1 << 7;
+        const CAPS_LOCK = 1 << 8;
+        const NUM_LOCK = 1 << 9;
+        const SCROLL_LOCK = 1 << 10;
+
+        const SHIFT = Self::LEFT_SHIFT.bits | Self::RIGHT_SHIFT.bits;
+        const CTRL = Self::LEFT_CTRL.bits | Self::RIGHT_CTRL.bits;
+        const ALT = Self::LEFT_ALT.bits | Self::RIGHT_ALT.bits;
+        const GUI = Self::LEFT_GUI.bits | Self::RIGHT_GUI.bits;
+    }
+}
+
 pub fn res_from_code(code: i64) -> Result<i64, SyscallError> {
     match code {
         x if x >= 0 => Ok(x),
         -1 => Err(SyscallError::InvalidBuffer),
         -2 => Err(SyscallError::InvalidUtf8),
-        -3 => Err(SyscallError::InvalidPagesLength),
-        -4 => Err(SyscallError::OutOfMemory),
+        -3 => Err(SyscallError::InvalidPage),
+        -4 => Err(SyscallError::InvalidPagesLength),
+        -5 => Err(SyscallError::OutOfMemory),
+        -6 => Err(SyscallError::InvalidArgument),
+        -7 => Err(SyscallError::PermissionDenied),
+        -8 => Err(SyscallError::NotFound),
+        -9 => Err(SyscallError::Busy),
+        -10 => Err(SyscallError::IoError),
+        -11 => Err(SyscallError::BrokenPipe),
+        -12 => Err(SyscallError::TimedOut),
         unknown => Err(SyscallError::UnknownError(unknown)),
     }
 }
@@ -71,7 +584,24 @@ pub mod raw {
     syscall_raw!(
         syscall_0(),
         syscall_1("rdi" = arg1),
-        syscall_2("rdi" = arg1, "rsi" = arg2)
+        syscall_2("rdi" = arg1, "rsi" = arg2),
+        syscall_3("rdi" = arg1, "rsi" = arg2, "rdx" = arg3),
+        syscall_4("rdi" = arg1, "rsi" = arg2, "rdx" = arg3, "r10" = arg4),
+        syscall_5(
+            "rdi" = arg1,
+            "rsi" = arg2,
+            "rdx" = arg3,
+            "r10" = arg4,
+            "r8" = arg5
+        ),
+        syscall_6(
+            "rdi" = arg1,
+            "rsi" = arg2,
+            "rdx" = arg3,
+            "r10" = arg4,
+            "r8" = arg5,
+            "r9" = arg6
+        )
     );
 }
 
@@ -81,6 +611,390 @@ pub fn print(string: &str) -> Result<(), SyscallError> {
         .map(|_| ())
 }
 
+/// Returns the current time of the given clock in milliseconds
+pub fn clock_get(clock: Clock) -> Result<u64, SyscallError> {
+    raw::syscall_1(Syscall::ClockGet, clock as u64).map(|ms| ms as u64)
+}
+
+/// Blocks the calling thread for at least the given number of milliseconds
+pub fn sleep(ms: u64) -> Result<(), SyscallError> {
+    raw::syscall_1(Syscall::Sleep, ms).map(|_| ())
+}
+
+/// Blocks the calling thread until the given clock reads at least `ms` milliseconds
+pub fn sleep_until(clock: Clock, ms: u64) -> Result<(), SyscallError> {
+    raw::syscall_2(Syscall::SleepUntil, clock as u64, ms).map(|_| ())
+}
+
+/// Turns the machine off. Only returns if the process is not allowed to.
+pub fn shutdown() -> SyscallError {
+    match raw::syscall_0(Syscall::Shutdown) {
+        Ok(_) => unreachable!(),
+        Err(e) => e,
+    }
+}
+
+/// Resets the machine. Only returns if the process is not allowed to.
+pub fn reboot() -> SyscallError {
+    match raw::syscall_0(Syscall::Reboot) {
+        Ok(_) => unreachable!(),
+        Err(e) => e,
+    }
+}
+
+/// Fills the buffer with information about the machine's PCI functions. Returns the total number of
+/// functions, which may be more than fit in the buffer.
+pub fn pci_devices(buf: &mut [PciDeviceInfo]) -> Result<usize, SyscallError> {
+    let (ptr, len) = (buf.as_mut_ptr(), buf.len());
+    raw::syscall_2(Syscall::PciDevices, ptr as u64, len as u64).map(|n| n as usize)
+}
+
+/// Claims a PCI function for this process, enabling it and allowing access to its io ports.
+/// Returns the legacy IRQ routed to it, or 0xff if it has none.
+pub fn pci_claim(address: PciAddress) -> Result<u8, SyscallError> {
+    raw::syscall_1(Syscall::PciClaim, address.0 as u64).map(|irq| irq as u8)
+}
+
+/// Maps a memory BAR of a claimed PCI function at the given page aligned address. Returns the size
+/// of the BAR.
+pub fn pci_map_bar(address: PciAddress, bar: u8, at: *mut u8) -> Result<u64, SyscallError> {
+    raw::syscall_3(Syscall::PciMapBar, address.0 as u64, bar as u64, at as u64)
+        .map(|size| size as u64)
+}
+
+/// Moves queued input events into the buffer without blocking. Returns how many were moved, which
+/// may be 0.
+pub fn input_read(buf: &mut [InputEvent]) -> Result<usize, SyscallError> {
+    let (ptr, len) = (buf.as_mut_ptr(), buf.len());
+    raw::syscall_2(Syscall::InputRead, ptr as u64, len as u64).map(|n| n as usize)
+}
+
+/// Blocks the calling thread until there are input events to read
+pub fn input_wait() -> Result<(), SyscallError> {
+    raw::syscall_0(Syscall::InputWait).map(|_| ())
+}
+
+/// Blocks until there are input events, then moves as many as fit into the buffer. Returns how
+/// many were moved, which is at least 1 unless the buffer is empty.
+pub fn input_read_blocking(buf: &mut [InputEvent]) -> Result<usize, SyscallError> {
+    loop {
+        // Another process may have read the events between waking and reading them
+        match input_read(buf)? {
+            0 => input_wait()?,
+            read => return Ok(read),
+        }
+    }
+}
+
+/// Maps the initial ramdisk read-only at the given page aligned address. Returns its length in
+/// bytes.
+pub fn initrd_map(at: *mut u8) -> Result<u64, SyscallError> {
+    raw::syscall_1(Syscall::InitrdMap, at as u64).map(|len| len as u64)
+}
+
+/// Fills the buffer with information about the block devices, indexed by their ids. Returns the
+/// total number of devices, which may be more than fit in the buffer.
+pub fn block_devices(buf: &mut [BlockDeviceInfo]) -> Result<usize, SyscallError> {
+    let (ptr, len) = (buf.as_mut_ptr(), buf.len());
+    raw::syscall_2(Syscall::BlockDevices, ptr as u64, len as u64).map(|n| n as usize)
+}
+
+/// Reads whole sectors from a block device into the buffer, starting at the given sector. At most
+/// 64 KiB can be read at once. Fails with `SyscallError::Busy` if the device has too many requests
+/// in flight, in which case it can be retried.
+pub fn block_read(device: usize, sector: u64, buf: &mut [u8]) -> Result<(), SyscallError> {
+    let (ptr, len) = (buf.as_mut_ptr(), buf.len());
+    raw::syscall_4(
+        Syscall::BlockRead,
+        device as u64,
+        sector,
+        ptr as u64,
+        len as u64,
+    )
+    .map(|_| ())
+}
+
+/// Writes whole sectors from the buffer to a block device, starting at the given sector. The same
+/// limits as `block_read` apply.
+pub fn block_write(device: usize, sector: u64, buf: &[u8]) -> Result<(), SyscallError> {
+    let (ptr, len) = (buf.as_ptr(), buf.len());
+    raw::syscall_4(
+        Syscall::BlockWrite,
+        device as u64,
+        sector,
+        ptr as u64,
+        len as u64,
+    )
+    .map(|_| ())
+}
+
+/// Fills the buffer with information about the network devices, indexed by their ids. Returns the
+/// total number of devices, which may be more than fit in the buffer.
+pub fn net_devices(buf: &mut [NetDeviceInfo]) -> Result<usize, SyscallError> {
+    let (ptr, len) = (buf.as_mut_ptr(), buf.len());
+    raw::syscall_2(Syscall::NetDevices, ptr as u64, len as u64).map(|n| n as usize)
+}
+
+/// Attaches this process to a network device, mapping the frame rings it shares with the kernel at
+/// the given page aligned address. The `notify` notification bits are signalled when frames
+/// arrive. Returns the length of the mapping. See `libwolffia::net` for a safe interface.
+pub fn net_attach(device: usize, at: *mut u8, notify: u64) -> Result<u64, SyscallError> {
+    raw::syscall_3(Syscall::NetAttach, device as u64, at as u64, notify).map(|len| len as u64)
+}
+
+/// Sends the frames waiting in the transmit ring of an attached network device. Returns how many
+/// were sent.
+pub fn net_transmit(device: usize) -> Result<u32, SyscallError> {
+    raw::syscall_1(Syscall::NetTransmit, device as u64).map(|sent| sent as u32)
+}
+
+/// Blocks the calling thread until any of the notification bits in `mask` are signalled, then
+/// clears and returns them. Only the low 63 bits can be used.
+pub fn notification_wait(mask: u64) -> Result<u64, SyscallError> {
+    raw::syscall_1(Syscall::NotificationWait, mask).map(|bits| bits as u64)
+}
+
+/// Sends a legacy IRQ raised by a claimed PCI function, or granted to this process with
+/// `irq_grant`, to this process as the given notification bits. The IRQ is masked each time it
+/// fires, until it is acknowledged with `irq_ack`.
+pub fn irq_listen(irq: u8, notify: u64) -> Result<(), SyscallError> {
+    raw::syscall_2(Syscall::IrqListen, irq as u64, notify).map(|_| ())
+}
+
+/// Unmasks an IRQ once the device's interrupt has been handled
+pub fn irq_ack(irq: u8) -> Result<(), SyscallError> {
+    raw::syscall_1(Syscall::IrqAck, irq as u64).map(|_| ())
+}
+
+/// Maps zeroed, physically contiguous memory at the given page aligned address, for devices to
+/// access directly. At most 4 MiB can be mapped at once. Returns its physical address.
+pub fn dma_map(at: *mut u8, len: usize) -> Result<u64, SyscallError> {
+    raw::syscall_2(Syscall::DmaMap, at as u64, len as u64).map(|physical| physical as u64)
+}
+
+/// Creates an IPC endpoint owned by this process, which other processes can look up by the given
+/// name unless it is empty. Fails with `SyscallError::Busy` if the name is taken. Returns the
+/// endpoint's id. See `libwolffia::ipc` for a safe interface.
+pub fn endpoint_create(name: &str) -> Result<u64, SyscallError> {
+    let (ptr, len) = (name.as_ptr(), name.len());
+    raw::syscall_2(Syscall::EndpointCreate, ptr as u64, len as u64).map(|id| id as u64)
+}
+
+/// Returns the id of the IPC endpoint with the given name
+pub fn endpoint_lookup(name: &str) -> Result<u64, SyscallError> {
+    let (ptr, len) = (name.as_ptr(), name.len());
+    raw::syscall_2(Syscall::EndpointLookup, ptr as u64, len as u64).map(|id| id as u64)
+}
+
+/// Sends a request to an IPC endpoint and blocks the calling thread until it is replied to.
+/// Requests and replies are at most 64 KiB. Returns the length of the reply, which is truncated to
+/// fit the buffer.
+pub fn ipc_call(endpoint: u64, request: &[u8], reply: &mut [u8]) -> Result<usize, SyscallError> {
+    raw::syscall_5(
+        Syscall::IpcCall,
+        endpoint,
+        request.as_ptr() as u64,
+        request.len() as u64,
+        reply.as_mut_ptr() as u64,
+        reply.len() as u64,
+    )
+    .map(|len| len as usize)
+}
+
+/// Blocks the calling thread until a call is made to one of this process's endpoints, then fills
+/// the buffer with the call's id and the caller's process id, as little endian u64s, followed by
+/// the request, truncated to fit. Returns the length of the request as received.
+///
+/// With a timeout, fails with `SyscallError::TimedOut` if no call is made within that many
+/// milliseconds. A timeout of 0 only takes a call which is already waiting.
+pub fn ipc_receive(
+    endpoint: u64,
+    buf: &mut [u8],
+    timeout_ms: Option<u64>,
+) -> Result<usize, SyscallError> {
+    let (ptr, len) = (buf.as_mut_ptr(), buf.len());
+    let timeout_ms = timeout_ms.unwrap_or(u64::max_value());
+    raw::syscall_4(Syscall::IpcReceive, endpoint, ptr as u64, len as u64, timeout_ms)
+        .map(|len| len as usize)
+}
+
+/// Replies to a call received by `ipc_receive`, waking the caller
+pub fn ipc_reply(call: u64, reply: &[u8]) -> Result<(), SyscallError> {
+    let (ptr, len) = (reply.as_ptr(), reply.len());
+    raw::syscall_3(Syscall::IpcReply, call, ptr as u64, len as u64).map(|_| ())
+}
+
+/// Maps zeroed pages at the given page aligned address
+pub fn map(at: *mut u8, pages: usize, flags: UserPageFlags) -> Result<(), SyscallError> {
+    raw::syscall_5(Syscall::Map, at as u64, pages as u64, flags.bits(), 0, 0).map(|_| ())
+}
+
+/// Maps pages of a memory object at the given page aligned address, starting at the page aligned
+/// offset into the object. The pages are faulted in when they are first accessed, either by this
+/// process or by a system call which is passed a buffer in them. See `memory::MemoryObject` for a
+/// safe interface.
+pub fn map_object(
+    at: *mut u8,
+    pages: usize,
+    flags: UserPageFlags,
+    object: u64,
+    offset: u64,
+) -> Result<(), SyscallError> {
+    raw::syscall_5(
+        Syscall::Map,
+        at as u64,
+        pages as u64,
+        flags.bits(),
+        object,
+        offset,
+    )
+    .map(|_| ())
+}
+
+/// Unmaps pages which were mapped with `map` or from memory objects, blocking until the dirty pages
+/// of files have been written back. Fails with `SyscallError::IoError` if any of them couldn't be,
+/// though the pages are unmapped regardless.
+pub fn unmap(at: *mut u8, pages: usize) -> Result<(), SyscallError> {
+    raw::syscall_2(Syscall::Unmap, at as u64, pages as u64).map(|_| ())
+}
+
+/// Writes back the dirty pages of files which were mapped from memory objects, like `unmap`, but
+/// keeps them mapped
+pub fn msync(at: *mut u8, pages: usize) -> Result<(), SyscallError> {
+    raw::syscall_2(Syscall::Msync, at as u64, pages as u64).map(|_| ())
+}
+
+/// Creates a memory object of zeroed pages. Returns its id.
+pub fn object_create(pages: usize) -> Result<u64, SyscallError> {
+    raw::syscall_1(Syscall::ObjectCreate, pages as u64).map(|id| id as u64)
+}
+
+/// Creates a memory object backed by a file which the process has open. Returns its id.
+pub fn object_from_file(endpoint: u64, handle: u64) -> Result<u64, SyscallError> {
+    raw::syscall_2(Syscall::ObjectFromFile, endpoint, handle).map(|id| id as u64)
+}
+
+/// Releases a memory object which the process created. Its pages stay mapped until they are
+/// unmapped.
+pub fn object_release(object: u64) -> Result<(), SyscallError> {
+    raw::syscall_1(Syscall::ObjectRelease, object).map(|_| ())
+}
+
+/// Reads from a file descriptor into the buffer, blocking until there is data. Returns how many
+/// bytes were read, which is 0 at the end of the file. See `libwolffia::io` for a safe interface.
+pub fn read(fd: u64, buf: &mut [u8]) -> Result<usize, SyscallError> {
+    let (ptr, len) = (buf.as_mut_ptr(), buf.len());
+    raw::syscall_3(Syscall::Read, fd, ptr as u64, len as u64).map(|len| len as usize)
+}
+
+/// Writes the buffer to a file descriptor, blocking until all of it is written. Writes are at most
+/// 64 KiB. Fails with `SyscallError::BrokenPipe` if the descriptor is a pipe which nothing reads.
+pub fn write(fd: u64, buf: &[u8]) -> Result<usize, SyscallError> {
+    let (ptr, len) = (buf.as_ptr(), buf.len());
+    raw::syscall_3(Syscall::Write, fd, ptr as u64, len as u64).map(|len| len as usize)
+}
+
+/// Closes a file descriptor. A pipe end is closed once all descriptors of it are.
+pub fn close(fd: u64) -> Result<(), SyscallError> {
+    raw::syscall_1(Syscall::Close, fd).map(|_| ())
+}
+
+/// Creates a pipe. Returns the descriptors of its read and write ends.
+pub fn pipe() -> Result<[u64; 2], SyscallError> {
+    let mut fds = [0; 2];
+    raw::syscall_1(Syscall::Pipe, fds.as_mut_ptr() as u64)?;
+    Ok(fds)
+}
+
+/// Spawns a process from an ELF file, under the given name and with a subset of this process's
+/// capabilities. The process's standard input, output and error are the given descriptors of this
+/// process, or this process's own if none are given. Returns the new process's id.
+pub fn spawn(
+    elf: &[u8],
+    name: &str,
+    capabilities: Capabilities,
+    stdio: Option<[u64; 3]>,
+) -> Result<u64, SyscallError> {
+    let stdio_ptr = match &stdio {
+        Some(stdio) => stdio.as_ptr() as u64,
+        None => 0,
+    };
+
+    let mut name_len = name.len().min(MAX_PROCESS_NAME);
+    while !name.is_char_boundary(name_len) {
+        name_len -= 1;
+    }
+
+    raw::syscall_6(
+        Syscall::Spawn,
+        elf.as_ptr() as u64,
+        elf.len() as u64,
+        name.as_ptr() as u64,
+        name_len as u64,
+        capabilities.bits(),
+        stdio_ptr,
+    )
+    .map(|pid| pid as u64)
+}
+
+/// Ends this process with the given exit code
+pub fn exit(code: u32) -> ! {
+    let _ = raw::syscall_1(Syscall::Exit, code as u64);
+    unreachable!()
+}
+
+/// Kills this process or one of its descendants, i.e a process which it spawned, or which one of
+/// those spawned. With `Capabilities::KILL`, any process which has no capabilities that this one
+/// doesn't can be killed; otherwise this fails with `SyscallError::PermissionDenied`.
+pub fn kill(pid: u64) -> Result<(), SyscallError> {
+    raw::syscall_1(Syscall::Kill, pid).map(|_| ())
+}
+
+/// Blocks until a child of this process exits, unless it already has, and returns how it ended.
+/// A child can only be waited on once.
+pub fn wait(pid: u64) -> Result<ExitStatus, SyscallError> {
+    raw::syscall_1(Syscall::Wait, pid).map(|status| ExitStatus::from_u64(status as u64))
+}
+
+/// Blocks until any child of this process exits, unless one already has, and returns its id and how
+/// it ended. Fails with `SyscallError::NotFound` if this process has no children left to wait on.
+pub fn wait_any() -> Result<(u64, ExitStatus), SyscallError> {
+    let mut status = 0u64;
+    let pid = raw::syscall_1(Syscall::WaitAny, &mut status as *mut u64 as u64)?;
+    Ok((pid as u64, ExitStatus::from_u64(status)))
+}
+
+/// Allows a child of this process to access the given io ports. This process must have the
+/// `DEVICES` capability. The child doesn't run until this process next blocks, so it can be
+/// granted ports straight after it is spawned.
+pub fn io_port_grant(pid: u64, ports: core::ops::RangeInclusive<u16>) -> Result<(), SyscallError> {
+    let (first, last) = (*ports.start() as u64, *ports.end() as u64);
+
+    if last < first {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    raw::syscall_3(Syscall::IoPortGrant, pid, first, last - first + 1).map(|_| ())
+}
+
+/// Allows a child of this process to listen to a legacy IRQ with `irq_listen`, like `io_port_grant`
+pub fn irq_grant(pid: u64, irq: u8) -> Result<(), SyscallError> {
+    raw::syscall_2(Syscall::IrqGrant, pid, irq as u64).map(|_| ())
+}
+
+/// Fills the buffer with the processes which are running, in order of their ids. Returns the total
+/// number of processes, which may be more than fit.
+pub fn process_list(buf: &mut [ProcessInfo]) -> Result<usize, SyscallError> {
+    let (ptr, len) = (buf.as_mut_ptr(), buf.len());
+    raw::syscall_2(Syscall::ProcessList, ptr as u64, len as u64).map(|n| n as usize)
+}
+
+pub fn memory_info() -> Result<MemoryInfo, SyscallError> {
+    let mut info = MemoryInfo::default();
+    raw::syscall_1(Syscall::MemoryInfo, &mut info as *mut MemoryInfo as u64)?;
+    Ok(info)
+}
+
 pub fn halt() -> ! {
     let _ = raw::syscall_0(Syscall::Halt);
     unreachable!()
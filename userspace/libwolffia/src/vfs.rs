@@ -0,0 +1,614 @@
+//! A virtual filesystem, made up of filesystems which are each served by a separate process over
+//! IPC. The mount table server, found through the `vfs` endpoint, maps an absolute path to the
+//! filesystem mounted at its longest prefix, and the path within that filesystem. Files are then
+//! opened, read and written by calling the filesystem's own endpoint.
+//!
+//! Every request is a `Request` header followed by a payload, such as a path or the data to write,
+//! and every reply is a `Reply` header followed by a payload, such as the data read. The headers
+//! are little endian. Transfers are split into pieces of at most `MAX_TRANSFER` bytes, so that
+//! messages fit in buffers on the stack.
+
+pub mod ext2;
+pub mod fat;
+pub mod initrd;
+pub mod mount;
+pub mod server;
+
+use crate::ipc::Endpoint;
+use crate::memory::MemoryObject;
+use crate::syscall::{self, SyscallError};
+use core::convert::TryInto;
+use core::str;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// The name of the mount table server's endpoint
+pub const MOUNT_ENDPOINT: &str = "vfs";
+/// The name of the endpoint of the tmpfs, which the kernel serves. Its files are held in memory,
+/// and share it with the processes which map them.
+pub const TMPFS_ENDPOINT: &str = "tmpfs";
+/// The most data read or written by one request
+pub const MAX_TRANSFER: usize = 4096;
+/// The longest path which can be opened
+pub const MAX_PATH: usize = 256;
+/// The longest name of a directory entry
+pub const MAX_NAME: usize = 255;
+
+pub const REQUEST_SIZE: usize = 32;
+pub const REPLY_SIZE: usize = 16;
+/// Large enough for any request or reply
+pub const MESSAGE_SIZE: usize = REQUEST_SIZE + MAX_TRANSFER;
+
+const DIR_ENTRY_HEADER_SIZE: usize = 16;
+const STAT_SIZE: usize = 16;
+
+/// The mount table server's endpoint, once it has been looked up
+static MOUNT_TABLE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum Op {
+    /// Opens the file at the path in the payload with the flags, returning its handle
+    Open = 0,
+    /// Reads up to `len` bytes of a file from the offset into the reply payload
+    Read = 1,
+    /// Writes the payload to a file at the offset, returning the length written
+    Write = 2,
+    /// Returns a `Stat` of a file in the reply payload
+    Stat = 3,
+    /// Returns the directory entry at the position given by the offset in the reply payload, and
+    /// the position of the next, or an empty payload at the end of the directory. Positions start
+    /// at 0, and are otherwise up to the filesystem.
+    ReadDir = 4,
+    Close = 5,
+    /// Asks the mount table server to mount the filesystem whose endpoint is the handle at the path
+    /// in the payload
+    Mount = 6,
+    /// Asks the mount table server for the filesystem which serves the path in the payload. Returns
+    /// its endpoint, and the path within it in the reply payload.
+    Resolve = 7,
+    /// Sets the length of a file to the offset, dropping or zero filling its end
+    Truncate = 8,
+    /// Removes the file or empty directory at the path in the payload
+    Unlink = 9,
+}
+
+impl Op {
+    pub fn from_u32(v: u32) -> Option<Op> {
+        match v {
+            0 => Some(Op::Open),
+            1 => Some(Op::Read),
+            2 => Some(Op::Write),
+            3 => Some(Op::Stat),
+            4 => Some(Op::ReadDir),
+            5 => Some(Op::Close),
+            6 => Some(Op::Mount),
+            7 => Some(Op::Resolve),
+            8 => Some(Op::Truncate),
+            9 => Some(Op::Unlink),
+            _ => None,
+        }
+    }
+}
+
+bitflags::bitflags! {
+    pub struct OpenFlags: u32 {
+        const READ = 1;
+        const WRITE = 1 << 1;
+        /// Creates the file if it doesn't exist
+        const CREATE = 1 << 2;
+        /// Empties the file once it is opened
+        const TRUNCATE = 1 << 3;
+        /// Opens a directory, to read its entries. With `CREATE`, creates a directory.
+        const DIRECTORY = 1 << 4;
+        /// Opens a symbolic link itself, rather than the file it points to. Reading it gives the
+        /// path which it points to.
+        const NO_FOLLOW = 1 << 5;
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Error {
+    NotFound,
+    AlreadyExists,
+    NotADirectory,
+    IsADirectory,
+    NotEmpty,
+    ReadOnly,
+    NoSpace,
+    InvalidArgument,
+    /// The handle is not that of an open file
+    BadHandle,
+    /// The filesystem can't have any more files open
+    TooManyOpen,
+    /// Too many symbolic links were followed, as happens when they form a loop
+    SymlinkLoop,
+    /// The filesystem can't do what was asked of it
+    Unsupported,
+    Io,
+    /// Calling the server failed
+    Ipc(SyscallError),
+}
+
+impl Error {
+    /// The status which a reply carries for this error
+    pub fn code(self) -> i64 {
+        match self {
+            Error::NotFound => -1,
+            Error::AlreadyExists => -2,
+            Error::NotADirectory => -3,
+            Error::IsADirectory => -4,
+            Error::NotEmpty => -5,
+            Error::ReadOnly => -6,
+            Error::NoSpace => -7,
+            Error::InvalidArgument => -8,
+            Error::BadHandle => -9,
+            Error::TooManyOpen => -10,
+            Error::Io | Error::Ipc(_) => -11,
+            Error::SymlinkLoop => -12,
+            Error::Unsupported => -13,
+        }
+    }
+
+    pub fn from_code(code: i64) -> Error {
+        match code {
+            -1 => Error::NotFound,
+            -2 => Error::AlreadyExists,
+            -3 => Error::NotADirectory,
+            -4 => Error::IsADirectory,
+            -5 => Error::NotEmpty,
+            -6 => Error::ReadOnly,
+            -7 => Error::NoSpace,
+            -8 => Error::InvalidArgument,
+            -9 => Error::BadHandle,
+            -10 => Error::TooManyOpen,
+            -12 => Error::SymlinkLoop,
+            -13 => Error::Unsupported,
+            _ => Error::Io,
+        }
+    }
+}
+
+impl From<SyscallError> for Error {
+    fn from(err: SyscallError) -> Self {
+        Error::Ipc(err)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Request {
+    pub op: u32,
+    pub flags: u32,
+    pub handle: u64,
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl Request {
+    pub fn new(op: Op) -> Request {
+        Request {
+            op: op as u32,
+            ..Request::default()
+        }
+    }
+
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0..4].copy_from_slice(&self.op.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.flags.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.handle.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.offset.to_le_bytes());
+        buf[24..32].copy_from_slice(&self.len.to_le_bytes());
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Request> {
+        let buf = buf.get(..REQUEST_SIZE)?;
+
+        Some(Request {
+            op: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+            flags: u32::from_le_bytes(buf[4..8].try_into().unwrap()),
+            handle: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+            offset: u64::from_le_bytes(buf[16..24].try_into().unwrap()),
+            len: u64::from_le_bytes(buf[24..32].try_into().unwrap()),
+        })
+    }
+}
+
+/// The header of a reply. A negative status is an error's code, and otherwise the value is the
+/// result of the request.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Reply {
+    pub status: i64,
+    pub value: u64,
+}
+
+impl Reply {
+    pub fn from_result(res: Result<u64, Error>) -> Reply {
+        match res {
+            Ok(value) => Reply { status: 0, value },
+            Err(err) => Reply {
+                status: err.code(),
+                value: 0,
+            },
+        }
+    }
+
+    pub fn result(&self) -> Result<u64, Error> {
+        if self.status < 0 {
+            Err(Error::from_code(self.status))
+        } else {
+            Ok(self.value)
+        }
+    }
+
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0..8].copy_from_slice(&self.status.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.value.to_le_bytes());
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Reply> {
+        let buf = buf.get(..REPLY_SIZE)?;
+
+        Some(Reply {
+            status: i64::from_le_bytes(buf[0..8].try_into().unwrap()),
+            value: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum FileKind {
+    File = 0,
+    Directory = 1,
+    Symlink = 2,
+}
+
+impl FileKind {
+    pub fn from_u32(v: u32) -> Option<FileKind> {
+        match v {
+            0 => Some(FileKind::File),
+            1 => Some(FileKind::Directory),
+            2 => Some(FileKind::Symlink),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Stat {
+    pub kind: FileKind,
+    /// The length of the file in bytes
+    pub size: u64,
+}
+
+impl Stat {
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        buf[0..4].copy_from_slice(&(self.kind as u32).to_le_bytes());
+        buf[4..8].copy_from_slice(&0u32.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.size.to_le_bytes());
+        STAT_SIZE
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Stat> {
+        let buf = buf.get(..STAT_SIZE)?;
+
+        Some(Stat {
+            kind: FileKind::from_u32(u32::from_le_bytes(buf[0..4].try_into().unwrap()))?,
+            size: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+        })
+    }
+}
+
+/// An entry of a directory, encoded as its kind and the length of its name as u32s, its size as a
+/// u64, then its name
+#[derive(Copy, Clone)]
+pub struct DirEntry {
+    pub kind: FileKind,
+    pub size: u64,
+    name: [u8; MAX_NAME],
+    name_len: usize,
+}
+
+impl DirEntry {
+    /// Fails with `Error::InvalidArgument` if the name is longer than `MAX_NAME`
+    pub fn new(kind: FileKind, size: u64, name: &str) -> Result<DirEntry, Error> {
+        if name.len() > MAX_NAME {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut entry = DirEntry {
+            kind,
+            size,
+            name: [0; MAX_NAME],
+            name_len: name.len(),
+        };
+
+        entry.name[..name.len()].copy_from_slice(name.as_bytes());
+        Ok(entry)
+    }
+
+    pub fn name(&self) -> &str {
+        // SAFETY: it was copied from a str, or checked to be UTF-8 when decoded
+        unsafe { str::from_utf8_unchecked(&self.name[..self.name_len]) }
+    }
+
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        buf[0..4].copy_from_slice(&(self.kind as u32).to_le_bytes());
+        buf[4..8].copy_from_slice(&(self.name_len as u32).to_le_bytes());
+        buf[8..16].copy_from_slice(&self.size.to_le_bytes());
+
+        let end = DIR_ENTRY_HEADER_SIZE + self.name_len;
+        buf[DIR_ENTRY_HEADER_SIZE..end].copy_from_slice(&self.name[..self.name_len]);
+        end
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<DirEntry> {
+        let kind = FileKind::from_u32(u32::from_le_bytes(buf.get(0..4)?.try_into().unwrap()))?;
+        let name_len = u32::from_le_bytes(buf.get(4..8)?.try_into().unwrap()) as usize;
+        let size = u64::from_le_bytes(buf.get(8..16)?.try_into().unwrap());
+        let name = buf.get(DIR_ENTRY_HEADER_SIZE..DIR_ENTRY_HEADER_SIZE + name_len)?;
+
+        DirEntry::new(kind, size, str::from_utf8(name).ok()?).ok()
+    }
+}
+
+impl core::fmt::Debug for DirEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("DirEntry")
+            .field("kind", &self.kind)
+            .field("size", &self.size)
+            .field("name", &self.name())
+            .finish()
+    }
+}
+
+/// Makes a request of a filesystem or the mount table server, receiving the reply's payload into
+/// the buffer. Returns the reply's value and the length of its payload.
+pub fn call(
+    endpoint: &Endpoint,
+    request: Request,
+    payload: &[u8],
+    reply_payload: &mut [u8],
+) -> Result<(u64, usize), Error> {
+    if payload.len() > MAX_TRANSFER {
+        return Err(Error::InvalidArgument);
+    }
+
+    let mut request_buf = [0; MESSAGE_SIZE];
+    request.encode(&mut request_buf);
+    request_buf[REQUEST_SIZE..REQUEST_SIZE + payload.len()].copy_from_slice(payload);
+
+    let mut reply_buf = [0; MESSAGE_SIZE];
+    let request_len = REQUEST_SIZE + payload.len();
+    let len = endpoint.call(&request_buf[..request_len], &mut reply_buf)?;
+    let reply = Reply::decode(&reply_buf[..len]).ok_or(Error::Io)?;
+    let value = reply.result()?;
+
+    let reply_len = (len - REPLY_SIZE).min(reply_payload.len());
+    reply_payload[..reply_len].copy_from_slice(&reply_buf[REPLY_SIZE..REPLY_SIZE + reply_len]);
+    Ok((value, reply_len))
+}
+
+fn mount_table() -> Result<Endpoint, Error> {
+    let mut id = MOUNT_TABLE.load(Ordering::Acquire);
+
+    if id == u64::MAX {
+        id = Endpoint::lookup(MOUNT_ENDPOINT)?.id();
+        MOUNT_TABLE.store(id, Ordering::Release);
+    }
+
+    Ok(Endpoint::from_id(id))
+}
+
+/// Mounts the filesystem served on the endpoint at an absolute path
+pub fn mount(path: &str, filesystem: &Endpoint) -> Result<(), Error> {
+    let mut request = Request::new(Op::Mount);
+    request.handle = filesystem.id();
+    call(&mount_table()?, request, path.as_bytes(), &mut []).map(|_| ())
+}
+
+/// Finds the filesystem which serves an absolute path. Returns its endpoint and the path within it,
+/// which is stored in the buffer.
+pub fn resolve<'a>(path: &str, buf: &'a mut [u8; MAX_PATH]) -> Result<(Endpoint, &'a str), Error> {
+    if path.len() > MAX_PATH {
+        return Err(Error::InvalidArgument);
+    }
+
+    let (endpoint, len) = call(
+        &mount_table()?,
+        Request::new(Op::Resolve),
+        path.as_bytes(),
+        buf,
+    )?;
+    let relative = str::from_utf8(&buf[..len]).map_err(|_| Error::Io)?;
+    Ok((Endpoint::from_id(endpoint), relative))
+}
+
+/// Creates a directory at an absolute path
+pub fn create_dir(path: &str) -> Result<(), Error> {
+    File::open(path, OpenFlags::CREATE | OpenFlags::DIRECTORY).map(|_| ())
+}
+
+/// Removes the file or empty directory at an absolute path. Files which are open stay readable and
+/// writable until they are closed.
+pub fn unlink(path: &str) -> Result<(), Error> {
+    let mut buf = [0; MAX_PATH];
+    let (endpoint, relative) = resolve(path, &mut buf)?;
+    let request = Request::new(Op::Unlink);
+    call(&endpoint, request, relative.as_bytes(), &mut []).map(|_| ())
+}
+
+/// A file or directory open on a filesystem. It is closed when dropped.
+#[derive(Debug)]
+pub struct File {
+    endpoint: Endpoint,
+    handle: u64,
+    position: u64,
+}
+
+impl File {
+    /// Opens the file at an absolute path
+    pub fn open(path: &str, flags: OpenFlags) -> Result<File, Error> {
+        let mut buf = [0; MAX_PATH];
+        let (endpoint, relative) = resolve(path, &mut buf)?;
+        File::open_on(endpoint, relative, flags)
+    }
+
+    /// Opens the file at a path within the filesystem served on the endpoint, bypassing the mount
+    /// table
+    pub fn open_on(endpoint: Endpoint, path: &str, flags: OpenFlags) -> Result<File, Error> {
+        let mut request = Request::new(Op::Open);
+        request.flags = flags.bits();
+        let (handle, _) = call(&endpoint, request, path.as_bytes(), &mut [])?;
+
+        Ok(File {
+            endpoint,
+            handle,
+            position: 0,
+        })
+    }
+
+    /// Reads from the current position, advancing it. Returns the length read, which is only less
+    /// than the buffer's at the end of the file.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let len = self.read_at(self.position, buf)?;
+        self.position += len as u64;
+        Ok(len)
+    }
+
+    /// Reads from the given offset. Returns the length read, which is only less than the buffer's
+    /// at the end of the file.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut done = 0;
+
+        while done < buf.len() {
+            let end = (done + MAX_TRANSFER).min(buf.len());
+            let mut request = Request::new(Op::Read);
+            request.handle = self.handle;
+            request.offset = offset + done as u64;
+            request.len = (end - done) as u64;
+
+            let (_, len) = call(&self.endpoint, request, &[], &mut buf[done..end])?;
+            let short = len < end - done;
+            done += len;
+
+            if short {
+                break;
+            }
+        }
+
+        Ok(done)
+    }
+
+    /// Writes at the current position, advancing it. Returns the length written.
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let len = self.write_at(self.position, buf)?;
+        self.position += len as u64;
+        Ok(len)
+    }
+
+    /// Writes at the given offset, extending the file if needed. Returns the length written.
+    pub fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Error> {
+        let mut done = 0;
+
+        while done < buf.len() {
+            let end = (done + MAX_TRANSFER).min(buf.len());
+            let mut request = Request::new(Op::Write);
+            request.handle = self.handle;
+            request.offset = offset + done as u64;
+            request.len = (end - done) as u64;
+
+            let (written, _) = call(&self.endpoint, request, &buf[done..end], &mut [])?;
+            let short = (written as usize) < end - done;
+            done += written as usize;
+
+            if short {
+                break;
+            }
+        }
+
+        Ok(done)
+    }
+
+    /// Sets the length of the file, dropping its end or extending it with zeroes
+    pub fn truncate(&self, len: u64) -> Result<(), Error> {
+        let mut request = Request::new(Op::Truncate);
+        request.handle = self.handle;
+        request.offset = len;
+        call(&self.endpoint, request, &[], &mut []).map(|_| ())
+    }
+
+    /// Creates a memory object backed by the file, so that its pages can be mapped. Files on any
+    /// filesystem can be mapped, except one which this process serves itself. The file must stay
+    /// open while the object is mapped, and mapping it writable needs it to be open for writing.
+    pub fn memory_object(&self) -> Result<MemoryObject, Error> {
+        let id = syscall::object_from_file(self.endpoint.id(), self.handle)?;
+        Ok(MemoryObject::from_id(id))
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn seek(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    pub fn stat(&self) -> Result<Stat, Error> {
+        let mut request = Request::new(Op::Stat);
+        request.handle = self.handle;
+
+        let mut buf = [0; STAT_SIZE];
+        let (_, len) = call(&self.endpoint, request, &[], &mut buf)?;
+        Stat::decode(&buf[..len]).ok_or(Error::Io)
+    }
+
+    /// Iterates over the entries of a directory opened with `OpenFlags::DIRECTORY`
+    pub fn read_dir(&self) -> ReadDir<'_> {
+        ReadDir {
+            dir: self,
+            position: Some(0),
+        }
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let mut request = Request::new(Op::Close);
+        request.handle = self.handle;
+        let _ = call(&self.endpoint, request, &[], &mut []);
+    }
+}
+
+pub struct ReadDir<'a> {
+    dir: &'a File,
+    /// The position of the next entry, or None at the end of the directory
+    position: Option<u64>,
+}
+
+impl<'a> Iterator for ReadDir<'a> {
+    type Item = Result<DirEntry, Error>;
+
+    fn next(&mut self) -> Option<Result<DirEntry, Error>> {
+        let mut request = Request::new(Op::ReadDir);
+        request.handle = self.dir.handle;
+        request.offset = self.position?;
+
+        let mut buf = [0; DIR_ENTRY_HEADER_SIZE + MAX_NAME];
+        let res = call(&self.dir.endpoint, request, &[], &mut buf);
+
+        match res {
+            Ok((_, 0)) => {
+                self.position = None;
+                None
+            }
+            Ok((next, len)) => {
+                self.position = Some(next);
+                Some(DirEntry::decode(&buf[..len]).ok_or(Error::Io))
+            }
+            Err(err) => {
+                self.position = None;
+                Some(Err(err))
+            }
+        }
+    }
+}
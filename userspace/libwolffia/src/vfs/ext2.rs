@@ -0,0 +1,645 @@
+//! Read-only ext2 filesystems on a block device, as made by `mke2fs -t ext2`. The filesystem can be
+//! on the whole device, or in a Linux partition in an MBR. ext3 filesystems can be read too, as
+//! long as their journal doesn't need recovering, but ext4's extents can't.
+//!
+//! Blocks are read through a cache of one block, and another for each level of indirect blocks, so
+//! reading a file from start to end reads each of its indirect blocks once.
+
+use super::server::FileSystem;
+use super::{DirEntry, Error, FileKind, OpenFlags, Stat, MAX_NAME, MAX_PATH};
+use crate::block::{self, BlockDevice};
+use core::convert::TryInto;
+use core::str;
+
+/// The most files which can be open at once
+pub const MAX_OPEN: usize = 32;
+
+/// The largest block size which can be used
+const MAX_BLOCK_SIZE: usize = 4096;
+/// The most symbolic links which are followed while looking up a path
+const MAX_SYMLINKS: u32 = 8;
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const SUPERBLOCK_SIZE: usize = 1024;
+const MAGIC: u16 = 0xef53;
+const GROUP_DESCRIPTOR_SIZE: u64 = 32;
+const ROOT_INODE: u32 = 2;
+/// The size of inodes in revision 0 filesystems, which don't record it, and the part of larger
+/// inodes which is read
+const GOOD_OLD_INODE_SIZE: usize = 128;
+
+/// Directory entries record the type of file
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+/// Block groups' bitmaps and inode tables can be in other groups
+const INCOMPAT_FLEX_BG: u32 = 0x0200;
+/// The incompatible features which can be read. Others change the layout, or mean that the
+/// journal must be recovered first.
+const SUPPORTED_INCOMPAT: u32 = INCOMPAT_FILETYPE | INCOMPAT_FLEX_BG;
+
+const MODE_TYPE_MASK: u16 = 0xf000;
+const MODE_DIRECTORY: u16 = 0x4000;
+const MODE_REGULAR: u16 = 0x8000;
+const MODE_SYMLINK: u16 = 0xa000;
+
+/// The block pointers in an inode, of which the first are direct, and the last point to single,
+/// double and triple indirect blocks
+const BLOCK_POINTERS: usize = 15;
+const DIRECT_BLOCKS: u64 = 12;
+const INDIRECT_LEVELS: usize = 3;
+
+const DIR_ENTRY_HEADER_SIZE: usize = 8;
+
+/// The MBR partition type of Linux filesystems
+const MBR_LINUX_TYPE: u8 = 0x83;
+
+/// The cache which data, directory and inode blocks are read through. Indirect blocks at each
+/// level of indirection are read through the caches after it.
+const DATA_CACHE: usize = 0;
+
+#[derive(Debug, Copy, Clone)]
+struct Inode {
+    mode: u16,
+    size: u64,
+    /// The 512 byte sectors taken up by the file's blocks
+    sectors: u32,
+    /// The block holding the file's extended attributes, or 0 if it has none
+    file_acl: u32,
+    /// The block pointers, or the target of a symbolic link short enough to fit in their place
+    block: [u8; BLOCK_POINTERS * 4],
+}
+
+impl Inode {
+    fn parse(bytes: &[u8]) -> Inode {
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let mode = u16::from_le_bytes(bytes[0..2].try_into().unwrap());
+
+        // Only regular files use the high half of the size, which directories use for their ACL
+        let size_high = match mode & MODE_TYPE_MASK {
+            MODE_REGULAR => u32_at(108) as u64,
+            _ => 0,
+        };
+
+        Inode {
+            mode,
+            size: (size_high << 32) | u32_at(4) as u64,
+            sectors: u32_at(28),
+            file_acl: u32_at(104),
+            block: bytes[40..40 + BLOCK_POINTERS * 4].try_into().unwrap(),
+        }
+    }
+
+    fn kind(&self) -> FileKind {
+        match self.mode & MODE_TYPE_MASK {
+            MODE_DIRECTORY => FileKind::Directory,
+            MODE_SYMLINK => FileKind::Symlink,
+            _ => FileKind::File,
+        }
+    }
+
+    fn pointer(&self, index: usize) -> u32 {
+        u32::from_le_bytes(self.block[index * 4..index * 4 + 4].try_into().unwrap())
+    }
+
+    /// The size shown for the file, which is 0 for directories
+    fn stat(&self) -> Stat {
+        let kind = self.kind();
+
+        let size = match kind {
+            FileKind::Directory => 0,
+            _ => self.size,
+        };
+
+        Stat { kind, size }
+    }
+}
+
+/// An entry read from a directory
+struct Record {
+    inode: u32,
+    name: [u8; MAX_NAME],
+    name_len: usize,
+    /// The byte offset of the entry after it in the directory
+    next: u64,
+}
+
+impl Record {
+    fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+
+    fn is_dot(&self) -> bool {
+        self.name() == b"." || self.name() == b".."
+    }
+}
+
+/// The block which was last read through a cache
+struct Cache {
+    block: Option<u64>,
+    data: [u8; MAX_BLOCK_SIZE],
+}
+
+impl Cache {
+    fn new() -> Cache {
+        Cache {
+            block: None,
+            data: [0; MAX_BLOCK_SIZE],
+        }
+    }
+}
+
+pub struct Ext2Fs<D: BlockDevice> {
+    device: D,
+    /// The device sector which the filesystem starts at
+    start: u64,
+    block_size: u64,
+    blocks_count: u64,
+    inodes_count: u32,
+    inodes_per_group: u32,
+    inode_size: u64,
+    group_count: u32,
+    /// The block which the block group descriptor table starts at
+    group_descriptors: u64,
+    caches: [Cache; 1 + INDIRECT_LEVELS],
+    open: [Option<Inode>; MAX_OPEN],
+}
+
+/// Checks that the device's sectors can hold a superblock, and fit in a block
+fn check_sector_size<D: BlockDevice>(device: &D) -> Result<usize, Error> {
+    match device.info().sector_size as usize {
+        size @ 512..=MAX_BLOCK_SIZE => Ok(size),
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+/// Reads the superblock of a filesystem starting at the given device sector
+fn read_superblock<D: BlockDevice>(
+    device: &mut D,
+    start: u64,
+) -> Result<[u8; SUPERBLOCK_SIZE], Error> {
+    let sector_size = check_sector_size(device)? as u64;
+    let within = (SUPERBLOCK_OFFSET % sector_size) as usize;
+    let len =
+        (within as u64 + SUPERBLOCK_SIZE as u64 + sector_size - 1) / sector_size * sector_size;
+    let mut sectors = [0; MAX_BLOCK_SIZE];
+
+    device
+        .read(
+            start + SUPERBLOCK_OFFSET / sector_size,
+            &mut sectors[..len as usize],
+        )
+        .map_err(|_| Error::Io)?;
+
+    Ok(sectors[within..within + SUPERBLOCK_SIZE]
+        .try_into()
+        .unwrap())
+}
+
+fn is_superblock(superblock: &[u8]) -> bool {
+    u16::from_le_bytes(superblock[56..58].try_into().unwrap()) == MAGIC
+}
+
+impl<D: BlockDevice> Ext2Fs<D> {
+    /// Mounts the filesystem on the device, or in the first Linux partition of its MBR. Fails with
+    /// `Error::InvalidArgument` if there is none.
+    pub fn new(mut device: D) -> Result<Ext2Fs<D>, Error> {
+        if is_superblock(&read_superblock(&mut device, 0)?) {
+            return Ext2Fs::with_start(device, 0);
+        }
+
+        let sector_size = check_sector_size(&device)?;
+        let mut sector = [0; MAX_BLOCK_SIZE];
+        device
+            .read(0, &mut sector[..sector_size])
+            .map_err(|_| Error::Io)?;
+
+        let start = block::mbr_partition(&sector[..sector_size], &[MBR_LINUX_TYPE]);
+        Ext2Fs::with_start(device, start.ok_or(Error::InvalidArgument)?)
+    }
+
+    /// Mounts the filesystem starting at the given sector of the device. Fails with
+    /// `Error::InvalidArgument` if it isn't an ext2 filesystem, or uses features which can't be
+    /// read.
+    pub fn with_start(mut device: D, start: u64) -> Result<Ext2Fs<D>, Error> {
+        let superblock = read_superblock(&mut device, start)?;
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(superblock[offset..offset + 4].try_into().unwrap());
+
+        if !is_superblock(&superblock) {
+            return Err(Error::InvalidArgument);
+        }
+
+        let blocks_count = u32_at(4) as u64;
+        let first_data_block = u32_at(20) as u64;
+        let blocks_per_group = u32_at(32) as u64;
+        let inodes_per_group = u32_at(40);
+        let revision = u32_at(76);
+
+        let block_size = match u32_at(24) {
+            log @ 0..=2 => 1024 << log,
+            _ => return Err(Error::InvalidArgument),
+        };
+
+        let (inode_size, incompat) = match revision {
+            0 => (GOOD_OLD_INODE_SIZE as u64, 0),
+            _ => (
+                u16::from_le_bytes(superblock[88..90].try_into().unwrap()) as u64,
+                u32_at(96),
+            ),
+        };
+
+        if block_size < device.info().sector_size as u64
+            || incompat & !SUPPORTED_INCOMPAT != 0
+            || blocks_per_group == 0
+            || inodes_per_group == 0
+            || inode_size < GOOD_OLD_INODE_SIZE as u64
+            || inode_size > block_size
+            || !inode_size.is_power_of_two()
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        let group_blocks = blocks_count
+            .checked_sub(first_data_block)
+            .ok_or(Error::InvalidArgument)?;
+
+        Ok(Ext2Fs {
+            device,
+            start,
+            block_size,
+            blocks_count,
+            inodes_count: u32_at(0),
+            inodes_per_group,
+            inode_size,
+            group_count: ((group_blocks + blocks_per_group - 1) / blocks_per_group) as u32,
+            // The descriptors are in the block after the superblock
+            group_descriptors: first_data_block + 1,
+            caches: [Cache::new(), Cache::new(), Cache::new(), Cache::new()],
+            open: [None; MAX_OPEN],
+        })
+    }
+
+    /// Reads a block of the filesystem through the given cache
+    fn load(&mut self, cache: usize, block: u64) -> Result<&[u8], Error> {
+        let block_size = self.block_size as usize;
+        let cache = &mut self.caches[cache];
+
+        if cache.block != Some(block) {
+            if block >= self.blocks_count {
+                return Err(Error::Io);
+            }
+
+            cache.block = None;
+            let device_sectors = self.block_size / self.device.info().sector_size as u64;
+            self.device
+                .read(
+                    self.start + block * device_sectors,
+                    &mut cache.data[..block_size],
+                )
+                .map_err(|_| Error::Io)?;
+            cache.block = Some(block);
+        }
+
+        Ok(&cache.data[..block_size])
+    }
+
+    /// Reads from the given byte offset into the filesystem, which must not cross a block
+    fn read_bytes(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        let within = (offset % self.block_size) as usize;
+        let data = self.load(DATA_CACHE, offset / self.block_size)?;
+        buf.copy_from_slice(&data[within..within + buf.len()]);
+        Ok(())
+    }
+
+    fn inode(&mut self, number: u32) -> Result<Inode, Error> {
+        if number == 0 || number > self.inodes_count {
+            return Err(Error::Io);
+        }
+
+        let group = (number - 1) / self.inodes_per_group;
+        let index = ((number - 1) % self.inodes_per_group) as u64;
+
+        if group >= self.group_count {
+            return Err(Error::Io);
+        }
+
+        let mut descriptor = [0; GROUP_DESCRIPTOR_SIZE as usize];
+        let descriptor_offset =
+            self.group_descriptors * self.block_size + group as u64 * GROUP_DESCRIPTOR_SIZE;
+        self.read_bytes(descriptor_offset, &mut descriptor)?;
+        let inode_table = u32::from_le_bytes(descriptor[8..12].try_into().unwrap()) as u64;
+
+        let mut bytes = [0; GOOD_OLD_INODE_SIZE];
+        let offset = inode_table * self.block_size + index * self.inode_size;
+        self.read_bytes(offset, &mut bytes)?;
+        Ok(Inode::parse(&bytes))
+    }
+
+    /// Whether the inode is a symbolic link whose target is kept in place of its block pointers
+    fn is_fast_symlink(&self, inode: &Inode) -> bool {
+        let acl_sectors = match inode.file_acl {
+            0 => 0,
+            _ => (self.block_size / 512) as u32,
+        };
+
+        inode.kind() == FileKind::Symlink && inode.sectors == acl_sectors
+    }
+
+    /// Finds the block which holds the block of the file at the given index, or returns None if
+    /// that block is a hole
+    fn block_of(&mut self, inode: &Inode, index: u64) -> Result<Option<u64>, Error> {
+        let per_block = self.block_size / 4;
+
+        let (mut block, levels, mut index) = if index < DIRECT_BLOCKS {
+            (inode.pointer(index as usize), 0, 0)
+        } else {
+            let mut index = index - DIRECT_BLOCKS;
+            let mut levels = 1;
+            let mut span = per_block;
+
+            while index >= span {
+                index -= span;
+                levels += 1;
+                span *= per_block;
+
+                if levels > INDIRECT_LEVELS {
+                    return Err(Error::Io);
+                }
+            }
+
+            (
+                inode.pointer(DIRECT_BLOCKS as usize + levels - 1),
+                levels,
+                index,
+            )
+        };
+
+        for level in 0..levels {
+            if block == 0 {
+                return Ok(None);
+            }
+
+            let span = per_block.pow((levels - level - 1) as u32);
+            let slot = (index / span) as usize * 4;
+            index %= span;
+
+            let table = self.load(DATA_CACHE + 1 + level, block as u64)?;
+            block = u32::from_le_bytes(table[slot..slot + 4].try_into().unwrap());
+        }
+
+        Ok(Some(block as u64).filter(|&block| block != 0))
+    }
+
+    /// Reads the file's data from the given offset, returning the length read
+    fn read_data(&mut self, inode: &Inode, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        if offset >= inode.size {
+            return Ok(0);
+        }
+
+        let len = (buf.len() as u64).min(inode.size - offset) as usize;
+
+        if self.is_fast_symlink(inode) {
+            let target = inode.block.get(offset as usize..offset as usize + len);
+            buf[..len].copy_from_slice(target.ok_or(Error::Io)?);
+            return Ok(len);
+        }
+
+        let mut done = 0;
+
+        while done < len {
+            let position = offset + done as u64;
+            let within = (position % self.block_size) as usize;
+            let chunk = (self.block_size as usize - within).min(len - done);
+
+            match self.block_of(inode, position / self.block_size)? {
+                Some(block) => {
+                    let data = self.load(DATA_CACHE, block)?;
+                    buf[done..done + chunk].copy_from_slice(&data[within..within + chunk]);
+                }
+                None => buf[done..done + chunk]
+                    .iter_mut()
+                    .for_each(|byte| *byte = 0),
+            }
+
+            done += chunk;
+        }
+
+        Ok(len)
+    }
+
+    /// Reads the entry of the directory at or after the given byte offset into it, skipping unused
+    /// entries
+    fn next_entry(&mut self, dir: &Inode, position: u64) -> Result<Option<Record>, Error> {
+        let mut position = position;
+
+        while position < dir.size {
+            let mut header = [0; DIR_ENTRY_HEADER_SIZE];
+
+            if self.read_data(dir, position, &mut header)? < DIR_ENTRY_HEADER_SIZE {
+                return Err(Error::Io);
+            }
+
+            let inode = u32::from_le_bytes(header[0..4].try_into().unwrap());
+            let rec_len = u16::from_le_bytes(header[4..6].try_into().unwrap()) as u64;
+            // Without the filetype feature, this is the low byte of a 16 bit length, but names
+            // are never longer than 255 bytes
+            let name_len = header[6] as usize;
+            let block_end = (position / self.block_size + 1) * self.block_size;
+
+            // Entries never cross a block
+            if rec_len < (DIR_ENTRY_HEADER_SIZE + name_len) as u64 || position + rec_len > block_end
+            {
+                return Err(Error::Io);
+            }
+
+            let next = position + rec_len;
+
+            if inode != 0 {
+                let mut record = Record {
+                    inode,
+                    name: [0; MAX_NAME],
+                    name_len,
+                    next,
+                };
+
+                let name_offset = position + DIR_ENTRY_HEADER_SIZE as u64;
+                self.read_data(dir, name_offset, &mut record.name[..name_len])?;
+                return Ok(Some(record));
+            }
+
+            position = next;
+        }
+
+        Ok(None)
+    }
+
+    /// Finds the inode of the entry with the given name in a directory
+    fn find(&mut self, dir: &Inode, name: &[u8]) -> Result<Option<u32>, Error> {
+        let mut position = 0;
+
+        while let Some(record) = self.next_entry(dir, position)? {
+            if record.name() == name {
+                return Ok(Some(record.inode));
+            }
+
+            position = record.next;
+        }
+
+        Ok(None)
+    }
+
+    /// Finds the inode at the path, following symbolic links on the way to it, and the one it
+    /// names if `follow` is set. Absolute targets are looked up from the root of this filesystem,
+    /// which is right when it is mounted at `/`.
+    fn lookup(&mut self, path: &str, follow: bool) -> Result<u32, Error> {
+        if path.len() > MAX_PATH {
+            return Err(Error::InvalidArgument);
+        }
+
+        // The rest of the path, after the links which have been followed are put in its place
+        let mut path_buf = [0; MAX_PATH];
+        let mut len = path.len();
+        path_buf[..len].copy_from_slice(path.as_bytes());
+
+        let mut start = 0;
+        let mut current = ROOT_INODE;
+        let mut links = 0;
+
+        loop {
+            while start < len && path_buf[start] == b'/' {
+                start += 1;
+            }
+
+            if start == len {
+                return Ok(current);
+            }
+
+            let end = path_buf[start..len]
+                .iter()
+                .position(|&byte| byte == b'/')
+                .map_or(len, |slash| start + slash);
+
+            let dir = self.inode(current)?;
+
+            if dir.kind() != FileKind::Directory {
+                return Err(Error::NotADirectory);
+            }
+
+            let child = self.find(&dir, &path_buf[start..end])?;
+            let child = child.ok_or(Error::NotFound)?;
+            let inode = self.inode(child)?;
+            let last = path_buf[end..len].iter().all(|&byte| byte == b'/');
+
+            if inode.kind() != FileKind::Symlink || (last && !follow) {
+                current = child;
+                start = end;
+                continue;
+            }
+
+            links += 1;
+
+            if links > MAX_SYMLINKS {
+                return Err(Error::SymlinkLoop);
+            }
+
+            let rest = len - end;
+
+            if inode.size == 0 || inode.size as usize + rest > MAX_PATH {
+                return Err(Error::InvalidArgument);
+            }
+
+            let target_len = inode.size as usize;
+            let mut target = [0; MAX_PATH];
+            self.read_data(&inode, 0, &mut target[..target_len])?;
+
+            path_buf.copy_within(end..len, target_len);
+            path_buf[..target_len].copy_from_slice(&target[..target_len]);
+            len = target_len + rest;
+            start = 0;
+
+            if target[0] == b'/' {
+                current = ROOT_INODE;
+            }
+        }
+    }
+
+    fn get(&self, handle: u64) -> Result<Inode, Error> {
+        self.open
+            .get(handle as usize)
+            .copied()
+            .flatten()
+            .ok_or(Error::BadHandle)
+    }
+}
+
+impl<D: BlockDevice> FileSystem for Ext2Fs<D> {
+    fn open(&mut self, path: &str, flags: OpenFlags) -> Result<u64, Error> {
+        if flags.intersects(OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE) {
+            return Err(Error::ReadOnly);
+        }
+
+        let handle = self.open.iter().position(|slot| slot.is_none());
+        let handle = handle.ok_or(Error::TooManyOpen)?;
+
+        let number = self.lookup(path, !flags.contains(OpenFlags::NO_FOLLOW))?;
+        let inode = self.inode(number)?;
+
+        if flags.contains(OpenFlags::DIRECTORY) && inode.kind() != FileKind::Directory {
+            return Err(Error::NotADirectory);
+        }
+
+        self.open[handle] = Some(inode);
+        Ok(handle as u64)
+    }
+
+    /// Reading a symbolic link, opened with `OpenFlags::NO_FOLLOW`, gives its target
+    fn read(&mut self, handle: u64, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        let inode = self.get(handle)?;
+
+        if inode.kind() == FileKind::Directory {
+            return Err(Error::IsADirectory);
+        }
+
+        self.read_data(&inode, offset, buf)
+    }
+
+    fn write(&mut self, _handle: u64, _offset: u64, _buf: &[u8]) -> Result<usize, Error> {
+        Err(Error::ReadOnly)
+    }
+
+    fn stat(&mut self, handle: u64) -> Result<Stat, Error> {
+        Ok(self.get(handle)?.stat())
+    }
+
+    /// The position is the byte offset of the next entry in the directory. Names which aren't
+    /// UTF-8 can't be in a path, so are skipped.
+    fn read_dir(&mut self, handle: u64, position: u64) -> Result<Option<(DirEntry, u64)>, Error> {
+        let dir = self.get(handle)?;
+
+        if dir.kind() != FileKind::Directory {
+            return Err(Error::NotADirectory);
+        }
+
+        let mut position = position;
+
+        while let Some(record) = self.next_entry(&dir, position)? {
+            position = record.next;
+
+            let name = match str::from_utf8(record.name()) {
+                Ok(name) if !record.is_dot() => name,
+                _ => continue,
+            };
+
+            let stat = self.inode(record.inode)?.stat();
+            let entry = DirEntry::new(stat.kind, stat.size, name)?;
+            return Ok(Some((entry, position)));
+        }
+
+        Ok(None)
+    }
+
+    fn close(&mut self, handle: u64) -> Result<(), Error> {
+        let slot = self.open.get_mut(handle as usize).ok_or(Error::BadHandle)?;
+        slot.take().map(|_| ()).ok_or(Error::BadHandle)
+    }
+}
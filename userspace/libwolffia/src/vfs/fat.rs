@@ -0,0 +1,1404 @@
+//! FAT12, FAT16 and FAT32 filesystems on a block device, with long file names. The filesystem can
+//! be on the whole device, as made by `mkfs.fat -C`, or in a partition in an MBR.
+//!
+//! Sectors are read through a cache of one sector, and written straight through to the device, so
+//! the filesystem is consistent on disk after each request.
+
+use super::server::FileSystem;
+use super::{DirEntry, Error, FileKind, OpenFlags, Stat};
+use crate::block::{self, BlockDevice};
+use crate::syscall::{self, Clock, SyscallError};
+use core::char;
+use core::convert::TryInto;
+use core::str;
+
+/// The most files which can be open at once
+pub const MAX_OPEN: usize = 32;
+
+/// The largest sector size which can be used
+const MAX_SECTOR_SIZE: usize = 4096;
+const DIR_ENTRY_SIZE: u64 = 32;
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+/// Long name entries have these attributes, which no file can
+const ATTR_LONG_NAME: u8 = 0x0f;
+const ATTR_LONG_NAME_MASK: u8 = 0x3f;
+
+/// The first byte of a deleted entry
+const ENTRY_DELETED: u8 = 0xe5;
+/// The first byte of the entry after the last in a directory
+const ENTRY_END: u8 = 0x00;
+/// Stands for a first byte of 0xe5 in short names
+const ENTRY_KANJI_E5: u8 = 0x05;
+
+/// Set in the sequence number of the long name entry which holds the end of the name, which comes
+/// first in the directory
+const LFN_LAST: u8 = 0x40;
+const LFN_SEQUENCE_MASK: u8 = 0x1f;
+/// The UTF-16 code units in each long name entry
+const LFN_UNITS: usize = 13;
+/// The most UTF-16 code units in a long name
+const MAX_LFN: usize = 255;
+/// The offsets of the code units in a long name entry
+const LFN_UNIT_OFFSETS: [usize; LFN_UNITS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// Set in the reserved byte of a short entry by Windows NT when the base or extension of the name
+/// is lower case
+const NT_LOWER_BASE: u8 = 0x08;
+const NT_LOWER_EXTENSION: u8 = 0x10;
+
+/// Characters which can't be in any name
+const INVALID_CHARS: &str = "\"*/:<>?\\|";
+/// Characters other than upper case letters and digits which can be in short names
+const SHORT_NAME_SPECIAL: &str = "!#$%&'()-@^_`{}~";
+
+const BOOT_SIGNATURE_OFFSET: usize = 510;
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+/// The MBR partition types of FAT filesystems
+const MBR_FAT_TYPES: [u8; 6] = [0x01, 0x04, 0x06, 0x0b, 0x0c, 0x0e];
+
+const FS_INFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FS_INFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FS_INFO_STRUCT_SIGNATURE_OFFSET: u64 = 484;
+const FS_INFO_FREE_COUNT_OFFSET: u64 = 488;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FatKind {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatKind {
+    /// FAT entries at least this mark the end of a chain
+    fn end_of_chain(self) -> u32 {
+        match self {
+            FatKind::Fat12 => 0xff8,
+            FatKind::Fat16 => 0xfff8,
+            FatKind::Fat32 => 0x0fff_fff8,
+        }
+    }
+
+    /// The FAT entry written at the end of a chain
+    fn end_marker(self) -> u32 {
+        match self {
+            FatKind::Fat12 => 0xfff,
+            FatKind::Fat16 => 0xffff,
+            FatKind::Fat32 => 0x0fff_ffff,
+        }
+    }
+}
+
+/// Where the entries of a directory are
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Dir {
+    /// The root directory of FAT12 and FAT16, which is in a fixed region before the clusters
+    FixedRoot,
+    /// A directory in the chain of clusters starting at the given one
+    Chain(u32),
+}
+
+/// A short directory entry, which every file has
+#[derive(Debug, Copy, Clone)]
+struct ShortEntry {
+    name: [u8; 11],
+    attr: u8,
+    nt_flags: u8,
+    /// The first cluster, or 0 if the file is empty
+    cluster: u32,
+    size: u32,
+}
+
+impl ShortEntry {
+    fn parse(bytes: &[u8]) -> ShortEntry {
+        let cluster_high = u16::from_le_bytes(bytes[20..22].try_into().unwrap()) as u32;
+        let cluster_low = u16::from_le_bytes(bytes[26..28].try_into().unwrap()) as u32;
+
+        ShortEntry {
+            name: bytes[0..11].try_into().unwrap(),
+            attr: bytes[11],
+            nt_flags: bytes[12],
+            cluster: (cluster_high << 16) | cluster_low,
+            size: u32::from_le_bytes(bytes[28..32].try_into().unwrap()),
+        }
+    }
+
+    fn encode(&self, (time, date): (u16, u16)) -> [u8; DIR_ENTRY_SIZE as usize] {
+        let mut bytes = [0; DIR_ENTRY_SIZE as usize];
+        bytes[0..11].copy_from_slice(&self.name);
+        bytes[11] = self.attr;
+        bytes[12] = self.nt_flags;
+        // Created, accessed and modified
+        bytes[14..16].copy_from_slice(&time.to_le_bytes());
+        bytes[16..18].copy_from_slice(&date.to_le_bytes());
+        bytes[18..20].copy_from_slice(&date.to_le_bytes());
+        bytes[20..22].copy_from_slice(&((self.cluster >> 16) as u16).to_le_bytes());
+        bytes[22..24].copy_from_slice(&time.to_le_bytes());
+        bytes[24..26].copy_from_slice(&date.to_le_bytes());
+        bytes[26..28].copy_from_slice(&(self.cluster as u16).to_le_bytes());
+        bytes[28..32].copy_from_slice(&self.size.to_le_bytes());
+        bytes
+    }
+
+    fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    /// Whether this is the `.` or `..` entry of a directory
+    fn is_dot(&self) -> bool {
+        &self.name == b".          " || &self.name == b"..         "
+    }
+
+    /// The name as it is displayed, e.g `README.TXT`
+    fn display_name(&self) -> Name {
+        let mut name = Name::new();
+        let base = trim_spaces(&self.name[0..8]);
+        let extension = trim_spaces(&self.name[8..11]);
+
+        for (i, &byte) in base.iter().enumerate() {
+            let byte = if i == 0 && byte == ENTRY_KANJI_E5 {
+                ENTRY_DELETED
+            } else {
+                byte
+            };
+
+            name.push(short_name_char(byte, self.nt_flags & NT_LOWER_BASE != 0));
+        }
+
+        if !extension.is_empty() {
+            name.push('.');
+
+            for &byte in extension {
+                name.push(short_name_char(
+                    byte,
+                    self.nt_flags & NT_LOWER_EXTENSION != 0,
+                ));
+            }
+        }
+
+        name
+    }
+}
+
+fn trim_spaces(bytes: &[u8]) -> &[u8] {
+    let len = bytes.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+    &bytes[..len]
+}
+
+/// Short names are in an OEM code page, of which only ASCII is understood
+fn short_name_char(byte: u8, lower: bool) -> char {
+    match byte {
+        b if b.is_ascii() && lower => b.to_ascii_lowercase() as char,
+        b if b.is_ascii() => b as char,
+        _ => char::REPLACEMENT_CHARACTER,
+    }
+}
+
+/// The checksum of a short name, which its long name entries carry
+fn checksum(name: &[u8; 11]) -> u8 {
+    name.iter()
+        .fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+}
+
+/// The name of a directory entry, as UTF-8
+#[derive(Copy, Clone)]
+struct Name {
+    /// Long enough for any long name, each of whose code units is up to 3 bytes of UTF-8
+    buf: [u8; MAX_LFN * 3],
+    len: usize,
+}
+
+impl Name {
+    fn new() -> Name {
+        Name {
+            buf: [0; MAX_LFN * 3],
+            len: 0,
+        }
+    }
+
+    fn from_utf16(units: &[u16]) -> Name {
+        let mut name = Name::new();
+
+        for c in char::decode_utf16(units.iter().copied()) {
+            name.push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+
+        name
+    }
+
+    fn push(&mut self, c: char) {
+        let len = c.len_utf8();
+
+        if self.len + len <= self.buf.len() {
+            c.encode_utf8(&mut self.buf[self.len..]);
+            self.len += len;
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // SAFETY: only whole chars are pushed
+        unsafe { str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+}
+
+/// Collects the long name entries before a short entry
+struct LongName {
+    units: [u16; LFN_UNITS * LFN_SEQUENCE_MASK as usize],
+    /// The checksum of the short name and the sequence number of the next entry, while the entries
+    /// so far are valid
+    expected: Option<(u8, u8)>,
+}
+
+impl LongName {
+    fn new() -> LongName {
+        LongName {
+            units: [0xffff; LFN_UNITS * LFN_SEQUENCE_MASK as usize],
+            expected: None,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        let sequence = bytes[0] & LFN_SEQUENCE_MASK;
+        let checksum = bytes[13];
+
+        if bytes[0] & LFN_LAST != 0 {
+            self.units = [0xffff; LFN_UNITS * LFN_SEQUENCE_MASK as usize];
+            self.expected = Some((checksum, sequence));
+        }
+
+        if sequence == 0 || self.expected != Some((checksum, sequence)) {
+            self.expected = None;
+            return;
+        }
+
+        let start = (sequence as usize - 1) * LFN_UNITS;
+
+        for (i, &offset) in LFN_UNIT_OFFSETS.iter().enumerate() {
+            self.units[start + i] =
+                u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap());
+        }
+
+        self.expected = Some((checksum, sequence - 1));
+    }
+
+    /// Returns the name, if all of its entries were seen and it belongs to the short name
+    fn finish(&mut self, short: &[u8; 11]) -> Option<Name> {
+        let expected = self.expected.take();
+
+        if expected != Some((checksum(short), 0)) {
+            return None;
+        }
+
+        let len = self.units.iter().position(|&u| u == 0 || u == 0xffff);
+        let len = len.unwrap_or(self.units.len()).min(MAX_LFN);
+        Some(Name::from_utf16(&self.units[..len]))
+    }
+}
+
+/// An entry found in a directory
+#[derive(Copy, Clone)]
+struct Found {
+    entry: ShortEntry,
+    /// The byte offset of the short entry
+    offset: u64,
+    /// The index of the entry after it in the directory
+    next: u32,
+    name: Name,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct OpenFile {
+    /// The byte offset of its short entry, or None for the root directory, which has none
+    entry: Option<u64>,
+    dir: bool,
+    writable: bool,
+}
+
+/// The sector which was last read
+struct Cache {
+    sector: Option<u64>,
+    data: [u8; MAX_SECTOR_SIZE],
+}
+
+pub struct FatFs<D: BlockDevice> {
+    device: D,
+    kind: FatKind,
+    /// The device sector which the filesystem starts at
+    start: u64,
+    /// The size of the filesystem's sectors, which can be a multiple of the device's
+    bytes_per_sector: u64,
+    sectors_per_cluster: u64,
+    reserved_sectors: u64,
+    fat_count: u64,
+    sectors_per_fat: u64,
+    /// The entries in the fixed root directory of FAT12 and FAT16
+    root_entries: u32,
+    /// The first cluster of the root directory of FAT32
+    root_cluster: u32,
+    first_data_sector: u64,
+    cluster_count: u32,
+    /// The sector of FAT32's hint of the free clusters, if there is one
+    fs_info_sector: Option<u64>,
+    /// Whether the free cluster count has been marked unknown since this started allocating
+    fs_info_invalidated: bool,
+    /// Where to start looking for a free cluster
+    next_free: u32,
+    cache: Cache,
+    open: [Option<OpenFile>; MAX_OPEN],
+}
+
+fn device_error(err: SyscallError) -> Error {
+    match err {
+        SyscallError::PermissionDenied => Error::ReadOnly,
+        _ => Error::Io,
+    }
+}
+
+/// Checks that the device's sectors can hold a boot sector, and fit in the cache
+fn check_sector_size<D: BlockDevice>(device: &D) -> Result<usize, Error> {
+    match device.info().sector_size as usize {
+        size @ 512..=MAX_SECTOR_SIZE => Ok(size),
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+/// Checks that a sector looks like the boot sector of a FAT filesystem
+fn is_boot_sector(sector: &[u8]) -> bool {
+    let bytes_per_sector = u16::from_le_bytes(sector[11..13].try_into().unwrap());
+    let sectors_per_cluster = sector[13];
+    let reserved_sectors = u16::from_le_bytes(sector[14..16].try_into().unwrap());
+
+    (sector[0] == 0xeb || sector[0] == 0xe9)
+        && sector[BOOT_SIGNATURE_OFFSET..BOOT_SIGNATURE_OFFSET + 2] == BOOT_SIGNATURE
+        && [512, 1024, 2048, 4096].contains(&bytes_per_sector)
+        && sectors_per_cluster.is_power_of_two()
+        && reserved_sectors > 0
+        && sector[16] > 0
+}
+
+impl<D: BlockDevice> FatFs<D> {
+    /// Mounts the filesystem on the device, or in the first FAT partition of its MBR. Fails with
+    /// `Error::InvalidArgument` if there is none.
+    pub fn new(mut device: D) -> Result<FatFs<D>, Error> {
+        let sector_size = check_sector_size(&device)?;
+        let mut sector = [0; MAX_SECTOR_SIZE];
+        device
+            .read(0, &mut sector[..sector_size])
+            .map_err(device_error)?;
+
+        if is_boot_sector(&sector) {
+            return FatFs::with_start(device, 0);
+        }
+
+        let start = block::mbr_partition(&sector[..sector_size], &MBR_FAT_TYPES);
+        FatFs::with_start(device, start.ok_or(Error::InvalidArgument)?)
+    }
+
+    /// Mounts the filesystem starting at the given sector of the device
+    pub fn with_start(mut device: D, start: u64) -> Result<FatFs<D>, Error> {
+        let device_sector_size = check_sector_size(&device)? as u64;
+        let mut sector = [0; MAX_SECTOR_SIZE];
+        let boot = &mut sector[..device_sector_size as usize];
+        device.read(start, boot).map_err(device_error)?;
+
+        if !is_boot_sector(boot) {
+            return Err(Error::InvalidArgument);
+        }
+
+        let u16_at =
+            |offset: usize| u16::from_le_bytes(boot[offset..offset + 2].try_into().unwrap());
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(boot[offset..offset + 4].try_into().unwrap());
+
+        let bytes_per_sector = u16_at(11) as u64;
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved_sectors = u16_at(14) as u64;
+        let fat_count = boot[16] as u64;
+        let root_entries = u16_at(17) as u32;
+        let total_sectors = match u16_at(19) {
+            0 => u32_at(32) as u64,
+            total => total as u64,
+        };
+        let sectors_per_fat = match u16_at(22) {
+            0 => u32_at(36) as u64,
+            size => size as u64,
+        };
+
+        if bytes_per_sector % device_sector_size != 0 {
+            return Err(Error::InvalidArgument);
+        }
+
+        let root_sectors =
+            (root_entries as u64 * DIR_ENTRY_SIZE + bytes_per_sector - 1) / bytes_per_sector;
+        let first_data_sector = reserved_sectors + fat_count * sectors_per_fat + root_sectors;
+        let data_sectors = total_sectors
+            .checked_sub(first_data_sector)
+            .ok_or(Error::InvalidArgument)?;
+        let cluster_count = (data_sectors / sectors_per_cluster) as u32;
+
+        // The kind of FAT is decided only by the number of clusters
+        let kind = match cluster_count {
+            0..=4084 => FatKind::Fat12,
+            4085..=65524 => FatKind::Fat16,
+            _ => FatKind::Fat32,
+        };
+
+        let (root_cluster, fs_info_sector) = match kind {
+            FatKind::Fat32 => (u32_at(44), Some(u16_at(48) as u64).filter(|&s| s != 0)),
+            _ => (0, None),
+        };
+
+        Ok(FatFs {
+            device,
+            kind,
+            start,
+            bytes_per_sector,
+            sectors_per_cluster,
+            reserved_sectors,
+            fat_count,
+            sectors_per_fat,
+            root_entries,
+            root_cluster,
+            first_data_sector,
+            cluster_count,
+            fs_info_sector,
+            fs_info_invalidated: false,
+            next_free: 2,
+            cache: Cache {
+                sector: None,
+                data: [0; MAX_SECTOR_SIZE],
+            },
+            open: [None; MAX_OPEN],
+        })
+    }
+
+    pub fn kind(&self) -> FatKind {
+        self.kind
+    }
+
+    /// Reads the given filesystem sector into the cache
+    fn load(&mut self, sector: u64) -> Result<(), Error> {
+        if self.cache.sector == Some(sector) {
+            return Ok(());
+        }
+
+        self.cache.sector = None;
+        let device_sectors = self.bytes_per_sector / self.device.info().sector_size as u64;
+        let buf = &mut self.cache.data[..self.bytes_per_sector as usize];
+        self.device
+            .read(self.start + sector * device_sectors, buf)
+            .map_err(device_error)?;
+        self.cache.sector = Some(sector);
+        Ok(())
+    }
+
+    /// Reads from the given byte offset into the filesystem
+    fn read_bytes(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        let mut done = 0;
+
+        while done < buf.len() {
+            let position = offset + done as u64;
+            let within = (position % self.bytes_per_sector) as usize;
+            let len = (self.bytes_per_sector as usize - within).min(buf.len() - done);
+
+            self.load(position / self.bytes_per_sector)?;
+            buf[done..done + len].copy_from_slice(&self.cache.data[within..within + len]);
+            done += len;
+        }
+
+        Ok(())
+    }
+
+    /// Writes at the given byte offset into the filesystem, straight through to the device
+    fn write_bytes(&mut self, offset: u64, buf: &[u8]) -> Result<(), Error> {
+        let sector_size = self.bytes_per_sector as usize;
+        let device_sectors = self.bytes_per_sector / self.device.info().sector_size as u64;
+        let mut done = 0;
+
+        while done < buf.len() {
+            let position = offset + done as u64;
+            let sector = position / self.bytes_per_sector;
+            let within = (position % self.bytes_per_sector) as usize;
+            let len = (sector_size - within).min(buf.len() - done);
+
+            // Whole sectors don't need to be read first
+            if len == sector_size {
+                self.cache.sector = Some(sector);
+            } else {
+                self.load(sector)?;
+            }
+
+            self.cache.data[within..within + len].copy_from_slice(&buf[done..done + len]);
+
+            let res = self.device.write(
+                self.start + sector * device_sectors,
+                &self.cache.data[..sector_size],
+            );
+
+            if let Err(err) = res {
+                self.cache.sector = None;
+                return Err(device_error(err));
+            }
+
+            done += len;
+        }
+
+        Ok(())
+    }
+
+    fn cluster_size(&self) -> u64 {
+        self.sectors_per_cluster * self.bytes_per_sector
+    }
+
+    /// The byte offset of a cluster. Clusters are numbered from 2.
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        let sector = self.first_data_sector + (cluster as u64 - 2) * self.sectors_per_cluster;
+        sector * self.bytes_per_sector
+    }
+
+    fn root_dir(&self) -> Dir {
+        match self.kind {
+            FatKind::Fat32 => Dir::Chain(self.root_cluster),
+            _ => Dir::FixedRoot,
+        }
+    }
+
+    /// The directory whose first cluster is given. `..` entries which lead to the root have a
+    /// cluster of 0.
+    fn dir_at(&self, cluster: u32) -> Dir {
+        if cluster == 0 {
+            self.root_dir()
+        } else {
+            Dir::Chain(cluster)
+        }
+    }
+
+    fn fat_entry(&mut self, cluster: u32) -> Result<u32, Error> {
+        let fat = self.reserved_sectors * self.bytes_per_sector;
+        let cluster = cluster as u64;
+
+        match self.kind {
+            FatKind::Fat12 => {
+                let mut bytes = [0; 2];
+                self.read_bytes(fat + cluster * 3 / 2, &mut bytes)?;
+                let value = u16::from_le_bytes(bytes);
+
+                // Entries are 12 bits, packed two to every three bytes
+                let value = if cluster & 1 == 1 {
+                    value >> 4
+                } else {
+                    value & 0xfff
+                };
+
+                Ok(value as u32)
+            }
+            FatKind::Fat16 => {
+                let mut bytes = [0; 2];
+                self.read_bytes(fat + cluster * 2, &mut bytes)?;
+                Ok(u16::from_le_bytes(bytes) as u32)
+            }
+            FatKind::Fat32 => {
+                let mut bytes = [0; 4];
+                self.read_bytes(fat + cluster * 4, &mut bytes)?;
+                Ok(u32::from_le_bytes(bytes) & 0x0fff_ffff)
+            }
+        }
+    }
+
+    /// Sets an entry in every copy of the FAT
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), Error> {
+        let cluster = cluster as u64;
+
+        for copy in 0..self.fat_count {
+            let fat = (self.reserved_sectors + copy * self.sectors_per_fat) * self.bytes_per_sector;
+
+            match self.kind {
+                FatKind::Fat12 => {
+                    let offset = fat + cluster * 3 / 2;
+                    let mut bytes = [0; 2];
+                    self.read_bytes(offset, &mut bytes)?;
+                    let old = u16::from_le_bytes(bytes);
+                    let value = value as u16;
+
+                    let new = if cluster & 1 == 1 {
+                        (old & 0x000f) | value << 4
+                    } else {
+                        (old & 0xf000) | (value & 0xfff)
+                    };
+
+                    self.write_bytes(offset, &new.to_le_bytes())?;
+                }
+                FatKind::Fat16 => {
+                    self.write_bytes(fat + cluster * 2, &(value as u16).to_le_bytes())?;
+                }
+                FatKind::Fat32 => {
+                    // The top four bits are reserved, and must be kept
+                    let offset = fat + cluster * 4;
+                    let mut bytes = [0; 4];
+                    self.read_bytes(offset, &mut bytes)?;
+                    let new = (u32::from_le_bytes(bytes) & 0xf000_0000) | (value & 0x0fff_ffff);
+                    self.write_bytes(offset, &new.to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the cluster after the given one in its chain, or None at the end of the chain
+    fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>, Error> {
+        let next = self.fat_entry(cluster)?;
+
+        if next >= self.kind.end_of_chain() {
+            Ok(None)
+        } else if next < 2 || next >= self.cluster_count + 2 {
+            // A free or bad cluster in a chain
+            Err(Error::Io)
+        } else {
+            Ok(Some(next))
+        }
+    }
+
+    /// Returns the cluster at the given index of the chain which starts at `first`. If the chain
+    /// is shorter, clusters are allocated to extend it if `allocate` is set, and otherwise None is
+    /// returned.
+    fn chain_cluster(
+        &mut self,
+        first: u32,
+        index: u64,
+        allocate: bool,
+    ) -> Result<Option<u32>, Error> {
+        let mut cluster = first;
+
+        for _ in 0..index {
+            cluster = match self.next_cluster(cluster)? {
+                Some(next) => next,
+                None if allocate => self.allocate_cluster(Some(cluster))?,
+                None => return Ok(None),
+            };
+        }
+
+        Ok(Some(cluster))
+    }
+
+    /// Allocates a zeroed cluster, appending it to the chain which ends with `previous` if given
+    fn allocate_cluster(&mut self, previous: Option<u32>) -> Result<u32, Error> {
+        for i in 0..self.cluster_count {
+            let cluster = 2 + (self.next_free - 2 + i) % self.cluster_count;
+
+            if self.fat_entry(cluster)? != 0 {
+                continue;
+            }
+
+            self.invalidate_fs_info()?;
+            self.zero_cluster(cluster)?;
+            self.set_fat_entry(cluster, self.kind.end_marker())?;
+
+            if let Some(previous) = previous {
+                self.set_fat_entry(previous, cluster)?;
+            }
+
+            self.next_free = cluster + 1;
+
+            if self.next_free >= self.cluster_count + 2 {
+                self.next_free = 2;
+            }
+
+            return Ok(cluster);
+        }
+
+        Err(Error::NoSpace)
+    }
+
+    fn zero_cluster(&mut self, cluster: u32) -> Result<(), Error> {
+        let zeroes = [0; MAX_SECTOR_SIZE];
+        let offset = self.cluster_offset(cluster);
+
+        for sector in 0..self.sectors_per_cluster {
+            let sector_offset = offset + sector * self.bytes_per_sector;
+            self.write_bytes(sector_offset, &zeroes[..self.bytes_per_sector as usize])?;
+        }
+
+        Ok(())
+    }
+
+    /// Frees every cluster in the chain which starts at `first`
+    fn free_chain(&mut self, first: u32) -> Result<(), Error> {
+        let mut cluster = Some(first).filter(|&c| c >= 2);
+
+        while let Some(current) = cluster {
+            cluster = self.next_cluster(current)?;
+            self.set_fat_entry(current, 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Marks FAT32's count of free clusters as unknown, rather than keeping it up to date, before
+    /// the first cluster is allocated
+    fn invalidate_fs_info(&mut self) -> Result<(), Error> {
+        let sector = match self.fs_info_sector {
+            Some(sector) if !self.fs_info_invalidated => sector,
+            _ => return Ok(()),
+        };
+
+        self.fs_info_invalidated = true;
+        let offset = sector * self.bytes_per_sector;
+        let mut lead = [0; 4];
+        let mut structure = [0; 4];
+        self.read_bytes(offset, &mut lead)?;
+        self.read_bytes(offset + FS_INFO_STRUCT_SIGNATURE_OFFSET, &mut structure)?;
+
+        if u32::from_le_bytes(lead) == FS_INFO_LEAD_SIGNATURE
+            && u32::from_le_bytes(structure) == FS_INFO_STRUCT_SIGNATURE
+        {
+            self.write_bytes(offset + FS_INFO_FREE_COUNT_OFFSET, &u32::MAX.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the byte offset of the entry at the given index of a directory, or None past its
+    /// end. Chained directories are extended to the index if `extend` is set.
+    fn entry_offset(&mut self, dir: Dir, index: u32, extend: bool) -> Result<Option<u64>, Error> {
+        match dir {
+            Dir::FixedRoot if index < self.root_entries => {
+                let root = (self.reserved_sectors + self.fat_count * self.sectors_per_fat)
+                    * self.bytes_per_sector;
+                Ok(Some(root + index as u64 * DIR_ENTRY_SIZE))
+            }
+            Dir::FixedRoot => Ok(None),
+            Dir::Chain(first) => {
+                let per_cluster = self.cluster_size() / DIR_ENTRY_SIZE;
+                let index = index as u64;
+                let cluster = self.chain_cluster(first, index / per_cluster, extend)?;
+                let within = (index % per_cluster) * DIR_ENTRY_SIZE;
+                Ok(cluster.map(|cluster| self.cluster_offset(cluster) + within))
+            }
+        }
+    }
+
+    /// Returns the first file at or after the given index of a directory, with its long name if it
+    /// has one
+    fn next_entry(&mut self, dir: Dir, index: u32) -> Result<Option<Found>, Error> {
+        let mut long_name = LongName::new();
+        let mut index = index;
+
+        loop {
+            let offset = match self.entry_offset(dir, index, false)? {
+                Some(offset) => offset,
+                None => return Ok(None),
+            };
+
+            let mut bytes = [0; DIR_ENTRY_SIZE as usize];
+            self.read_bytes(offset, &mut bytes)?;
+            index += 1;
+
+            match bytes[0] {
+                ENTRY_END => return Ok(None),
+                ENTRY_DELETED => {
+                    long_name.expected = None;
+                    continue;
+                }
+                _ => (),
+            }
+
+            if bytes[11] & ATTR_LONG_NAME_MASK == ATTR_LONG_NAME {
+                long_name.push(&bytes);
+                continue;
+            }
+
+            let entry = ShortEntry::parse(&bytes);
+
+            if entry.attr & ATTR_VOLUME_ID != 0 {
+                long_name.expected = None;
+                continue;
+            }
+
+            let name = long_name
+                .finish(&entry.name)
+                .unwrap_or_else(|| entry.display_name());
+
+            return Ok(Some(Found {
+                entry,
+                offset,
+                next: index,
+                name,
+            }));
+        }
+    }
+
+    /// Finds the entry with the given long or short name in a directory, ignoring case
+    fn find(&mut self, dir: Dir, name: &str) -> Result<Option<Found>, Error> {
+        let mut index = 0;
+
+        while let Some(found) = self.next_entry(dir, index)? {
+            if found.name.as_str().eq_ignore_ascii_case(name)
+                || found
+                    .entry
+                    .display_name()
+                    .as_str()
+                    .eq_ignore_ascii_case(name)
+            {
+                return Ok(Some(found));
+            }
+
+            index = found.next;
+        }
+
+        Ok(None)
+    }
+
+    /// Finds the directory at a path. Returns it and its first cluster, which is 0 for the root.
+    fn lookup_dir(&mut self, path: &str) -> Result<(Dir, u32), Error> {
+        let mut dir = (self.root_dir(), 0);
+
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            let found = self.find(dir.0, component)?.ok_or(Error::NotFound)?;
+
+            if !found.entry.is_dir() {
+                return Err(Error::NotADirectory);
+            }
+
+            dir = (self.dir_at(found.entry.cluster), found.entry.cluster);
+        }
+
+        Ok(dir)
+    }
+
+    fn read_entry(&mut self, offset: u64) -> Result<ShortEntry, Error> {
+        let mut bytes = [0; DIR_ENTRY_SIZE as usize];
+        self.read_bytes(offset, &mut bytes)?;
+        Ok(ShortEntry::parse(&bytes))
+    }
+
+    fn set_entry_cluster(&mut self, offset: u64, cluster: u32) -> Result<(), Error> {
+        self.write_bytes(offset + 20, &((cluster >> 16) as u16).to_le_bytes())?;
+        self.write_bytes(offset + 26, &(cluster as u16).to_le_bytes())
+    }
+
+    /// Sets the size of a file, and its modification time to now
+    fn set_entry_size(&mut self, offset: u64, size: u32) -> Result<(), Error> {
+        let (time, date) = timestamp();
+        let mut bytes = [0; 10];
+        bytes[0..2].copy_from_slice(&time.to_le_bytes());
+        bytes[2..4].copy_from_slice(&date.to_le_bytes());
+        bytes[6..10].copy_from_slice(&size.to_le_bytes());
+
+        // The first cluster's low half is between the modification date and the size
+        let mut cluster_low = [0; 2];
+        self.read_bytes(offset + 26, &mut cluster_low)?;
+        bytes[4..6].copy_from_slice(&cluster_low);
+
+        self.write_bytes(offset + 22, &bytes)
+    }
+
+    /// Returns the byte offset of a position in a file, and how many bytes follow it in the same
+    /// cluster. If `allocate` is set, the file is extended with clusters to reach the position.
+    fn locate(&mut self, entry: u64, position: u64, allocate: bool) -> Result<(u64, usize), Error> {
+        let mut first = self.read_entry(entry)?.cluster;
+
+        if first == 0 {
+            if !allocate {
+                return Err(Error::Io);
+            }
+
+            first = self.allocate_cluster(None)?;
+            self.set_entry_cluster(entry, first)?;
+        }
+
+        let cluster_size = self.cluster_size();
+        let cluster = self.chain_cluster(first, position / cluster_size, allocate)?;
+        let cluster = cluster.ok_or(Error::Io)?;
+        let within = position % cluster_size;
+
+        Ok((
+            self.cluster_offset(cluster) + within,
+            (cluster_size - within) as usize,
+        ))
+    }
+
+    /// Finds a run of free entries in a directory, extending it if needed. Returns the index of the
+    /// first.
+    fn find_free_entries(&mut self, dir: Dir, count: u32) -> Result<u32, Error> {
+        let mut index = 0;
+        let mut run = 0;
+
+        loop {
+            let offset = self.entry_offset(dir, index, true)?.ok_or(Error::NoSpace)?;
+
+            let mut first = [0];
+            self.read_bytes(offset, &mut first)?;
+
+            if first[0] == ENTRY_END || first[0] == ENTRY_DELETED {
+                run += 1;
+
+                if run == count {
+                    return Ok(index + 1 - count);
+                }
+            } else {
+                run = 0;
+            }
+
+            index += 1;
+        }
+    }
+
+    /// Whether an entry in the directory has the given short name
+    fn short_name_taken(&mut self, dir: Dir, short: &[u8; 11]) -> Result<bool, Error> {
+        let mut index = 0;
+
+        while let Some(found) = self.next_entry(dir, index)? {
+            if &found.entry.name == short {
+                return Ok(true);
+            }
+
+            index = found.next;
+        }
+
+        Ok(false)
+    }
+
+    /// Picks the short name for a new file. Returns it, and whether the file needs a long name.
+    fn short_name_for(&mut self, dir: Dir, name: &str) -> Result<([u8; 11], bool), Error> {
+        if let Some(short) = exact_short_name(name) {
+            return Ok((short, false));
+        }
+
+        // Like Windows, the basis is the upper case name without invalid characters, truncated,
+        // with a numeric tail
+        let (base, extension) = match name.rfind('.') {
+            Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+            _ => (name, ""),
+        };
+
+        let mut basis = [b' '; 11];
+        let base_len = short_name_part(base, &mut basis[0..8]);
+        short_name_part(extension, &mut basis[8..11]);
+
+        for n in 1..1_000_000u32 {
+            let mut tail = [0; 7];
+            let tail_len = write_tail(n, &mut tail);
+            let mut short = basis;
+            let start = base_len.min(8 - tail_len);
+            short[start..start + tail_len].copy_from_slice(&tail[..tail_len]);
+
+            if !self.short_name_taken(dir, &short)? {
+                return Ok((short, true));
+            }
+        }
+
+        Err(Error::AlreadyExists)
+    }
+
+    /// Creates a file or directory in the directory. `parent_cluster` is the directory's first
+    /// cluster, for the new directory's `..` entry. Returns the byte offset of its short entry.
+    fn create(
+        &mut self,
+        dir: Dir,
+        parent_cluster: u32,
+        name: &str,
+        directory: bool,
+    ) -> Result<u64, Error> {
+        let units = name.encode_utf16().count();
+
+        if units > MAX_LFN
+            || name.is_empty()
+            || name == "."
+            || name == ".."
+            || name.ends_with('.')
+            || name.ends_with(' ')
+            || name.chars().any(|c| c < ' ' || INVALID_CHARS.contains(c))
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        let (short, needs_long_name) = self.short_name_for(dir, name)?;
+        let long_entries = if needs_long_name {
+            ((units + LFN_UNITS - 1) / LFN_UNITS) as u32
+        } else {
+            0
+        };
+
+        let start = self.find_free_entries(dir, long_entries + 1)?;
+        let checksum = checksum(&short);
+
+        // The long name entries come before the short entry, in reverse order
+        for i in 0..long_entries {
+            let sequence = (long_entries - i) as u8;
+            let mut bytes = [0; DIR_ENTRY_SIZE as usize];
+            bytes[0] = if i == 0 {
+                sequence | LFN_LAST
+            } else {
+                sequence
+            };
+            bytes[11] = ATTR_LONG_NAME;
+            bytes[13] = checksum;
+
+            let start_unit = (sequence as usize - 1) * LFN_UNITS;
+            let mut name_units = name.encode_utf16().skip(start_unit);
+
+            // The name is terminated by a NUL if it doesn't fill the entry, then padded
+            for (j, &offset) in LFN_UNIT_OFFSETS.iter().enumerate() {
+                let unit = match name_units.next() {
+                    Some(unit) => unit,
+                    None if start_unit + j == units => 0,
+                    None => 0xffff,
+                };
+
+                bytes[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+
+            let offset = self.entry_offset(dir, start + i, false)?.ok_or(Error::Io)?;
+            self.write_bytes(offset, &bytes)?;
+        }
+
+        let now = timestamp();
+        let cluster = if directory {
+            let cluster = self.allocate_cluster(None)?;
+            let offset = self.cluster_offset(cluster);
+            let mut dot = ShortEntry {
+                name: *b".          ",
+                attr: ATTR_DIRECTORY,
+                nt_flags: 0,
+                cluster,
+                size: 0,
+            };
+
+            self.write_bytes(offset, &dot.encode(now))?;
+            dot.name = *b"..         ";
+            dot.cluster = parent_cluster;
+            self.write_bytes(offset + DIR_ENTRY_SIZE, &dot.encode(now))?;
+
+            cluster
+        } else {
+            0
+        };
+
+        let entry = ShortEntry {
+            name: short,
+            attr: if directory {
+                ATTR_DIRECTORY
+            } else {
+                ATTR_ARCHIVE
+            },
+            nt_flags: 0,
+            cluster,
+            size: 0,
+        };
+
+        let index = start + long_entries;
+        let offset = self.entry_offset(dir, index, false)?.ok_or(Error::Io)?;
+        self.write_bytes(offset, &entry.encode(now))?;
+        Ok(offset)
+    }
+
+    fn get(&self, handle: u64) -> Result<OpenFile, Error> {
+        self.open
+            .get(handle as usize)
+            .copied()
+            .flatten()
+            .ok_or(Error::BadHandle)
+    }
+}
+
+/// Returns the name as a short name, if it is already a valid upper case 8.3 name
+fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, extension) = match name.find('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+
+    let valid = |part: &str, max: usize| {
+        part.len() <= max
+            && part.bytes().all(|b| {
+                b.is_ascii_uppercase()
+                    || b.is_ascii_digit()
+                    || SHORT_NAME_SPECIAL.contains(b as char)
+            })
+    };
+
+    if base.is_empty() || !valid(base, 8) || !valid(extension, 3) {
+        return None;
+    }
+
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + extension.len()].copy_from_slice(extension.as_bytes());
+    Some(short)
+}
+
+/// Fills part of a short name from part of a long name, upper case and without characters which
+/// short names can't have. Returns the length filled.
+fn short_name_part(part: &str, out: &mut [u8]) -> usize {
+    let chars =
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| match c.to_ascii_uppercase() {
+                c if c.is_ascii_alphanumeric() || SHORT_NAME_SPECIAL.contains(c) => c as u8,
+                _ => b'_',
+            });
+
+    let mut len = 0;
+
+    for (dst, c) in out.iter_mut().zip(chars) {
+        *dst = c;
+        len += 1;
+    }
+
+    len
+}
+
+/// Writes the `~n` tail of a short name. Returns its length.
+fn write_tail(n: u32, out: &mut [u8; 7]) -> usize {
+    let mut digits = [0; 6];
+    let mut len = 0;
+    let mut n = n;
+
+    while n > 0 {
+        digits[len] = b'0' + (n % 10) as u8;
+        n /= 10;
+        len += 1;
+    }
+
+    out[0] = b'~';
+
+    for i in 0..len {
+        out[1 + i] = digits[len - 1 - i];
+    }
+
+    len + 1
+}
+
+/// The current time and date, as FAT stores them
+fn timestamp() -> (u16, u16) {
+    let ms = syscall::clock_get(Clock::Realtime).unwrap_or(0);
+    let seconds = ms / 1000;
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+
+    // FAT dates start in 1980
+    if year < 1980 {
+        return (0, 1 << 5 | 1);
+    }
+
+    let in_day = seconds % 86400;
+    let time = ((in_day / 3600) << 11) | ((in_day / 60 % 60) << 5) | ((in_day % 60) / 2);
+    let date = (((year - 1980) as u64) << 9) | ((month as u64) << 5) | day as u64;
+    (time as u16, date as u16)
+}
+
+/// Converts days since the unix epoch to a year, month and day, as in
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month as u32, day as u32)
+}
+
+impl<D: BlockDevice> FileSystem for FatFs<D> {
+    fn open(&mut self, path: &str, flags: OpenFlags) -> Result<u64, Error> {
+        let handle = self.open.iter().position(|slot| slot.is_none());
+        let handle = handle.ok_or(Error::TooManyOpen)?;
+        let wants_dir = flags.contains(OpenFlags::DIRECTORY);
+        let writable = flags.intersects(OpenFlags::WRITE | OpenFlags::TRUNCATE);
+
+        let (entry, is_dir) = if path.is_empty() {
+            (None, true)
+        } else {
+            let (parent, name) = match path.rfind('/') {
+                Some(slash) => (&path[..slash], &path[slash + 1..]),
+                None => ("", path),
+            };
+
+            let (dir, parent_cluster) = self.lookup_dir(parent)?;
+
+            match self.find(dir, name)? {
+                // Creating a directory which exists fails, but creating a file opens it
+                Some(_) if wants_dir && flags.contains(OpenFlags::CREATE) => {
+                    return Err(Error::AlreadyExists)
+                }
+                Some(found) => (Some(found.offset), found.entry.is_dir()),
+                None if flags.contains(OpenFlags::CREATE) => {
+                    let offset = self.create(dir, parent_cluster, name, wants_dir)?;
+                    (Some(offset), wants_dir)
+                }
+                None => return Err(Error::NotFound),
+            }
+        };
+
+        match (is_dir, entry) {
+            (false, _) if wants_dir => return Err(Error::NotADirectory),
+            (true, _) if writable => return Err(Error::IsADirectory),
+            (false, Some(entry)) => {
+                let short = self.read_entry(entry)?;
+
+                if writable && short.attr & ATTR_READ_ONLY != 0 {
+                    return Err(Error::ReadOnly);
+                }
+
+                if flags.contains(OpenFlags::TRUNCATE) && short.size > 0 {
+                    self.free_chain(short.cluster)?;
+                    self.set_entry_cluster(entry, 0)?;
+                    self.set_entry_size(entry, 0)?;
+                }
+            }
+            _ => (),
+        }
+
+        self.open[handle] = Some(OpenFile {
+            entry,
+            dir: is_dir,
+            writable,
+        });
+
+        Ok(handle as u64)
+    }
+
+    fn read(&mut self, handle: u64, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        let entry = match self.get(handle)? {
+            OpenFile { dir: true, .. } => return Err(Error::IsADirectory),
+            OpenFile { entry, .. } => entry.ok_or(Error::BadHandle)?,
+        };
+
+        let size = self.read_entry(entry)?.size as u64;
+
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let len = buf.len().min((size - offset) as usize);
+        let mut done = 0;
+
+        while done < len {
+            let (at, room) = self.locate(entry, offset + done as u64, false)?;
+            let n = room.min(len - done);
+            self.read_bytes(at, &mut buf[done..done + n])?;
+            done += n;
+        }
+
+        Ok(len)
+    }
+
+    fn write(&mut self, handle: u64, offset: u64, buf: &[u8]) -> Result<usize, Error> {
+        let entry = match self.get(handle)? {
+            OpenFile { dir: true, .. } => return Err(Error::IsADirectory),
+            OpenFile {
+                writable: false, ..
+            } => return Err(Error::ReadOnly),
+            OpenFile { entry, .. } => entry.ok_or(Error::BadHandle)?,
+        };
+
+        let end = offset + buf.len() as u64;
+
+        if end > u32::MAX as u64 {
+            return Err(Error::NoSpace);
+        }
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // Anything between the end of the file and the offset reads as zeroes. Clusters are zeroed
+        // when allocated, so only the rest of the last one needs to be.
+        let size = self.read_entry(entry)?.size as u64;
+
+        if offset > size && size % self.cluster_size() != 0 {
+            let (at, room) = self.locate(entry, size, false)?;
+            let zeroes = [0; MAX_SECTOR_SIZE];
+            let mut len = room.min((offset - size) as usize);
+            let mut at = at;
+
+            while len > 0 {
+                let n = len.min(MAX_SECTOR_SIZE);
+                self.write_bytes(at, &zeroes[..n])?;
+                at += n as u64;
+                len -= n;
+            }
+        }
+
+        let mut done = 0;
+
+        while done < buf.len() {
+            let (at, room) = self.locate(entry, offset + done as u64, true)?;
+            let n = room.min(buf.len() - done);
+            self.write_bytes(at, &buf[done..done + n])?;
+            done += n;
+        }
+
+        self.set_entry_size(entry, size.max(end) as u32)?;
+        Ok(done)
+    }
+
+    fn stat(&mut self, handle: u64) -> Result<Stat, Error> {
+        let open = self.get(handle)?;
+
+        let size = match open.entry {
+            Some(entry) if !open.dir => self.read_entry(entry)?.size as u64,
+            _ => 0,
+        };
+
+        let kind = if open.dir {
+            FileKind::Directory
+        } else {
+            FileKind::File
+        };
+
+        Ok(Stat { kind, size })
+    }
+
+    /// The position is the index of the next entry in the directory
+    fn read_dir(&mut self, handle: u64, position: u64) -> Result<Option<(DirEntry, u64)>, Error> {
+        let dir = match self.get(handle)? {
+            OpenFile { dir: false, .. } => return Err(Error::NotADirectory),
+            OpenFile { entry: None, .. } => self.root_dir(),
+            OpenFile {
+                entry: Some(entry), ..
+            } => {
+                let cluster = self.read_entry(entry)?.cluster;
+                self.dir_at(cluster)
+            }
+        };
+
+        let mut index = position as u32;
+
+        while let Some(found) = self.next_entry(dir, index)? {
+            index = found.next;
+
+            if found.entry.is_dot() {
+                continue;
+            }
+
+            let (kind, size) = if found.entry.is_dir() {
+                (FileKind::Directory, 0)
+            } else {
+                (FileKind::File, found.entry.size as u64)
+            };
+
+            let entry = DirEntry::new(kind, size, found.name.as_str())?;
+            return Ok(Some((entry, index as u64)));
+        }
+
+        Ok(None)
+    }
+
+    fn close(&mut self, handle: u64) -> Result<(), Error> {
+        let slot = self.open.get_mut(handle as usize).ok_or(Error::BadHandle)?;
+        slot.take().map(|_| ()).ok_or(Error::BadHandle)
+    }
+}
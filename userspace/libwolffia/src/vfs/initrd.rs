@@ -0,0 +1,165 @@
+//! The initrd as a read-only filesystem. Archives only list files, so a directory exists whenever a
+//! file's path passes through it.
+
+use super::server::FileSystem;
+use super::{DirEntry, Error, FileKind, OpenFlags, Stat};
+use crate::initrd::{self, Archive};
+
+/// The most files which can be open at once
+pub const MAX_OPEN: usize = 32;
+
+#[derive(Debug, Copy, Clone)]
+enum Open<'a> {
+    File(initrd::File<'a>),
+    /// A directory, named by its path without leading or trailing `/`, which is empty for the root
+    Directory(&'a str),
+}
+
+pub struct InitrdFs<'a> {
+    archive: Archive<'a>,
+    open: [Option<Open<'a>>; MAX_OPEN],
+}
+
+impl<'a> InitrdFs<'a> {
+    pub fn new(archive: Archive<'a>) -> InitrdFs<'a> {
+        InitrdFs {
+            archive,
+            open: [None; MAX_OPEN],
+        }
+    }
+
+    fn get(&self, handle: u64) -> Result<Open<'a>, Error> {
+        self.open
+            .get(handle as usize)
+            .copied()
+            .flatten()
+            .ok_or(Error::BadHandle)
+    }
+
+    /// Finds the directory at the path, borrowing its name from the path of a file in it
+    fn find_dir(&self, path: &str) -> Option<&'a str> {
+        if path.is_empty() {
+            return Some("");
+        }
+
+        self.archive.files().find_map(|file| {
+            let name = initrd::strip_root(file.name);
+
+            if name.starts_with(path) && name[path.len()..].starts_with('/') {
+                Some(&name[..path.len()])
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// If the file at the path is below the directory, returns the name of the entry of the directory
+/// which it is in or below, and what kind of entry that is
+fn child_of<'b>(dir: &str, path: &'b str) -> Option<(&'b str, FileKind)> {
+    let path = initrd::strip_root(path);
+
+    let rest = if dir.is_empty() {
+        path
+    } else if path.starts_with(dir) && path[dir.len()..].starts_with('/') {
+        &path[dir.len() + 1..]
+    } else {
+        return None;
+    };
+
+    match rest.find('/') {
+        Some(end) => Some((&rest[..end], FileKind::Directory)),
+        None => Some((rest, FileKind::File)),
+    }
+}
+
+impl<'a> FileSystem for InitrdFs<'a> {
+    fn open(&mut self, path: &str, flags: OpenFlags) -> Result<u64, Error> {
+        if flags.intersects(OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE) {
+            return Err(Error::ReadOnly);
+        }
+
+        let file = self.archive.find(path);
+        let open = match (file, self.find_dir(path)) {
+            (Some(_), _) if flags.contains(OpenFlags::DIRECTORY) => {
+                return Err(Error::NotADirectory)
+            }
+            (Some(file), _) => Open::File(file),
+            (None, Some(dir)) => Open::Directory(dir),
+            (None, None) => return Err(Error::NotFound),
+        };
+
+        let handle = self.open.iter().position(|slot| slot.is_none());
+        let handle = handle.ok_or(Error::TooManyOpen)?;
+        self.open[handle] = Some(open);
+        Ok(handle as u64)
+    }
+
+    fn read(&mut self, handle: u64, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        let data = match self.get(handle)? {
+            Open::File(file) => file.data,
+            Open::Directory(_) => return Err(Error::IsADirectory),
+        };
+
+        let start = (offset as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+
+    fn write(&mut self, _handle: u64, _offset: u64, _buf: &[u8]) -> Result<usize, Error> {
+        Err(Error::ReadOnly)
+    }
+
+    fn stat(&mut self, handle: u64) -> Result<Stat, Error> {
+        Ok(match self.get(handle)? {
+            Open::File(file) => Stat {
+                kind: FileKind::File,
+                size: file.data.len() as u64,
+            },
+            Open::Directory(_) => Stat {
+                kind: FileKind::Directory,
+                size: 0,
+            },
+        })
+    }
+
+    /// The position is the index of the next file in the archive to look at. Subdirectories are
+    /// listed at the first file below them.
+    fn read_dir(&mut self, handle: u64, position: u64) -> Result<Option<(DirEntry, u64)>, Error> {
+        let dir = match self.get(handle)? {
+            Open::Directory(dir) => dir,
+            Open::File(_) => return Err(Error::NotADirectory),
+        };
+
+        let files = self.archive.files().enumerate().skip(position as usize);
+
+        for (index, file) in files {
+            let (name, kind) = match child_of(dir, file.name) {
+                Some(child) => child,
+                None => continue,
+            };
+
+            let mut earlier = self.archive.files().take(index);
+
+            if earlier.any(|earlier| child_of(dir, earlier.name).map(|(n, _)| n) == Some(name)) {
+                continue;
+            }
+
+            let size = match kind {
+                FileKind::File => file.data.len() as u64,
+                _ => 0,
+            };
+
+            let entry = DirEntry::new(kind, size, name)?;
+            return Ok(Some((entry, index as u64 + 1)));
+        }
+
+        Ok(None)
+    }
+
+    fn close(&mut self, handle: u64) -> Result<(), Error> {
+        let slot = self.open.get_mut(handle as usize).ok_or(Error::BadHandle)?;
+        slot.take().map(|_| ()).ok_or(Error::BadHandle)
+    }
+}
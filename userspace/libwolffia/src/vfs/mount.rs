@@ -0,0 +1,141 @@
+//! The mount table server, which maps absolute paths to the filesystems serving them. It is run by
+//! a single process, which other processes find through the `vfs` endpoint.
+
+use super::server::path_of;
+use super::{Error, Op, Reply, Request, MAX_PATH, MESSAGE_SIZE, REPLY_SIZE, REQUEST_SIZE};
+use crate::ipc::{Endpoint, RECEIVE_HEADER_SIZE};
+use crate::syscall::SyscallError;
+
+/// The most filesystems which can be mounted at once
+pub const MAX_MOUNTS: usize = 16;
+
+#[derive(Copy, Clone)]
+struct Mount {
+    /// The mount point, without leading or trailing `/`
+    path: [u8; MAX_PATH],
+    path_len: usize,
+    endpoint: u64,
+}
+
+impl Mount {
+    fn path(&self) -> &[u8] {
+        &self.path[..self.path_len]
+    }
+
+    /// If the path is at or below the mount point, returns the rest of it
+    fn strip<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let mount = self.path();
+
+        if mount.is_empty() {
+            return Some(path);
+        }
+
+        if !path.as_bytes().starts_with(mount) {
+            return None;
+        }
+
+        match &path[mount.len()..] {
+            "" => Some(""),
+            rest if rest.starts_with('/') => Some(&rest[1..]),
+            _ => None,
+        }
+    }
+}
+
+pub struct MountTable {
+    mounts: [Option<Mount>; MAX_MOUNTS],
+}
+
+impl MountTable {
+    pub fn new() -> MountTable {
+        MountTable {
+            mounts: [None; MAX_MOUNTS],
+        }
+    }
+
+    /// Mounts the filesystem served on the endpoint at the path, which is relative to the root
+    pub fn mount(&mut self, path: &str, endpoint: u64) -> Result<(), Error> {
+        let mounts = self.mounts.iter().flatten();
+
+        if mounts.clone().any(|mount| mount.path() == path.as_bytes()) {
+            return Err(Error::AlreadyExists);
+        }
+
+        let slot = self.mounts.iter_mut().find(|mount| mount.is_none());
+        let slot = slot.ok_or(Error::NoSpace)?;
+
+        let mut mount = Mount {
+            path: [0; MAX_PATH],
+            path_len: path.len(),
+            endpoint,
+        };
+
+        mount.path[..path.len()].copy_from_slice(path.as_bytes());
+        *slot = Some(mount);
+        Ok(())
+    }
+
+    /// Finds the filesystem mounted at the longest prefix of the path. Returns its endpoint and the
+    /// rest of the path.
+    pub fn resolve<'a>(&self, path: &'a str) -> Result<(u64, &'a str), Error> {
+        self.mounts
+            .iter()
+            .flatten()
+            .filter_map(|mount| Some((mount, mount.strip(path)?)))
+            .max_by_key(|(mount, _)| mount.path_len)
+            .map(|(mount, rest)| (mount.endpoint, rest))
+            .ok_or(Error::NotFound)
+    }
+
+    /// Creates the `vfs` endpoint and answers mount and resolve requests on it forever. Only
+    /// returns if creating the endpoint, or receiving or replying, fails.
+    pub fn serve(&mut self) -> SyscallError {
+        let endpoint = match Endpoint::create(super::MOUNT_ENDPOINT) {
+            Ok(endpoint) => endpoint,
+            Err(err) => return err,
+        };
+
+        let mut request_buf = [0; RECEIVE_HEADER_SIZE + MESSAGE_SIZE];
+        let mut reply_buf = [0; REPLY_SIZE + MAX_PATH];
+
+        loop {
+            let received = match endpoint.receive(&mut request_buf) {
+                Ok(received) => received,
+                Err(err) => return err,
+            };
+
+            let request = Request::decode(received.request);
+            let payload = received.request.get(REQUEST_SIZE..).unwrap_or(&[]);
+            let op = request.and_then(|request| Op::from_u32(request.op));
+            let (res, payload_len) = match (request, op) {
+                (Some(request), Some(Op::Mount)) => {
+                    let res = path_of(payload).and_then(|path| self.mount(path, request.handle));
+                    (res.map(|_| 0), 0)
+                }
+                (Some(_), Some(Op::Resolve)) => {
+                    match path_of(payload).and_then(|path| self.resolve(path)) {
+                        Ok((endpoint, rest)) => {
+                            let end = REPLY_SIZE + rest.len();
+                            reply_buf[REPLY_SIZE..end].copy_from_slice(rest.as_bytes());
+                            (Ok(endpoint), rest.len())
+                        }
+                        Err(err) => (Err(err), 0),
+                    }
+                }
+                _ => (Err(Error::InvalidArgument), 0),
+            };
+
+            Reply::from_result(res).encode(&mut reply_buf);
+
+            if let Err(err) = received.call.reply(&reply_buf[..REPLY_SIZE + payload_len]) {
+                return err;
+            }
+        }
+    }
+}
+
+impl Default for MountTable {
+    fn default() -> Self {
+        MountTable::new()
+    }
+}
@@ -0,0 +1,132 @@
+//! Serving a filesystem over the VFS protocol. A filesystem implements `FileSystem`, then its
+//! process creates an endpoint, mounts it and hands it to `serve`.
+
+use super::{
+    DirEntry, Error, Op, OpenFlags, Reply, Request, Stat, MAX_PATH, MAX_TRANSFER, MESSAGE_SIZE,
+    REPLY_SIZE, REQUEST_SIZE,
+};
+use crate::ipc::{Endpoint, RECEIVE_HEADER_SIZE};
+use crate::syscall::SyscallError;
+use core::str;
+
+/// A filesystem, whose files are named by paths relative to its root, without a leading `/`. The
+/// root itself is the empty path.
+pub trait FileSystem {
+    /// Opens a file or directory, returning a handle to it which stays valid until it is closed
+    fn open(&mut self, path: &str, flags: OpenFlags) -> Result<u64, Error>;
+
+    /// Reads from the given offset, returning the length read. This is only less than the buffer's
+    /// at the end of the file.
+    fn read(&mut self, handle: u64, offset: u64, buf: &mut [u8]) -> Result<usize, Error>;
+
+    /// Writes at the given offset, extending the file if needed. Returns the length written.
+    fn write(&mut self, handle: u64, offset: u64, buf: &[u8]) -> Result<usize, Error>;
+
+    fn stat(&mut self, handle: u64) -> Result<Stat, Error>;
+
+    /// Returns the entry of a directory at the given position, and the position of the next, or
+    /// None at the end of the directory. The first entry is at position 0.
+    fn read_dir(&mut self, handle: u64, position: u64) -> Result<Option<(DirEntry, u64)>, Error>;
+
+    fn close(&mut self, handle: u64) -> Result<(), Error>;
+
+    /// Sets the length of a file, dropping its end or extending it with zeroes
+    fn truncate(&mut self, _handle: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::Unsupported)
+    }
+
+    /// Removes a file or empty directory
+    fn unlink(&mut self, _path: &str) -> Result<(), Error> {
+        Err(Error::Unsupported)
+    }
+}
+
+/// Receives requests on the endpoint and answers them from the filesystem, forever. Only returns
+/// if receiving or replying fails.
+pub fn serve<F: FileSystem>(endpoint: &Endpoint, fs: &mut F) -> SyscallError {
+    let mut request_buf = [0; RECEIVE_HEADER_SIZE + MESSAGE_SIZE];
+    let mut reply_buf = [0; MESSAGE_SIZE];
+
+    loop {
+        let received = match endpoint.receive(&mut request_buf) {
+            Ok(received) => received,
+            Err(err) => return err,
+        };
+
+        let payload = &mut reply_buf[REPLY_SIZE..];
+        let (res, payload_len) = match Request::decode(received.request) {
+            Some(request) => handle(fs, request, &received.request[REQUEST_SIZE..], payload),
+            None => (Err(Error::InvalidArgument), 0),
+        };
+
+        Reply::from_result(res).encode(&mut reply_buf);
+
+        if let Err(err) = received.call.reply(&reply_buf[..REPLY_SIZE + payload_len]) {
+            return err;
+        }
+    }
+}
+
+/// Answers one request. Returns the result, and the length of the payload written to the buffer.
+fn handle<F: FileSystem>(
+    fs: &mut F,
+    request: Request,
+    payload: &[u8],
+    reply_payload: &mut [u8],
+) -> (Result<u64, Error>, usize) {
+    let op = match Op::from_u32(request.op) {
+        Some(op) => op,
+        None => return (Err(Error::InvalidArgument), 0),
+    };
+
+    match op {
+        Op::Open => {
+            let path = match path_of(payload) {
+                Ok(path) => path,
+                Err(err) => return (Err(err), 0),
+            };
+
+            let flags = OpenFlags::from_bits_truncate(request.flags);
+            (fs.open(path, flags), 0)
+        }
+        Op::Read => {
+            let len = (request.len as usize).min(MAX_TRANSFER);
+
+            match fs.read(request.handle, request.offset, &mut reply_payload[..len]) {
+                Ok(len) => (Ok(len as u64), len),
+                Err(err) => (Err(err), 0),
+            }
+        }
+        Op::Write => {
+            let res = fs.write(request.handle, request.offset, payload);
+            (res.map(|len| len as u64), 0)
+        }
+        Op::Stat => match fs.stat(request.handle) {
+            Ok(stat) => (Ok(0), stat.encode(reply_payload)),
+            Err(err) => (Err(err), 0),
+        },
+        Op::ReadDir => match fs.read_dir(request.handle, request.offset) {
+            Ok(Some((entry, next))) => (Ok(next), entry.encode(reply_payload)),
+            Ok(None) => (Ok(0), 0),
+            Err(err) => (Err(err), 0),
+        },
+        Op::Close => (fs.close(request.handle).map(|_| 0), 0),
+        Op::Truncate => (fs.truncate(request.handle, request.offset).map(|_| 0), 0),
+        Op::Unlink => match path_of(payload) {
+            Ok(path) => (fs.unlink(path).map(|_| 0), 0),
+            Err(err) => (Err(err), 0),
+        },
+        // Filesystems don't mount each other
+        Op::Mount | Op::Resolve => (Err(Error::InvalidArgument), 0),
+    }
+}
+
+/// Parses the path which a request carries, stripping any leading or trailing `/`
+pub(super) fn path_of(payload: &[u8]) -> Result<&str, Error> {
+    if payload.len() > MAX_PATH {
+        return Err(Error::InvalidArgument);
+    }
+
+    let path = str::from_utf8(payload).map_err(|_| Error::InvalidArgument)?;
+    Ok(path.trim_matches('/'))
+}
@@ -0,0 +1,265 @@
+//! Reading lines of input, from the keyboard if the shell may read input events, or else from its
+//! standard input. Lines typed on the keyboard can be edited, and earlier ones recalled.
+
+use libwolffia::prelude::*;
+use libwolffia::syscall::{self, InputEvent, KeyCode, Modifiers, SyscallError};
+use libwolffia::Stdin;
+
+/// Characters typed past this are ignored
+pub const MAX_LINE: usize = 128;
+/// How many earlier lines are kept to recall with the up and down keys
+const HISTORY_LEN: usize = 16;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Source {
+    Keyboard,
+    Stdin,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    /// Ctrl+C, which throws the line away
+    Cancel,
+    /// Ctrl+D, or the end of standard input
+    EndOfInput,
+}
+
+#[derive(Copy, Clone)]
+struct Line {
+    buf: [u8; MAX_LINE],
+    len: usize,
+}
+
+impl Line {
+    const EMPTY: Line = Line {
+        buf: [0; MAX_LINE],
+        len: 0,
+    };
+
+    fn as_str(&self) -> &str {
+        // Only ASCII is ever put into the buffer
+        core::str::from_utf8(&self.buf[..self.len]).unwrap()
+    }
+}
+
+pub struct Editor {
+    source: Source,
+    events: [InputEvent; 16],
+    events_len: usize,
+    next_event: usize,
+    line: Line,
+    /// Where in the line characters are inserted
+    cursor: usize,
+    history: [Line; HISTORY_LEN],
+    /// How many lines are in the history, which are kept oldest first
+    history_len: usize,
+    /// The line of the history which is being edited, or `history_len` for a new line
+    history_index: usize,
+}
+
+impl Editor {
+    pub fn new() -> Editor {
+        Editor {
+            source: Source::Keyboard,
+            events: [InputEvent::default(); 16],
+            events_len: 0,
+            next_event: 0,
+            line: Line::EMPTY,
+            cursor: 0,
+            history: [Line::EMPTY; HISTORY_LEN],
+            history_len: 0,
+            history_index: 0,
+        }
+    }
+
+    /// Prints the prompt and reads a line into `buf`, returning it without its line ending. Returns
+    /// None at the end of the input.
+    pub fn read_line<'a>(&mut self, prompt: &str, buf: &'a mut [u8; MAX_LINE]) -> Option<&'a str> {
+        self.line = Line::EMPTY;
+        self.cursor = 0;
+        self.history_index = self.history_len;
+        print!("{}", prompt);
+
+        loop {
+            match self.next_key() {
+                Key::Enter => break,
+                Key::EndOfInput if self.line.len == 0 => {
+                    println!();
+                    return None;
+                }
+                // Standard input may end without a line ending
+                Key::EndOfInput if self.source == Source::Stdin => break,
+                Key::EndOfInput => (),
+                Key::Cancel => {
+                    println!("^C");
+                    self.line = Line::EMPTY;
+                    self.cursor = 0;
+                    self.history_index = self.history_len;
+                    print!("{}", prompt);
+                    continue;
+                }
+                key => self.edit(key),
+            }
+
+            self.redraw(prompt);
+        }
+
+        println!();
+
+        let line = self.line;
+        if line.len != 0 {
+            self.remember(line);
+        }
+
+        buf[..line.len].copy_from_slice(&line.buf[..line.len]);
+        Some(core::str::from_utf8(&buf[..line.len]).unwrap())
+    }
+
+    fn edit(&mut self, key: Key) {
+        let line = &mut self.line;
+
+        match key {
+            Key::Char(c) if c.is_ascii() && !c.is_ascii_control() && line.len < MAX_LINE => {
+                line.buf.copy_within(self.cursor..line.len, self.cursor + 1);
+                line.buf[self.cursor] = c as u8;
+                line.len += 1;
+                self.cursor += 1;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                line.buf.copy_within(self.cursor..line.len, self.cursor - 1);
+                line.len -= 1;
+                self.cursor -= 1;
+            }
+            Key::Delete if self.cursor < line.len => {
+                line.buf.copy_within(self.cursor + 1..line.len, self.cursor);
+                line.len -= 1;
+            }
+            Key::Left if self.cursor > 0 => self.cursor -= 1,
+            Key::Right if self.cursor < line.len => self.cursor += 1,
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = line.len,
+            Key::Up if self.history_index > 0 => {
+                self.history_index -= 1;
+                self.line = self.history[self.history_index];
+                self.cursor = self.line.len;
+            }
+            Key::Down if self.history_index < self.history_len => {
+                self.history_index += 1;
+                self.line = if self.history_index == self.history_len {
+                    Line::EMPTY
+                } else {
+                    self.history[self.history_index]
+                };
+                self.cursor = self.line.len;
+            }
+            _ => (),
+        }
+    }
+
+    /// Rewrites the line after an edit, and puts the screen's cursor back in it
+    fn redraw(&self, prompt: &str) {
+        // Lines read from standard input are not echoed
+        if self.source == Source::Stdin {
+            return;
+        }
+
+        print!("\r{}{}\x1b[K", prompt, self.line.as_str());
+
+        let back = self.line.len - self.cursor;
+        if back > 0 {
+            print!("\x1b[{}D", back);
+        }
+    }
+
+    fn remember(&mut self, line: Line) {
+        if self.history_len == HISTORY_LEN {
+            self.history.copy_within(1.., 0);
+            self.history_len -= 1;
+        }
+
+        self.history[self.history_len] = line;
+        self.history_len += 1;
+    }
+
+    fn next_key(&mut self) -> Key {
+        loop {
+            let key = match self.source {
+                Source::Keyboard => self.next_keyboard_key(),
+                Source::Stdin => Some(next_stdin_key()),
+            };
+
+            if let Some(key) = key {
+                return key;
+            }
+        }
+    }
+
+    /// Returns the key pressed by the next input event, if it was a key press which means anything
+    /// to the editor. Falls back to standard input if the shell may not read input events.
+    fn next_keyboard_key(&mut self) -> Option<Key> {
+        if self.next_event == self.events_len {
+            match syscall::input_read_blocking(&mut self.events) {
+                Ok(read) => self.events_len = read,
+                Err(SyscallError::PermissionDenied) => {
+                    self.source = Source::Stdin;
+                    return None;
+                }
+                Err(err) => panic!("Error reading input: {:?}", err),
+            }
+
+            self.next_event = 0;
+        }
+
+        let event = self.events[self.next_event];
+        self.next_event += 1;
+
+        if event.kind != InputEvent::KIND_KEY_PRESS {
+            return None;
+        }
+
+        let ctrl = event.modifiers().intersects(Modifiers::CTRL);
+
+        let key = match event.key()? {
+            KeyCode::C if ctrl => Key::Cancel,
+            KeyCode::D if ctrl => Key::EndOfInput,
+            KeyCode::Enter | KeyCode::KeypadEnter => Key::Enter,
+            KeyCode::Backspace => Key::Backspace,
+            KeyCode::Delete => Key::Delete,
+            KeyCode::Left => Key::Left,
+            KeyCode::Right => Key::Right,
+            KeyCode::Home => Key::Home,
+            KeyCode::End => Key::End,
+            KeyCode::Up => Key::Up,
+            KeyCode::Down => Key::Down,
+            _ if ctrl => return None,
+            _ => Key::Char(event.character()?),
+        };
+
+        Some(key)
+    }
+}
+
+fn next_stdin_key() -> Key {
+    let mut byte = [0];
+
+    match Stdin.read(&mut byte) {
+        Ok(0) | Err(_) => Key::EndOfInput,
+        Ok(_) => match byte[0] {
+            b'\n' | b'\r' => Key::Enter,
+            0x08 | 0x7f => Key::Backspace,
+            0x03 => Key::Cancel,
+            0x04 => Key::EndOfInput,
+            byte => Key::Char(byte as char),
+        },
+    }
+}
@@ -0,0 +1,291 @@
+#![no_std]
+#![no_main]
+
+mod editor;
+
+use editor::{Editor, MAX_LINE};
+use libwolffia::initrd::{self, Archive};
+use libwolffia::prelude::*;
+use libwolffia::syscall::{self, Capabilities, ExitStatus, ProcessInfo, SyscallError};
+use libwolffia::vfs::{self, Error, File, FileKind, OpenFlags};
+
+const PROMPT: &str = "> ";
+/// Processes past this are not shown by `ps`
+const MAX_PROCESSES: usize = 64;
+
+#[libwolffia::main]
+fn main() {
+    let initrd = match initrd::map() {
+        Ok(initrd) => Some(initrd),
+        Err(err) => {
+            eprintln!("shell: couldn't map the initrd: {:?}", err);
+            None
+        }
+    };
+
+    let mut editor = Editor::new();
+    let mut buf = [0; MAX_LINE];
+
+    while let Some(line) = editor.read_line(PROMPT, &mut buf) {
+        let mut words = line.split_whitespace();
+
+        let command = match words.next() {
+            Some(command) => command,
+            None => continue,
+        };
+
+        match command {
+            "help" => help(),
+            "exit" => break,
+            "echo" => echo(words),
+            "ls" => ls(words.next().unwrap_or("/")),
+            "cat" => words.for_each(cat),
+            "ps" => ps(),
+            "kill" => words.for_each(kill),
+            "mem" => mem(),
+            _ => run(initrd, command, words),
+        }
+    }
+}
+
+fn help() {
+    println!("Commands:");
+    println!("  echo [WORDS...]  print the words");
+    println!("  ls [DIR]         list a directory, `/` by default");
+    println!("  cat FILES...     print files");
+    println!("  ps               list the running processes");
+    println!("  kill PIDS...     end processes, if the shell may");
+    println!("  mem              show how much memory is used");
+    println!("  exit             leave the shell");
+    println!("Paths are from the root, where the initrd is mounted at `/initrd`.");
+    println!("Anything else is run as a program from the initrd, looked up in `bin` if needed.");
+    println!("Programs get no capabilities unless they are listed before them, e.g:");
+    println!("  +initrd +storage PROGRAM");
+}
+
+fn echo<'a>(words: impl Iterator<Item = &'a str>) {
+    for (i, word) in words.enumerate() {
+        if i != 0 {
+            print!(" ");
+        }
+
+        print!("{}", word);
+    }
+
+    println!();
+}
+
+/// Lists a directory through the mount table server, with a `/` after the names of directories
+fn ls(path: &str) {
+    let dir = match File::open(path, OpenFlags::READ | OpenFlags::DIRECTORY) {
+        Ok(dir) => dir,
+        Err(err) => return report("ls", path, err),
+    };
+
+    for entry in dir.read_dir() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => return report("ls", path, err),
+        };
+
+        let suffix = if entry.kind == FileKind::Directory {
+            "/"
+        } else {
+            ""
+        };
+
+        println!("{:>10} {}{}", entry.size, entry.name(), suffix);
+    }
+}
+
+/// Prints a file read through the mount table server
+fn cat(path: &str) {
+    let mut file = match File::open(path, OpenFlags::READ) {
+        Ok(file) => file,
+        Err(err) => return report("cat", path, err),
+    };
+
+    let mut buf = [0; vfs::MAX_TRANSFER];
+    // The start of a UTF-8 sequence which the last read cut off
+    let mut kept = 0;
+
+    loop {
+        let len = match file.read(&mut buf[kept..]) {
+            Ok(len) => len,
+            Err(err) => return report("cat", path, err),
+        };
+
+        // Reads only come up short at the end of the file
+        let end = len < buf.len() - kept;
+        let len = kept + len;
+        kept = print_utf8(&buf[..len], end);
+        buf.copy_within(len - kept..len, 0);
+
+        if end {
+            break;
+        }
+    }
+}
+
+/// Prints what is valid UTF-8, and a replacement character for each invalid sequence. Unless this
+/// is the end of the text, a sequence which is cut off at the end is left to be printed with the
+/// rest of it, and its length is returned.
+fn print_utf8(mut data: &[u8], end: bool) -> usize {
+    while !data.is_empty() {
+        match core::str::from_utf8(data) {
+            Ok(text) => {
+                print!("{}", text);
+                break;
+            }
+            Err(err) => {
+                let valid = err.valid_up_to();
+                // SAFETY: the bytes up to here were checked to be valid
+                let text = unsafe { core::str::from_utf8_unchecked(&data[..valid]) };
+                print!("{}", text);
+
+                if err.error_len().is_none() && !end {
+                    return data.len() - valid;
+                }
+
+                print!("\u{FFFD}");
+                let invalid = err.error_len().unwrap_or(data.len() - valid);
+                data = &data[valid + invalid..];
+            }
+        }
+    }
+
+    0
+}
+
+fn report(command: &str, path: &str, err: Error) {
+    let reason = match err {
+        Error::NotFound => "no such file or directory",
+        Error::NotADirectory => "not a directory",
+        Error::IsADirectory => "is a directory",
+        Error::Ipc(SyscallError::NotFound) => "no filesystem is serving it",
+        err => return eprintln!("{}: {}: {:?}", command, path, err),
+    };
+
+    eprintln!("{}: {}: {}", command, path, reason);
+}
+
+fn ps() {
+    let mut processes = [ProcessInfo::default(); MAX_PROCESSES];
+
+    let count = match syscall::process_list(&mut processes) {
+        Ok(count) => count,
+        Err(err) => return eprintln!("ps: {:?}", err),
+    };
+
+    println!(
+        "{:>5} {:>6} {:<8} {:<16} NAME",
+        "PID", "PARENT", "STATE", "CAPABILITIES"
+    );
+
+    for process in &processes[..count.min(MAX_PROCESSES)] {
+        let state = match process.state {
+            ProcessInfo::STATE_RUNNABLE => "runnable",
+            ProcessInfo::STATE_BLOCKED => "blocked",
+            _ => "?",
+        };
+
+        print!("{:>5} ", process.pid);
+
+        match process.parent() {
+            Some(parent) => print!("{:>6} ", parent),
+            None => print!("{:>6} ", "-"),
+        }
+
+        println!(
+            "{:<8} {:<#16x} {}",
+            state,
+            process.capabilities,
+            process.name()
+        );
+    }
+
+    if count > MAX_PROCESSES {
+        println!("...and {} more", count - MAX_PROCESSES);
+    }
+}
+
+fn kill(pid: &str) {
+    let res = match pid.parse() {
+        Ok(pid) => syscall::kill(pid),
+        Err(_) => return eprintln!("kill: {}: not a process id", pid),
+    };
+
+    match res {
+        Ok(()) => (),
+        Err(SyscallError::NotFound) => eprintln!("kill: {}: no such process", pid),
+        Err(SyscallError::PermissionDenied) => eprintln!("kill: {}: not allowed", pid),
+        Err(err) => eprintln!("kill: {}: {:?}", pid, err),
+    }
+}
+
+fn mem() {
+    let info = match syscall::memory_info() {
+        Ok(info) => info,
+        Err(err) => return eprintln!("mem: {:?}", err),
+    };
+
+    println!("{:>12} {:>12} {:>12}", "TOTAL", "USED", "KERNEL HEAP");
+    println!(
+        "{:>9} KiB {:>9} KiB {:>9} KiB",
+        info.usable / 1024,
+        info.allocated / 1024,
+        info.kernel_heap / 1024
+    );
+}
+
+/// Spawns a program from the initrd and waits for it to exit. Its standard input and output are
+/// the shell's. It is given the capabilities named by the words starting with `+` which come
+/// before it, and no others.
+fn run<'a>(initrd: Option<Archive>, first: &'a str, mut words: impl Iterator<Item = &'a str>) {
+    let mut capabilities = Capabilities::empty();
+    let mut name = first;
+
+    while name.starts_with('+') {
+        match Capabilities::from_name(&name[1..]) {
+            Some(capability) => capabilities |= capability,
+            None => return eprintln!("{}: no such capability", &name[1..]),
+        }
+
+        name = match words.next() {
+            Some(name) => name,
+            None => return eprintln!("expected a program after the capabilities"),
+        };
+    }
+
+    let file = initrd.and_then(|initrd| {
+        initrd.find(name).or_else(|| {
+            let mut path = [0; MAX_LINE + 4];
+            let len = name.len() + 4;
+
+            path[..4].copy_from_slice(b"bin/");
+            path[4..len].copy_from_slice(name.as_bytes());
+            initrd.find(core::str::from_utf8(&path[..len]).unwrap())
+        })
+    });
+
+    let file = match file {
+        Some(file) => file,
+        None => return eprintln!("{}: command not found", name),
+    };
+
+    let pid = match syscall::spawn(file.data, name, capabilities, None) {
+        Ok(pid) => pid,
+        Err(SyscallError::PermissionDenied) => {
+            return eprintln!("{}: the shell can't give it those capabilities", name)
+        }
+        Err(err) => return eprintln!("{}: couldn't run: {:?}", name, err),
+    };
+
+    match syscall::wait(pid) {
+        Ok(ExitStatus::Exited(0)) => (),
+        Ok(ExitStatus::Exited(code)) => eprintln!("{}: exited with code {}", name, code),
+        Ok(ExitStatus::Killed) => eprintln!("{}: killed", name),
+        Ok(ExitStatus::Unknown(status)) => eprintln!("{}: exited with status {:#x}", name, status),
+        Err(err) => eprintln!("{}: couldn't wait for it: {:?}", name, err),
+    }
+}
@@ -0,0 +1,31 @@
+//! The mount table server, which filesystem servers mount themselves through, and which other
+//! processes ask which filesystem serves a path. It is found through the `vfs` endpoint, and
+//! mounts the kernel's tmpfs at `/tmp` itself.
+
+#![no_std]
+#![no_main]
+
+use libwolffia::ipc::Endpoint;
+use libwolffia::prelude::*;
+use libwolffia::syscall;
+use libwolffia::vfs::mount::MountTable;
+use libwolffia::vfs::TMPFS_ENDPOINT;
+
+/// Where the tmpfs is mounted, relative to the root
+const TMPFS_MOUNT_POINT: &str = "tmp";
+
+#[libwolffia::main]
+fn main() {
+    let mut table = MountTable::new();
+
+    match Endpoint::lookup(TMPFS_ENDPOINT) {
+        Ok(tmpfs) => table
+            .mount(TMPFS_MOUNT_POINT, tmpfs.id())
+            .expect("The mount table is empty"),
+        Err(err) => eprintln!("vfs: couldn't find the tmpfs: {:?}", err),
+    }
+
+    let err = table.serve();
+    eprintln!("vfs: serving the mount table failed: {:?}", err);
+    syscall::exit(1);
+}